@@ -0,0 +1,165 @@
+//! Authentication of the requests of the external API. Clients give a bearer token
+//! in the `Authorization` header: a static token of a user, read from the file of
+//! `AUTH_CONFIG`, or the token of a service account issued by the controller.
+//! Without this file, authentication is disabled and everyone is anonymous.
+
+use crate::api::external::services::service_account::{find_service_account, hash_token};
+use crate::api::types::error::ApiError;
+use crate::database::Storage;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Default location of the authentication settings
+const DEFAULT_CONFIG_PATH: &str = "/etc/rik/controller/auth.json";
+
+/// Authentication settings, e.g.
+/// `{"tokens": [{"user": "admin", "token": "<secret>", "admin": true}]}`
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub tokens: Vec<StaticToken>,
+}
+
+/// Token of a user, given in the settings
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StaticToken {
+    pub user: String,
+    pub token: String,
+    /// Administrators are allowed every request, whatever the roles of the tenants
+    #[serde(default)]
+    pub admin: bool,
+}
+
+/// Who sent a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Identity {
+    /// Anyone, when authentication is disabled
+    Anonymous,
+    /// User of a static token
+    User(String),
+    /// Service account, by name
+    ServiceAccount(String),
+}
+
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Identity::Anonymous => write!(f, "anonymous"),
+            Identity::User(name) => write!(f, "user {}", name),
+            Identity::ServiceAccount(name) => write!(f, "service account {}", name),
+        }
+    }
+}
+
+/// Authenticates the requests, disabled by default
+#[derive(Default)]
+pub struct Authenticator {
+    enabled: bool,
+    /// Users of the static tokens, by SHA-256 of the token
+    users: HashMap<String, String>,
+    admins: HashSet<String>,
+}
+
+impl Authenticator {
+    pub fn new(config: AuthConfig) -> Authenticator {
+        Authenticator {
+            enabled: true,
+            admins: config
+                .tokens
+                .iter()
+                .filter(|token| token.admin)
+                .map(|token| token.user.clone())
+                .collect(),
+            users: config
+                .tokens
+                .into_iter()
+                .map(|token| (hash_token(&token.token), token.user))
+                .collect(),
+        }
+    }
+
+    /// Read the settings from the file of `AUTH_CONFIG`. Authentication is only
+    /// disabled when `AUTH_CONFIG` isn't set and the default file doesn't exist, a file
+    /// given explicitly must be readable.
+    pub fn from_env() -> Result<Authenticator, String> {
+        Authenticator::load(std::env::var("AUTH_CONFIG").ok().as_deref())
+    }
+
+    /// Read the settings from a file, or from the default one if `None`
+    fn load(path: Option<&str>) -> Result<Authenticator, String> {
+        let file = path.unwrap_or(DEFAULT_CONFIG_PATH);
+        let content = match std::fs::read_to_string(file) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && path.is_none() => {
+                return Ok(Authenticator::default())
+            }
+            Err(e) => return Err(format!("Cannot read {}: {}", file, e)),
+        };
+        let config: AuthConfig =
+            serde_json::from_str(&content).map_err(|e| format!("Invalid {}: {}", file, e))?;
+        Ok(Authenticator::new(config))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn is_admin(&self, identity: &Identity) -> bool {
+        matches!(identity, Identity::User(user) if self.admins.contains(user))
+    }
+
+    /// Identify the sender of a request by its bearer token
+    pub fn authenticate(
+        &self,
+        request: &tiny_http::Request,
+        storage: &dyn Storage,
+    ) -> Result<Identity, ApiError> {
+        if !self.enabled {
+            return Ok(Identity::Anonymous);
+        }
+        let token =
+            bearer_token(request).ok_or_else(|| ApiError::unauthorized("Missing bearer token"))?;
+        if let Some(user) = self.users.get(&hash_token(token)) {
+            return Ok(Identity::User(user.clone()));
+        }
+        find_service_account(storage, token)
+            .map(|account| Identity::ServiceAccount(account.name))
+            .ok_or_else(|| ApiError::unauthorized("Invalid token"))
+    }
+}
+
+/// Get the token of the `Authorization: Bearer <token>` header of a request
+pub fn bearer_token(request: &tiny_http::Request) -> Option<&str> {
+    let header = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))?;
+    let (scheme, token) = header.value.as_str().trim().split_once(' ')?;
+    match scheme.eq_ignore_ascii_case("Bearer") {
+        true => Some(token.trim()).filter(|token| !token.is_empty()),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_explicit_config() {
+        let path = std::env::temp_dir().join("rik-controller-missing-auth.json");
+        let error = Authenticator::load(path.to_str()).err().unwrap();
+        assert!(error.starts_with("Cannot read"));
+
+        let path = std::env::temp_dir().join(format!("rik-auth-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"tokens": [{"user": "root", "token": "secret"}]}"#,
+        )
+        .unwrap();
+        let authenticator = Authenticator::load(path.to_str()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(authenticator.is_enabled());
+    }
+}
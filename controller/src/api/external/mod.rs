@@ -1,48 +1,59 @@
+pub mod auth;
+pub mod rbac;
 mod routes;
-mod services;
+pub(crate) mod services;
 
+use crate::api::external::auth::Authenticator;
+use crate::api::external::rbac::AuditLog;
 use crate::api::ApiChannel;
-use crate::database::RikDataBase;
-use crate::logger::{LogType, LoggingChannel};
+use crate::database::Storage;
+use crate::logger::{new_request_id, RequestScope, REQUEST_ID_HEADER};
 use dotenv::dotenv;
+use std::str::FromStr;
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::Arc;
 use std::thread;
+use std::time::Instant;
+use telemetry::TraceContext;
 use tiny_http::{Request, Server as TinyServer};
-
-use colored::Colorize;
+use tracing::{debug, error, info, warn};
 
 pub struct Server {
-    logger: Sender<LoggingChannel>,
     internal_sender: Sender<ApiChannel>,
     external_receiver: Receiver<ApiChannel>,
+    authenticator: Arc<Authenticator>,
+    audit: Arc<AuditLog>,
 }
 
 impl Server {
     pub fn new(
-        logger_sender: Sender<LoggingChannel>,
         internal_sender: Sender<ApiChannel>,
         external_receiver: Receiver<ApiChannel>,
+        authenticator: Authenticator,
+        audit: AuditLog,
     ) -> Server {
         Server {
-            logger: logger_sender,
             internal_sender,
             external_receiver,
+            authenticator: Arc::new(authenticator),
+            audit: Arc::new(audit),
         }
     }
 
-    pub fn run(&self, db: Arc<RikDataBase>) {
-        self.run_server(db);
+    pub fn run(&self, storage: Arc<dyn Storage>) {
+        self.run_server(storage);
         self.listen_notification();
     }
 
     fn listen_notification(&self) {
         for notification in &self.external_receiver {
-            println!("{}", notification);
+            debug!("{}", notification);
         }
     }
 
-    fn run_server(&self, db: Arc<RikDataBase>) {
+    fn run_server(&self, storage: Arc<dyn Storage>) {
+        services::namespace::create_default_namespace(storage.as_ref()).unwrap();
+
         let host = String::from("0.0.0.0");
         dotenv().ok();
         let port: usize = match std::env::var("PORT") {
@@ -53,43 +64,93 @@ impl Server {
         let server = Arc::new(server);
 
         let mut guards = Vec::with_capacity(4);
+        let watch_limit = routes::WatchLimit::from_env();
 
         for _ in 0..4 {
             let server = server.clone();
-            let db = db.clone();
+            let storage = storage.clone();
             let internal_sender = self.internal_sender.clone();
-            let logger = self.logger.clone();
+            let authenticator = self.authenticator.clone();
+            let audit = self.audit.clone();
+            let watch_limit = watch_limit.clone();
 
-            let guard = thread::spawn(move || loop {
-                let router = routes::Router::new();
-                let connection = db.open().unwrap();
+            let guard = thread::spawn(move || {
+                let router = routes::Router::new(authenticator, audit);
 
-                let mut req: Request = server.recv().unwrap();
+                loop {
+                    let mut req: Request = server.recv().unwrap();
 
-                if let Some(res) = router.handle(&mut req, &connection, &internal_sender, &logger) {
-                    req.respond(res).unwrap();
-                    continue;
+                    // The request id given by the client is kept, so it can correlate its own logs
+                    let request_id = req
+                        .headers()
+                        .iter()
+                        .find(|header| header.field.equiv(REQUEST_ID_HEADER))
+                        .map(|header| header.value.to_string())
+                        .unwrap_or_else(new_request_id);
+                    // W3C trace context headers, to continue the trace of the client
+                    let trace_context: TraceContext = req
+                        .headers()
+                        .iter()
+                        .filter(|header| {
+                            header.field.equiv("traceparent") || header.field.equiv("tracestate")
+                        })
+                        .map(|header| {
+                            (
+                                header.field.as_str().as_str().to_lowercase(),
+                                header.value.to_string(),
+                            )
+                        })
+                        .collect();
+                    let url = req.url().to_string();
+                    let scope =
+                        RequestScope::enter(&request_id, req.method(), &url, &trace_context);
+                    let started = Instant::now();
+
+                    let watch = router
+                        .watch_request(&req, storage.as_ref())
+                        .map(|watch| watch.and_then(|watch| Ok((watch, watch_limit.acquire()?))));
+                    let response = match watch {
+                        Some(Ok((watch, slot))) => {
+                            // A watch streams until the client disconnects, it has its own thread
+                            let storage = storage.clone();
+                            thread::spawn(move || {
+                                let _slot = slot;
+                                let _scope = RequestScope::enter(
+                                    &request_id,
+                                    req.method(),
+                                    &url,
+                                    &trace_context,
+                                );
+                                info!("Watch started");
+                                watch.serve(req, storage.as_ref());
+                            });
+                            continue;
+                        }
+                        Some(Err(response)) => response,
+                        None => router.handle(&mut req, storage.as_ref(), &internal_sender),
+                    };
+                    info!(
+                        status = response.status_code().0,
+                        elapsed_ms = started.elapsed().as_millis() as u64,
+                        "Request handled"
+                    );
+                    let header = format!("{}: {}", REQUEST_ID_HEADER, request_id);
+                    let response = match tiny_http::Header::from_str(&header) {
+                        Ok(header) => response.with_header(header),
+                        Err(_) => response,
+                    };
+                    if let Err(e) = req.respond(response) {
+                        error!("Cannot send response: {}", e);
+                    }
+                    drop(scope);
                 }
-                logger
-                    .send(LoggingChannel {
-                        message: String::from("Route not found"),
-                        log_type: LogType::Log,
-                    })
-                    .unwrap();
-                req.respond(tiny_http::Response::empty(tiny_http::StatusCode::from(404)))
-                    .unwrap();
             });
 
             guards.push(guard);
         }
-        self.logger
-            .send(LoggingChannel {
-                message: format!(
-                    "{}",
-                    format!("Server running on http://{}:{}", host, port).green()
-                ),
-                log_type: LogType::Log,
-            })
-            .unwrap();
+        if !self.authenticator.is_enabled() {
+            warn!("Authentication is disabled, the API is open to anyone");
+        }
+        info!("Server running on http://{}:{}", host, port);
     }
 }
@@ -0,0 +1,371 @@
+//! Role-based access control of the external API. Tenants define roles, the verbs
+//! they allow on kinds of resources in the namespaces they own, and bind them to users
+//! and service accounts. Every request is checked before its handler runs, and every
+//! decision is written to the audit trail of `AUDIT_LOG`.
+
+use crate::api::external::auth::{Authenticator, Identity};
+use crate::api::external::services::namespace::namespace_tenant;
+use crate::api::external::services::tenant::find_tenant;
+use crate::api::types::tenant::{Role, RoleBinding, TenantSpec};
+use crate::database::Storage;
+use serde::Serialize;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::error;
+
+/// Default location of the audit trail
+const DEFAULT_AUDIT_LOG: &str = "/var/log/rik/audit.log";
+
+/// Kinds of resources living in a namespace
+pub const NAMESPACED_RESOURCES: [&str; 2] = ["workloads", "instances"];
+
+/// What a request does: a verb on a kind of resources, in a namespace for the
+/// resources living in one. No namespace means every namespace.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Access {
+    pub verb: String,
+    pub resource: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.verb, self.resource)?;
+        match &self.namespace {
+            Some(namespace) => write!(f, " in namespace {}", namespace),
+            None if NAMESPACED_RESOURCES.contains(&self.resource.as_str()) => {
+                write!(f, " in every namespace")
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Whether a request is allowed, and why
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Decision {
+    pub allowed: bool,
+    pub reason: String,
+}
+
+impl Decision {
+    fn allow(reason: impl Into<String>) -> Decision {
+        Decision {
+            allowed: true,
+            reason: reason.into(),
+        }
+    }
+
+    fn deny(reason: impl Into<String>) -> Decision {
+        Decision {
+            allowed: false,
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Decide whether the sender of a request may access resources, with the roles the
+/// tenants bind to it. Roles only apply in the namespaces owned by their tenant, so
+/// the resources without namespace and the requests about every namespace are left
+/// to the administrators. Administrators are allowed everything, as is everyone when
+/// authentication is disabled.
+pub fn authorize(
+    storage: &dyn Storage,
+    authenticator: &Authenticator,
+    identity: &Identity,
+    access: &Access,
+) -> Decision {
+    match identity {
+        Identity::Anonymous => return Decision::allow("Authentication is disabled"),
+        identity if authenticator.is_admin(identity) => return Decision::allow("Administrator"),
+        _ => {}
+    }
+    let denied = Decision::deny(format!("No role allows {} to {}", identity, access));
+    let owner = match access
+        .namespace
+        .as_deref()
+        .and_then(|namespace| namespace_tenant(storage, namespace))
+    {
+        Some(owner) => owner,
+        None => return denied,
+    };
+    let tenant = match find_tenant(storage, &owner) {
+        Ok(tenant) => tenant,
+        Err(_) => return denied,
+    };
+    let spec: TenantSpec = match serde_json::from_value(tenant.value) {
+        Ok(spec) => spec,
+        Err(_) => return denied,
+    };
+    let role = spec
+        .bindings
+        .iter()
+        .filter(|binding| binds(binding, identity))
+        .filter_map(|binding| spec.roles.iter().find(|role| role.name == binding.role))
+        .find(|role| allows(role, access));
+    match role {
+        Some(role) => Decision::allow(format!("Role {} of tenant {}", role.name, owner)),
+        None => denied,
+    }
+}
+
+/// Whether a binding gives its role to the sender of a request
+fn binds(binding: &RoleBinding, identity: &Identity) -> bool {
+    match identity {
+        Identity::User(name) => binding.users.contains(name),
+        Identity::ServiceAccount(name) => binding.service_accounts.contains(name),
+        Identity::Anonymous => false,
+    }
+}
+
+/// Whether a role allows an access in a namespace of its tenant
+fn allows(role: &Role, access: &Access) -> bool {
+    let matches = |values: &[String], value: &str| values.iter().any(|v| v == "*" || v == value);
+    matches(&role.verbs, &access.verb)
+        && matches(&role.resources, &access.resource)
+        && access
+            .namespace
+            .as_deref()
+            .is_some_and(|namespace| matches(&role.namespaces, namespace))
+}
+
+/// Decision of the audit trail, written as a JSON line
+#[derive(Serialize)]
+struct AuditRecord<'a> {
+    timestamp: i64,
+    identity: String,
+    path: &'a str,
+    #[serde(flatten)]
+    access: Option<&'a Access>,
+    #[serde(flatten)]
+    decision: &'a Decision,
+}
+
+/// Audit trail of the authorization decisions, disabled by default
+#[derive(Default)]
+pub struct AuditLog {
+    writer: Option<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl AuditLog {
+    pub fn new(writer: Box<dyn Write + Send>) -> AuditLog {
+        AuditLog {
+            writer: Some(Mutex::new(writer)),
+        }
+    }
+
+    /// Append the decisions to the file of `AUDIT_LOG`
+    pub fn from_env() -> Result<AuditLog, String> {
+        let path = std::env::var("AUDIT_LOG").unwrap_or_else(|_| String::from(DEFAULT_AUDIT_LOG));
+        if let Some(directory) = Path::new(&path).parent() {
+            std::fs::create_dir_all(directory)
+                .map_err(|e| format!("Cannot create {}: {}", directory.display(), e))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("Cannot open {}: {}", path, e))?;
+        Ok(AuditLog::new(Box::new(file)))
+    }
+
+    /// Write a decision about a request to the audit trail
+    pub fn record(&self, identity: &Identity, path: &str, access: &Access, decision: &Decision) {
+        self.write(identity, path, Some(access), decision);
+    }
+
+    /// Write to the audit trail that the sender of a request could not be authenticated,
+    /// the request is denied before knowing what it does
+    pub fn record_unauthenticated(&self, path: &str, reason: &str) {
+        self.write(&Identity::Anonymous, path, None, &Decision::deny(reason));
+    }
+
+    fn write(&self, identity: &Identity, path: &str, access: Option<&Access>, decision: &Decision) {
+        let writer = match &self.writer {
+            Some(writer) => writer,
+            None => return,
+        };
+        let record = AuditRecord {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs() as i64,
+            identity: identity.to_string(),
+            path,
+            access,
+            decision,
+        };
+        let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+        let line = serde_json::to_string(&record).unwrap();
+        if let Err(e) = writeln!(writer, "{}", line).and_then(|_| writer.flush()) {
+            error!("Cannot write the audit trail: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::external::auth::AuthConfig;
+    use crate::database::memory::MemoryStorage;
+    use crate::database::RikRepository;
+    use std::sync::Arc;
+
+    fn access(verb: &str, resource: &str, namespace: Option<&str>) -> Access {
+        Access {
+            verb: verb.to_string(),
+            resource: resource.to_string(),
+            namespace: namespace.map(String::from),
+        }
+    }
+
+    /// Writer sharing what it is given with the test
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_authorize() {
+        let storage = MemoryStorage::default();
+        let spec = r#"{"roles": [{"name": "developer", "verbs": ["get", "list", "create"],
+            "resources": ["workloads"], "namespaces": ["dev"]}],
+            "bindings": [{"role": "developer", "users": ["alice"], "service_accounts": ["ci"]}]}"#;
+        RikRepository::insert(&storage, "/tenant/acme", spec).unwrap();
+        let namespace = r#"{"name": "dev", "tenant": "acme"}"#;
+        RikRepository::insert(&storage, "/namespace/dev", namespace).unwrap();
+        let config: AuthConfig = serde_json::from_str(
+            r#"{"tokens": [{"user": "root", "token": "secret", "admin": true}]}"#,
+        )
+        .unwrap();
+        let authenticator = Authenticator::new(config);
+        let alice = Identity::User(String::from("alice"));
+        let ci = Identity::ServiceAccount(String::from("ci"));
+
+        let decision = authorize(
+            &storage,
+            &authenticator,
+            &alice,
+            &access("create", "workloads", Some("dev")),
+        );
+        assert_eq!(decision, Decision::allow("Role developer of tenant acme"));
+        let decision = authorize(
+            &storage,
+            &authenticator,
+            &ci,
+            &access("list", "workloads", Some("dev")),
+        );
+        assert!(decision.allowed);
+
+        for denied in [
+            access("delete", "workloads", Some("dev")),
+            access("create", "workloads", Some("prod")),
+            access("list", "workloads", None),
+            access("list", "tenants", None),
+        ] {
+            assert!(!authorize(&storage, &authenticator, &alice, &denied).allowed);
+        }
+        let decision = authorize(
+            &storage,
+            &authenticator,
+            &Identity::User(String::from("bob")),
+            &access("get", "workloads", Some("dev")),
+        );
+        assert_eq!(
+            decision,
+            Decision::deny("No role allows user bob to get workloads in namespace dev")
+        );
+
+        let root = Identity::User(String::from("root"));
+        let decision = authorize(
+            &storage,
+            &authenticator,
+            &root,
+            &access("delete", "tenants", None),
+        );
+        assert_eq!(decision, Decision::allow("Administrator"));
+    }
+
+    #[test]
+    fn test_authorize_across_tenants() {
+        let storage = MemoryStorage::default();
+        let spec = r#"{"roles": [{"name": "owner", "verbs": ["*"], "resources": ["*"],
+            "namespaces": ["*"]}], "bindings": [{"role": "owner", "users": ["mallory"]}]}"#;
+        RikRepository::insert(&storage, "/tenant/globex", spec).unwrap();
+        RikRepository::insert(&storage, "/tenant/acme", "{}").unwrap();
+        let namespace = r#"{"name": "ops", "tenant": "globex"}"#;
+        RikRepository::insert(&storage, "/namespace/ops", namespace).unwrap();
+        let namespace = r#"{"name": "dev", "tenant": "acme"}"#;
+        RikRepository::insert(&storage, "/namespace/dev", namespace).unwrap();
+        let authenticator = Authenticator::new(AuthConfig::default());
+        let mallory = Identity::User(String::from("mallory"));
+
+        let decision = authorize(
+            &storage,
+            &authenticator,
+            &mallory,
+            &access("create", "workloads", Some("ops")),
+        );
+        assert_eq!(decision, Decision::allow("Role owner of tenant globex"));
+        // The roles of globex don't reach the namespaces of acme, the ones without
+        // tenant, nor the resources of every tenant
+        for denied in [
+            access("create", "workloads", Some("dev")),
+            access("create", "workloads", Some("default")),
+            access("list", "instances", None),
+            access("delete", "tenants", None),
+        ] {
+            assert!(!authorize(&storage, &authenticator, &mallory, &denied).allowed);
+        }
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let buffer = SharedBuffer::default();
+        let audit = AuditLog::new(Box::new(buffer.clone()));
+        let identity = Identity::User(String::from("alice"));
+        let access = access("delete", "workloads", Some("dev"));
+        audit.record(
+            &identity,
+            "/api/v1/workloads/42",
+            &access,
+            &Decision::deny("No role allows user alice to delete workloads in namespace dev"),
+        );
+
+        let content = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let record: serde_json::Value = serde_json::from_str(content.trim_end()).unwrap();
+        assert_eq!(record["identity"], "user alice");
+        assert_eq!(record["path"], "/api/v1/workloads/42");
+        assert_eq!(record["verb"], "delete");
+        assert_eq!(record["namespace"], "dev");
+        assert_eq!(record["allowed"], false);
+    }
+
+    #[test]
+    fn test_audit_log_unauthenticated() {
+        let buffer = SharedBuffer::default();
+        let audit = AuditLog::new(Box::new(buffer.clone()));
+        audit.record_unauthenticated("/api/v1/workloads", "Invalid bearer token");
+
+        let content = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let record: serde_json::Value = serde_json::from_str(content.trim_end()).unwrap();
+        assert_eq!(record["identity"], "anonymous");
+        assert_eq!(record["path"], "/api/v1/workloads");
+        assert_eq!(record["verb"], serde_json::Value::Null);
+        assert_eq!(record["allowed"], false);
+        assert_eq!(record["reason"], "Invalid bearer token");
+    }
+}
@@ -0,0 +1,28 @@
+use crate::database::Storage;
+use route_recognizer;
+use std::io;
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+
+use crate::api;
+use crate::api::external::services::event::find_events;
+use crate::api::ApiChannel;
+use tracing::info;
+
+pub fn get(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    if let Ok(events) = find_events(storage, params.find("kind"), params.find("object")) {
+        let events_json = serde_json::to_string(&events).unwrap();
+        info!("Events found");
+        Ok(tiny_http::Response::from_string(events_json)
+            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+            .with_status_code(tiny_http::StatusCode::from(200)))
+    } else {
+        Ok(tiny_http::Response::from_string("Cannot find events")
+            .with_status_code(tiny_http::StatusCode::from(500)))
+    }
+}
@@ -1,37 +1,34 @@
 use route_recognizer;
-use rusqlite::Connection;
 use std::io;
-use std::str::FromStr;
 use std::sync::mpsc::Sender;
 
 use crate::api;
-use crate::api::external::services::element::elements_set_right_name;
-use crate::api::external::services::instance::send_create_instance;
+use crate::api::external::routes::v1::read_body;
+use crate::api::external::services::element::{
+    check_resource_version, list_options, page_response,
+};
+use crate::api::external::services::instance::{create_instances, delete_instance};
+use crate::api::external::services::namespace::namespace_instances_prefix;
 use crate::api::types::element::OnlyId;
 use crate::api::types::instance::InstanceDefinition;
-use crate::api::{ApiChannel, CRUD};
-use crate::database::RikRepository;
-use crate::logger::{LogType, LoggingChannel};
+use crate::api::ApiChannel;
+use crate::database::{RikRepository, Storage};
+use tracing::{error, info, warn};
 
 pub fn get(
     _: &mut tiny_http::Request,
-    _: &route_recognizer::Params,
-    connection: &Connection,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
     _: &Sender<ApiChannel>,
-    logger: &Sender<LoggingChannel>,
 ) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
-    if let Ok(mut instances) = RikRepository::find_all(connection, "/instance") {
-        instances = elements_set_right_name(instances.clone());
-        let instances_json = serde_json::to_string(&instances).unwrap();
-        logger
-            .send(LoggingChannel {
-                message: String::from("Instances found"),
-                log_type: LogType::Log,
-            })
-            .unwrap();
-        Ok(tiny_http::Response::from_string(instances_json)
-            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
-            .with_status_code(tiny_http::StatusCode::from(200)))
+    let prefix = match params.find("namespace") {
+        Some(namespace) => namespace_instances_prefix(namespace),
+        None => String::from("/instance"),
+    };
+    let options = list_options(params)?;
+    if let Ok(instances) = RikRepository::list(storage, &prefix, &options) {
+        info!("Instances found");
+        Ok(page_response(instances)?.with_status_code(tiny_http::StatusCode::from(200)))
     } else {
         Ok(tiny_http::Response::from_string("Cannot find instances")
             .with_status_code(tiny_http::StatusCode::from(500)))
@@ -39,66 +36,18 @@ pub fn get(
 }
 
 pub fn create(
-    req: &mut tiny_http::Request,
-    _: &route_recognizer::Params,
-    connection: &Connection,
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
     internal_sender: &Sender<ApiChannel>,
-    logger: &Sender<LoggingChannel>,
 ) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
-    let mut content = String::new();
-    req.as_reader().read_to_string(&mut content).unwrap();
-
-    let mut instance: InstanceDefinition = serde_json::from_str(&content)?;
-
-    //Workload not found
-    if RikRepository::find_one(connection, &instance.workload_id, "/workload").is_err() {
-        logger
-            .send(LoggingChannel {
-                message: format!("Workload id {} not found", &instance.workload_id),
-                log_type: LogType::Warn,
-            })
-            .unwrap();
-        return Ok(tiny_http::Response::from_string(format!(
-            "Workload id {} not found",
-            &instance.workload_id
-        ))
-        .with_status_code(tiny_http::StatusCode::from(404)));
-    }
-
-    if instance.name.is_some() {
-        // Check name is not used
-        if RikRepository::check_duplicate_name(
-            connection,
-            &format!("/instance/%/default/{}", instance.get_name()),
-        )
-        .is_ok()
-        {
-            logger
-                .send(LoggingChannel {
-                    message: String::from("Name already used"),
-                    log_type: LogType::Warn,
-                })
-                .unwrap();
-            return Ok(tiny_http::Response::from_string("Name already used")
-                .with_status_code(tiny_http::StatusCode::from(404)));
-        }
-
-        // Name cannot be used with multiple replicas
-        if instance.get_replicas() > 1 {
-            return Ok(
-                tiny_http::Response::from_string("Cannot use name with multiple replicas")
-                    .with_status_code(tiny_http::StatusCode::from(400)),
-            );
-        }
-    }
+    let content = read_body(params);
 
-    for _ in 0..instance.get_replicas() {
-        send_create_instance(
-            connection,
-            internal_sender,
-            instance.workload_id.clone(),
-            &instance.name,
-        );
+    let instance: InstanceDefinition = serde_json::from_str(content)?;
+    if let Err(error) = create_instances(storage, internal_sender, instance) {
+        warn!("{}", error.message);
+        return Ok(tiny_http::Response::from_string(error.message)
+            .with_status_code(tiny_http::StatusCode::from(error.status)));
     }
 
     Ok(tiny_http::Response::from_string("").with_status_code(tiny_http::StatusCode::from(201)))
@@ -106,40 +55,22 @@ pub fn create(
 
 pub fn delete(
     req: &mut tiny_http::Request,
-    _: &route_recognizer::Params,
-    connection: &Connection,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
     internal_sender: &Sender<ApiChannel>,
-    logger: &Sender<LoggingChannel>,
 ) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
-    let mut content = String::new();
-    req.as_reader().read_to_string(&mut content).unwrap();
-    let OnlyId { id: delete_id } = serde_json::from_str(&content)?;
+    let content = read_body(params);
+    let OnlyId { id: delete_id } = serde_json::from_str(content)?;
 
-    if let Ok(instance) = RikRepository::find_one(connection, &delete_id, "/instance") {
-        internal_sender
-            .send(ApiChannel {
-                action: CRUD::Delete,
-                workload_id: None,
-                workload_definition: None,
-                instance_id: Some(delete_id),
-            })
-            .unwrap();
-        RikRepository::delete(connection, &instance.id).unwrap();
+    if let Ok(instance) = RikRepository::find_one(storage, &delete_id, "/instance") {
+        check_resource_version(req, params, &instance)?;
+        let id = instance.id.clone();
+        delete_instance(storage, internal_sender, instance)?;
 
-        logger
-            .send(LoggingChannel {
-                message: format!("Delete instance {}", instance.id),
-                log_type: LogType::Log,
-            })
-            .unwrap();
+        info!("Delete instance {}", id);
         Ok(tiny_http::Response::from_string("").with_status_code(tiny_http::StatusCode::from(204)))
     } else {
-        logger
-            .send(LoggingChannel {
-                message: format!("Instance id {} not found", delete_id),
-                log_type: LogType::Error,
-            })
-            .unwrap();
+        error!("Instance id {} not found", delete_id);
         Ok(
             tiny_http::Response::from_string(format!("Instance id {} not found", delete_id))
                 .with_status_code(tiny_http::StatusCode::from(404)),
@@ -1,83 +1,895 @@
 use route_recognizer;
-use rusqlite::Connection;
 use std::io;
 use std::sync::mpsc::Sender;
 
 use crate::api;
+use crate::api::external::auth::{bearer_token, Authenticator, Identity};
+use crate::api::external::rbac::{self, Access, AuditLog, NAMESPACED_RESOURCES};
+use crate::api::types::error::ApiError;
 use crate::api::ApiChannel;
-use crate::logger::{LogType, LoggingChannel};
+use crate::database::{RikRepository, Storage};
+use definition::workload::DEFAULT_NAMESPACE;
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::{error, info_span, warn};
 
+mod event;
 mod instance;
+mod namespace;
 mod tenant;
+mod v1;
 mod workload;
 
+pub use v1::watch::{Watch, WatchLimit};
+
 type Handler = fn(
     &mut tiny_http::Request,
     &route_recognizer::Params,
-    &Connection,
+    &dyn Storage,
     &Sender<ApiChannel>,
-    &Sender<LoggingChannel>,
 ) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError>;
 
 pub struct Router {
     routes: Vec<(tiny_http::Method, route_recognizer::Router<Handler>)>,
+    authenticator: Arc<Authenticator>,
+    audit: Arc<AuditLog>,
 }
 
 impl Router {
-    pub fn new() -> Router {
+    pub fn new(authenticator: Arc<Authenticator>, audit: Arc<AuditLog>) -> Router {
         let mut get = route_recognizer::Router::<Handler>::new();
         let mut post = route_recognizer::Router::<Handler>::new();
 
         let base_path = "/api/v0";
 
         // GET
+        get.add(&format!("{}/events.list", base_path), event::get);
         get.add(&format!("{}/instances.list", base_path), instance::get);
+        get.add(&format!("{}/namespaces.list", base_path), namespace::get);
         get.add(&format!("{}/tenants.list", base_path), tenant::get);
         get.add(&format!("{}/workloads.list", base_path), workload::get);
         // POST
         post.add(&format!("{}/instances.create", base_path), instance::create);
+        post.add(
+            &format!("{}/namespaces.create", base_path),
+            namespace::create,
+        );
         post.add(&format!("{}/tenants.create", base_path), tenant::create);
         post.add(&format!("{}/workloads.create", base_path), workload::create);
         post.add(&format!("{}/instances.delete", base_path), instance::delete);
+        post.add(
+            &format!("{}/namespaces.delete", base_path),
+            namespace::delete,
+        );
         post.add(&format!("{}/tenants.delete", base_path), tenant::delete);
+        post.add(&format!("{}/tenants.quota", base_path), tenant::set_quota);
         post.add(&format!("{}/workloads.delete", base_path), workload::delete);
 
+        // v1, one URL per resource
+        let mut put = route_recognizer::Router::<Handler>::new();
+        let mut patch = route_recognizer::Router::<Handler>::new();
+        let mut delete = route_recognizer::Router::<Handler>::new();
+        for (resources, list, create, get_one, delete_one) in [
+            (
+                "workloads",
+                v1::workload::list as Handler,
+                v1::workload::create as Handler,
+                v1::workload::get as Handler,
+                v1::workload::delete as Handler,
+            ),
+            (
+                "instances",
+                v1::instance::list,
+                v1::instance::create,
+                v1::instance::get,
+                v1::instance::delete,
+            ),
+            (
+                "tenants",
+                v1::tenant::list,
+                v1::tenant::create,
+                v1::tenant::get,
+                v1::tenant::delete,
+            ),
+            (
+                "serviceaccounts",
+                v1::service_account::list,
+                v1::service_account::create,
+                v1::service_account::get,
+                v1::service_account::delete,
+            ),
+        ] {
+            let collection = format!("{}/{}", v1::BASE_PATH, resources);
+            let item = format!("{}/:id", collection);
+            get.add(&collection, list);
+            post.add(&collection, create);
+            get.add(&item, get_one);
+            delete.add(&item, delete_one);
+        }
+        get.add(&format!("{}/health", v1::BASE_PATH), v1::health::get);
+        // Instances are updated by the scheduler only
+        put.add(
+            &format!("{}/workloads/:id", v1::BASE_PATH),
+            v1::workload::update,
+        );
+        patch.add(
+            &format!("{}/workloads/:id", v1::BASE_PATH),
+            v1::workload::patch,
+        );
+        put.add(
+            &format!("{}/tenants/:id", v1::BASE_PATH),
+            v1::tenant::update,
+        );
+        patch.add(&format!("{}/tenants/:id", v1::BASE_PATH), v1::tenant::patch);
+
         Router {
             routes: vec![
                 ("GET".parse().unwrap(), get),
                 ("POST".parse().unwrap(), post),
+                ("PUT".parse().unwrap(), put),
+                ("PATCH".parse().unwrap(), patch),
+                ("DELETE".parse().unwrap(), delete),
             ],
+            authenticator,
+            audit,
+        }
+    }
+
+    /// Identify the sender of a request, the failures are written to the audit trail.
+    /// The health of the controller is given to anyone, e.g. to probes.
+    pub fn authenticate(
+        &self,
+        request: &tiny_http::Request,
+        storage: &dyn Storage,
+    ) -> Result<Identity, tiny_http::Response<io::Cursor<Vec<u8>>>> {
+        let url = request.url();
+        let path = url.split_once('?').map_or(url, |(path, _)| path);
+        if path == format!("{}/health", v1::BASE_PATH) {
+            return Ok(Identity::Anonymous);
+        }
+        self.authenticator
+            .authenticate(request, storage)
+            .map_err(|error| {
+                warn!("{}", error.message);
+                self.audit.record_unauthenticated(path, &error.message);
+                error_response(path.starts_with(v1::BASE_PATH), &error)
+                    .with_header(tiny_http::Header::from_str("WWW-Authenticate: Bearer").unwrap())
+            })
+    }
+
+    /// Get the watch asked by a request, if it is one. Watches stream the changes of
+    /// resources, so they are served apart from the other requests.
+    pub fn watch_request(
+        &self,
+        request: &tiny_http::Request,
+        storage: &dyn Storage,
+    ) -> Option<Result<Watch, tiny_http::Response<io::Cursor<Vec<u8>>>>> {
+        let watch = Watch::from_request(request)?;
+        let identity = match self.authenticate(request, storage) {
+            Ok(identity) => identity,
+            Err(response) => return Some(Err(response)),
+        };
+        let url = request.url();
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let mut params = route_recognizer::Params::new();
+        insert_query_params(&mut params, query);
+        if let Err(error) = self.authorize(&identity, request.method(), path, &params, storage) {
+            warn!("{}", error.message);
+            return Some(Err(v1::error_response(&error)));
         }
+        // The token of a service account can be revoked while the watch streams
+        let watch = match (&identity, bearer_token(request)) {
+            (Identity::ServiceAccount(_), Some(token)) => {
+                watch.map(|watch| watch.with_service_account_token(token))
+            }
+            _ => watch,
+        };
+        Some(watch.map_err(|error| {
+            warn!("{}", error.message);
+            v1::error_response(&error)
+        }))
     }
 
+    /// Check the sender of a request is allowed to send it, and write the decision
+    /// to the audit trail. The public routes are allowed to anyone.
+    fn authorize(
+        &self,
+        identity: &Identity,
+        method: &tiny_http::Method,
+        path: &str,
+        params: &route_recognizer::Params,
+        storage: &dyn Storage,
+    ) -> Result<(), ApiError> {
+        if !self.authenticator.is_enabled() {
+            return Ok(());
+        }
+        let access = match request_access(method, path, params, storage) {
+            Some(access) => access?,
+            None => return Ok(()),
+        };
+        let decision = rbac::authorize(storage, &self.authenticator, identity, &access);
+        self.audit.record(identity, path, &access, &decision);
+        match decision.allowed {
+            true => Ok(()),
+            false => Err(ApiError::forbidden(decision.reason)),
+        }
+    }
+
+    /// Handle a request of an authenticated and authorized sender with the handler of
+    /// its route. Errors of the `v1` routes are answered with a JSON body.
     pub fn handle(
         &self,
         request: &mut tiny_http::Request,
-        connection: &Connection,
+        storage: &dyn Storage,
         internal_sender: &Sender<ApiChannel>,
-        logger: &Sender<LoggingChannel>,
-    ) -> Option<tiny_http::Response<io::Cursor<Vec<u8>>>> {
-        self.routes
+    ) -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+        let identity = match self.authenticate(request, storage) {
+            Ok(identity) => identity,
+            Err(response) => return response,
+        };
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+        let json_errors = path.starts_with(v1::BASE_PATH);
+
+        let route = self
+            .routes
             .iter()
-            .find(|&&(ref method, _)| method == request.method())
-            .and_then(|&(_, ref routes)| {
-                if let Ok(res) = routes.recognize(request.url()) {
-                    Some(
-                        res.handler()(request, res.params(), connection, internal_sender, logger)
-                            .unwrap_or_else(|error| {
-                                logger
-                                    .send(LoggingChannel {
-                                        message: error.to_string(),
-                                        log_type: LogType::Error,
-                                    })
-                                    .unwrap();
-                                tiny_http::Response::from_string(error.to_string())
-                                    .with_status_code(tiny_http::StatusCode::from(400))
-                            }),
-                    )
-                } else {
-                    None
+            .find(|(method, _)| method == request.method())
+            .and_then(|(_, routes)| routes.recognize(path).ok());
+        let mut res = match route {
+            Some(res) => res,
+            None => {
+                // The route may exist for other methods
+                let allowed: Vec<String> = self
+                    .routes
+                    .iter()
+                    .filter(|(_, routes)| routes.recognize(path).is_ok())
+                    .map(|(method, _)| method.to_string())
+                    .collect();
+                if allowed.is_empty() {
+                    warn!("Route not found");
+                    return error_response(json_errors, &ApiError::not_found("Route not found"));
                 }
-            })
+                warn!("Method not allowed");
+                let allow = format!("Allow: {}", allowed.join(", "));
+                return error_response(json_errors, &ApiError::new(405, "Method not allowed"))
+                    .with_header(tiny_http::Header::from_str(&allow).unwrap());
+            }
+        };
+
+        // Query parameters are given to the handler along with the route parameters,
+        // and the body, which is read first to authorize the request
+        insert_query_params(res.params_mut(), query);
+        let mut body = String::new();
+        if let Err(e) = request.as_reader().read_to_string(&mut body) {
+            let error = ApiError::bad_request(format!("Cannot read the body: {}", e));
+            warn!("{}", error.message);
+            return error_response(json_errors, &error);
+        }
+        res.params_mut().insert(v1::BODY_PARAM.to_string(), body);
+        let method = request.method().clone();
+        if let Err(error) = self.authorize(&identity, &method, path, res.params(), storage) {
+            warn!("{}", error.message);
+            return error_response(json_errors, &error);
+        }
+        let span = info_span!("handler", route = path, %identity);
+        let result =
+            span.in_scope(|| res.handler()(request, res.params(), storage, internal_sender));
+        result.unwrap_or_else(|error| {
+            let error = match error {
+                api::RikError::Api(error) => error,
+                error => ApiError::new(error.status(), error.to_string()),
+            };
+            if error.status >= 500 {
+                error!("{}", error.message);
+            } else {
+                warn!("{}", error.message);
+            }
+            error_response(json_errors, &error)
+        })
+    }
+}
+
+/// Add the query parameters, percent-decoded, to the parameters of a route
+fn insert_query_params(params: &mut route_recognizer::Params, query: &str) {
+    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+        params.insert(key.into_owned(), value.into_owned());
+    }
+}
+
+/// Get what a request does, `None` for the public routes
+fn request_access(
+    method: &tiny_http::Method,
+    path: &str,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+) -> Option<Result<Access, ApiError>> {
+    let (resource, verb) = match path.strip_prefix("/api/v0/") {
+        // `/api/v0/<resources>.<action>`
+        Some(route) => {
+            let (resource, action) = route.split_once('.').unwrap_or((route, ""));
+            let verb = match action {
+                "quota" => "update",
+                action => action,
+            };
+            (resource, verb.to_string())
+        }
+        None => {
+            let route = path.strip_prefix(v1::BASE_PATH).unwrap_or(path);
+            let resource = route.trim_start_matches('/').split('/').next()?;
+            if resource == "health" {
+                return None;
+            }
+            let verb = match (method, params.find("id").is_some()) {
+                (tiny_http::Method::Get, false) if params.find("watch") == Some("true") => "watch",
+                (tiny_http::Method::Get, false) => "list",
+                (tiny_http::Method::Get, true) => "get",
+                (tiny_http::Method::Post, _) => "create",
+                (tiny_http::Method::Put | tiny_http::Method::Patch, _) => "update",
+                (tiny_http::Method::Delete, _) => "delete",
+                (method, _) => return Some(Ok(unknown_access(method, resource))),
+            };
+            (resource, verb.to_string())
+        }
+    };
+    let namespace = match NAMESPACED_RESOURCES.contains(&resource) {
+        true => request_namespace(resource, &verb, params, storage),
+        false => Ok(None),
+    };
+    Some(namespace.map(|namespace| Access {
+        verb,
+        resource: resource.to_string(),
+        namespace,
+    }))
+}
+
+/// Access no role but the ones allowing every verb can give
+fn unknown_access(method: &tiny_http::Method, resource: &str) -> Access {
+    Access {
+        verb: method.to_string().to_lowercase(),
+        resource: resource.to_string(),
+        namespace: None,
+    }
+}
+
+/// Get the namespace of the resource of a request: the one of the resource of its
+/// URL or body, or the one it gives. Resources are created in the default namespace
+/// unless told otherwise, the other requests without namespace are about every
+/// namespace. Instances are created in the namespace of their workload, a request
+/// giving another one is rejected.
+fn request_namespace(
+    resource: &str,
+    verb: &str,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+) -> Result<Option<String>, ApiError> {
+    let body: serde_json::Value = serde_json::from_str(v1::read_body(params)).unwrap_or_default();
+    // Database names of namespaced resources end with `<namespace>/<name>`
+    let namespace_of = |name: &str| name.rsplit('/').nth(1).map(String::from);
+    if resource == "instances" && verb == "create" {
+        let namespace = body["workload_id"]
+            .as_str()
+            .and_then(|id| RikRepository::find_one(storage, id, "/workload").ok())
+            .and_then(|workload| namespace_of(&workload.name));
+        return match body["namespace"].as_str() {
+            Some(given) if Some(given) != namespace.as_deref() => {
+                Err(ApiError::bad_request(format!(
+                    "Instances are created in the namespace of their workload, not {}",
+                    given
+                )))
+            }
+            _ => Ok(namespace),
+        };
+    }
+    if let Some(id) = params.find("id").or_else(|| body["id"].as_str()) {
+        return Ok(storage
+            .get(id)
+            .ok()
+            .and_then(|element| namespace_of(&element.name)));
+    }
+    if let Some(namespace) = body["namespace"].as_str() {
+        return Ok(Some(namespace.to_string()));
+    }
+    Ok(match verb {
+        "create" => Some(DEFAULT_NAMESPACE.to_string()),
+        _ => params.find("namespace").map(String::from),
+    })
+}
+
+fn error_response(json: bool, error: &ApiError) -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+    match json {
+        true => v1::error_response(error),
+        false => tiny_http::Response::from_string(error.message.clone())
+            .with_status_code(tiny_http::StatusCode::from(error.status)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::external::auth::AuthConfig;
+    use crate::api::external::services::namespace::create_default_namespace;
+    use crate::database::memory::MemoryStorage;
+    use std::io::Read;
+    use std::sync::mpsc::channel;
+    use tiny_http::{Method, TestRequest};
+
+    fn send(
+        router: &Router,
+        storage: &dyn Storage,
+        internal_sender: &Sender<ApiChannel>,
+        method: Method,
+        path: String,
+        body: &'static str,
+    ) -> (u16, serde_json::Value) {
+        send_with_token(router, storage, internal_sender, None, method, path, body)
+    }
+
+    fn send_with_token(
+        router: &Router,
+        storage: &dyn Storage,
+        internal_sender: &Sender<ApiChannel>,
+        token: Option<&str>,
+        method: Method,
+        path: String,
+        body: &'static str,
+    ) -> (u16, serde_json::Value) {
+        // Paths of test requests must be static
+        let path: &'static str = Box::leak(path.into_boxed_str());
+        let mut request = TestRequest::new()
+            .with_method(method)
+            .with_path(path)
+            .with_body(body);
+        if let Some(token) = token {
+            let header = format!("Authorization: Bearer {}", token);
+            request = request.with_header(tiny_http::Header::from_str(&header).unwrap());
+        }
+        let mut request: tiny_http::Request = request.into();
+        let response = router.handle(&mut request, storage, internal_sender);
+        let status = response.status_code().0;
+        let mut content = String::new();
+        response.into_reader().read_to_string(&mut content).unwrap();
+        (status, serde_json::from_str(&content).unwrap_or_default())
+    }
+
+    #[test]
+    fn test_v1_workloads() {
+        let storage = MemoryStorage::default();
+        create_default_namespace(&storage).unwrap();
+        let router = Router::new(Arc::default(), Arc::default());
+        let (internal_sender, internal_receiver) = channel::<ApiChannel>();
+        let sender = &internal_sender;
+        let workload = r#"{"api_version": "v0", "kind": "pod", "name": "web",
+            "spec": {"containers": [{"name": "nginx", "image": "nginx:latest"}]}}"#;
+
+        let path = String::from("/api/v1/workloads");
+        let (status, created) = send(&router, &storage, sender, Method::Post, path, workload);
+        assert_eq!(status, 201);
+        assert_eq!(created["name"], "web");
+        let id = created["id"].as_str().unwrap().to_string();
+        let item = format!("/api/v1/workloads/{}", id);
+
+        let path = String::from("/api/v1/workloads");
+        let (status, error) = send(&router, &storage, sender, Method::Post, path, workload);
+        assert_eq!(status, 409);
+        assert_eq!(error["error"], "Conflict");
+        assert_eq!(error["message"], "Name already used");
+
+        // Names cannot be mistaken for the namespace of the workload
+        let nested = r#"{"api_version": "v0", "kind": "pod", "name": "x/dev/y",
+            "spec": {"containers": [{"name": "nginx", "image": "nginx:latest"}]}}"#;
+        for path in ["/api/v1/workloads", "/api/v0/workloads.create"] {
+            let (status, _) = send(
+                &router,
+                &storage,
+                sender,
+                Method::Post,
+                path.to_string(),
+                nested,
+            );
+            assert_eq!(status, 400);
+        }
+        assert!(RikRepository::find_by_name(&storage, "/workload/pod/default/x/dev/y").is_err());
+
+        // Namespaces are matched as they are, `%` is no wildcard
+        for path in [
+            "/api/v1/workloads?namespace=default",
+            "/api/v1/workloads?namespace=def%61ult",
+        ] {
+            let (_, listed) = send(&router, &storage, sender, Method::Get, path.to_string(), "");
+            assert_eq!(listed.as_array().unwrap().len(), 1);
+        }
+        for path in [
+            "/api/v1/workloads?namespace=%",
+            "/api/v0/workloads.list?namespace=%",
+            "/api/v1/workloads?namespace=%25",
+        ] {
+            let (status, listed) =
+                send(&router, &storage, sender, Method::Get, path.to_string(), "");
+            assert_eq!((status, listed.as_array().unwrap().len()), (200, 0));
+        }
+
+        let (status, found) = send(&router, &storage, sender, Method::Get, item.clone(), "");
+        assert_eq!(status, 200);
+        assert_eq!(found["value"]["replicas"], 1);
+
+        let patch = r#"{"replicas": 3}"#;
+        let (status, patched) = send(
+            &router,
+            &storage,
+            sender,
+            Method::Patch,
+            item.clone(),
+            patch,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(patched["value"]["replicas"], 3);
+        assert_eq!(patched["value"]["spec"], found["value"]["spec"]);
+        assert!(patched["resource_version"].as_i64() > found["resource_version"].as_i64());
+
+        let stale = format!("{}?resourceVersion={}", item, found["resource_version"]);
+        let (status, error) = send(&router, &storage, sender, Method::Patch, stale, patch);
+        assert_eq!((status, error["error"].as_str()), (409, Some("Conflict")));
+        let stale = format!("{}?resourceVersion={}", item, found["resource_version"]);
+        let (status, _) = send(&router, &storage, sender, Method::Delete, stale, "");
+        assert_eq!(status, 409);
+
+        let patch = r#"{"name": "api"}"#;
+        let (status, error) = send(
+            &router,
+            &storage,
+            sender,
+            Method::Patch,
+            item.clone(),
+            patch,
+        );
+        assert_eq!((status, error["status"].as_u64()), (400, Some(400)));
+
+        let path = format!("/api/v1/instances/{}", id);
+        let (status, _) = send(&router, &storage, sender, Method::Put, path, "{}");
+        assert_eq!(status, 405);
+
+        let current = format!("{}?resourceVersion={}", item, patched["resource_version"]);
+        let (status, _) = send(&router, &storage, sender, Method::Delete, current, "");
+        assert_eq!(status, 204);
+        let notification = internal_receiver.try_recv().unwrap();
+        assert_eq!(notification.workload_id, Some(id.clone()));
+        let (status, error) = send(&router, &storage, sender, Method::Get, item, "");
+        assert_eq!(status, 404);
+        assert_eq!(error["message"], format!("Workload id {} not found", id));
+
+        let path = String::from("/api/v1/nodes");
+        let (status, error) = send(&router, &storage, sender, Method::Get, path, "");
+        assert_eq!((status, error["error"].as_str()), (404, Some("Not Found")));
+    }
+
+    #[test]
+    fn test_v1_authentication() {
+        let storage = MemoryStorage::default();
+        let config: AuthConfig = serde_json::from_str(
+            r#"{"tokens": [{"user": "admin", "token": "secret", "admin": true}]}"#,
+        )
+        .unwrap();
+        let router = Router::new(Arc::new(Authenticator::new(config)), Arc::default());
+        let (internal_sender, _internal_receiver) = channel::<ApiChannel>();
+        let sender = &internal_sender;
+        let accounts = String::from("/api/v1/serviceaccounts");
+
+        let health = String::from("/api/v1/health");
+        let (status, _) = send(&router, &storage, sender, Method::Get, health, "");
+        assert_ne!(status, 401);
+
+        let (status, error) = send(&router, &storage, sender, Method::Get, accounts.clone(), "");
+        assert_eq!(
+            (status, error["message"].as_str()),
+            (401, Some("Missing bearer token"))
+        );
+        let (status, error) = send_with_token(
+            &router,
+            &storage,
+            sender,
+            Some("wrong"),
+            Method::Get,
+            accounts.clone(),
+            "",
+        );
+        assert_eq!(
+            (status, error["message"].as_str()),
+            (401, Some("Invalid token"))
+        );
+
+        let (status, created) = send_with_token(
+            &router,
+            &storage,
+            sender,
+            Some("secret"),
+            Method::Post,
+            accounts.clone(),
+            r#"{"name": "ci"}"#,
+        );
+        assert_eq!(status, 201);
+        assert!(created["value"].get("token_hash").is_none());
+        let token: &'static str = Box::leak(created["token"].as_str().unwrap().into());
+        let item = format!("{}/{}", accounts, created["id"].as_str().unwrap());
+
+        // The service account is authenticated, but no role is bound to it
+        let (status, error) = send_with_token(
+            &router,
+            &storage,
+            sender,
+            Some(token),
+            Method::Get,
+            accounts.clone(),
+            "",
+        );
+        assert_eq!(status, 403);
+        assert_eq!(
+            error["message"],
+            "No role allows service account ci to list serviceaccounts"
+        );
+
+        let (status, _) = send_with_token(
+            &router,
+            &storage,
+            sender,
+            Some("secret"),
+            Method::Delete,
+            item,
+            "",
+        );
+        assert_eq!(status, 204);
+        let (status, _) = send_with_token(
+            &router,
+            &storage,
+            sender,
+            Some(token),
+            Method::Get,
+            accounts,
+            "",
+        );
+        assert_eq!(status, 401);
+    }
+
+    #[test]
+    fn test_v1_authorization() {
+        let storage = MemoryStorage::default();
+        create_default_namespace(&storage).unwrap();
+        let config: AuthConfig = serde_json::from_str(
+            r#"{"tokens": [{"user": "root", "token": "root-secret", "admin": true},
+                {"user": "alice", "token": "alice-secret"}]}"#,
+        )
+        .unwrap();
+        let router = Router::new(Arc::new(Authenticator::new(config)), Arc::default());
+        let (internal_sender, _internal_receiver) = channel::<ApiChannel>();
+        let sender = &internal_sender;
+        let workload = r#"{"api_version": "v0", "kind": "pod", "name": "web", "namespace": "dev",
+            "spec": {"containers": [{"name": "nginx", "image": "nginx:latest"}]}}"#;
+        let alice = Some("alice-secret");
+        let root = Some("root-secret");
+
+        let path = String::from("/api/v1/workloads");
+        let (status, error) = send_with_token(
+            &router,
+            &storage,
+            sender,
+            alice,
+            Method::Post,
+            path,
+            workload,
+        );
+        assert_eq!(status, 403);
+        assert_eq!(
+            error["message"],
+            "No role allows user alice to create workloads in namespace dev"
+        );
+
+        let tenant = r#"{"name": "acme", "roles": [{"name": "developer",
+            "verbs": ["get", "create"], "resources": ["workloads"], "namespaces": ["dev"]}],
+            "bindings": [{"role": "developer", "users": ["alice"]}]}"#;
+        let path = String::from("/api/v1/tenants");
+        let (status, _) =
+            send_with_token(&router, &storage, sender, alice, Method::Post, path, tenant);
+        assert_eq!(status, 403);
+        let path = String::from("/api/v1/tenants");
+        let (status, _) =
+            send_with_token(&router, &storage, sender, root, Method::Post, path, tenant);
+        assert_eq!(status, 201);
+        let path = String::from("/api/v0/namespaces.create");
+        let namespace = r#"{"name": "dev", "tenant": "acme"}"#;
+        let (status, _) = send_with_token(
+            &router,
+            &storage,
+            sender,
+            root,
+            Method::Post,
+            path,
+            namespace,
+        );
+        assert_eq!(status, 200);
+
+        let path = String::from("/api/v1/workloads");
+        let (status, created) = send_with_token(
+            &router,
+            &storage,
+            sender,
+            alice,
+            Method::Post,
+            path,
+            workload,
+        );
+        assert_eq!(status, 201);
+        assert_eq!(created["value"]["tenant"], "acme");
+        let item = format!("/api/v1/workloads/{}", created["id"].as_str().unwrap());
+        let (status, _) = send_with_token(
+            &router,
+            &storage,
+            sender,
+            alice,
+            Method::Get,
+            item.clone(),
+            "",
+        );
+        assert_eq!(status, 200);
+        let (status, error) =
+            send_with_token(&router, &storage, sender, alice, Method::Delete, item, "");
+        assert_eq!(
+            (status, error["message"].as_str()),
+            (
+                403,
+                Some("No role allows user alice to delete workloads in namespace dev")
+            )
+        );
+
+        let path = String::from("/api/v1/workloads");
+        let (status, error) =
+            send_with_token(&router, &storage, sender, alice, Method::Get, path, "");
+        assert_eq!(
+            error["message"],
+            "No role allows user alice to list workloads in every namespace"
+        );
+        assert_eq!(status, 403);
+    }
+
+    #[test]
+    fn test_v1_instance_namespace() {
+        let storage = MemoryStorage::default();
+        create_default_namespace(&storage).unwrap();
+        let tenant = r#"{"roles": [{"name": "developer", "verbs": ["create"],
+            "resources": ["instances"], "namespaces": ["default"]}],
+            "bindings": [{"role": "developer", "users": ["alice"]}]}"#;
+        RikRepository::insert(&storage, "/tenant/acme", tenant).unwrap();
+        let workload = r#"{"api_version": "v0", "kind": "pod", "name": "web",
+            "namespace": "prod", "spec": {"containers": []}}"#;
+        let workload_id =
+            RikRepository::insert(&storage, "/workload/pod/prod/web", workload).unwrap();
+        let config: AuthConfig =
+            serde_json::from_str(r#"{"tokens": [{"user": "alice", "token": "alice-secret"}]}"#)
+                .unwrap();
+        let router = Router::new(Arc::new(Authenticator::new(config)), Arc::default());
+        let (internal_sender, _internal_receiver) = channel::<ApiChannel>();
+        let alice = Some("alice-secret");
+
+        // The namespace of the body does not take over the one of the workload
+        let body = format!(
+            r#"{{"workload_id": "{}", "namespace": "default"}}"#,
+            workload_id
+        );
+        let body: &'static str = Box::leak(body.into_boxed_str());
+        for path in ["/api/v1/instances", "/api/v0/instances.create"] {
+            let (status, _) = send_with_token(
+                &router,
+                &storage,
+                &internal_sender,
+                alice,
+                Method::Post,
+                path.to_string(),
+                body,
+            );
+            assert_eq!(status, 400);
+        }
+        let body = format!(r#"{{"workload_id": "{}"}}"#, workload_id);
+        let body: &'static str = Box::leak(body.into_boxed_str());
+        let (status, error) = send_with_token(
+            &router,
+            &storage,
+            &internal_sender,
+            alice,
+            Method::Post,
+            String::from("/api/v1/instances"),
+            body,
+        );
+        assert_eq!(status, 403);
+        assert_eq!(
+            error["message"],
+            "No role allows user alice to create instances in namespace prod"
+        );
+    }
+
+    #[test]
+    fn test_delete_namespace() {
+        let storage = MemoryStorage::default();
+        create_default_namespace(&storage).unwrap();
+        let namespace_id =
+            RikRepository::insert(&storage, "/namespace/prod", r#"{"name": "prod"}"#).unwrap();
+        let workload = r#"{"api_version": "v0", "kind": "pod", "name": "web",
+            "namespace": "prod", "spec": {"containers": []}}"#;
+        let workload_id =
+            RikRepository::insert(&storage, "/workload/pod/prod/web", workload).unwrap();
+        let instance = format!(r#"{{"workload_id": "{}"}}"#, workload_id);
+        let instance_id =
+            RikRepository::insert(&storage, "/instance/prod/web-1", &instance).unwrap();
+        RikRepository::insert(&storage, "/instance/default/api-1", "{}").unwrap();
+        let other = r#"{"api_version": "v0", "kind": "pod", "name": "web",
+            "namespace": "prod-eu", "spec": {"containers": []}}"#;
+        let other_id = RikRepository::insert(&storage, "/workload/pod/prod-eu/web", other).unwrap();
+        let router = Router::new(Arc::default(), Arc::default());
+        let (internal_sender, internal_receiver) = channel::<ApiChannel>();
+
+        let body = format!(r#"{{"id": "{}"}}"#, namespace_id);
+        let body: &'static str = Box::leak(body.into_boxed_str());
+        let path = String::from("/api/v0/namespaces.delete");
+        let (status, _) = send(
+            &router,
+            &storage,
+            &internal_sender,
+            Method::Post,
+            path,
+            body,
+        );
+        assert_eq!(status, 204);
+
+        // The scheduler is asked to destroy the instances and the workloads of the namespace
+        let notifications: Vec<ApiChannel> = internal_receiver.try_iter().collect();
+        assert!(notifications
+            .iter()
+            .any(|notification| notification.instance_id.as_deref() == Some(&instance_id)));
+        assert!(notifications
+            .iter()
+            .any(|notification| notification.workload_id.as_deref() == Some(&workload_id)));
+        assert!(storage.get(&instance_id).is_err());
+        assert!(storage.get(&workload_id).is_err());
+        let remaining = RikRepository::find_all(&storage, "/instance/").unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].name, "/instance/default/api-1");
+        // The workloads of the other namespaces are left alone
+        assert!(storage.get(&other_id).is_ok());
+        assert!(!notifications
+            .iter()
+            .any(|notification| notification.workload_id.as_deref() == Some(&other_id)));
+    }
+
+    #[test]
+    fn test_v0_legacy_tenants() {
+        let storage = MemoryStorage::default();
+        create_default_namespace(&storage).unwrap();
+        let router = Router::new(Arc::default(), Arc::default());
+        let (internal_sender, _internal_receiver) = channel::<ApiChannel>();
+        let sender = &internal_sender;
+        // Tenants were first stored under the name given by their client
+        RikRepository::insert(
+            &storage,
+            "/tenant/default/jean",
+            r#""/tenant/default/jean""#,
+        )
+        .unwrap();
+
+        // Values which aren't JSON are tenants without settings
+        let path = String::from("/api/v0/tenants.create");
+        let body = r#"{"id": "", "name": "acme", "value": "acme"}"#;
+        let (status, _) = send(&router, &storage, sender, Method::Post, path, body);
+        assert_eq!(status, 200);
+        let acme = RikRepository::find_by_name(&storage, "/tenant/acme").unwrap();
+        assert_eq!(acme.value, serde_json::json!({}));
+
+        let path = String::from("/api/v0/tenants.create");
+        let body = r#"{"id": "", "name": "jean", "value": "{}"}"#;
+        let (status, _) = send(&router, &storage, sender, Method::Post, path, body);
+        assert_eq!(status, 409);
+        let workload = r#"{"api_version": "v0", "kind": "pod", "name": "web", "tenant": "jean",
+            "spec": {"containers": [{"name": "nginx", "image": "nginx:latest"}]}}"#;
+        let path = String::from("/api/v1/workloads");
+        let (status, created) = send(&router, &storage, sender, Method::Post, path, workload);
+        assert_eq!(status, 201);
+        assert_eq!(created["value"]["tenant"], "jean");
     }
 }
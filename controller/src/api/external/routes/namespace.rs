@@ -0,0 +1,118 @@
+use route_recognizer;
+use std::io;
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+
+use crate::api;
+use crate::api::external::routes::v1::read_body;
+use crate::api::external::services::element::elements_set_right_name;
+use crate::api::external::services::namespace::{
+    delete_namespace_resources, namespace_element_name, namespace_exists, validate_namespace_name,
+};
+use crate::api::external::services::tenant::find_tenant;
+use crate::api::types::element::OnlyId;
+use crate::api::types::namespace::Namespace;
+use crate::api::ApiChannel;
+use crate::database::{RikRepository, Storage};
+use definition::workload::DEFAULT_NAMESPACE;
+use tracing::{error, info};
+
+pub fn get(
+    _: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    if let Ok(mut namespaces) = RikRepository::find_all(storage, "/namespace/") {
+        namespaces = elements_set_right_name(namespaces.clone());
+        let namespaces_json = serde_json::to_string(&namespaces).unwrap();
+        info!("Namespaces found");
+        Ok(tiny_http::Response::from_string(namespaces_json)
+            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+            .with_status_code(tiny_http::StatusCode::from(200)))
+    } else {
+        Ok(tiny_http::Response::from_string("Cannot find namespaces")
+            .with_status_code(tiny_http::StatusCode::from(500)))
+    }
+}
+
+pub fn create(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let content = read_body(params);
+    let namespace: Namespace = serde_json::from_str(content)?;
+
+    if let Err(message) = validate_namespace_name(&namespace.name) {
+        return Ok(tiny_http::Response::from_string(message)
+            .with_status_code(tiny_http::StatusCode::from(400)));
+    }
+    if namespace_exists(storage, &namespace.name) {
+        return Ok(tiny_http::Response::from_string("Name already used")
+            .with_status_code(tiny_http::StatusCode::from(409)));
+    }
+    if let Some(tenant) = &namespace.tenant {
+        if find_tenant(storage, tenant).is_err() {
+            return Ok(
+                tiny_http::Response::from_string(format!("Tenant {} not found", tenant))
+                    .with_status_code(tiny_http::StatusCode::from(400)),
+            );
+        }
+    }
+
+    if let Ok(inserted_id) = RikRepository::insert(
+        storage,
+        &namespace_element_name(&namespace.name),
+        &serde_json::to_string(&namespace).unwrap(),
+    ) {
+        info!("Namespace {} successfully created", namespace.name);
+        Ok(tiny_http::Response::from_string(
+            serde_json::to_string(&OnlyId { id: inserted_id }).unwrap(),
+        )
+        .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+        .with_status_code(tiny_http::StatusCode::from(200)))
+    } else {
+        error!("Cannot create namespace");
+        Ok(tiny_http::Response::from_string("Cannot create namespace")
+            .with_status_code(tiny_http::StatusCode::from(500)))
+    }
+}
+
+pub fn delete(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    internal_sender: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let content = read_body(params);
+    let OnlyId { id: delete_id } = serde_json::from_str(content)?;
+
+    if let Ok(namespace) = RikRepository::find_one(storage, &delete_id, "/namespace/") {
+        let Namespace { name, .. } = serde_json::from_value(namespace.value)?;
+        if name == DEFAULT_NAMESPACE {
+            return Ok(
+                tiny_http::Response::from_string("The default namespace cannot be deleted")
+                    .with_status_code(tiny_http::StatusCode::from(400)),
+            );
+        }
+
+        // Workloads and instances of the namespace are deleted along with it
+        if let Err(e) = delete_namespace_resources(storage, internal_sender, &name) {
+            error!("Cannot delete resources of namespace {}: {}", name, e);
+            return Ok(tiny_http::Response::from_string("Cannot delete namespace")
+                .with_status_code(tiny_http::StatusCode::from(500)));
+        }
+        RikRepository::delete(storage, &namespace.id).unwrap();
+
+        info!("Delete namespace {}", name);
+        Ok(tiny_http::Response::from_string("").with_status_code(tiny_http::StatusCode::from(204)))
+    } else {
+        error!("Namespace id {} not found", delete_id);
+        Ok(
+            tiny_http::Response::from_string(format!("Namespace id {} not found", delete_id))
+                .with_status_code(tiny_http::StatusCode::from(404)),
+        )
+    }
+}
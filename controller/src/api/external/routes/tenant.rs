@@ -1,36 +1,32 @@
 use route_recognizer;
-use rusqlite::Connection;
 use std::io;
 use std::str::FromStr;
 use std::sync::mpsc::Sender;
 
 use crate::api;
-use crate::api::external::services::element::elements_set_right_name;
+use crate::api::external::routes::v1::read_body;
+use crate::api::external::services::element::{
+    check_resource_version, list_options, page_response,
+};
+use crate::api::external::services::tenant::{
+    find_tenant, parse_tenant_spec, tenant_element_name, validate_roles,
+};
 use crate::api::types::element::OnlyId;
-use crate::api::types::tenant::Tenant;
+use crate::api::types::tenant::{Tenant, TenantQuota, TenantSpec};
 use crate::api::ApiChannel;
-use crate::database::RikRepository;
-use crate::logger::{LogType, LoggingChannel};
+use crate::database::{RikRepository, Storage};
+use tracing::{error, info};
 
 pub fn get(
     _: &mut tiny_http::Request,
-    _: &route_recognizer::Params,
-    connection: &Connection,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
     _: &Sender<ApiChannel>,
-    logger: &Sender<LoggingChannel>,
 ) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
-    if let Ok(mut tenants) = RikRepository::find_all(connection, "/tenant") {
-        tenants = elements_set_right_name(tenants.clone());
-        let tenants_json = serde_json::to_string(&tenants).unwrap();
-        logger
-            .send(LoggingChannel {
-                message: String::from("Tenant found"),
-                log_type: LogType::Log,
-            })
-            .unwrap();
-        Ok(tiny_http::Response::from_string(tenants_json)
-            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
-            .with_status_code(tiny_http::StatusCode::from(200)))
+    let options = list_options(params)?;
+    if let Ok(tenants) = RikRepository::list(storage, "/tenant", &options) {
+        info!("Tenant found");
+        Ok(page_response(tenants)?.with_status_code(tiny_http::StatusCode::from(200)))
     } else {
         Ok(tiny_http::Response::from_string("Cannot find tenant")
             .with_status_code(tiny_http::StatusCode::from(500)))
@@ -38,33 +34,33 @@ pub fn get(
 }
 
 pub fn create(
-    req: &mut tiny_http::Request,
-    _: &route_recognizer::Params,
-    connection: &Connection,
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
     _: &Sender<ApiChannel>,
-    logger: &Sender<LoggingChannel>,
 ) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
-    let mut content = String::new();
-    req.as_reader().read_to_string(&mut content).unwrap();
-    let tenant: Tenant = serde_json::from_str(&content)?;
+    let content = read_body(params);
+    let tenant: Tenant = serde_json::from_str(content)?;
+    // Ensure the settings of the tenant, like its quota, are valid
+    let (spec, value) = parse_tenant_spec(&tenant.value)?;
+    if let Err(message) = validate_roles(&spec) {
+        return Ok(tiny_http::Response::from_string(message)
+            .with_status_code(tiny_http::StatusCode::from(400)));
+    }
+    let name = tenant_element_name(&tenant.name);
 
-    if RikRepository::insert(connection, &tenant.name, &tenant.value).is_ok() {
-        logger
-            .send(LoggingChannel {
-                message: String::from("Create tenant"),
-                log_type: LogType::Log,
-            })
-            .unwrap();
+    if find_tenant(storage, &tenant.name).is_ok() {
+        return Ok(tiny_http::Response::from_string("Name already used")
+            .with_status_code(tiny_http::StatusCode::from(409)));
+    }
+
+    if RikRepository::insert(storage, &name, &value).is_ok() {
+        info!("Create tenant");
         Ok(tiny_http::Response::from_string(content)
             .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
             .with_status_code(tiny_http::StatusCode::from(200)))
     } else {
-        logger
-            .send(LoggingChannel {
-                message: String::from("Cannot create tenant"),
-                log_type: LogType::Error,
-            })
-            .unwrap();
+        error!("Cannot create tenant");
         Ok(tiny_http::Response::from_string("Cannot create tenant")
             .with_status_code(tiny_http::StatusCode::from(500)))
     }
@@ -72,35 +68,53 @@ pub fn create(
 
 pub fn delete(
     req: &mut tiny_http::Request,
-    _: &route_recognizer::Params,
-    connection: &Connection,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
     _: &Sender<ApiChannel>,
-    logger: &Sender<LoggingChannel>,
 ) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
-    let mut content = String::new();
-    req.as_reader().read_to_string(&mut content).unwrap();
-    let OnlyId { id: delete_id } = serde_json::from_str(&content)?;
+    let content = read_body(params);
+    let OnlyId { id: delete_id } = serde_json::from_str(content)?;
 
-    if let Ok(tenant) = RikRepository::find_one(connection, &delete_id, "/tenant") {
-        RikRepository::delete(connection, &tenant.id).unwrap();
+    if let Ok(tenant) = RikRepository::find_one(storage, &delete_id, "/tenant") {
+        check_resource_version(req, params, &tenant)?;
+        storage.delete(&tenant.id, Some(tenant.resource_version))?;
 
-        logger
-            .send(LoggingChannel {
-                message: String::from("Delete tenant"),
-                log_type: LogType::Log,
-            })
-            .unwrap();
+        info!("Delete tenant");
         Ok(tiny_http::Response::from_string("").with_status_code(tiny_http::StatusCode::from(204)))
     } else {
-        logger
-            .send(LoggingChannel {
-                message: format!("Tenant id {} not found", delete_id),
-                log_type: LogType::Error,
-            })
-            .unwrap();
+        error!("Tenant id {} not found", delete_id);
         Ok(
             tiny_http::Response::from_string(format!("Tenant id {} not found", delete_id))
                 .with_status_code(tiny_http::StatusCode::from(404)),
         )
     }
 }
+
+pub fn set_quota(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let content = read_body(params);
+    let TenantQuota { id, quota } = serde_json::from_str(content)?;
+
+    if let Ok(tenant) = RikRepository::find_one(storage, &id, "/tenant") {
+        check_resource_version(req, params, &tenant)?;
+        let mut spec: TenantSpec = serde_json::from_value(tenant.value).unwrap_or_default();
+        spec.quota = quota;
+        let value = serde_json::to_string(&spec)?;
+        RikRepository::update(storage, &tenant.id, &value, Some(tenant.resource_version))?;
+
+        info!("Set quota of tenant {}", tenant.id);
+        Ok(tiny_http::Response::from_string(value)
+            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+            .with_status_code(tiny_http::StatusCode::from(200)))
+    } else {
+        error!("Tenant id {} not found", id);
+        Ok(
+            tiny_http::Response::from_string(format!("Tenant id {} not found", id))
+                .with_status_code(tiny_http::StatusCode::from(404)),
+        )
+    }
+}
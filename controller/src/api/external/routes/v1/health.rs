@@ -0,0 +1,32 @@
+use crate::api;
+use crate::api::external::routes::v1::json_response;
+use crate::api::internal::connection::{scheduler_health, SchedulerHealth};
+use crate::api::ApiChannel;
+use serde::Serialize;
+
+use crate::database::Storage;
+use route_recognizer;
+use std::io;
+use std::sync::mpsc::Sender;
+
+#[derive(Serialize)]
+struct Health {
+    /// `ok`, or `degraded` while the scheduler is disconnected
+    status: &'static str,
+    scheduler: SchedulerHealth,
+}
+
+/// Health of the controller, `503` while the scheduler is disconnected
+pub fn get(
+    _: &mut tiny_http::Request,
+    _: &route_recognizer::Params,
+    _: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let scheduler = scheduler_health();
+    let (status, code) = match scheduler.connected {
+        true => ("ok", 200),
+        false => ("degraded", 503),
+    };
+    json_response(code, &Health { status, scheduler })
+}
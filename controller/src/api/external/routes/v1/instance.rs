@@ -0,0 +1,73 @@
+use crate::api;
+use crate::api::external::routes::v1::{
+    element_response, find_resource, id_param, list_response, read_body,
+};
+use crate::api::external::services::element::{check_resource_version, list_options};
+use crate::api::external::services::instance::{create_instances, delete_instance};
+use crate::api::types::instance::InstanceDefinition;
+use crate::api::ApiChannel;
+use crate::database::{escape_prefix, RikRepository, Storage};
+use tracing::info;
+
+use route_recognizer;
+use std::io;
+use std::sync::mpsc::Sender;
+
+const PREFIX: &str = "/instance/";
+
+pub fn list(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let prefix = match params.find("namespace") {
+        Some(namespace) => format!("{}{}/", PREFIX, escape_prefix(namespace)),
+        None => String::from(PREFIX),
+    };
+    list_response(RikRepository::list(
+        storage,
+        &prefix,
+        &list_options(params)?,
+    ))
+}
+
+/// Instances are created by the scheduler, the request is accepted once they are
+/// asked to it. They are listed as soon as the scheduler placed them.
+pub fn create(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    internal_sender: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let instance: InstanceDefinition = serde_json::from_str(read_body(params))?;
+    create_instances(storage, internal_sender, instance)?;
+    Ok(tiny_http::Response::from_string("").with_status_code(tiny_http::StatusCode::from(202)))
+}
+
+pub fn get(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    element_response(
+        200,
+        &find_resource(storage, id_param(params), PREFIX, "Instance")?,
+    )
+}
+
+pub fn delete(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    internal_sender: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let instance = find_resource(storage, id_param(params), PREFIX, "Instance")?;
+    check_resource_version(req, params, &instance)?;
+    let id = instance.id.clone();
+    delete_instance(storage, internal_sender, instance)?;
+
+    info!("Instance {} deleted", id);
+    Ok(tiny_http::Response::from_string("").with_status_code(tiny_http::StatusCode::from(204)))
+}
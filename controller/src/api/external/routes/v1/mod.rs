@@ -0,0 +1,93 @@
+//! Resource oriented API: every resource has its own URL, `/api/v1/<resources>/<id>`,
+//! handled with the HTTP method matching the action. Errors are answered with a JSON
+//! body, see [`ApiError`].
+
+use crate::api;
+use crate::api::external::services::element::{element_set_right_name, etag_header, page_response};
+use crate::api::types::element::Element;
+use crate::api::types::error::ApiError;
+use crate::database::{Page, RikRepository, Storage};
+use serde::Serialize;
+use std::io;
+use std::str::FromStr;
+
+pub mod health;
+pub mod instance;
+pub mod service_account;
+pub mod tenant;
+pub mod watch;
+pub mod workload;
+
+pub const BASE_PATH: &str = "/api/v1";
+
+/// Parameter the router gives the body of a request in
+pub const BODY_PARAM: &str = "$body";
+
+/// Response with a JSON body
+pub fn json_response<T: Serialize>(
+    status: u16,
+    body: &T,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    Ok(
+        tiny_http::Response::from_string(serde_json::to_string(body)?)
+            .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+            .with_status_code(tiny_http::StatusCode::from(status)),
+    )
+}
+
+/// Response of a resource, with its resource version as `ETag`
+pub fn element_response(
+    status: u16,
+    element: &Element,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    Ok(json_response(status, element)?.with_header(etag_header(element)))
+}
+
+/// Response of an error, with a JSON body
+pub fn error_response(error: &ApiError) -> tiny_http::Response<io::Cursor<Vec<u8>>> {
+    tiny_http::Response::from_string(serde_json::to_string(error).unwrap())
+        .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap())
+        .with_status_code(tiny_http::StatusCode::from(error.status))
+}
+
+/// Get the body of a request, read by the router before the handler runs
+pub fn read_body(params: &route_recognizer::Params) -> &str {
+    params.find(BODY_PARAM).unwrap_or_default()
+}
+
+/// Get the id given in the URL of a route
+pub fn id_param(params: &route_recognizer::Params) -> &str {
+    params.find("id").unwrap_or_default()
+}
+
+/// Find the resource with the given id, among the elements whose database name
+/// starts with the given prefix
+pub fn find_resource(
+    storage: &dyn Storage,
+    id: &str,
+    prefix: &str,
+    resource: &str,
+) -> Result<Element, api::RikError> {
+    RikRepository::find_one(storage, id, prefix)
+        .map(element_set_right_name)
+        .map_err(|_| ApiError::not_found(format!("{} id {} not found", resource, id)).into())
+}
+
+/// Response listing a page of resources, with the token of the next page if any
+pub fn list_response(
+    page: crate::database::Result<Page>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    Ok(page_response(page?)?)
+}
+
+/// Response of a created resource, with its URL as location
+pub fn created_response(
+    resources: &str,
+    element: &Element,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let location = format!("Location: {}/{}/{}", BASE_PATH, resources, element.id);
+    Ok(
+        element_response(201, element)?
+            .with_header(tiny_http::Header::from_str(&location).unwrap()),
+    )
+}
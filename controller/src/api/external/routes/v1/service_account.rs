@@ -0,0 +1,116 @@
+use crate::api;
+use crate::api::external::routes::v1::{
+    element_response, find_resource, id_param, json_response, list_response, read_body, BASE_PATH,
+};
+use crate::api::external::services::element::{check_resource_version, etag_header, list_options};
+use crate::api::external::services::service_account::{
+    hash_token, hide_token_hash, issue_token, service_account_element_name, SERVICE_ACCOUNT_PREFIX,
+};
+use crate::api::types::error::ApiError;
+use crate::api::types::service_account::{ServiceAccount, ServiceAccountDefinition};
+use crate::api::ApiChannel;
+use crate::database::{RikRepository, Storage};
+use tracing::info;
+
+use route_recognizer;
+use std::io;
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn list(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let page = RikRepository::list(storage, SERVICE_ACCOUNT_PREFIX, &list_options(params)?);
+    list_response(page.map(|mut page| {
+        page.elements = page.elements.into_iter().map(hide_token_hash).collect();
+        page
+    }))
+}
+
+/// Create a service account, its token is only given in the response
+pub fn create(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let definition: ServiceAccountDefinition = serde_json::from_str(read_body(params))?;
+    if definition.name.is_empty() || definition.name.contains('/') {
+        return Err(ApiError::bad_request(format!(
+            "Invalid service account name {}",
+            definition.name
+        ))
+        .into());
+    }
+    let name = service_account_element_name(&definition.name);
+    if RikRepository::find_by_name(storage, &name).is_ok() {
+        return Err(ApiError::conflict("Name already used").into());
+    }
+
+    let token = issue_token();
+    let account = ServiceAccount {
+        name: definition.name,
+        token_hash: hash_token(&token),
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64,
+    };
+    let id = RikRepository::insert(storage, &name, &serde_json::to_string(&account)?)?;
+    info!("Service account {} successfully created", id);
+
+    let element = hide_token_hash(find_resource(
+        storage,
+        &id,
+        SERVICE_ACCOUNT_PREFIX,
+        "Service account",
+    )?);
+    let mut body = serde_json::to_value(&element)?;
+    body["token"] = serde_json::Value::String(token);
+    let location = format!("Location: {}/serviceaccounts/{}", BASE_PATH, id);
+    Ok(json_response(201, &body)?
+        .with_header(etag_header(&element))
+        .with_header(tiny_http::Header::from_str(&location).unwrap()))
+}
+
+pub fn get(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let element = hide_token_hash(find_resource(
+        storage,
+        id_param(params),
+        SERVICE_ACCOUNT_PREFIX,
+        "Service account",
+    )?);
+    element_response(200, &element)
+}
+
+/// Delete a service account, its token is revoked
+pub fn delete(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let account = find_resource(
+        storage,
+        id_param(params),
+        SERVICE_ACCOUNT_PREFIX,
+        "Service account",
+    )?;
+    check_resource_version(req, params, &account)?;
+    storage.delete(&account.id, Some(account.resource_version))?;
+
+    info!(
+        "Service account {} deleted, its token is revoked",
+        account.id
+    );
+    Ok(tiny_http::Response::from_string("").with_status_code(tiny_http::StatusCode::from(204)))
+}
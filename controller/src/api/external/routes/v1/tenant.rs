@@ -0,0 +1,120 @@
+use crate::api;
+use crate::api::external::routes::v1::{
+    created_response, element_response, find_resource, id_param, list_response, read_body,
+};
+use crate::api::external::services::element::merge_patch;
+use crate::api::external::services::element::{check_resource_version, list_options};
+use crate::api::external::services::tenant::{find_tenant, tenant_element_name, validate_roles};
+use crate::api::types::element::Element;
+use crate::api::types::error::ApiError;
+use crate::api::types::tenant::{TenantDefinition, TenantSpec};
+use crate::api::ApiChannel;
+use crate::database::{RikRepository, Storage};
+use tracing::info;
+
+use route_recognizer;
+use std::io;
+use std::sync::mpsc::Sender;
+
+const PREFIX: &str = "/tenant/";
+
+pub fn list(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    list_response(RikRepository::list(storage, PREFIX, &list_options(params)?))
+}
+
+pub fn create(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let tenant: TenantDefinition = serde_json::from_str(read_body(params))?;
+    if tenant.name.is_empty() || tenant.name.contains('/') {
+        return Err(ApiError::bad_request(format!("Invalid tenant name {}", tenant.name)).into());
+    }
+    validate_roles(&tenant.spec).map_err(ApiError::bad_request)?;
+    let name = tenant_element_name(&tenant.name);
+    if find_tenant(storage, &tenant.name).is_ok() {
+        return Err(ApiError::conflict("Name already used").into());
+    }
+
+    let id = RikRepository::insert(storage, &name, &serde_json::to_string(&tenant.spec)?)?;
+    info!("Tenant {} successfully created", id);
+    created_response("tenants", &find_resource(storage, &id, PREFIX, "Tenant")?)
+}
+
+pub fn get(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    element_response(
+        200,
+        &find_resource(storage, id_param(params), PREFIX, "Tenant")?,
+    )
+}
+
+/// Replace the settings of a tenant, its name cannot be changed
+pub fn update(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let current = find_resource(storage, id_param(params), PREFIX, "Tenant")?;
+    check_resource_version(req, params, &current)?;
+    let spec: TenantSpec = serde_json::from_str(read_body(params))?;
+    replace(storage, current, spec)
+}
+
+/// Apply a JSON merge patch to the settings of a tenant
+pub fn patch(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let current = find_resource(storage, id_param(params), PREFIX, "Tenant")?;
+    check_resource_version(req, params, &current)?;
+    let patch: serde_json::Value = serde_json::from_str(read_body(params))?;
+    let mut value = current.value.clone();
+    merge_patch(&mut value, &patch);
+    let spec: TenantSpec = serde_json::from_value(value)?;
+    replace(storage, current, spec)
+}
+
+fn replace(
+    storage: &dyn Storage,
+    current: Element,
+    spec: TenantSpec,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    validate_roles(&spec).map_err(ApiError::bad_request)?;
+    RikRepository::update(
+        storage,
+        &current.id,
+        &serde_json::to_string(&spec)?,
+        Some(current.resource_version),
+    )?;
+    info!("Tenant {} updated", current.id);
+    element_response(200, &find_resource(storage, &current.id, PREFIX, "Tenant")?)
+}
+
+pub fn delete(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let tenant = find_resource(storage, id_param(params), PREFIX, "Tenant")?;
+    check_resource_version(req, params, &tenant)?;
+    storage.delete(&tenant.id, Some(tenant.resource_version))?;
+
+    info!("Tenant {} deleted", tenant.id);
+    Ok(tiny_http::Response::from_string("").with_status_code(tiny_http::StatusCode::from(204)))
+}
@@ -0,0 +1,436 @@
+//! Watch of the resources: `GET /api/v1/<resources>?watch=true` streams their changes as
+//! server-sent events, whose id is the resource version of the change. The resources
+//! are first sent as `ADDED` events, unless the watch resumes after a resource version,
+//! given by the `resourceVersion` parameter or the `Last-Event-ID` header.
+
+use crate::api::external::routes::v1::error_response;
+use crate::api::external::services::element::element_set_right_name;
+use crate::api::external::services::service_account::find_service_account;
+use crate::api::types::error::ApiError;
+use crate::api::types::watch::{WatchEvent, WatchEventType};
+use crate::database::{ListOptions, Storage};
+use dotenv::dotenv;
+use std::borrow::Cow;
+use std::io::{self, Result as IoResult, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// How often the changes are checked
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// A comment is sent when nothing changed for a while, to find out closed connections
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// Changes read from the database at once
+const BATCH_SIZE: usize = 500;
+/// Watches streaming at once unless `MAX_WATCHES` says otherwise, each has a thread
+/// polling the database
+const DEFAULT_MAX_WATCHES: usize = 64;
+
+/// Bounds the watches streaming at once, the watches above it are refused with a `503`
+#[derive(Clone)]
+pub struct WatchLimit {
+    active: Arc<AtomicUsize>,
+    max: usize,
+}
+
+/// Place of a streaming watch, given back when it is dropped
+pub struct WatchSlot {
+    active: Arc<AtomicUsize>,
+}
+
+impl WatchLimit {
+    pub fn new(max: usize) -> WatchLimit {
+        WatchLimit {
+            active: Arc::new(AtomicUsize::new(0)),
+            max,
+        }
+    }
+
+    /// Get the limit from `MAX_WATCHES`
+    pub fn from_env() -> WatchLimit {
+        dotenv().ok();
+        let max = std::env::var("MAX_WATCHES")
+            .ok()
+            .and_then(|max| max.parse().ok())
+            .unwrap_or(DEFAULT_MAX_WATCHES);
+        WatchLimit::new(max)
+    }
+
+    /// Take a place for a new watch, or get the response refusing it
+    pub fn acquire(&self) -> Result<WatchSlot, tiny_http::Response<io::Cursor<Vec<u8>>>> {
+        let taken = self
+            .active
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| {
+                (active < self.max).then_some(active + 1)
+            });
+        match taken {
+            Ok(_) => Ok(WatchSlot {
+                active: self.active.clone(),
+            }),
+            Err(_) => {
+                let error = ApiError::new(
+                    503,
+                    format!("Too many watches, at most {} at once", self.max),
+                );
+                warn!("{}", error.message);
+                Err(error_response(&error)
+                    .with_header(tiny_http::Header::from_str("Retry-After: 5").unwrap()))
+            }
+        }
+    }
+}
+
+impl Drop for WatchSlot {
+    fn drop(&mut self) {
+        self.active.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watch {
+    /// Database prefix of the watched resources
+    prefix: &'static str,
+    namespace: Option<String>,
+    /// Resource version the watch resumes after
+    resource_version: Option<i64>,
+    /// Token of the service account which opened the watch, the watch is closed once
+    /// the token is revoked
+    token: Option<String>,
+}
+
+impl Watch {
+    /// Get the watch asked by a request, if it is one
+    pub fn from_request(request: &tiny_http::Request) -> Option<Result<Watch, ApiError>> {
+        if request.method() != &tiny_http::Method::Get {
+            return None;
+        }
+        let last_event_id = request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv("Last-Event-ID"))
+            .map(|header| header.value.to_string());
+        Watch::parse(request.url(), last_event_id.as_deref())
+    }
+
+    fn parse(url: &str, last_event_id: Option<&str>) -> Option<Result<Watch, ApiError>> {
+        let (path, query) = url.split_once('?')?;
+        let params: Vec<(Cow<str>, Cow<str>)> = form_urlencoded::parse(query.as_bytes()).collect();
+        let param = |key: &str| {
+            params
+                .iter()
+                .find(|(param, _)| param == key)
+                .map(|(_, value)| value.as_ref())
+        };
+        if param("watch") != Some("true") {
+            return None;
+        }
+        let prefix = match path.strip_prefix(super::BASE_PATH)? {
+            "/workloads" => "/workload/",
+            "/instances" => "/instance/",
+            "/tenants" => "/tenant/",
+            _ => return None,
+        };
+
+        // A client reconnecting resumes after the last event it received
+        let resource_version = match last_event_id.or_else(|| param("resourceVersion")) {
+            Some(version) => match version.parse() {
+                Ok(version) => Some(version),
+                Err(_) => {
+                    return Some(Err(ApiError::bad_request(format!(
+                        "Invalid resource version {}",
+                        version
+                    ))))
+                }
+            },
+            None => None,
+        };
+        Some(Ok(Watch {
+            prefix,
+            namespace: param("namespace").map(String::from),
+            resource_version,
+            token: None,
+        }))
+    }
+
+    /// Close the watch once the token of a service account is revoked
+    pub fn with_service_account_token(mut self, token: &str) -> Watch {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Stream the changes to the client until it disconnects
+    pub fn serve(self, request: tiny_http::Request, storage: &dyn Storage) {
+        let (version, initial) = match self.start(storage) {
+            Ok(start) => start,
+            Err(error) => {
+                request.respond(error_response(&error)).ok();
+                return;
+            }
+        };
+
+        let mut writer = request.into_writer();
+        let streamed = write_headers(&mut writer).and_then(|_| {
+            for event in &initial {
+                write_chunk(&mut writer, format_event(event).as_bytes())?;
+            }
+            self.stream(storage, version, &mut writer)
+        });
+        if let Err(e) = streamed {
+            info!("Watch closed: {}", e);
+        }
+    }
+
+    /// Get the resource version the watch starts after, and the events to send first
+    fn start(&self, storage: &dyn Storage) -> Result<(i64, Vec<WatchEvent>), ApiError> {
+        if let Some(version) = self.resource_version {
+            let oldest = storage
+                .oldest_version()
+                .map_err(|e| ApiError::internal(e.to_string()))?;
+            if matches!(oldest, Some(oldest) if version < oldest - 1) {
+                return Err(ApiError::new(
+                    410,
+                    format!("Resource version {} is too old, list again", version),
+                ));
+            }
+            return Ok((version, Vec::new()));
+        }
+
+        // The changes are watched after the version of the listed resources
+        let page = storage
+            .list(self.prefix, &ListOptions::default())
+            .map_err(|e| ApiError::internal(e.to_string()))?;
+        let version = page.version;
+        let events = page
+            .elements
+            .into_iter()
+            .map(|object| WatchEvent {
+                event_type: WatchEventType::Added,
+                resource_version: version,
+                object,
+            })
+            .filter_map(|event| self.select(event))
+            .collect();
+        Ok((version, events))
+    }
+
+    fn stream(
+        &self,
+        storage: &dyn Storage,
+        mut version: i64,
+        writer: &mut impl Write,
+    ) -> IoResult<()> {
+        let mut last_write = Instant::now();
+        let mut last_check = Instant::now();
+        loop {
+            let changes = storage
+                .watch(self.prefix, version, BATCH_SIZE)
+                .map_err(std::io::Error::other)?;
+            let read = changes.len();
+            for change in changes {
+                version = change.resource_version;
+                if let Some(event) = self.select(change) {
+                    write_chunk(writer, format_event(&event).as_bytes())?;
+                    last_write = Instant::now();
+                }
+            }
+            if read == BATCH_SIZE {
+                continue;
+            }
+            if last_check.elapsed() >= HEARTBEAT_INTERVAL {
+                if self.is_revoked(storage) {
+                    return Err(std::io::Error::other("the token was revoked"));
+                }
+                last_check = Instant::now();
+            }
+            if last_write.elapsed() >= HEARTBEAT_INTERVAL {
+                debug!("Watch heartbeat at version {}", version);
+                write_chunk(writer, b": heartbeat\n\n")?;
+                last_write = Instant::now();
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Whether the service account which opened the watch was deleted since
+    fn is_revoked(&self, storage: &dyn Storage) -> bool {
+        self.token
+            .as_deref()
+            .is_some_and(|token| find_service_account(storage, token).is_none())
+    }
+
+    /// Keep the events of the watched namespace, with the name of their resource
+    fn select(&self, mut event: WatchEvent) -> Option<WatchEvent> {
+        if let Some(namespace) = &self.namespace {
+            // Names end with `/<namespace>/<name>`
+            if event.object.name.rsplit('/').nth(1) != Some(namespace.as_str()) {
+                return None;
+            }
+        }
+        event.object = element_set_right_name(event.object);
+        Some(event)
+    }
+}
+
+/// Format an event as a server-sent event
+fn format_event(event: &WatchEvent) -> String {
+    format!(
+        "id: {}\nevent: {}\ndata: {}\n\n",
+        event.resource_version,
+        event.event_type.as_str(),
+        serde_json::to_string(event).unwrap()
+    )
+}
+
+/// The body is sent as chunks, so each event reaches the client as soon as it is written
+fn write_headers(writer: &mut impl Write) -> IoResult<()> {
+    writer.write_all(
+        b"HTTP/1.1 200 OK\r\n\
+        Content-Type: text/event-stream\r\n\
+        Cache-Control: no-cache\r\n\
+        Transfer-Encoding: chunked\r\n\r\n",
+    )?;
+    writer.flush()
+}
+
+fn write_chunk(writer: &mut impl Write, data: &[u8]) -> IoResult<()> {
+    write!(writer, "{:x}\r\n", data.len())?;
+    writer.write_all(data)?;
+    writer.write_all(b"\r\n")?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::external::services::service_account::{
+        hash_token, issue_token, service_account_element_name,
+    };
+    use crate::api::types::service_account::ServiceAccount;
+    use crate::database::memory::MemoryStorage;
+    use crate::database::RikRepository;
+
+    #[test]
+    fn test_parse_watch() {
+        assert_eq!(Watch::parse("/api/v1/instances", None), None);
+        assert_eq!(Watch::parse("/api/v1/instances?watch=false", None), None);
+        assert_eq!(Watch::parse("/api/v1/health?watch=true", None), None);
+        assert_eq!(
+            Watch::parse(
+                "/api/v1/instances?watch=true&namespace=prod&resourceVersion=4",
+                None
+            ),
+            Some(Ok(Watch {
+                prefix: "/instance/",
+                namespace: Some(String::from("prod")),
+                resource_version: Some(4),
+                token: None,
+            }))
+        );
+        assert_eq!(
+            Watch::parse("/api/v1/workloads?watch=true&resourceVersion=4", Some("7"))
+                .unwrap()
+                .unwrap()
+                .resource_version,
+            Some(7)
+        );
+        assert_eq!(
+            Watch::parse("/api/v1/tenants?watch=true&resourceVersion=last", None)
+                .unwrap()
+                .unwrap_err()
+                .status,
+            400
+        );
+    }
+
+    #[test]
+    fn test_watch_changes() {
+        let storage = MemoryStorage::default();
+
+        let watch = Watch::parse("/api/v1/instances?watch=true&namespace=default", None)
+            .unwrap()
+            .unwrap();
+        let web = RikRepository::insert(
+            &storage,
+            "/instance/default/web-1",
+            r#"{"status": "Pending"}"#,
+        )
+        .unwrap();
+        RikRepository::insert(&storage, "/instance/prod/api-1", r#"{"status": "Pending"}"#)
+            .unwrap();
+        let (version, initial) = watch.start(&storage).unwrap();
+        assert_eq!(initial.len(), 1);
+        assert_eq!(initial[0].object.name, "web-1");
+        assert_eq!(initial[0].resource_version, version);
+
+        RikRepository::update(&storage, &web, r#"{"status": "Running"}"#, None).unwrap();
+        RikRepository::delete(&storage, &web).unwrap();
+        let events: Vec<WatchEvent> = storage
+            .watch(watch.prefix, version, BATCH_SIZE)
+            .unwrap()
+            .into_iter()
+            .filter_map(|event| watch.select(event))
+            .collect();
+        let types: Vec<WatchEventType> = events.iter().map(|event| event.event_type).collect();
+        assert_eq!(
+            types,
+            vec![WatchEventType::Modified, WatchEventType::Deleted]
+        );
+        assert_eq!(events[0].object.value["status"], "Running");
+        assert!(format_event(&events[1]).starts_with(&format!(
+            "id: {}\nevent: DELETED\ndata: {{",
+            events[1].resource_version
+        )));
+
+        // A watch cannot resume after compacted changes
+        storage.compact(1).unwrap();
+        let resumed = Watch::parse(
+            &format!("/api/v1/instances?watch=true&resourceVersion={}", version),
+            None,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(resumed.start(&storage).unwrap_err().status, 410);
+    }
+
+    #[test]
+    fn test_watch_revoked() {
+        let storage = MemoryStorage::default();
+        let token = issue_token();
+        let account = ServiceAccount {
+            name: String::from("ci"),
+            token_hash: hash_token(&token),
+            created_at: 0,
+        };
+        let id = RikRepository::insert(
+            &storage,
+            &service_account_element_name("ci"),
+            &serde_json::to_string(&account).unwrap(),
+        )
+        .unwrap();
+        let watch = Watch::parse("/api/v1/workloads?watch=true", None)
+            .unwrap()
+            .unwrap();
+        assert!(!watch.is_revoked(&storage));
+        let watch = watch.with_service_account_token(&token);
+        assert!(!watch.is_revoked(&storage));
+
+        RikRepository::delete(&storage, &id).unwrap();
+        assert!(watch.is_revoked(&storage));
+    }
+
+    #[test]
+    fn test_watch_limit() {
+        let limit = WatchLimit::new(2);
+        let first = limit.acquire().ok().unwrap();
+        let _second = limit.acquire().ok().unwrap();
+        let refused = limit.acquire().err().unwrap();
+        assert_eq!(refused.status_code().0, 503);
+
+        // A closed watch gives its place back
+        drop(first);
+        assert!(limit.acquire().is_ok());
+    }
+}
@@ -0,0 +1,159 @@
+use crate::api;
+use crate::api::external::routes::v1::{
+    created_response, element_response, find_resource, id_param, list_response, read_body,
+};
+use crate::api::external::services::element::merge_patch;
+use crate::api::external::services::element::{check_resource_version, list_options};
+use crate::api::external::services::namespace::namespace_workloads_prefix;
+use crate::api::external::services::workload::{
+    check_workload_name, delete_workload, prepare_workload, workload_element_name, STATUS_FIELDS,
+};
+use crate::api::types::element::Element;
+use crate::api::types::error::ApiError;
+use crate::api::ApiChannel;
+use crate::database::{RikRepository, Storage};
+use tracing::info;
+
+use definition::workload::WorkloadDefinition;
+use route_recognizer;
+use std::io;
+use std::sync::mpsc::Sender;
+
+const PREFIX: &str = "/workload/";
+
+pub fn list(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let prefix = match params.find("namespace") {
+        Some(namespace) => namespace_workloads_prefix(namespace),
+        None => String::from(PREFIX),
+    };
+    list_response(RikRepository::list(
+        storage,
+        &prefix,
+        &list_options(params)?,
+    ))
+}
+
+pub fn create(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let mut workload: WorkloadDefinition = serde_json::from_str(read_body(params))?;
+    prepare_workload(storage, &mut workload, true)?;
+    check_workload_name(storage, &workload, None)?;
+
+    let id = RikRepository::insert(
+        storage,
+        &workload_element_name(&workload),
+        &serde_json::to_string(&workload)?,
+    )?;
+    info!("Workload {} successfully created", id);
+    created_response(
+        "workloads",
+        &find_resource(storage, &id, PREFIX, "Workload")?,
+    )
+}
+
+pub fn get(
+    _: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    element_response(
+        200,
+        &find_resource(storage, id_param(params), PREFIX, "Workload")?,
+    )
+}
+
+/// Replace the definition of a workload
+pub fn update(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let current = find_resource(storage, id_param(params), PREFIX, "Workload")?;
+    check_resource_version(req, params, &current)?;
+    let workload: WorkloadDefinition = serde_json::from_str(read_body(params))?;
+    replace(storage, current, workload)
+}
+
+/// Apply a JSON merge patch to the definition of a workload
+pub fn patch(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    _: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let current = find_resource(storage, id_param(params), PREFIX, "Workload")?;
+    check_resource_version(req, params, &current)?;
+    let patch: serde_json::Value = serde_json::from_str(read_body(params))?;
+    let mut value = current.value.clone();
+    merge_patch(&mut value, &patch);
+    let workload: WorkloadDefinition = serde_json::from_value(value)?;
+    replace(storage, current, workload)
+}
+
+/// Store the new definition of a workload, if it wasn't written since it was read.
+/// The running instances are left as they are, the definition applies to the
+/// instances created afterwards.
+fn replace(
+    storage: &dyn Storage,
+    current: Element,
+    mut workload: WorkloadDefinition,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let previous: WorkloadDefinition = serde_json::from_value(current.value.clone())?;
+    // They are part of the database name of the workload
+    if workload.get_kind() != previous.get_kind()
+        || workload.name != previous.name
+        || workload.get_namespace() != previous.get_namespace()
+    {
+        return Err(ApiError::bad_request(
+            "The kind, name and namespace of a workload cannot be changed",
+        )
+        .into());
+    }
+    let new_workload = workload.tenant != previous.tenant;
+    prepare_workload(storage, &mut workload, new_workload)?;
+
+    // The status of the workload is kept
+    let mut value = serde_json::to_value(&workload)?;
+    for field in STATUS_FIELDS {
+        if let Some(status) = current.value.get(field) {
+            value[field] = status.clone();
+        }
+    }
+    RikRepository::update(
+        storage,
+        &current.id,
+        &serde_json::to_string(&value)?,
+        Some(current.resource_version),
+    )?;
+    info!("Workload {} updated", current.id);
+    element_response(
+        200,
+        &find_resource(storage, &current.id, PREFIX, "Workload")?,
+    )
+}
+
+pub fn delete(
+    req: &mut tiny_http::Request,
+    params: &route_recognizer::Params,
+    storage: &dyn Storage,
+    internal_sender: &Sender<ApiChannel>,
+) -> Result<tiny_http::Response<io::Cursor<Vec<u8>>>, api::RikError> {
+    let workload = find_resource(storage, id_param(params), PREFIX, "Workload")?;
+    check_resource_version(req, params, &workload)?;
+    let id = workload.id.clone();
+    delete_workload(storage, internal_sender, workload)?;
+
+    info!("Workload {} deleted", id);
+    Ok(tiny_http::Response::from_string("").with_status_code(tiny_http::StatusCode::from(204)))
+}
@@ -1,13 +1,242 @@
 use crate::api::types::element::Element;
+use crate::api::types::error::ApiError;
+use crate::database::{ListOptions, Page, PageCursor, SortBy};
+use std::str::FromStr;
+
+/// Elements listed at most in a page
+pub const MAX_LIST_LIMIT: usize = 1000;
+/// Header giving the token of the next page of a list, when more elements follow
+pub const CONTINUE_HEADER: &str = "X-Continue";
+/// Header of a write giving the resource version it expects, as the `ETag` of the
+/// resource
+pub const IF_MATCH_HEADER: &str = "If-Match";
+
+/// Replace the database name of an element by the name of the resource
+pub fn element_set_right_name(mut element: Element) -> Element {
+    if let Some(name) = element.name.rsplit('/').next() {
+        let name = name.to_string();
+        element.set_name(name);
+    }
+    element
+}
 
 pub fn elements_set_right_name(elements: Vec<Element>) -> Vec<Element> {
-    let mut result: Vec<Element> = Vec::new();
-    for mut element in elements {
-        let mut split: Vec<&str> = element.name.split('/').collect();
-        if let Some(v) = split.pop() {
-            let _ = &element.set_name(v.to_string());
+    elements.into_iter().map(element_set_right_name).collect()
+}
+
+/// Read the filters and page of a list from the parameters of its request: `limit`,
+/// `continue`, `sort` (`creationTime` or `name`), `status` and `workloadId`
+pub fn list_options(params: &route_recognizer::Params) -> Result<ListOptions, ApiError> {
+    let limit = match params.find("limit") {
+        Some(limit) => match limit.parse() {
+            Ok(limit) if (1..=MAX_LIST_LIMIT).contains(&limit) => Some(limit),
+            _ => {
+                return Err(ApiError::bad_request(format!(
+                    "Invalid limit {}, expected 1 to {}",
+                    limit, MAX_LIST_LIMIT
+                )))
+            }
+        },
+        None => None,
+    };
+    let sort = match params.find("sort") {
+        None | Some("creationTime") => SortBy::CreationTime,
+        Some("name") => SortBy::Name,
+        Some(sort) => {
+            return Err(ApiError::bad_request(format!(
+                "Invalid sort {}, expected creationTime or name",
+                sort
+            )))
+        }
+    };
+    Ok(ListOptions {
+        limit,
+        after: params
+            .find("continue")
+            .map(parse_continue_token)
+            .transpose()?,
+        sort,
+        status: params.find("status").map(String::from),
+        workload_id: params.find("workloadId").map(String::from),
+    })
+}
+
+/// Opaque token of the next page of a list
+pub fn continue_token(cursor: &PageCursor) -> String {
+    base64::encode_config(serde_json::to_vec(cursor).unwrap(), base64::URL_SAFE_NO_PAD)
+}
+
+fn parse_continue_token(token: &str) -> Result<PageCursor, ApiError> {
+    base64::decode_config(token, base64::URL_SAFE_NO_PAD)
+        .ok()
+        .and_then(|cursor| serde_json::from_slice(&cursor).ok())
+        .ok_or_else(|| ApiError::bad_request(format!("Invalid continue token {}", token)))
+}
+
+/// Response listing a page of elements, with the token of the next page if any
+pub fn page_response(
+    page: Page,
+) -> Result<tiny_http::Response<std::io::Cursor<Vec<u8>>>, serde_json::Error> {
+    let elements = elements_set_right_name(page.elements);
+    let mut response = tiny_http::Response::from_string(serde_json::to_string(&elements)?)
+        .with_header(tiny_http::Header::from_str("Content-Type: application/json").unwrap());
+    if let Some(next) = page.next {
+        let header = format!("{}: {}", CONTINUE_HEADER, continue_token(&next));
+        response = response.with_header(tiny_http::Header::from_str(&header).unwrap());
+    }
+    Ok(response)
+}
+
+/// Get the resource version a write expects the resource at, from the `If-Match`
+/// header or the `resourceVersion` parameter of its request
+pub fn expected_version(
+    request: &tiny_http::Request,
+    params: &route_recognizer::Params,
+) -> Result<Option<i64>, ApiError> {
+    let if_match = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(IF_MATCH_HEADER))
+        .map(|header| header.value.as_str());
+    parse_expected_version(if_match, params.find("resourceVersion"))
+}
+
+fn parse_expected_version(
+    if_match: Option<&str>,
+    resource_version: Option<&str>,
+) -> Result<Option<i64>, ApiError> {
+    // The ETag is quoted, and `*` matches any version
+    let version = match if_match.map(|etag| etag.trim().trim_start_matches("W/").trim_matches('"'))
+    {
+        Some("*") => return Ok(None),
+        Some(version) => Some(version),
+        None => resource_version,
+    };
+    version
+        .map(|version| {
+            version
+                .parse()
+                .map_err(|_| ApiError::bad_request(format!("Invalid resource version {}", version)))
+        })
+        .transpose()
+}
+
+/// Refuse a write on a resource written since the version its request expects
+pub fn check_resource_version(
+    request: &tiny_http::Request,
+    params: &route_recognizer::Params,
+    element: &Element,
+) -> Result<(), ApiError> {
+    match expected_version(request, params)? {
+        Some(version) if version != element.resource_version => Err(ApiError::conflict(format!(
+            "Resource version {} is stale, the resource is at version {}",
+            version, element.resource_version
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// `ETag` header of an element, its resource version
+pub fn etag_header(element: &Element) -> tiny_http::Header {
+    tiny_http::Header::from_str(&format!("ETag: \"{}\"", element.resource_version)).unwrap()
+}
+
+/// Apply a JSON merge patch (RFC 7386) to a value: the members of the patch
+/// replace the ones of the value, `null` members are removed
+pub fn merge_patch(value: &mut serde_json::Value, patch: &serde_json::Value) {
+    match patch {
+        serde_json::Value::Object(members) => {
+            if !value.is_object() {
+                *value = serde_json::Value::Object(serde_json::Map::new());
+            }
+            let object = value.as_object_mut().unwrap();
+            for (key, member) in members {
+                if member.is_null() {
+                    object.remove(key);
+                } else {
+                    merge_patch(
+                        object.entry(key.clone()).or_insert(serde_json::Value::Null),
+                        member,
+                    );
+                }
+            }
+        }
+        _ => *value = patch.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_patch() {
+        let mut value = json!({
+            "name": "web",
+            "replicas": 1,
+            "spec": {"containers": [{"name": "nginx"}]},
+            "tenant": "acme"
+        });
+        merge_patch(
+            &mut value,
+            &json!({"replicas": 3, "spec": {"containers": []}, "tenant": null, "node_selector": {"disk": "ssd"}}),
+        );
+        assert_eq!(
+            value,
+            json!({
+                "name": "web",
+                "replicas": 3,
+                "spec": {"containers": []},
+                "node_selector": {"disk": "ssd"}
+            })
+        );
+    }
+
+    #[test]
+    fn test_expected_version() {
+        assert_eq!(parse_expected_version(None, None).unwrap(), None);
+        assert_eq!(
+            parse_expected_version(Some("\"7\""), Some("3")).unwrap(),
+            Some(7)
+        );
+        assert_eq!(
+            parse_expected_version(Some("W/\"7\""), None).unwrap(),
+            Some(7)
+        );
+        assert_eq!(parse_expected_version(Some("*"), Some("3")).unwrap(), None);
+        assert_eq!(parse_expected_version(None, Some("3")).unwrap(), Some(3));
+        assert_eq!(
+            parse_expected_version(Some("\"latest\""), None)
+                .unwrap_err()
+                .status,
+            400
+        );
+    }
+
+    #[test]
+    fn test_list_options() {
+        let mut params = route_recognizer::Params::new();
+        assert_eq!(list_options(&params).unwrap(), ListOptions::default());
+
+        let cursor = PageCursor {
+            rowid: 12,
+            name: String::from("/instance/default/web-1"),
+        };
+        params.insert(String::from("limit"), String::from("10"));
+        params.insert(String::from("sort"), String::from("name"));
+        params.insert(String::from("continue"), continue_token(&cursor));
+        params.insert(String::from("workloadId"), String::from("web"));
+        let options = list_options(&params).unwrap();
+        assert_eq!(options.limit, Some(10));
+        assert_eq!(options.sort, SortBy::Name);
+        assert_eq!(options.after, Some(cursor));
+        assert_eq!(options.workload_id.as_deref(), Some("web"));
+
+        for (key, value) in [("limit", "0"), ("sort", "age"), ("continue", "abc")] {
+            let mut params = route_recognizer::Params::new();
+            params.insert(key.to_string(), value.to_string());
+            assert_eq!(list_options(&params).unwrap_err().status, 400);
         }
-        result.push(element.clone());
     }
-    result
 }
@@ -0,0 +1,127 @@
+use crate::api::types::element::Element;
+use crate::api::types::event::ClusterEvent;
+use crate::database::{escape_prefix, RikRepository};
+use crate::database::{Result, Storage};
+use dotenv::dotenv;
+use std::time::Duration;
+
+/// Events are kept an hour unless `EVENT_TTL` says otherwise
+const DEFAULT_EVENT_TTL: Duration = Duration::from_secs(3600);
+
+/// Get how long events are kept, from `EVENT_TTL` in seconds
+pub fn event_ttl() -> Duration {
+    dotenv().ok();
+    std::env::var("EVENT_TTL")
+        .ok()
+        .and_then(|ttl| ttl.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_EVENT_TTL)
+}
+
+/// Get the database prefix of the events, optionally restricted to a kind of
+/// object and an object
+pub fn event_element_prefix(object_kind: Option<&str>, object_id: Option<&str>) -> String {
+    match (object_kind, object_id) {
+        (Some(kind), Some(id)) => format!("/event/{}/{}/", kind, id),
+        (Some(kind), None) => format!("/event/{}/", kind),
+        (None, Some(id)) => format!("/event/%/{}/", id),
+        (None, None) => String::from("/event/"),
+    }
+}
+
+pub fn store_event(storage: &dyn Storage, event: &ClusterEvent) -> Result<String> {
+    // Several events of an object can share the same timestamp, the database name is
+    // made unique by the id of the element
+    let name = format!(
+        "{}{}",
+        event_element_prefix(Some(&event.object_kind), Some(&event.object_id)),
+        event.timestamp
+    );
+    RikRepository::insert(storage, &name, &serde_json::to_string(event).unwrap())
+}
+
+/// Find the events of the cluster, optionally about a kind of object or an object,
+/// oldest first
+pub fn find_events(
+    storage: &dyn Storage,
+    object_kind: Option<&str>,
+    object_id: Option<&str>,
+) -> Result<Vec<Element>> {
+    let prefix = event_element_prefix(
+        object_kind.map(escape_prefix).as_deref(),
+        object_id.map(escape_prefix).as_deref(),
+    );
+    let mut events: Vec<Element> = RikRepository::find_all(storage, &prefix)?
+        .into_iter()
+        // `%` matches any characters in prefixes, the filters are checked again
+        .filter(|element| {
+            object_kind.is_none_or(|kind| element.value["object_kind"] == kind)
+                && object_id.is_none_or(|id| element.value["object_id"] == id)
+        })
+        .collect();
+    events.sort_by_key(|element| element.value["timestamp"].as_i64());
+    Ok(events)
+}
+
+/// Delete the events older than the TTL, returns how many were deleted
+pub fn purge_expired_events(storage: &dyn Storage, ttl: Duration, now: i64) -> Result<usize> {
+    let expired: Vec<Element> = RikRepository::find_all(storage, "/event/")?
+        .into_iter()
+        .filter(|element| {
+            element.value["timestamp"]
+                .as_i64()
+                .is_none_or(|timestamp| timestamp + (ttl.as_secs() as i64) < now)
+        })
+        .collect();
+    for event in &expired {
+        RikRepository::delete(storage, &event.id)?;
+    }
+    Ok(expired.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::memory::MemoryStorage;
+    use rstest::rstest;
+
+    fn event(object_kind: &str, object_id: &str, reason: &str, timestamp: i64) -> ClusterEvent {
+        ClusterEvent {
+            object_kind: object_kind.to_string(),
+            object_id: object_id.to_string(),
+            reason: reason.to_string(),
+            message: String::new(),
+            event_type: String::from("Normal"),
+            source: String::from("scheduler"),
+            timestamp,
+        }
+    }
+
+    #[rstest]
+    fn test_find_and_purge_events() {
+        let storage = MemoryStorage::default();
+
+        store_event(&storage, &event("instance", "web-a1b2", "Started", 20)).unwrap();
+        store_event(&storage, &event("instance", "web-a1b2", "Scheduled", 10)).unwrap();
+        store_event(&storage, &event("instance", "web_a1b2", "Scheduled", 10)).unwrap();
+        store_event(&storage, &event("worker", "debian", "Registered", 5)).unwrap();
+
+        let events = find_events(&storage, Some("instance"), Some("web-a1b2")).unwrap();
+        let reasons: Vec<&str> = events
+            .iter()
+            .map(|event| event.value["reason"].as_str().unwrap())
+            .collect();
+        assert_eq!(reasons, vec!["Scheduled", "Started"]);
+        assert_eq!(
+            find_events(&storage, Some("worker"), None).unwrap().len(),
+            1
+        );
+        assert_eq!(find_events(&storage, None, None).unwrap().len(), 4);
+
+        let purged = purge_expired_events(&storage, Duration::from_secs(10), 25).unwrap();
+        assert_eq!(purged, 3);
+        let events = find_events(&storage, None, None).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].value["reason"], "Started");
+    }
+}
@@ -1,12 +1,18 @@
+use crate::api::external::services::tenant::{check_quota, get_workload_quota};
+use crate::api::external::services::workload::record_instances_request;
+use crate::api::types::element::Element;
+use crate::api::types::error::ApiError;
+use crate::api::types::instance::InstanceDefinition;
 use crate::api::{ApiChannel, CRUD};
-use crate::database::RikRepository;
-use definition::workload::WorkloadDefinition;
+use crate::database::{RikRepository, Storage};
+use crate::logger::current_request_id;
+use definition::workload::{WorkloadDefinition, WorkloadKind};
 use names::Generator;
-use rusqlite::Connection;
 use std::sync::mpsc::Sender;
+use tracing::error;
 
 pub fn send_create_instance(
-    connection: &Connection,
+    storage: &dyn Storage,
     internal_sender: &Sender<ApiChannel>,
     workload_id: String,
     name: &Option<String>,
@@ -18,19 +24,123 @@ pub fn send_create_instance(
         None => &random_name,
     };
 
-    let workload_db = match RikRepository::find_one(connection, &workload_id, "/workload") {
+    let workload_db = match RikRepository::find_one(storage, &workload_id, "/workload") {
         Ok(workload) => workload,
         Err(err) => panic!("{}", err),
     };
     let workload: WorkloadDefinition =
         serde_json::from_str(&workload_db.value.to_string()).unwrap();
 
+    let quota = get_workload_quota(storage, &workload);
+    if let Err(e) = record_instances_request(storage, &workload_id, &workload) {
+        error!("Cannot record instances of workload {}: {}", workload_id, e);
+    }
+
     internal_sender
         .send(ApiChannel {
             action: CRUD::Create,
             workload_id: Some(workload_id),
             workload_definition: Some(workload),
             instance_id: None,
+            quota,
+            correlation_id: current_request_id(),
+            trace_context: telemetry::current_context(),
+        })
+        .unwrap();
+}
+
+/// Check the instances of a workload can be created, and ask the scheduler to create them
+pub fn create_instances(
+    storage: &dyn Storage,
+    internal_sender: &Sender<ApiChannel>,
+    mut instance: InstanceDefinition,
+) -> Result<(), ApiError> {
+    let workload =
+        RikRepository::find_one(storage, &instance.workload_id, "/workload").map_err(|_| {
+            ApiError::not_found(format!("Workload id {} not found", &instance.workload_id))
+        })?;
+    let definition: WorkloadDefinition = serde_json::from_value(workload.value)
+        .map_err(|e| ApiError::internal(format!("Invalid workload definition: {}", e)))?;
+    match definition.get_kind() {
+        WorkloadKind::CronJob => {
+            return Err(ApiError::bad_request(
+                "Instances of a cronjob are created by its schedule",
+            ));
+        }
+        WorkloadKind::Job if instance.get_replicas() > 1 => {
+            return Err(ApiError::bad_request(
+                "Cannot use replicas with a job, use completions instead",
+            ));
+        }
+        WorkloadKind::DaemonSet if instance.get_replicas() > 1 => {
+            return Err(ApiError::bad_request(
+                "Cannot use replicas with a daemonset, it runs once on every matching worker",
+            ));
+        }
+        _ => {}
+    }
+
+    if instance.name.is_some() {
+        // Check name is not used
+        if RikRepository::check_duplicate_name(
+            storage,
+            &format!(
+                "/instance/{}/{}",
+                definition.get_namespace(),
+                instance.get_name()
+            ),
+        )
+        .is_ok()
+        {
+            return Err(ApiError::conflict("Name already used"));
+        }
+
+        // Name cannot be used with multiple replicas
+        if instance.get_replicas() > 1 {
+            return Err(ApiError::bad_request(
+                "Cannot use name with multiple replicas",
+            ));
+        }
+    }
+
+    let instances = match definition.get_kind() {
+        WorkloadKind::Job => {
+            let job = definition.get_job_spec();
+            job.get_parallelism().min(job.get_completions()) as u64
+        }
+        _ => instance.get_replicas() as u64,
+    };
+    check_quota(storage, &definition, false, instances).map_err(ApiError::forbidden)?;
+
+    for _ in 0..instance.get_replicas() {
+        send_create_instance(
+            storage,
+            internal_sender,
+            instance.workload_id.clone(),
+            &instance.name,
+        );
+    }
+    Ok(())
+}
+
+/// Delete an instance if it wasn't written since it was read, and ask the scheduler
+/// to destroy it
+pub fn delete_instance(
+    storage: &dyn Storage,
+    internal_sender: &Sender<ApiChannel>,
+    instance: Element,
+) -> crate::database::Result<()> {
+    storage.delete(&instance.id, Some(instance.resource_version))?;
+    internal_sender
+        .send(ApiChannel {
+            action: CRUD::Delete,
+            workload_id: None,
+            workload_definition: None,
+            instance_id: Some(instance.id.clone()),
+            quota: None,
+            correlation_id: current_request_id(),
+            trace_context: telemetry::current_context(),
         })
         .unwrap();
+    Ok(())
 }
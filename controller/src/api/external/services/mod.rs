@@ -1,2 +1,7 @@
 pub mod element;
+pub mod event;
 pub mod instance;
+pub mod namespace;
+pub mod service_account;
+pub mod tenant;
+pub mod workload;
@@ -0,0 +1,115 @@
+use crate::api::external::services::instance::delete_instance;
+use crate::api::external::services::workload::delete_workload;
+use crate::api::types::element::Element;
+use crate::api::types::namespace::Namespace;
+use crate::api::ApiChannel;
+use crate::database::{escape_prefix, RikRepository};
+use crate::database::{Result, Storage};
+use definition::workload::DEFAULT_NAMESPACE;
+use std::sync::mpsc::Sender;
+
+/// Get the database name of a namespace
+pub fn namespace_element_name(name: &str) -> String {
+    format!("/namespace/{}", name)
+}
+
+/// Namespaces are part of the database names of the resources, they are
+/// restricted to lowercase alphanumeric characters and `-`
+pub fn validate_namespace_name(name: &str) -> Result<(), String> {
+    validate_name("namespace", name)
+}
+
+/// Check a part of the database name of a resource, e.g. the kind or the name of
+/// a workload, follows the rules of the namespaces so it cannot be mistaken for
+/// another part
+pub fn validate_name(field: &str, name: &str) -> Result<(), String> {
+    let is_valid = !name.is_empty()
+        && name.len() <= 63
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !name.starts_with('-')
+        && !name.ends_with('-');
+    match is_valid {
+        true => Ok(()),
+        false => Err(format!(
+            "Invalid {} {}, it must only contain lowercase alphanumeric characters or '-'",
+            field, name
+        )),
+    }
+}
+
+pub fn namespace_exists(storage: &dyn Storage, name: &str) -> bool {
+    RikRepository::find_by_name(storage, &namespace_element_name(name)).is_ok()
+}
+
+/// Get the tenant owning a namespace, `None` if it has none or doesn't exist
+pub fn namespace_tenant(storage: &dyn Storage, name: &str) -> Option<String> {
+    let element = RikRepository::find_by_name(storage, &namespace_element_name(name)).ok()?;
+    serde_json::from_value::<Namespace>(element.value)
+        .ok()?
+        .tenant
+}
+
+/// Create the default namespace if it doesn't exist yet
+pub fn create_default_namespace(storage: &dyn Storage) -> Result<()> {
+    if !namespace_exists(storage, DEFAULT_NAMESPACE) {
+        let namespace = Namespace {
+            name: DEFAULT_NAMESPACE.to_string(),
+            tenant: None,
+        };
+        RikRepository::insert(
+            storage,
+            &namespace_element_name(DEFAULT_NAMESPACE),
+            &serde_json::to_string(&namespace).unwrap(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Database prefix of the workloads of a namespace, whatever their kind
+pub fn namespace_workloads_prefix(name: &str) -> String {
+    format!("/workload/%/{}/", escape_prefix(name))
+}
+
+/// Find the workloads of a namespace
+pub fn find_namespace_workloads(storage: &dyn Storage, name: &str) -> Result<Vec<Element>> {
+    RikRepository::find_all(storage, &namespace_workloads_prefix(name))
+}
+
+/// Database prefix of the instances of a namespace
+pub fn namespace_instances_prefix(name: &str) -> String {
+    format!("/instance/{}/", escape_prefix(name))
+}
+
+/// Delete the instances and the workloads of a namespace, and ask the scheduler
+/// to destroy them
+pub fn delete_namespace_resources(
+    storage: &dyn Storage,
+    internal_sender: &Sender<ApiChannel>,
+    name: &str,
+) -> Result<()> {
+    for instance in RikRepository::find_all(storage, &namespace_instances_prefix(name))? {
+        delete_instance(storage, internal_sender, instance)?;
+    }
+    for workload in find_namespace_workloads(storage, name)? {
+        delete_workload(storage, internal_sender, workload)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_namespace_name() {
+        assert!(validate_namespace_name("default").is_ok());
+        assert!(validate_namespace_name("team-42").is_ok());
+        assert!(validate_namespace_name("").is_err());
+        assert!(validate_namespace_name("Team").is_err());
+        assert!(validate_namespace_name("team/42").is_err());
+        assert!(validate_namespace_name("team%").is_err());
+        assert!(validate_namespace_name("-team").is_err());
+    }
+}
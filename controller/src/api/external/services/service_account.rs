@@ -0,0 +1,83 @@
+use crate::api::types::element::Element;
+use crate::api::types::service_account::ServiceAccount;
+use crate::database::{RikRepository, Storage};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Database names of the service accounts start with it
+pub const SERVICE_ACCOUNT_PREFIX: &str = "/serviceaccount/";
+
+/// Tokens issued by the controller start with it, to be told apart from the static ones
+const TOKEN_PREFIX: &str = "rik_sa_";
+
+/// Get the database name of a service account
+pub fn service_account_element_name(name: &str) -> String {
+    format!("{}{}", SERVICE_ACCOUNT_PREFIX, name)
+}
+
+/// Generate a new token, 244 random bits
+pub fn issue_token() -> String {
+    format!(
+        "{}{}{}",
+        TOKEN_PREFIX,
+        Uuid::new_v4().to_simple(),
+        Uuid::new_v4().to_simple()
+    )
+}
+
+/// Tokens are only kept as their SHA-256, in hexadecimal
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Find the service account of a token, if it wasn't revoked
+pub fn find_service_account(storage: &dyn Storage, token: &str) -> Option<ServiceAccount> {
+    if !token.starts_with(TOKEN_PREFIX) {
+        return None;
+    }
+    let token_hash = hash_token(token);
+    RikRepository::find_all(storage, SERVICE_ACCOUNT_PREFIX)
+        .ok()?
+        .into_iter()
+        .filter_map(|element| serde_json::from_value::<ServiceAccount>(element.value).ok())
+        .find(|account| account.token_hash == token_hash)
+}
+
+/// Remove the hash of the token of a service account from its element, before it is
+/// answered
+pub fn hide_token_hash(mut element: Element) -> Element {
+    if let Some(fields) = element.value.as_object_mut() {
+        fields.remove("token_hash");
+    }
+    element
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::fixtures::storage;
+
+    #[test]
+    fn test_find_service_account() {
+        let storage = storage();
+        let token = issue_token();
+        let account = ServiceAccount {
+            name: String::from("ci"),
+            token_hash: hash_token(&token),
+            created_at: 0,
+        };
+        RikRepository::insert(
+            &storage,
+            &service_account_element_name("ci"),
+            &serde_json::to_string(&account).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(find_service_account(&storage, &token), Some(account));
+        assert_eq!(find_service_account(&storage, &issue_token()), None);
+        assert_eq!(find_service_account(&storage, "ci"), None);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::api::types::element::Element;
+use crate::api::types::instance::InstanceStatus;
+use crate::api::types::tenant::{TenantSpec, ROLE_RESOURCES, ROLE_VERBS};
+use crate::database::RikRepository;
+use crate::database::{Result, Storage, StorageError};
+use definition::quota::{ResourceQuota, ResourceUsage};
+use definition::workload::WorkloadDefinition;
+
+/// Get the database name of a tenant
+pub fn tenant_element_name(name: &str) -> String {
+    format!("/tenant/{}", name.trim_start_matches("/tenant/"))
+}
+
+/// Get the name of a tenant from its database name. The first tenants are stored
+/// under the name their clients gave, e.g. `/tenant/default/jean`, whose last
+/// segment is the name of the tenant.
+pub fn tenant_name(element_name: &str) -> &str {
+    element_name.rsplit('/').next().unwrap_or(element_name)
+}
+
+/// Find a tenant by name, be it stored under its database name or a legacy one
+pub fn find_tenant(storage: &dyn Storage, name: &str) -> Result<Element> {
+    match RikRepository::find_by_name(storage, &tenant_element_name(name)) {
+        Err(StorageError::NotFound) => RikRepository::find_all(storage, "/tenant/")?
+            .into_iter()
+            .find(|tenant| tenant_name(&tenant.name) == tenant_name(name))
+            .ok_or(StorageError::NotFound),
+        result => result,
+    }
+}
+
+/// Read the settings of a tenant from the value given by a client, with the value
+/// to store. Values which aren't a JSON object predate the settings, these tenants
+/// have no quota and no roles.
+pub fn parse_tenant_spec(value: &str) -> serde_json::Result<(TenantSpec, String)> {
+    match serde_json::from_str(value) {
+        Ok(value @ serde_json::Value::Object(_)) => {
+            let spec = serde_json::from_value(value)?;
+            let value = serde_json::to_string(&spec)?;
+            Ok((spec, value))
+        }
+        _ => Ok((TenantSpec::default(), String::from("{}"))),
+    }
+}
+
+/// Check the roles of a tenant only name known verbs and resources, and its
+/// bindings only name its roles
+pub fn validate_roles(spec: &TenantSpec) -> std::result::Result<(), String> {
+    for role in &spec.roles {
+        if role.name.is_empty() {
+            return Err(String::from("Roles must have a name"));
+        }
+        if let Some(verb) = role
+            .verbs
+            .iter()
+            .find(|verb| *verb != "*" && !ROLE_VERBS.contains(&verb.as_str()))
+        {
+            return Err(format!("Unknown verb {} in role {}", verb, role.name));
+        }
+        if let Some(resource) = role
+            .resources
+            .iter()
+            .find(|resource| *resource != "*" && !ROLE_RESOURCES.contains(&resource.as_str()))
+        {
+            return Err(format!(
+                "Unknown resource {} in role {}",
+                resource, role.name
+            ));
+        }
+    }
+    match spec
+        .bindings
+        .iter()
+        .find(|binding| !spec.roles.iter().any(|role| role.name == binding.role))
+    {
+        Some(binding) => Err(format!("Role {} not found", binding.role)),
+        None => Ok(()),
+    }
+}
+
+/// Get the quota of a tenant, `None` if the tenant is unlimited
+pub fn get_quota(storage: &dyn Storage, tenant: &str) -> Result<Option<ResourceQuota>> {
+    let tenant = find_tenant(storage, tenant)?;
+    let spec: TenantSpec = serde_json::from_value(tenant.value).unwrap_or_default();
+    Ok(spec.quota)
+}
+
+/// Get the quota applying to a workload, `None` if it has no tenant or the tenant is unlimited
+pub fn get_workload_quota(
+    storage: &dyn Storage,
+    workload: &WorkloadDefinition,
+) -> Option<ResourceQuota> {
+    workload
+        .tenant
+        .as_ref()
+        .and_then(|tenant| get_quota(storage, tenant).ok().flatten())
+}
+
+/// Compute the resources used by the workloads of a tenant, instances that
+/// are being destroyed or completed don't count
+pub fn tenant_usage(storage: &dyn Storage, tenant: &str) -> Result<ResourceUsage> {
+    let mut usage = ResourceUsage::default();
+    let workloads: Vec<(String, WorkloadDefinition)> =
+        RikRepository::find_all(storage, "/workload")?
+            .into_iter()
+            .filter_map(|workload| {
+                let definition: WorkloadDefinition = serde_json::from_value(workload.value).ok()?;
+                (definition.tenant.as_deref() == Some(tenant)).then_some((workload.id, definition))
+            })
+            .collect();
+    usage.workloads = workloads.len() as u64;
+
+    for instance in RikRepository::find_all(storage, "/instance")? {
+        let status: InstanceStatus = match serde_json::from_value(instance.value) {
+            Ok(status) => status,
+            Err(_) => continue,
+        };
+        if !status.is_active() {
+            continue;
+        }
+        if let Some((_, definition)) = workloads
+            .iter()
+            .find(|(id, _)| Some(id) == status.workload_id.as_ref())
+        {
+            usage.add_instances(definition, 1);
+        }
+    }
+    Ok(usage)
+}
+
+/// Check the quota of the workload tenant allows to create the workload, or
+/// to add instances to it. The error names the exceeded limit. Workloads without
+/// tenant are deliberately not limited, they belong to the administrators of the
+/// cluster.
+pub fn check_quota(
+    storage: &dyn Storage,
+    workload: &WorkloadDefinition,
+    new_workload: bool,
+    instances: u64,
+) -> std::result::Result<(), String> {
+    let tenant = match &workload.tenant {
+        Some(tenant) => tenant,
+        None => return Ok(()),
+    };
+    let quota = match get_quota(storage, tenant) {
+        Ok(Some(quota)) => quota,
+        Ok(None) => return Ok(()),
+        Err(_) => return Err(format!("Tenant {} not found", tenant)),
+    };
+
+    let mut usage = tenant_usage(storage, tenant).map_err(|e| e.to_string())?;
+    if new_workload {
+        usage.workloads += 1;
+    }
+    usage.add_instances(workload, instances);
+    quota
+        .check(&usage)
+        .map_err(|e| format!("Tenant {}: {}", tenant, e))
+}
@@ -0,0 +1,190 @@
+use crate::api::external::services::namespace::{
+    namespace_exists, namespace_tenant, validate_name,
+};
+use crate::api::external::services::tenant::{check_quota, find_tenant};
+use crate::api::internal::cronjob::parse_schedule;
+use crate::api::types::element::Element;
+use crate::api::types::error::ApiError;
+use crate::api::{ApiChannel, CRUD};
+use crate::database::{escape_prefix, RikRepository, Storage};
+use crate::logger::current_request_id;
+use definition::workload::{WorkloadDefinition, WorkloadKind};
+use std::sync::mpsc::Sender;
+
+/// Check the settings specific to the kind of the workload
+pub fn validate_workload(workload: &WorkloadDefinition) -> Result<(), String> {
+    match workload.get_kind() {
+        WorkloadKind::Pod | WorkloadKind::DaemonSet => Ok(()),
+        WorkloadKind::Job => {
+            let job = workload.get_job_spec();
+            if job.get_completions() == 0 || job.get_parallelism() == 0 {
+                return Err(String::from(
+                    "Job completions and parallelism must be greater than 0",
+                ));
+            }
+            Ok(())
+        }
+        WorkloadKind::CronJob => {
+            let cron_job = workload
+                .cron_job
+                .as_ref()
+                .ok_or_else(|| String::from("A cronjob must define a cron_job schedule"))?;
+            parse_schedule(&cron_job.schedule)
+                .map_err(|e| format!("Invalid cron schedule {}: {}", cron_job.schedule, e))?;
+            Ok(())
+        }
+    }
+}
+
+/// Get the database name of a workload
+pub fn workload_element_name(workload: &WorkloadDefinition) -> String {
+    format!(
+        "/workload/{}/{}/{}",
+        workload.kind,
+        workload.get_namespace(),
+        workload.name
+    )
+}
+
+/// Check a workload can be stored, with the defaults of its optional settings
+/// applied. `new_workload` tells whether the workload counts as a new one in the
+/// quota of its tenant.
+pub fn prepare_workload(
+    storage: &dyn Storage,
+    workload: &mut WorkloadDefinition,
+    new_workload: bool,
+) -> Result<(), ApiError> {
+    if workload.replicas.is_none() {
+        workload.replicas = Some(1);
+    }
+    // The namespace of a workload is read back from its database name
+    validate_name("kind", &workload.kind).map_err(ApiError::bad_request)?;
+    validate_name("name", &workload.name).map_err(ApiError::bad_request)?;
+    validate_workload(workload).map_err(ApiError::bad_request)?;
+    // Workloads of a namespace owned by a tenant belong to it
+    if let Some(owner) = namespace_tenant(storage, workload.get_namespace()) {
+        match &workload.tenant {
+            Some(tenant) if *tenant != owner => {
+                return Err(ApiError::bad_request(format!(
+                    "Namespace {} belongs to tenant {}",
+                    workload.get_namespace(),
+                    owner
+                )));
+            }
+            _ => workload.tenant = Some(owner),
+        }
+    }
+    if let Some(tenant) = &workload.tenant {
+        if find_tenant(storage, tenant).is_err() {
+            return Err(ApiError::bad_request(format!(
+                "Tenant {} not found",
+                tenant
+            )));
+        }
+    }
+    check_quota(storage, workload, new_workload, 0).map_err(ApiError::forbidden)?;
+
+    let namespace = workload.get_namespace().to_string();
+    if !namespace_exists(storage, &namespace) {
+        return Err(ApiError::not_found(format!(
+            "Namespace {} not found",
+            namespace
+        )));
+    }
+    workload.namespace = Some(namespace);
+    Ok(())
+}
+
+/// Check the name of a workload is not used by another workload of its namespace
+pub fn check_workload_name(
+    storage: &dyn Storage,
+    workload: &WorkloadDefinition,
+    id: Option<&str>,
+) -> Result<(), ApiError> {
+    // The prefix matches the names starting with the name, they are checked again
+    let used = RikRepository::find_all(
+        storage,
+        &format!(
+            "/workload/%/{}/{}",
+            escape_prefix(workload.get_namespace()),
+            escape_prefix(&workload.name)
+        ),
+    )
+    .map_err(|e| ApiError::internal(e.to_string()))?
+    .into_iter()
+    .any(|element| {
+        element.name.rsplit('/').next() == Some(workload.name.as_str())
+            && Some(element.id.as_str()) != id
+    });
+    match used {
+        true => Err(ApiError::conflict("Name already used")),
+        false => Ok(()),
+    }
+}
+
+/// Delete a workload if it wasn't written since it was read, and unschedule it. Its
+/// instances are removed by the scheduler.
+pub fn delete_workload(
+    storage: &dyn Storage,
+    internal_sender: &Sender<ApiChannel>,
+    workload: Element,
+) -> crate::database::Result<()> {
+    storage.delete(&workload.id, Some(workload.resource_version))?;
+    if let Ok(definition) = serde_json::from_value::<WorkloadDefinition>(workload.value) {
+        // Cron jobs only live in the controller, the scheduler doesn't know about them
+        if definition.get_kind() != WorkloadKind::CronJob {
+            internal_sender
+                .send(ApiChannel {
+                    action: CRUD::Delete,
+                    workload_id: Some(workload.id.clone()),
+                    workload_definition: Some(definition),
+                    instance_id: None,
+                    quota: None,
+                    correlation_id: current_request_id(),
+                    trace_context: telemetry::current_context(),
+                })
+                .unwrap();
+        }
+    }
+    Ok(())
+}
+
+/// Instances the workload should have, set once instances of the workload were requested
+pub const DESIRED_INSTANCES_FIELD: &str = "desired_instances";
+
+/// Difference between the desired instances and the active ones, see the reconciler
+pub const DRIFT_FIELD: &str = "drift";
+
+/// Fields of the value of a workload maintained by the controller, they are not part of
+/// its definition
+pub const STATUS_FIELDS: [&str; 4] = [
+    "status",
+    "scheduling_failure",
+    DESIRED_INSTANCES_FIELD,
+    DRIFT_FIELD,
+];
+
+/// Add the instances of a request to the desired state of a workload. Every request
+/// adds the replicas of a pod workload, a job runs its parallelism, and the instances
+/// of a daemon set depend on the workers so they are not counted.
+pub fn record_instances_request(
+    storage: &dyn Storage,
+    workload_id: &str,
+    definition: &WorkloadDefinition,
+) -> crate::database::Result<()> {
+    RikRepository::modify(storage, workload_id, "/workload", |workload| {
+        let desired = workload[DESIRED_INSTANCES_FIELD]
+            .as_u64()
+            .unwrap_or_default();
+        let desired = match definition.get_kind() {
+            WorkloadKind::Pod => desired + definition.replicas.unwrap_or(1) as u64,
+            WorkloadKind::Job => {
+                let job = definition.get_job_spec();
+                job.get_parallelism().min(job.get_completions()) as u64
+            }
+            WorkloadKind::DaemonSet | WorkloadKind::CronJob => 0,
+        };
+        workload[DESIRED_INSTANCES_FIELD] = serde_json::Value::from(desired);
+        true
+    })
+}
@@ -0,0 +1,293 @@
+//! State of the connection between the controller and the scheduler. The status
+//! updates stream is subscribed again with an exponential backoff when it breaks, and
+//! the scheduling requests sent meanwhile are queued, then sent in order once the
+//! stream is subscribed again.
+
+use proto::controller::WorkloadScheduling;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Notify;
+use tonic::Code;
+use tracing::error;
+
+/// Delay before the first reconnection attempt
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// Longest delay between two reconnection attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// Requests queued while the scheduler is disconnected, the oldest ones are dropped
+/// beyond it
+const MAX_QUEUED_REQUESTS: usize = 1024;
+
+static HEALTH: Mutex<SchedulerHealth> = Mutex::new(SchedulerHealth {
+    connected: false,
+    since: 0,
+    last_error: None,
+    reconnections: 0,
+    queued_requests: 0,
+});
+
+/// Connection to the scheduler, as reported by the health endpoint
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SchedulerHealth {
+    pub connected: bool,
+    /// Seconds since the UNIX epoch of the last connection or disconnection
+    pub since: i64,
+    /// Why the scheduler was last disconnected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// Connections since the controller started, but the first one
+    pub reconnections: u64,
+    /// Scheduling requests waiting for the scheduler
+    pub queued_requests: usize,
+}
+
+/// Get the state of the connection to the scheduler
+pub fn scheduler_health() -> SchedulerHealth {
+    HEALTH.lock().unwrap().clone()
+}
+
+fn update_health(update: impl FnOnce(&mut SchedulerHealth)) {
+    update(&mut HEALTH.lock().unwrap());
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Whether a request failed because the scheduler cannot be reached, rather than
+/// being answered with an error. Errors of the transport are the only ones with
+/// a source.
+pub fn is_disconnected(status: &tonic::Status) -> bool {
+    match status.code() {
+        Code::Unavailable => true,
+        Code::Unknown => std::error::Error::source(status).is_some(),
+        _ => false,
+    }
+}
+
+/// Exponential backoff between reconnection attempts
+#[derive(Debug)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Backoff {
+        Backoff {
+            initial,
+            max,
+            current: initial,
+        }
+    }
+
+    /// Delay before the next attempt, doubled at each attempt up to the maximum
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Backoff {
+        Backoff::new(INITIAL_BACKOFF, MAX_BACKOFF)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Outbox {
+    connected: bool,
+    /// Whether the scheduler was connected once already
+    reconnecting: bool,
+    requests: VecDeque<WorkloadScheduling>,
+}
+
+/// Shared between the clones of the client: scheduling requests are sent directly
+/// while the scheduler is connected, and queued otherwise
+#[derive(Debug, Default, Clone)]
+pub struct SchedulerConnection {
+    outbox: Arc<Mutex<Outbox>>,
+    /// Notified when a request finds the scheduler disconnected
+    lost: Arc<Notify>,
+}
+
+impl SchedulerConnection {
+    pub fn is_connected(&self) -> bool {
+        self.outbox.lock().unwrap().connected
+    }
+
+    /// Queue a request if the scheduler is disconnected, otherwise give it back to
+    /// be sent
+    pub fn queue_if_disconnected(&self, request: WorkloadScheduling) -> Option<WorkloadScheduling> {
+        let mut outbox = self.outbox.lock().unwrap();
+        if outbox.connected {
+            return Some(request);
+        }
+        Self::push(&mut outbox, request);
+        None
+    }
+
+    /// Queue a request the scheduler could not be reached for, requests are queued
+    /// from now on until the connection is established again
+    pub fn disconnected(&self, request: WorkloadScheduling, reason: &str) {
+        {
+            let mut outbox = self.outbox.lock().unwrap();
+            Self::push(&mut outbox, request);
+            Self::set_disconnected(&mut outbox, reason);
+        }
+        self.lost.notify_one();
+    }
+
+    /// Mark the scheduler disconnected, e.g. when the status updates stream broke
+    pub fn lost(&self, reason: &str) {
+        Self::set_disconnected(&mut self.outbox.lock().unwrap(), reason);
+    }
+
+    /// Wait for a request to find the scheduler disconnected
+    pub async fn wait_lost(&self) {
+        self.lost.notified().await
+    }
+
+    /// Forget the requests which found the scheduler disconnected before the status
+    /// updates stream was subscribed again, the notification is stored when nobody
+    /// waits for it and would break the new stream otherwise
+    pub async fn clear_lost(&self) {
+        tokio::select! {
+            biased;
+            _ = self.lost.notified() => {}
+            _ = std::future::ready(()) => {}
+        }
+    }
+
+    /// Take the next queued request to send once the scheduler is reachable again.
+    /// The connection is established once the queue is empty, so the requests sent
+    /// meanwhile stay behind the queued ones.
+    pub fn next_queued(&self) -> Option<WorkloadScheduling> {
+        let mut outbox = self.outbox.lock().unwrap();
+        let request = outbox.requests.pop_front();
+        let queued_requests = outbox.requests.len();
+        let connected = request.is_none() && !outbox.connected;
+        let reconnected = connected && outbox.reconnecting;
+        if connected {
+            outbox.connected = true;
+            outbox.reconnecting = true;
+        }
+        update_health(|health| {
+            health.queued_requests = queued_requests;
+            if connected {
+                if reconnected {
+                    health.reconnections += 1;
+                }
+                health.connected = true;
+                health.since = now();
+            }
+        });
+        request
+    }
+
+    /// Put back a queued request the scheduler could not be reached for
+    pub fn requeue(&self, request: WorkloadScheduling) {
+        let mut outbox = self.outbox.lock().unwrap();
+        outbox.requests.push_front(request);
+        let queued_requests = outbox.requests.len();
+        update_health(|health| health.queued_requests = queued_requests);
+    }
+
+    fn push(outbox: &mut Outbox, request: WorkloadScheduling) {
+        if outbox.requests.len() >= MAX_QUEUED_REQUESTS {
+            if let Some(dropped) = outbox.requests.pop_front() {
+                error!(
+                    "Too many requests queued for the scheduler, dropping the request on workload {}",
+                    dropped.workload_id
+                );
+            }
+        }
+        outbox.requests.push_back(request);
+        let queued_requests = outbox.requests.len();
+        update_health(|health| health.queued_requests = queued_requests);
+    }
+
+    fn set_disconnected(outbox: &mut Outbox, reason: &str) {
+        let was_connected = outbox.connected;
+        outbox.connected = false;
+        update_health(|health| {
+            if was_connected || health.since == 0 {
+                health.since = now();
+            }
+            health.connected = false;
+            health.last_error = Some(reason.to_string());
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(workload_id: &str) -> WorkloadScheduling {
+        WorkloadScheduling {
+            workload_id: workload_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+        let delays: Vec<u64> = (0..5).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 5, 5]);
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_requests_queued_while_disconnected() {
+        let connection = SchedulerConnection::default();
+        assert!(!connection.is_connected());
+        assert!(connection.queue_if_disconnected(request("a")).is_none());
+        assert!(connection.queue_if_disconnected(request("b")).is_none());
+
+        // Queued requests are sent in order before the connection is established
+        let first = connection.next_queued().unwrap();
+        connection.requeue(first);
+        assert_eq!(connection.next_queued().unwrap().workload_id, "a");
+        assert_eq!(connection.next_queued().unwrap().workload_id, "b");
+        assert!(!connection.is_connected());
+        assert!(connection.next_queued().is_none());
+        assert!(connection.is_connected());
+        assert!(connection.queue_if_disconnected(request("c")).is_some());
+
+        connection.disconnected(request("c"), "transport error");
+        assert!(!connection.is_connected());
+        assert!(connection.queue_if_disconnected(request("d")).is_none());
+        assert_eq!(connection.next_queued().unwrap().workload_id, "c");
+        assert_eq!(connection.next_queued().unwrap().workload_id, "d");
+    }
+
+    #[tokio::test]
+    async fn test_clear_lost() {
+        let connection = SchedulerConnection::default();
+        let wait = Duration::from_millis(50);
+        connection.disconnected(request("a"), "transport error");
+        connection.clear_lost().await;
+        assert!(tokio::time::timeout(wait, connection.wait_lost())
+            .await
+            .is_err());
+
+        connection.disconnected(request("b"), "transport error");
+        assert!(tokio::time::timeout(wait, connection.wait_lost())
+            .await
+            .is_ok());
+    }
+}
@@ -0,0 +1,274 @@
+use crate::api::external::services::tenant::{check_quota, get_workload_quota};
+use crate::api::external::services::workload::record_instances_request;
+use crate::api::internal::{encode_quota, RikControllerClient};
+use crate::api::types::element::Element;
+use crate::api::CRUD;
+use crate::database::{RikRepository, Storage};
+use crate::logger::new_request_id;
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use definition::workload::{ConcurrencyPolicy, WorkloadDefinition};
+use proto::controller::WorkloadScheduling;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+/// Interval between two checks of the cron jobs schedules
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Parse a cron expression. The standard format with 5 fields (minute, hour,
+/// day of month, month, day of week) is accepted, as well as the format
+/// with a leading seconds field.
+pub fn parse_schedule(expression: &str) -> Result<Schedule, cron::error::Error> {
+    let expression = match expression.split_whitespace().count() {
+        5 => format!("0 {}", expression),
+        _ => expression.to_string(),
+    };
+    Schedule::from_str(&expression)
+}
+
+/// Jobs created by a cron job are named after it, suffixed by their creation timestamp.
+/// Returns the timestamp if the job belongs to the cron job.
+fn job_timestamp(cron_job_name: &str, job: &Element) -> Option<i64> {
+    job.name
+        .rsplit('/')
+        .next()?
+        .strip_prefix(cron_job_name)?
+        .strip_prefix('-')?
+        .parse()
+        .ok()
+}
+
+/// A job is finished once the scheduler reported its outcome
+pub fn is_finished(job: &Element) -> bool {
+    matches!(job.value["status"].as_str(), Some("Succeeded" | "Failed"))
+}
+
+/// `CronJobController` creates the jobs of the `cronjob` workloads when their
+/// schedule is due, and removes the jobs exceeding the history limits.
+pub struct CronJobController {
+    storage: Arc<dyn Storage>,
+    client: RikControllerClient,
+    /// Last time each cron job schedule was checked, by cron job id
+    last_checks: HashMap<String, DateTime<Utc>>,
+}
+
+impl CronJobController {
+    pub fn new(storage: Arc<dyn Storage>, client: RikControllerClient) -> CronJobController {
+        CronJobController {
+            storage,
+            client,
+            last_checks: HashMap::new(),
+        }
+    }
+
+    pub async fn run(mut self) {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let storage = self.storage.clone();
+            let requests = self.process(storage.as_ref(), Utc::now());
+
+            for request in requests {
+                let workload_id = request.workload_id.clone();
+                match self.client.schedule_instance(request).await {
+                    Ok(None) => {}
+                    Ok(Some(failure)) => {
+                        RikControllerClient::set_scheduling_failure(
+                            self.storage.as_ref(),
+                            &failure,
+                        );
+                        error!(
+                            "Scheduler rejected job {}: {}",
+                            workload_id, failure.message
+                        );
+                    }
+                    Err(e) => error!("Cannot send job {} to scheduler: {}", workload_id, e),
+                }
+            }
+        }
+    }
+
+    /// Check every cron job, and return the scheduling requests to send
+    fn process(&mut self, storage: &dyn Storage, now: DateTime<Utc>) -> Vec<WorkloadScheduling> {
+        let cron_jobs = match RikRepository::find_all(storage, "/workload/cronjob") {
+            Ok(cron_jobs) => cron_jobs,
+            Err(_) => return Vec::new(),
+        };
+        let jobs = RikRepository::find_all(storage, "/workload/job").unwrap_or_default();
+
+        // Forget the cron jobs that were deleted
+        self.last_checks
+            .retain(|id, _| cron_jobs.iter().any(|cron_job| &cron_job.id == id));
+
+        let mut requests = Vec::new();
+        for cron_job in cron_jobs {
+            let definition: WorkloadDefinition = match serde_json::from_value(cron_job.value) {
+                Ok(definition) => definition,
+                Err(_) => continue,
+            };
+            let schedule = match definition
+                .cron_job
+                .as_ref()
+                .map(|spec| parse_schedule(&spec.schedule))
+            {
+                Some(Ok(schedule)) => schedule,
+                _ => continue,
+            };
+
+            // A cron job is never due when it is first seen, so a restart
+            // of the controller doesn't create jobs twice
+            let last_check = self.last_checks.insert(cron_job.id.clone(), now);
+            let is_due = last_check
+                .and_then(|last_check| schedule.after(&last_check).next())
+                .is_some_and(|next| next <= now);
+
+            let namespace_prefix = format!("/workload/job/{}/", definition.get_namespace());
+            let mut owned_jobs: Vec<(i64, &Element)> = jobs
+                .iter()
+                .filter(|job| job.name.starts_with(&namespace_prefix))
+                .filter_map(|job| job_timestamp(&definition.name, job).map(|ts| (ts, job)))
+                .collect();
+            owned_jobs.sort_by_key(|(timestamp, _)| -timestamp);
+
+            if is_due {
+                requests.append(&mut self.create_job(storage, &definition, &owned_jobs, now));
+            }
+            self.clean_history(storage, &definition, &owned_jobs);
+        }
+        requests
+    }
+
+    fn create_job(
+        &self,
+        storage: &dyn Storage,
+        cron_job: &WorkloadDefinition,
+        owned_jobs: &[(i64, &Element)],
+        now: DateTime<Utc>,
+    ) -> Vec<WorkloadScheduling> {
+        let mut requests = Vec::new();
+        let active_jobs: Vec<&Element> = owned_jobs
+            .iter()
+            .map(|(_, job)| *job)
+            .filter(|job| !is_finished(job))
+            .collect();
+        let spec = cron_job.cron_job.as_ref().unwrap();
+
+        match spec.get_concurrency_policy() {
+            ConcurrencyPolicy::Forbid if !active_jobs.is_empty() => {
+                warn!(
+                    "Skip job of cron job {}, previous job is still running",
+                    cron_job.name
+                );
+                return requests;
+            }
+            ConcurrencyPolicy::Replace => {
+                for job in active_jobs {
+                    requests.push(WorkloadScheduling {
+                        workload_id: job.id.clone(),
+                        definition: job.value.to_string(),
+                        action: CRUD::Delete as i32,
+                        quota: String::new(),
+                        correlation_id: new_request_id(),
+                        instances: 0,
+                    });
+                    let _ = RikRepository::delete(storage, &job.id);
+                }
+            }
+            _ => {}
+        }
+
+        let mut job = cron_job.clone();
+        job.kind = "job".to_string();
+        job.name = format!("{}-{}", cron_job.name, now.timestamp());
+        job.cron_job = None;
+        let definition = serde_json::to_string(&job).unwrap();
+
+        let job_spec = job.get_job_spec();
+        let instances = job_spec.get_parallelism().min(job_spec.get_completions());
+        if let Err(message) = check_quota(storage, &job, true, instances as u64) {
+            warn!("Skip job of cron job {}: {}", cron_job.name, message);
+            return requests;
+        }
+
+        let name = format!(
+            "/workload/{}/{}/{}",
+            job.kind,
+            job.get_namespace(),
+            job.name
+        );
+        match RikRepository::insert(storage, &name, &definition) {
+            Ok(job_id) => {
+                info!("Cron job {} created job {}", cron_job.name, job_id);
+                if let Err(e) = record_instances_request(storage, &job_id, &job) {
+                    error!("Cannot record instances of job {}: {}", job_id, e);
+                }
+                requests.push(WorkloadScheduling {
+                    workload_id: job_id,
+                    definition,
+                    action: CRUD::Create as i32,
+                    quota: encode_quota(&get_workload_quota(storage, &job)),
+                    correlation_id: new_request_id(),
+                    instances: 0,
+                });
+            }
+            Err(e) => error!("Cannot create job of cron job {}: {}", cron_job.name, e),
+        }
+        requests
+    }
+
+    /// Delete the oldest finished jobs exceeding the history limits of the cron job
+    fn clean_history(
+        &self,
+        storage: &dyn Storage,
+        cron_job: &WorkloadDefinition,
+        owned_jobs: &[(i64, &Element)],
+    ) {
+        let spec = cron_job.cron_job.as_ref().unwrap();
+        let mut succeeded_limit = spec.get_successful_jobs_history_limit() as usize;
+        let mut failed_limit = spec.get_failed_jobs_history_limit() as usize;
+
+        // Jobs are sorted from the newest to the oldest
+        for (_, job) in owned_jobs.iter().filter(|(_, job)| is_finished(job)) {
+            let limit = match job.value["status"].as_str() {
+                Some("Succeeded") => &mut succeeded_limit,
+                _ => &mut failed_limit,
+            };
+            if *limit > 0 {
+                *limit -= 1;
+            } else if RikRepository::delete(storage, &job.id).is_ok() {
+                debug!(
+                    "Removed job {} from cron job {} history",
+                    job.id, cron_job.name
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_schedule() {
+        assert!(parse_schedule("*/5 * * * *").is_ok());
+        assert!(parse_schedule("0 */5 * * * *").is_ok());
+        assert!(parse_schedule("every five minutes").is_err());
+    }
+
+    #[test]
+    fn test_job_timestamp() {
+        let job = Element {
+            id: "id".to_string(),
+            name: "/workload/job/default/backup-1666000000".to_string(),
+            value: serde_json::json!({}),
+            resource_version: 0,
+        };
+        assert_eq!(job_timestamp("backup", &job), Some(1666000000));
+        assert_eq!(job_timestamp("back", &job), None);
+        assert_eq!(job_timestamp("backup-1666000000", &job), None);
+    }
+}
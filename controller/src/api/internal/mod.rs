@@ -1,175 +1,535 @@
-use crate::api::types::instance::InstanceStatus;
+pub mod connection;
+pub mod cronjob;
+pub mod reconciler;
+
+use crate::api::external::services::event::{event_ttl, purge_expired_events, store_event};
+use crate::api::internal::connection::{is_disconnected, Backoff, SchedulerConnection};
+use crate::api::internal::cronjob::CronJobController;
+use crate::api::internal::reconciler::Reconciler;
+use crate::api::types::event::ClusterEvent;
+use crate::api::types::instance::{status_name, InstanceStatus};
 use crate::api::{ApiChannel, CRUD};
-use crate::database::RikDataBase;
-use crate::database::RikRepository;
-use crate::logger::{LogType, LoggingChannel};
+use crate::database::{RikRepository, Storage, StorageError};
+use crate::logger::new_request_id;
+use definition::quota::ResourceQuota;
+use definition::workload::{WorkloadDefinition, DEFAULT_NAMESPACE};
 use dotenv::dotenv;
 use proto::common::worker_status::Status;
+use proto::common::{ResourceStatus, SchedulingFailure, SchedulingFailureReason, WorkloadMetric};
 use proto::controller::controller_client::ControllerClient;
 use proto::controller::WorkloadScheduling;
-use rusqlite::Connection;
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 use uuid::Uuid;
 
+/// How often expired events are deleted
+const EVENT_PURGE_INTERVAL: Duration = Duration::from_secs(60);
+/// Changes of the cluster kept for the watches to resume, older ones are compacted
+/// along with the expired events
+const KEPT_CHANGES: i64 = 10_000;
+
+/// How long connecting to the scheduler may take
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[derive(Clone)]
-struct RikControllerClient {
-    client: ControllerClient<tonic::transport::Channel>,
+pub struct RikControllerClient {
+    client: ControllerClient<Channel>,
+    connection: SchedulerConnection,
 }
 
 #[allow(dead_code)]
 impl RikControllerClient {
-    pub async fn connect() -> Result<RikControllerClient, tonic::transport::Error> {
+    /// Create the client of the scheduler, which is connected once the status updates
+    /// are watched, see [`RikControllerClient::watch_status_updates`]. TLS is enabled
+    /// when `SCHEDULER_TLS_CA` is set, the client presents `SCHEDULER_TLS_CERT` and
+    /// `SCHEDULER_TLS_KEY` when the scheduler verifies clients.
+    pub fn connect() -> Result<RikControllerClient, Box<dyn std::error::Error>> {
         dotenv().ok();
         let scheduler_url = match std::env::var("SCHEDULER_URL") {
             Ok(val) => val,
             Err(_e) => "http://127.0.0.1:4996".to_string(),
         };
-        let client = ControllerClient::connect(scheduler_url).await?;
-        Ok(RikControllerClient { client })
+        let mut endpoint = Endpoint::from_shared(scheduler_url)?.connect_timeout(CONNECT_TIMEOUT);
+        if let Some(tls) = RikControllerClient::tls_config()? {
+            endpoint = endpoint.tls_config(tls)?;
+        }
+        Ok(RikControllerClient {
+            client: ControllerClient::new(endpoint.connect_lazy()),
+            connection: SchedulerConnection::default(),
+        })
+    }
+
+    fn tls_config() -> Result<Option<ClientTlsConfig>, std::io::Error> {
+        let ca = match std::env::var("SCHEDULER_TLS_CA") {
+            Ok(ca) => ca,
+            Err(_) => return Ok(None),
+        };
+        let mut config =
+            ClientTlsConfig::new().ca_certificate(Certificate::from_pem(std::fs::read(ca)?));
+        if let (Ok(cert), Ok(key)) = (
+            std::env::var("SCHEDULER_TLS_CERT"),
+            std::env::var("SCHEDULER_TLS_KEY"),
+        ) {
+            config = config.identity(Identity::from_pem(
+                std::fs::read(cert)?,
+                std::fs::read(key)?,
+            ));
+        }
+        if let Ok(domain) = std::env::var("SCHEDULER_TLS_DOMAIN") {
+            config = config.domain_name(domain);
+        }
+        Ok(Some(config))
     }
 
+    /// Whether the scheduler is connected, requests are queued otherwise
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_connected()
+    }
+
+    /// Send a scheduling request, returns why the scheduler rejected it if so.
+    /// The request is queued while the scheduler is disconnected, and sent once it
+    /// is connected again.
     pub async fn schedule_instance(
         &mut self,
         instance: WorkloadScheduling,
-    ) -> Result<(), tonic::Status> {
-        let request = tonic::Request::new(instance);
-        self.client.schedule_instance(request).await?;
-        Ok(())
+    ) -> Result<Option<SchedulingFailure>, tonic::Status> {
+        let instance = match self.connection.queue_if_disconnected(instance) {
+            Some(instance) => instance,
+            None => {
+                warn!("Scheduler is disconnected, request queued");
+                return Ok(None);
+            }
+        };
+        match self.send(instance.clone()).await {
+            Err(status) if is_disconnected(&status) => {
+                warn!(
+                    "Scheduler is unreachable, request on workload {} queued: {}",
+                    instance.workload_id, status
+                );
+                self.connection.disconnected(instance, status.message());
+                Ok(None)
+            }
+            result => result,
+        }
+    }
+
+    async fn send(
+        &mut self,
+        instance: WorkloadScheduling,
+    ) -> Result<Option<SchedulingFailure>, tonic::Status> {
+        let span = info_span!(
+            "grpc",
+            method = "ScheduleInstance",
+            workload_id = %instance.workload_id,
+            correlation_id = %instance.correlation_id,
+        );
+        let mut request = tonic::Request::new(instance);
+        span.in_scope(|| telemetry::inject_metadata(request.metadata_mut()));
+        let result = self
+            .client
+            .schedule_instance(request)
+            .instrument(span)
+            .await?;
+        Ok(result.into_inner().failure)
+    }
+
+    /// Subscribe to the status updates of the scheduler, and subscribe again with an
+    /// exponential backoff whenever the stream breaks. The requests queued while the
+    /// scheduler was disconnected are sent once subscribed.
+    pub async fn watch_status_updates(&mut self, storage: Arc<dyn Storage>) {
+        let mut backoff = Backoff::default();
+        loop {
+            let reason = match self
+                .get_status_updates(storage.as_ref(), &mut backoff)
+                .await
+            {
+                Ok(()) => String::from("status updates stream closed by the scheduler"),
+                Err(e) => e.message().to_string(),
+            };
+            self.connection.lost(&reason);
+            let delay = backoff.next_delay();
+            warn!(
+                "Scheduler disconnected: {}, reconnecting in {:?}",
+                reason, delay
+            );
+            tokio::time::sleep(delay).await;
+        }
     }
 
-    pub async fn get_status_updates(
+    async fn get_status_updates(
         &mut self,
-        database: Arc<RikDataBase>,
+        storage: &dyn Storage,
+        backoff: &mut Backoff,
     ) -> Result<(), tonic::Status> {
-        let connection: Connection = database.open().unwrap();
         let request = tonic::Request::new(());
         let mut stream = self.client.get_status_updates(request).await?.into_inner();
-        while let Some(status) = stream.message().await? {
-            println!("Received status update request {:?}", status);
+        info!("Subscribed to the status updates of the scheduler");
+        backoff.reset();
+        self.connection.clear_lost().await;
+        self.send_queued(storage).await?;
+
+        loop {
+            let status = tokio::select! {
+                status = stream.message() => status?,
+                _ = self.connection.wait_lost() => {
+                    return Err(tonic::Status::unavailable("scheduling request failed"));
+                }
+            };
+            let status = match status {
+                Some(status) => status,
+                None => return Ok(()),
+            };
+            debug!("Received status update request {:?}", status);
             if let Some(status) = status.status {
+                if let Status::Workload(workload_metric) = &status {
+                    RikControllerClient::set_workload_status(storage, workload_metric);
+                }
+                if let Status::SchedulingFailure(failure) = &status {
+                    RikControllerClient::set_scheduling_failure(storage, failure);
+                }
+                if let Status::Event(event) = status {
+                    if let Err(e) = store_event(storage, &ClusterEvent::from(event)) {
+                        error!("Cannot store event: {}", e);
+                    }
+                    continue;
+                }
                 let instance_id = match status.clone() {
                     Status::Instance(instance_metric) => Some(instance_metric.instance_id),
-                    Status::Worker(_worker_metric) => None,
+                    _ => None,
                 };
                 let instance_status = match status {
                     Status::Instance(instance_metric) => Some(instance_metric),
-                    Status::Worker(_worker_metric) => None,
+                    _ => None,
                 };
                 if let (Some(instance_id), Some(instance_status)) = (instance_id, instance_status) {
-                    let id: String;
-                    if let Ok(previous_instance) = RikRepository::check_duplicate_name(
-                        &connection,
-                        &format!("/instance/default/{}", instance_id),
-                    ) {
-                        id = previous_instance.id
-                    } else {
-                        id = Uuid::new_v4().to_string();
+                    let previous_instance = RikRepository::check_duplicate_name(
+                        storage,
+                        &format!("/instance/%/{}", instance_id),
+                    )
+                    .ok();
+                    // The workload and the worker of the instance are only reported when
+                    // it is scheduled
+                    let scheduled = |field: &str| {
+                        metrics_field(&instance_status.metrics, field).or_else(|| {
+                            previous_instance.as_ref().and_then(|previous| {
+                                previous.value[field].as_str().map(String::from)
+                            })
+                        })
+                    };
+                    let workload_id = scheduled("workload_id");
+                    let worker_id = scheduled("worker_id");
+                    // The workload is running again once one of its instances is placed
+                    if let (None, Some(workload_id)) = (&previous_instance, &workload_id) {
+                        RikControllerClient::clear_scheduling_failure(storage, workload_id);
+                    }
+                    let (id, name) = match previous_instance {
+                        Some(previous_instance) => (previous_instance.id, previous_instance.name),
+                        None => (
+                            Uuid::new_v4().to_string(),
+                            format!(
+                                "/instance/{}/{}",
+                                RikControllerClient::workload_namespace(storage, &workload_id),
+                                instance_id
+                            ),
+                        ),
+                    };
+                    let completed = matches!(
+                        ResourceStatus::from(instance_status.status),
+                        ResourceStatus::Succeeded | ResourceStatus::Failed
+                    );
+                    let exit_code = instance_status.exit_code;
+                    let mut instance_status = InstanceStatus::new(instance_status.status as usize);
+                    instance_status.workload_id = workload_id;
+                    instance_status.worker_id = worker_id;
+                    if completed {
+                        instance_status.exit_code = Some(exit_code);
                     }
-                    let instance_status = InstanceStatus::new(instance_status.status as usize);
 
-                    let name = format!("/instance/default/{}", instance_id);
                     let value = serde_json::to_string(&instance_status).unwrap();
-                    match RikRepository::upsert(&connection, &id, &name, &value, "/instance") {
+                    match RikRepository::upsert(storage, &id, &name, &value, "/instance") {
                         Ok(value) => value,
                         Err(e) => panic!("{:?}", e),
                     };
                 }
             }
         }
+    }
+
+    /// Send the requests queued while the scheduler was disconnected, in order
+    async fn send_queued(&mut self, storage: &dyn Storage) -> Result<(), tonic::Status> {
+        while let Some(request) = self.connection.next_queued() {
+            let workload_id = request.workload_id.clone();
+            match self.send(request.clone()).await {
+                Ok(None) => debug!("Sent queued request on workload {}", workload_id),
+                Ok(Some(failure)) => {
+                    RikControllerClient::set_scheduling_failure(storage, &failure);
+                    error!(
+                        "Scheduler rejected the queued request on workload {}: {}",
+                        workload_id, failure.message
+                    );
+                }
+                Err(status) if is_disconnected(&status) => {
+                    self.connection.requeue(request);
+                    return Err(status);
+                }
+                Err(e) => error!(
+                    "Cannot send the queued request on workload {} to scheduler: {}",
+                    workload_id, e
+                ),
+            }
+        }
         Ok(())
     }
+
+    /// Get the namespace of the workload of an instance
+    fn workload_namespace(storage: &dyn Storage, workload_id: &Option<String>) -> String {
+        workload_id
+            .as_ref()
+            .and_then(|id| RikRepository::find_one(storage, id, "/workload").ok())
+            .and_then(|workload| serde_json::from_value::<WorkloadDefinition>(workload.value).ok())
+            .map(|definition| definition.get_namespace().to_string())
+            .unwrap_or_else(|| DEFAULT_NAMESPACE.to_string())
+    }
+
+    /// Persist the status reported by the scheduler for a whole workload,
+    /// e.g. the outcome of a job
+    fn set_workload_status(storage: &dyn Storage, workload_metric: &WorkloadMetric) {
+        let id = &workload_metric.workload_id;
+        let status = status_name(workload_metric.status as usize);
+        match RikRepository::modify(storage, id, "/workload", |workload| {
+            workload["status"] = serde_json::Value::String(status.clone());
+            true
+        }) {
+            Ok(()) | Err(StorageError::NotFound) => {}
+            Err(e) => error!("Cannot update status of workload {}: {}", id, e),
+        }
+    }
+
+    /// Persist on the workload why the scheduler could not honor its scheduling request,
+    /// so users can see why it isn't running
+    fn set_scheduling_failure(storage: &dyn Storage, failure: &SchedulingFailure) {
+        let id = &failure.workload_id;
+        let reason = SchedulingFailureReason::from_i32(failure.reason)
+            .unwrap_or(SchedulingFailureReason::UnknownFailure);
+        match RikRepository::modify(storage, id, "/workload", |workload| {
+            workload["scheduling_failure"] = serde_json::json!({
+                "reason": reason.as_str_name(),
+                "message": failure.message,
+            });
+            true
+        }) {
+            Ok(()) | Err(StorageError::NotFound) => {}
+            Err(e) => error!("Cannot update scheduling failure of workload {}: {}", id, e),
+        }
+    }
+
+    fn clear_scheduling_failure(storage: &dyn Storage, workload_id: &str) {
+        match RikRepository::modify(storage, workload_id, "/workload", |workload| {
+            workload
+                .as_object_mut()
+                .is_some_and(|fields| fields.remove("scheduling_failure").is_some())
+        }) {
+            Ok(()) | Err(StorageError::NotFound) => {}
+            Err(e) => error!(
+                "Cannot clear scheduling failure of workload {}: {}",
+                workload_id, e
+            ),
+        }
+    }
+}
+
+/// Delete the expired events and the old changes of the cluster periodically
+async fn purge_events(storage: Arc<dyn Storage>) {
+    let ttl = event_ttl();
+    let mut interval = tokio::time::interval(EVENT_PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() as i64)
+            .unwrap_or_default();
+        let purged = purge_expired_events(storage.as_ref(), ttl, now);
+        match purged {
+            Ok(0) => {}
+            Ok(purged) => info!("Purged {} expired events", purged),
+            Err(e) => error!("Cannot purge expired events: {}", e),
+        }
+        if let Err(e) = storage.compact(KEPT_CHANGES) {
+            error!("Cannot compact changes: {}", e);
+        }
+    }
+}
+
+/// Encode a quota to be sent to the scheduler, an empty string means no quota
+pub fn encode_quota(quota: &Option<ResourceQuota>) -> String {
+    quota
+        .as_ref()
+        .map(|quota| serde_json::to_string(quota).unwrap())
+        .unwrap_or_default()
+}
+
+/// Extract a field from the metrics of an instance scheduled by the scheduler,
+/// formatted as `"workload_id": "<id>", "worker_id": "<id>"`
+fn metrics_field(metrics: &str, field: &str) -> Option<String> {
+    let metrics: serde_json::Value = serde_json::from_str(&format!("{{{}}}", metrics)).ok()?;
+    metrics[field].as_str().map(String::from)
 }
 
 #[allow(dead_code)]
 pub struct Server {
-    logger: Sender<LoggingChannel>,
     external_sender: Sender<ApiChannel>,
     internal_receiver: Receiver<ApiChannel>,
 }
 
 impl Server {
     pub fn new(
-        logger_sender: Sender<LoggingChannel>,
         external_sender: Sender<ApiChannel>,
         internal_receiver: Receiver<ApiChannel>,
     ) -> Server {
         Server {
-            logger: logger_sender,
             external_sender,
             internal_receiver,
         }
     }
 
-    pub async fn run(&self, database: Arc<RikDataBase>) {
-        let client: RikControllerClient = RikControllerClient::connect().await.unwrap();
+    pub async fn run(&self, storage: Arc<dyn Storage>) {
+        let client = match RikControllerClient::connect() {
+            Ok(client) => client,
+            Err(e) => {
+                error!("Invalid scheduler configuration: {}", e);
+                std::process::exit(1);
+            }
+        };
 
         let mut client_clone = client.clone();
-        let database = database.clone();
-
+        let status_storage = storage.clone();
         tokio::spawn(async move {
-            client_clone.get_status_updates(database).await.unwrap();
+            client_clone
+                .watch_status_updates(status_storage)
+                .instrument(info_span!("grpc", method = "GetStatusUpdates"))
+                .await
         });
 
-        self.listen_notification(client).await;
+        tokio::spawn(purge_events(storage.clone()));
+
+        let cron_jobs = CronJobController::new(storage.clone(), client.clone());
+        tokio::spawn(cron_jobs.run());
+
+        let reconciler = Reconciler::new(storage.clone(), client.clone());
+        tokio::spawn(reconciler.run());
+
+        self.listen_notification(client, storage).await;
     }
 
-    async fn listen_notification(&self, mut client: RikControllerClient) {
+    async fn listen_notification(
+        &self,
+        mut client: RikControllerClient,
+        storage: Arc<dyn Storage>,
+    ) {
         for notification in &self.internal_receiver {
-            match notification.action {
-                CRUD::Create => {
-                    // Create instance
-                    // Send workload to sheduler
-                    self.logger
-                        .send(LoggingChannel {
-                            message: format!(
-                                "Ctrl to scheduler schedule instance workload_id : {:?}",
-                                notification.workload_id
-                            ),
-                            log_type: LogType::Log,
-                        })
-                        .unwrap();
-                    if let Some(workload_id) = notification.workload_id {
-                        if let Some(workload_definition) = notification.workload_definition {
-                            client
-                                .schedule_instance(WorkloadScheduling {
-                                    workload_id,
-                                    definition: serde_json::to_string(&workload_definition)
-                                        .unwrap(),
-                                    action: CRUD::Create as i32,
-                                })
-                                .await
-                                .unwrap();
+            // Requests which don't come from the REST API get their own correlation id
+            let correlation_id = notification
+                .correlation_id
+                .clone()
+                .unwrap_or_else(new_request_id);
+            let span = info_span!(
+                "notification",
+                action = ?notification.action,
+                workload_id = ?notification.workload_id,
+                %correlation_id,
+            );
+            telemetry::set_parent(&span, &notification.trace_context);
+            async {
+                match notification.action {
+                    CRUD::Create => {
+                        // Create instance
+                        // Send workload to sheduler
+                        info!(
+                            %correlation_id,
+                            "Ctrl to scheduler schedule instance workload_id : {:?}",
+                            notification.workload_id
+                        );
+                        if let Some(workload_id) = notification.workload_id {
+                            if let Some(workload_definition) = notification.workload_definition {
+                                let result = client
+                                    .schedule_instance(WorkloadScheduling {
+                                        workload_id: workload_id.clone(),
+                                        definition: serde_json::to_string(&workload_definition)
+                                            .unwrap(),
+                                        action: CRUD::Create as i32,
+                                        quota: encode_quota(&notification.quota),
+                                        correlation_id: correlation_id.clone(),
+                                        instances: 0,
+                                    })
+                                    .await;
+                                self.handle_scheduling_result(
+                                    storage.as_ref(),
+                                    &workload_id,
+                                    result,
+                                );
+                            }
                         }
                     }
-                }
-                CRUD::Delete => {
-                    // Delete instance
-                    // Send instruction to sheduler
-                    self.logger
-                        .send(LoggingChannel {
-                            message: format!(
-                                "Ctrl to scheduler delete workload: {:?}",
-                                notification.workload_id
-                            ),
-                            log_type: LogType::Log,
-                        })
-                        .unwrap();
-                    if let Some(workload_id) = notification.workload_id {
-                        if let Some(workload_definition) = notification.workload_definition {
-                            client
-                                .schedule_instance(WorkloadScheduling {
-                                    workload_id,
-                                    definition: serde_json::to_string(&workload_definition)
-                                        .unwrap(),
-                                    action: CRUD::Delete as i32,
-                                })
-                                .await
-                                .unwrap();
+                    CRUD::Delete => {
+                        // Delete instance
+                        // Send instruction to sheduler
+                        info!(
+                            %correlation_id,
+                            "Ctrl to scheduler delete workload: {:?}",
+                            notification.workload_id
+                        );
+                        if let Some(workload_id) = notification.workload_id {
+                            if let Some(workload_definition) = notification.workload_definition {
+                                let result = client
+                                    .schedule_instance(WorkloadScheduling {
+                                        workload_id: workload_id.clone(),
+                                        definition: serde_json::to_string(&workload_definition)
+                                            .unwrap(),
+                                        action: CRUD::Delete as i32,
+                                        quota: encode_quota(&notification.quota),
+                                        correlation_id: correlation_id.clone(),
+                                        instances: 0,
+                                    })
+                                    .await;
+                                self.handle_scheduling_result(
+                                    storage.as_ref(),
+                                    &workload_id,
+                                    result,
+                                );
+                            }
                         }
                     }
                 }
             }
+            .instrument(span)
+            .await;
+        }
+    }
+
+    /// Persist why the scheduler rejected a request on a workload, so users can see
+    /// why it isn't running
+    fn handle_scheduling_result(
+        &self,
+        storage: &dyn Storage,
+        workload_id: &str,
+        result: Result<Option<SchedulingFailure>, tonic::Status>,
+    ) {
+        match result {
+            Ok(None) => {}
+            Ok(Some(failure)) => {
+                RikControllerClient::set_scheduling_failure(storage, &failure);
+                error!(
+                    "Scheduler rejected the request on workload {}: {}",
+                    workload_id, failure.message
+                );
+            }
+            Err(e) => error!(
+                "Cannot send the request on workload {} to scheduler: {}",
+                workload_id, e
+            ),
         }
     }
 }
@@ -0,0 +1,389 @@
+use crate::api::external::services::event::store_event;
+use crate::api::external::services::tenant::get_workload_quota;
+use crate::api::external::services::workload::{DESIRED_INSTANCES_FIELD, DRIFT_FIELD};
+use crate::api::internal::cronjob::is_finished;
+use crate::api::internal::{encode_quota, RikControllerClient};
+use crate::api::types::element::Element;
+use crate::api::types::event::ClusterEvent;
+use crate::api::types::instance::InstanceStatus;
+use crate::database::{RikRepository, Storage, StorageError};
+use crate::logger::new_request_id;
+use definition::workload::{WorkloadDefinition, WorkloadKind};
+use dotenv::dotenv;
+use proto::common::WorkloadRequestKind;
+use proto::controller::WorkloadScheduling;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, warn};
+
+/// Workloads are reconciled every 30 seconds unless `RECONCILE_INTERVAL` says otherwise
+const DEFAULT_RECONCILE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Get the interval between two reconciliations, from `RECONCILE_INTERVAL` in seconds
+pub fn reconcile_interval() -> Duration {
+    dotenv().ok();
+    std::env::var("RECONCILE_INTERVAL")
+        .ok()
+        .and_then(|interval| interval.parse().ok())
+        .filter(|interval| *interval > 0)
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_RECONCILE_INTERVAL)
+}
+
+/// Difference between the instances a workload should have and its active ones,
+/// stored in the value of the workload
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Drift {
+    pub desired: u64,
+    pub observed: u64,
+    /// Seconds since the UNIX epoch
+    pub since: i64,
+}
+
+/// `Reconciler` periodically compares the desired workloads of the storage with the
+/// instances reported by the scheduler, and sends the scheduler the requests aligning
+/// them. Reconcile requests are idempotent, so they are sent for every scheduled
+/// workload: a workload the scheduler lost, e.g. on a restart, is created again and the
+/// replicas of a pod workload are set back to its desired instances. Active instances
+/// of a deleted workload are destroyed.
+pub struct Reconciler {
+    storage: Arc<dyn Storage>,
+    client: RikControllerClient,
+    /// Workloads out of sync at the previous reconciliation. Instances take a while
+    /// to be placed, a workload drifts when it is out of sync twice in a row.
+    out_of_sync: HashSet<String>,
+}
+
+impl Reconciler {
+    pub fn new(storage: Arc<dyn Storage>, client: RikControllerClient) -> Reconciler {
+        Reconciler {
+            storage,
+            client,
+            out_of_sync: HashSet::new(),
+        }
+    }
+
+    pub async fn run(mut self) {
+        let mut interval = tokio::time::interval(reconcile_interval());
+        loop {
+            interval.tick().await;
+            // The instances are only known once the scheduler reports them, and every
+            // workload is reconciled again after a reconnection anyway
+            if !self.client.is_connected() {
+                debug!("Scheduler is disconnected, reconciliation skipped");
+                continue;
+            }
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs() as i64)
+                .unwrap_or_default();
+            let requests = reconcile(self.storage.as_ref(), &mut self.out_of_sync, now);
+
+            for request in requests {
+                let workload_id = request.workload_id.clone();
+                match self.client.schedule_instance(request).await {
+                    Ok(None) => {}
+                    Ok(Some(failure)) => {
+                        RikControllerClient::set_scheduling_failure(
+                            self.storage.as_ref(),
+                            &failure,
+                        );
+                        warn!(
+                            "Scheduler rejected the reconciliation of workload {}: {}",
+                            workload_id, failure.message
+                        );
+                    }
+                    Err(e) => error!(
+                        "Cannot send the reconciliation of workload {} to scheduler: {}",
+                        workload_id, e
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// Compare every workload with its active instances, mark the drifts, and return
+/// the reconcile requests to send. `out_of_sync` holds the workloads out of sync at
+/// the previous reconciliation, it is replaced by the ones out of sync now
+fn reconcile(
+    storage: &dyn Storage,
+    out_of_sync: &mut HashSet<String>,
+    now: i64,
+) -> Vec<WorkloadScheduling> {
+    let workloads = match RikRepository::find_all(storage, "/workload/") {
+        Ok(workloads) => workloads,
+        Err(e) => {
+            error!("Cannot reconcile workloads: {}", e);
+            return Vec::new();
+        }
+    };
+    let mut observed = active_instances(storage);
+
+    let mut still_out_of_sync = HashSet::new();
+    let mut requests = Vec::new();
+    for mut workload in workloads {
+        let active = observed.remove(&workload.id).unwrap_or_default();
+        let definition: WorkloadDefinition = match serde_json::from_value(workload.value.clone()) {
+            Ok(definition) => definition,
+            Err(_) => continue,
+        };
+        let desired = match workload.value[DESIRED_INSTANCES_FIELD].as_u64() {
+            Some(desired) => desired,
+            // Instances were never requested, unless the workload was scheduled
+            // before its desired instances were recorded
+            None if active > 0 => {
+                adopt(storage, &mut workload, active);
+                active
+            }
+            None => continue,
+        };
+        if definition.get_kind() == WorkloadKind::CronJob || is_finished(&workload) {
+            continue;
+        }
+
+        let in_sync = match definition.get_kind() {
+            WorkloadKind::Pod => active == desired,
+            // The instances of a job depend on its progress, and the ones of a daemon
+            // set on the workers, they are only expected to exist
+            _ => active > 0,
+        };
+        if in_sync {
+            clear_drift(storage, &mut workload, now);
+        } else {
+            if out_of_sync.contains(&workload.id) {
+                mark_drift(storage, &mut workload, desired, active, now);
+            }
+            still_out_of_sync.insert(workload.id.clone());
+        }
+
+        requests.push(WorkloadScheduling {
+            workload_id: workload.id.clone(),
+            definition: serde_json::to_string(&definition).unwrap(),
+            action: WorkloadRequestKind::Reconcile as i32,
+            quota: encode_quota(&get_workload_quota(storage, &definition)),
+            correlation_id: new_request_id(),
+            instances: desired.min(u32::MAX as u64) as u32,
+        });
+    }
+    *out_of_sync = still_out_of_sync;
+
+    // The remaining instances belong to workloads which were deleted
+    for (workload_id, active) in observed {
+        warn!(
+            "{} instances of deleted workload {} are still active, destroying them",
+            active, workload_id
+        );
+        requests.push(WorkloadScheduling {
+            // The definition of a deleted workload is not known anymore, the
+            // scheduler doesn't need it to destroy a workload it knows
+            definition: serde_json::json!({
+                "api_version": "v0",
+                "kind": "pod",
+                "name": workload_id,
+                "spec": {"containers": []},
+            })
+            .to_string(),
+            workload_id,
+            action: WorkloadRequestKind::Destroy as i32,
+            quota: String::new(),
+            correlation_id: new_request_id(),
+            instances: 0,
+        });
+    }
+    requests
+}
+
+fn mark_drift(
+    storage: &dyn Storage,
+    workload: &mut Element,
+    desired: u64,
+    observed: u64,
+    now: i64,
+) {
+    let previous: Option<Drift> = serde_json::from_value(workload.value[DRIFT_FIELD].clone())
+        .ok()
+        .flatten();
+    let drift = Drift {
+        desired,
+        observed,
+        since: previous.map(|drift| drift.since).unwrap_or(now),
+    };
+    if previous == Some(drift) {
+        return;
+    }
+    if previous.is_none() {
+        warn!(
+            "Workload {} drifted, {} instances desired and {} active",
+            workload.id, desired, observed
+        );
+        emit(
+            storage,
+            &workload.id,
+            "DriftDetected",
+            format!("{} instances desired, {} active", desired, observed),
+            now,
+        );
+    }
+    let mut value = workload.value.clone();
+    value[DRIFT_FIELD] = serde_json::to_value(drift).unwrap();
+    update(storage, workload, value);
+}
+
+fn clear_drift(storage: &dyn Storage, workload: &mut Element, now: i64) {
+    let mut value = workload.value.clone();
+    let removed = value
+        .as_object_mut()
+        .and_then(|fields| fields.remove(DRIFT_FIELD));
+    if removed.is_some() {
+        info!("Workload {} is back in sync", workload.id);
+        emit(
+            storage,
+            &workload.id,
+            "DriftResolved",
+            String::from("Active instances match the desired ones"),
+            now,
+        );
+        update(storage, workload, value);
+    }
+}
+
+/// Count the active instances of each workload
+fn active_instances(storage: &dyn Storage) -> HashMap<String, u64> {
+    let mut active: HashMap<String, u64> = HashMap::new();
+    for instance in RikRepository::find_all(storage, "/instance/").unwrap_or_default() {
+        let status: InstanceStatus = match serde_json::from_value(instance.value) {
+            Ok(status) => status,
+            Err(_) => continue,
+        };
+        if let (true, Some(workload_id)) = (status.is_active(), status.workload_id) {
+            *active.entry(workload_id).or_default() += 1;
+        }
+    }
+    active
+}
+
+/// Take the active instances of a workload scheduled before its desired instances
+/// were recorded as its desired instances
+fn adopt(storage: &dyn Storage, workload: &mut Element, active: u64) {
+    debug!(
+        "Workload {} has {} active instances, recording them as desired",
+        workload.id, active
+    );
+    let mut value = workload.value.clone();
+    value[DESIRED_INSTANCES_FIELD] = serde_json::Value::from(active);
+    update(storage, workload, value);
+}
+
+/// Write the new value of a workload, unless it was written since it was read, in
+/// which case the next pass works from its new value
+fn update(storage: &dyn Storage, workload: &mut Element, value: serde_json::Value) {
+    match RikRepository::update(
+        storage,
+        &workload.id,
+        &value.to_string(),
+        Some(workload.resource_version),
+    ) {
+        Ok(version) => {
+            workload.value = value;
+            workload.resource_version = version;
+        }
+        Err(StorageError::Conflict { .. }) => {
+            debug!("Workload {} changed meanwhile, skipping", workload.id)
+        }
+        Err(e) => error!("Cannot update status of workload {}: {}", workload.id, e),
+    }
+}
+
+fn emit(storage: &dyn Storage, workload_id: &str, reason: &str, message: String, now: i64) {
+    let event = ClusterEvent {
+        object_kind: String::from("workload"),
+        object_id: workload_id.to_string(),
+        reason: reason.to_string(),
+        message,
+        event_type: String::from(match reason {
+            "DriftDetected" => "Warning",
+            _ => "Normal",
+        }),
+        source: String::from("controller"),
+        timestamp: now,
+    };
+    if let Err(e) = store_event(storage, &event) {
+        error!("Cannot store event: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::external::services::event::find_events;
+    use crate::database::memory::MemoryStorage;
+    use serde_json::json;
+
+    fn instance(storage: &dyn Storage, name: &str, workload_id: &str, status: &str) {
+        let value = json!({"workload_id": workload_id, "status": status}).to_string();
+        RikRepository::insert(storage, &format!("/instance/default/{}", name), &value).unwrap();
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let storage: &dyn Storage = &MemoryStorage::default();
+
+        let definition = json!({
+            "api_version": "v0",
+            "kind": "pod",
+            "name": "web",
+            "replicas": 2,
+            "spec": {"containers": [{"name": "nginx", "image": "nginx:latest"}]},
+            DESIRED_INSTANCES_FIELD: 2,
+        });
+        let web = RikRepository::insert(
+            storage,
+            "/workload/pod/default/web",
+            &definition.to_string(),
+        )
+        .unwrap();
+        instance(storage, "web-1", &web, "Running");
+        instance(storage, "web-2", &web, "Terminated");
+        instance(storage, "orphan-1", "deleted", "Running");
+
+        let mut out_of_sync = HashSet::new();
+        let requests = reconcile(storage, &mut out_of_sync, 10);
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].workload_id, web);
+        assert_eq!(requests[0].action, WorkloadRequestKind::Reconcile as i32);
+        assert_eq!(requests[0].instances, 2);
+        assert_eq!(requests[1].workload_id, "deleted");
+        assert_eq!(requests[1].action, WorkloadRequestKind::Destroy as i32);
+        // Instances may still be placed, the workload doesn't drift yet
+        let workload = RikRepository::find_one(storage, &web, "/workload").unwrap();
+        assert!(workload.value.get(DRIFT_FIELD).is_none());
+
+        reconcile(storage, &mut out_of_sync, 20);
+        let workload = RikRepository::find_one(storage, &web, "/workload").unwrap();
+        let drift: Drift = serde_json::from_value(workload.value[DRIFT_FIELD].clone()).unwrap();
+        assert_eq!(
+            drift,
+            Drift {
+                desired: 2,
+                observed: 1,
+                since: 20
+            }
+        );
+
+        instance(storage, "web-3", &web, "Running");
+        reconcile(storage, &mut out_of_sync, 30);
+        let workload = RikRepository::find_one(storage, &web, "/workload").unwrap();
+        assert!(workload.value.get(DRIFT_FIELD).is_none());
+        assert!(out_of_sync.is_empty());
+
+        let reasons: Vec<String> = find_events(storage, Some("workload"), Some(&web))
+            .unwrap()
+            .iter()
+            .map(|event| event.value["reason"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(reasons, vec!["DriftDetected", "DriftResolved"]);
+    }
+}
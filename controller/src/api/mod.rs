@@ -2,9 +2,13 @@ pub mod external;
 pub mod internal;
 pub mod types;
 
+use crate::api::types::error::ApiError;
+use crate::database::StorageError;
+use definition::quota::ResourceQuota;
 use definition::workload::WorkloadDefinition;
 use std::fmt::{Display, Formatter, Result};
-#[allow(dead_code)]
+use telemetry::TraceContext;
+#[allow(dead_code, clippy::upper_case_acronyms)]
 #[derive(Debug)]
 pub enum CRUD {
     Create = 0,
@@ -15,12 +19,29 @@ pub enum CRUD {
 pub enum RikError {
     IoError(std::io::Error),
     HttpRequestError(serde_json::Error),
+    DatabaseError(StorageError),
+    /// Request refused, answered with the status of the error
+    Api(ApiError),
+}
+impl RikError {
+    /// HTTP status code answering the error
+    pub fn status(&self) -> u16 {
+        match *self {
+            RikError::Api(ref e) => e.status,
+            RikError::DatabaseError(StorageError::NotFound) => 404,
+            RikError::DatabaseError(StorageError::Conflict { .. }) => 409,
+            RikError::DatabaseError(_) => 500,
+            _ => 400,
+        }
+    }
 }
 impl Display for RikError {
     fn fmt(&self, f: &mut Formatter) -> Result {
         match *self {
             RikError::IoError(ref e) => e.fmt(f),
             RikError::HttpRequestError(ref e) => e.fmt(f),
+            RikError::DatabaseError(ref e) => e.fmt(f),
+            RikError::Api(ref e) => write!(f, "{}", e.message),
         }
     }
 }
@@ -30,6 +51,8 @@ impl std::error::Error for RikError {
         match *self {
             RikError::IoError(ref e) => Some(e),
             RikError::HttpRequestError(ref e) => Some(e),
+            RikError::DatabaseError(ref e) => Some(e),
+            RikError::Api(_) => None,
         }
     }
 }
@@ -46,18 +69,36 @@ impl From<serde_json::Error> for RikError {
     }
 }
 
+impl From<StorageError> for RikError {
+    fn from(e: StorageError) -> RikError {
+        RikError::DatabaseError(e)
+    }
+}
+
+impl From<ApiError> for RikError {
+    fn from(e: ApiError) -> RikError {
+        RikError::Api(e)
+    }
+}
+
 pub struct ApiChannel {
     action: CRUD,
     workload_id: Option<String>,
     instance_id: Option<String>,
     workload_definition: Option<WorkloadDefinition>,
+    /// Resource quota of the workload tenant, checked again by the scheduler
+    quota: Option<ResourceQuota>,
+    /// Id of the request at the origin of the action, sent along to the scheduler
+    correlation_id: Option<String>,
+    /// Trace context of the request at the origin of the action
+    trace_context: TraceContext,
 }
 impl Display for ApiChannel {
     fn fmt(&self, f: &mut Formatter) -> Result {
         write!(
             f,
-            "Action: {:?}, Workload id: {:?}, Instance id: {:?}",
-            self.action, self.workload_id, self.instance_id
+            "Action: {:?}, Workload id: {:?}, Instance id: {:?}, Correlation id: {:?}",
+            self.action, self.workload_id, self.instance_id, self.correlation_id
         )
     }
 }
@@ -11,6 +11,10 @@ pub struct Element {
     pub id: String,
     pub name: String,
     pub value: serde_json::Value,
+    /// Version of the storage when the element was last written, writes can
+    /// expect the element to still be at this version
+    #[serde(default)]
+    pub resource_version: i64,
 }
 
 #[allow(dead_code)]
@@ -20,9 +24,15 @@ impl Element {
             id,
             name,
             value: serde_json::from_str(&value).unwrap(),
+            resource_version: 0,
         }
     }
 
+    pub fn with_version(mut self, resource_version: i64) -> Element {
+        self.resource_version = resource_version;
+        self
+    }
+
     pub fn set_name(&mut self, name: String) {
         self.name = name;
     }
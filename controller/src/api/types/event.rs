@@ -0,0 +1,35 @@
+use proto::common::{Event, EventType};
+use serde::{Deserialize, Serialize};
+
+/// Lifecycle event of an object of the cluster, reported by the scheduler or a worker
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClusterEvent {
+    pub object_kind: String,
+    pub object_id: String,
+    pub reason: String,
+    pub message: String,
+    /// `Normal` or `Warning`
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub source: String,
+    /// Seconds since the UNIX epoch
+    pub timestamp: i64,
+}
+
+impl From<Event> for ClusterEvent {
+    fn from(event: Event) -> ClusterEvent {
+        let event_type = match EventType::from_i32(event.event_type) {
+            Some(EventType::Warning) => "Warning",
+            _ => "Normal",
+        };
+        ClusterEvent {
+            object_kind: event.object_kind,
+            object_id: event.object_id,
+            reason: event.reason,
+            message: event.message,
+            event_type: event_type.to_string(),
+            source: event.source,
+            timestamp: event.timestamp,
+        }
+    }
+}
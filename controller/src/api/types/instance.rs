@@ -25,6 +25,7 @@ impl InstanceDefinition {
     }
 }
 
+#[allow(dead_code)]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Instance {
     pub id: usize,
@@ -35,25 +36,47 @@ pub struct Instance {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct InstanceStatus {
-    // pub workload_id: String,
+    /// Workload of the instance, reported by the scheduler when the instance is scheduled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workload_id: Option<String>,
+    /// Worker running the instance, reported with its workload
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker_id: Option<String>,
     pub status: String,
+    /// Exit code of the instance once it completed, only reported for jobs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
 }
 impl InstanceStatus {
     pub fn new(status: usize) -> InstanceStatus {
-        let str_status = match status {
-            0 => "Unknown".to_string(),
-            1 => "Pending".to_string(),
-            2 => "Running".to_string(),
-            3 => "Failed".to_string(),
-            4 => "Terminated".to_string(),
-            5 => "Creating".to_string(),
-            6 => "Destroying".to_string(),
-            _ => "Creating".to_string(),
-        };
-
         InstanceStatus {
-            // workload_id: workload_id,
-            status: str_status,
+            workload_id: None,
+            worker_id: None,
+            status: status_name(status),
+            exit_code: None,
         }
     }
+
+    /// An active instance uses resources of the cluster
+    pub fn is_active(&self) -> bool {
+        !matches!(
+            self.status.as_str(),
+            "Terminated" | "Destroying" | "Succeeded" | "Failed"
+        )
+    }
+}
+
+/// Get the displayable name of a `common.ResourceStatus`
+pub fn status_name(status: usize) -> String {
+    match status {
+        0 => "Unknown".to_string(),
+        1 => "Pending".to_string(),
+        2 => "Running".to_string(),
+        3 => "Failed".to_string(),
+        4 => "Terminated".to_string(),
+        5 => "Creating".to_string(),
+        6 => "Destroying".to_string(),
+        7 => "Succeeded".to_string(),
+        _ => "Creating".to_string(),
+    }
 }
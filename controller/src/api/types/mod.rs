@@ -1,3 +1,8 @@
 pub mod element;
+pub mod error;
+pub mod event;
 pub mod instance;
+pub mod namespace;
+pub mod service_account;
 pub mod tenant;
+pub mod watch;
@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+/// Service account, authenticating with a token issued by the controller, stored
+/// as its value
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ServiceAccount {
+    pub name: String,
+    /// SHA-256 of its token, the token itself is only given when the account is created
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub token_hash: String,
+    /// Seconds since the UNIX epoch
+    pub created_at: i64,
+}
+
+/// Body of a request creating a service account
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ServiceAccountDefinition {
+    pub name: String,
+}
@@ -1,3 +1,4 @@
+use definition::quota::ResourceQuota;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
@@ -13,3 +14,57 @@ impl fmt::Display for Tenant {
         write!(f, "Id: {}, Name: {}", self.id, self.name)
     }
 }
+
+/// Verbs a role can allow, `*` allows all of them
+pub const ROLE_VERBS: [&str; 6] = ["get", "list", "watch", "create", "update", "delete"];
+
+/// Kinds of resources a role can give access to, `*` gives access to all of them.
+/// Only the resources living in a namespace belong to a tenant, the other ones are
+/// left to the administrators.
+pub const ROLE_RESOURCES: [&str; 2] = ["workloads", "instances"];
+
+/// Settings of a tenant, stored as its value
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TenantSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<ResourceQuota>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<Role>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bindings: Vec<RoleBinding>,
+}
+
+/// Permissions of a role: the verbs it allows on kinds of resources, in namespaces.
+/// A role only applies in the namespaces owned by its tenant, `*` matches all of them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Role {
+    pub name: String,
+    pub verbs: Vec<String>,
+    pub resources: Vec<String>,
+    pub namespaces: Vec<String>,
+}
+
+/// Users and service accounts given a role of the tenant
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RoleBinding {
+    pub role: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub service_accounts: Vec<String>,
+}
+
+/// Body of a request setting the quota of a tenant, no quota means unlimited
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TenantQuota {
+    pub id: String,
+    pub quota: Option<ResourceQuota>,
+}
+
+/// Body of a request creating a tenant, with its settings
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TenantDefinition {
+    pub name: String,
+    #[serde(flatten)]
+    pub spec: TenantSpec,
+}
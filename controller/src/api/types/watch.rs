@@ -0,0 +1,32 @@
+use crate::api::types::element::Element;
+use serde::Serialize;
+
+/// Kind of change of a watched resource
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum WatchEventType {
+    Added,
+    Modified,
+    Deleted,
+}
+
+impl WatchEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WatchEventType::Added => "ADDED",
+            WatchEventType::Modified => "MODIFIED",
+            WatchEventType::Deleted => "DELETED",
+        }
+    }
+}
+
+/// Change of a resource, streamed to the clients watching it
+#[derive(Serialize, Debug, Clone)]
+pub struct WatchEvent {
+    #[serde(rename = "type")]
+    pub event_type: WatchEventType,
+    /// Version of the cluster after the change, a watch resumes after it
+    pub resource_version: i64,
+    /// The resource after the change, or before its deletion
+    pub object: Element,
+}
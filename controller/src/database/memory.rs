@@ -0,0 +1,165 @@
+//! In-memory storage, for the tests
+
+use super::{
+    check_version, matches_prefix, ListOptions, Page, PageCursor, Result, SortBy, Storage,
+    StorageError,
+};
+use crate::api::types::element::Element;
+use crate::api::types::watch::{WatchEvent, WatchEventType};
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+#[derive(Default)]
+struct State {
+    /// Version of the last change
+    version: i64,
+    /// Creation order of the last element
+    position: i64,
+    /// Elements by id, with their creation order
+    elements: HashMap<String, (i64, Element)>,
+    changes: VecDeque<WatchEvent>,
+}
+
+#[derive(Default)]
+pub struct MemoryStorage {
+    state: Mutex<State>,
+}
+
+impl State {
+    fn record_change(&mut self, event_type: WatchEventType, element: &Element) -> i64 {
+        self.version += 1;
+        self.changes.push_back(WatchEvent {
+            event_type,
+            resource_version: self.version,
+            object: element.clone().with_version(self.version),
+        });
+        self.version
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, id: &str) -> Result<Element> {
+        let state = self.state.lock().unwrap();
+        state
+            .elements
+            .get(id)
+            .map(|(_, element)| element.clone())
+            .ok_or(StorageError::NotFound)
+    }
+
+    fn list(&self, prefix: &str, options: &ListOptions) -> Result<Page> {
+        let state = self.state.lock().unwrap();
+        let mut rows: Vec<&(i64, Element)> = state
+            .elements
+            .values()
+            .filter(|(_, element)| matches_prefix(&element.name, prefix))
+            .filter(|(_, element)| {
+                options
+                    .status
+                    .as_ref()
+                    .is_none_or(|status| element.value["status"] == status.as_str())
+            })
+            .filter(|(_, element)| {
+                options
+                    .workload_id
+                    .as_ref()
+                    .is_none_or(|workload_id| element.value["workload_id"] == workload_id.as_str())
+            })
+            .filter(|(position, element)| match (&options.after, options.sort) {
+                (None, _) => true,
+                (Some(after), SortBy::CreationTime) => *position > after.rowid,
+                (Some(after), SortBy::Name) => {
+                    (element.name.as_str(), *position) > (after.name.as_str(), after.rowid)
+                }
+            })
+            .collect();
+        match options.sort {
+            SortBy::CreationTime => rows.sort_by_key(|(position, _)| *position),
+            SortBy::Name => rows.sort_by(|(a_position, a), (b_position, b)| {
+                (&a.name, a_position).cmp(&(&b.name, b_position))
+            }),
+        }
+
+        let mut next = None;
+        if let Some(limit) = options.limit {
+            if rows.len() > limit {
+                rows.truncate(limit);
+                next = rows.last().map(|(position, element)| PageCursor {
+                    rowid: *position,
+                    name: element.name.clone(),
+                });
+            }
+        }
+        Ok(Page {
+            elements: rows
+                .into_iter()
+                .map(|(_, element)| element.clone())
+                .collect(),
+            next,
+            version: state.version,
+        })
+    }
+
+    fn put(&self, id: &str, name: &str, value: &str, expected_version: Option<i64>) -> Result<i64> {
+        let mut state = self.state.lock().unwrap();
+        let current = state
+            .elements
+            .get(id)
+            .map(|(position, element)| (*position, element.resource_version));
+        check_version(expected_version, current.map(|(_, version)| version))?;
+
+        let element = Element::new(id.to_string(), name.to_string(), value.to_string());
+        let (event_type, position) = match current {
+            Some((position, _)) => (WatchEventType::Modified, position),
+            None => {
+                state.position += 1;
+                (WatchEventType::Added, state.position)
+            }
+        };
+        let version = state.record_change(event_type, &element);
+        state
+            .elements
+            .insert(id.to_string(), (position, element.with_version(version)));
+        Ok(version)
+    }
+
+    fn delete(&self, id: &str, expected_version: Option<i64>) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if expected_version.is_some() {
+            let current = state
+                .elements
+                .get(id)
+                .map(|(_, element)| element.resource_version);
+            check_version(expected_version, current)?;
+        }
+        if let Some((_, element)) = state.elements.remove(id) {
+            state.record_change(WatchEventType::Deleted, &element);
+        }
+        Ok(())
+    }
+
+    fn watch(&self, prefix: &str, version: i64, limit: usize) -> Result<Vec<WatchEvent>> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .changes
+            .iter()
+            .filter(|change| change.resource_version > version)
+            .filter(|change| matches_prefix(&change.object.name, prefix))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    fn oldest_version(&self) -> Result<Option<i64>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.changes.front().map(|change| change.resource_version))
+    }
+
+    fn compact(&self, kept: i64) -> Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        let compacted = state.changes.len().saturating_sub(kept.max(0) as usize);
+        state.changes.drain(..compacted);
+        Ok(compacted)
+    }
+}
@@ -0,0 +1,405 @@
+//! Versioned migrations of the SQLite schema. Each migration runs once, in its own
+//! transaction, and is recorded in the `schema_migrations` table.
+
+use super::{Result, StorageError};
+
+use rusqlite::{params, Connection, Transaction};
+use tracing::info;
+
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    up: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+/// Migrations of the schema, by increasing version. A released migration is never
+/// changed, a new one is added instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Store the elements and their changes",
+        up: elements,
+    },
+    Migration {
+        version: 2,
+        description: "Store workloads, instances, tenants and events in typed tables",
+        up: typed_tables,
+    },
+];
+
+/// Version of the schema when every migration is applied
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// Version of the schema of a database, 0 if it was never migrated
+pub fn schema_version(connection: &Connection) -> rusqlite::Result<i64> {
+    connection.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Apply the migrations a database misses, returns the version of its schema.
+/// A database migrated by a newer controller is refused rather than misread.
+pub fn migrate(connection: &mut Connection) -> Result<i64> {
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version         INTEGER PRIMARY KEY,
+            description     TEXT NOT NULL,
+            applied_at      INTEGER NOT NULL
+        );",
+    )?;
+    let current = schema_version(connection)?;
+    if current > latest_version() {
+        return Err(StorageError::Backend(
+            format!(
+                "Database schema version {} is newer than the supported version {}",
+                current,
+                latest_version()
+            )
+            .into(),
+        ));
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let transaction = connection.transaction()?;
+        (migration.up)(&transaction)?;
+        transaction.execute(
+            "INSERT INTO schema_migrations (version, description, applied_at)
+            VALUES (?1, ?2, strftime('%s', 'now'))",
+            params![migration.version, migration.description],
+        )?;
+        transaction.commit()?;
+        info!(
+            "Applied database migration {}: {}",
+            migration.version, migration.description
+        );
+    }
+    Ok(latest_version())
+}
+
+/// Elements stored as JSON under their name in the `cluster` table, and their
+/// changes in the `changes` table. Databases created before the migrations already
+/// have the `cluster` table, without the version of the elements: their elements
+/// are recorded as added, in the order they were stored, and take the version of
+/// this change. A version of 0 means the element doesn't exist.
+fn elements(transaction: &Transaction) -> rusqlite::Result<()> {
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS cluster (
+            id              TEXT PRIMARY KEY,
+            name            TEXT NOT NULL,
+            value           BLOB NOT NULL,
+            version         INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX IF NOT EXISTS cluster_name_index ON cluster (name);
+        CREATE INDEX IF NOT EXISTS cluster_name_id_index ON cluster (name,id);
+        CREATE TABLE IF NOT EXISTS changes (
+            version         INTEGER PRIMARY KEY AUTOINCREMENT,
+            type            TEXT NOT NULL,
+            id              TEXT NOT NULL,
+            name            TEXT NOT NULL,
+            value           BLOB NOT NULL
+        );",
+    )?;
+    let versioned: bool = transaction.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info('cluster') WHERE name = 'version'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !versioned {
+        transaction.execute_batch(
+            "ALTER TABLE cluster ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
+            INSERT INTO changes (type, id, name, value)
+                SELECT 'ADDED', id, name, value FROM cluster ORDER BY rowid;
+            UPDATE cluster SET version =
+                (SELECT MAX(version) FROM changes WHERE changes.id = cluster.id);",
+        )?;
+    }
+    Ok(())
+}
+
+/// Workloads, instances, tenants and events get their own table, with the fields
+/// they are queried by as columns. The other elements, e.g. the namespaces, stay in
+/// the `cluster` table, and the `elements` view lists all of them.
+///
+/// Every table gets the creation order of its elements as `position`, and when they
+/// were created and updated. The creation time of the existing elements is unknown,
+/// the time of the migration is used. The `cluster` table is rebuilt with these
+/// columns, SQLite cannot add columns without default.
+fn typed_tables(transaction: &Transaction) -> rusqlite::Result<()> {
+    transaction.execute_batch(
+        "DROP INDEX IF EXISTS cluster_name_index;
+        DROP INDEX IF EXISTS cluster_name_id_index;
+        ALTER TABLE cluster RENAME TO legacy_cluster;
+        CREATE TEMPORARY VIEW migrated AS
+            SELECT id, name, value, version, rowid AS position,
+                strftime('%s', 'now') AS created_at, strftime('%s', 'now') AS updated_at,
+                CASE WHEN json_valid(value) THEN value END AS document
+            FROM legacy_cluster;
+
+        CREATE TABLE cluster (
+            id              TEXT PRIMARY KEY,
+            name            TEXT NOT NULL,
+            value           BLOB NOT NULL,
+            version         INTEGER NOT NULL,
+            position        INTEGER NOT NULL,
+            created_at      INTEGER NOT NULL,
+            updated_at      INTEGER NOT NULL
+        );
+        CREATE INDEX cluster_name_index ON cluster (name);
+
+        CREATE TABLE workloads (
+            id              TEXT PRIMARY KEY,
+            name            TEXT NOT NULL,
+            value           BLOB NOT NULL,
+            version         INTEGER NOT NULL,
+            position        INTEGER NOT NULL,
+            created_at      INTEGER NOT NULL,
+            updated_at      INTEGER NOT NULL,
+            kind            TEXT,
+            namespace       TEXT,
+            tenant          TEXT,
+            status          TEXT
+        );
+        CREATE INDEX workloads_name_index ON workloads (name);
+        CREATE INDEX workloads_namespace_index ON workloads (namespace);
+        CREATE INDEX workloads_tenant_index ON workloads (tenant);
+
+        CREATE TABLE instances (
+            id              TEXT PRIMARY KEY,
+            name            TEXT NOT NULL,
+            value           BLOB NOT NULL,
+            version         INTEGER NOT NULL,
+            position        INTEGER NOT NULL,
+            created_at      INTEGER NOT NULL,
+            updated_at      INTEGER NOT NULL,
+            workload_id     TEXT REFERENCES workloads (id) ON DELETE SET NULL,
+            worker_id       TEXT,
+            status          TEXT
+        );
+        CREATE INDEX instances_name_index ON instances (name);
+        CREATE INDEX instances_workload_index ON instances (workload_id);
+        CREATE INDEX instances_worker_index ON instances (worker_id);
+        CREATE INDEX instances_status_index ON instances (status);
+
+        CREATE TABLE tenants (
+            id              TEXT PRIMARY KEY,
+            name            TEXT NOT NULL,
+            value           BLOB NOT NULL,
+            version         INTEGER NOT NULL,
+            position        INTEGER NOT NULL,
+            created_at      INTEGER NOT NULL,
+            updated_at      INTEGER NOT NULL
+        );
+        CREATE INDEX tenants_name_index ON tenants (name);
+
+        CREATE TABLE events (
+            id              TEXT PRIMARY KEY,
+            name            TEXT NOT NULL,
+            value           BLOB NOT NULL,
+            version         INTEGER NOT NULL,
+            position        INTEGER NOT NULL,
+            created_at      INTEGER NOT NULL,
+            updated_at      INTEGER NOT NULL,
+            object_kind     TEXT,
+            object_id       TEXT,
+            reason          TEXT,
+            timestamp       INTEGER
+        );
+        CREATE INDEX events_name_index ON events (name);
+        CREATE INDEX events_object_index ON events (object_kind, object_id);
+        CREATE INDEX events_timestamp_index ON events (timestamp);
+
+        INSERT INTO workloads
+            SELECT id, name, value, version, position, created_at, updated_at,
+                json_extract(document, '$.kind'),
+                COALESCE(json_extract(document, '$.namespace'), 'default'),
+                json_extract(document, '$.tenant'),
+                json_extract(document, '$.status')
+            FROM migrated WHERE name LIKE '/workload/%';
+        INSERT INTO instances
+            SELECT id, name, value, version, position, created_at, updated_at,
+                (SELECT workloads.id FROM workloads
+                    WHERE workloads.id = json_extract(document, '$.workload_id')),
+                json_extract(document, '$.worker_id'),
+                json_extract(document, '$.status')
+            FROM migrated WHERE name LIKE '/instance/%';
+        INSERT INTO tenants
+            SELECT id, name, value, version, position, created_at, updated_at
+            FROM migrated WHERE name LIKE '/tenant/%';
+        INSERT INTO events
+            SELECT id, name, value, version, position, created_at, updated_at,
+                json_extract(document, '$.object_kind'),
+                json_extract(document, '$.object_id'),
+                json_extract(document, '$.reason'),
+                json_extract(document, '$.timestamp')
+            FROM migrated WHERE name LIKE '/event/%';
+        INSERT INTO cluster
+            SELECT id, name, value, version, position, created_at, updated_at
+            FROM migrated WHERE name NOT LIKE '/workload/%' AND name NOT LIKE '/instance/%'
+                AND name NOT LIKE '/tenant/%' AND name NOT LIKE '/event/%';
+        DROP VIEW migrated;
+        DROP TABLE legacy_cluster;
+
+        CREATE VIEW elements AS
+            SELECT 'cluster' AS source, id, name, value, version, position, created_at,
+                NULL AS status, NULL AS workload_id FROM cluster
+            UNION ALL SELECT 'workloads', id, name, value, version, position, created_at,
+                status, NULL FROM workloads
+            UNION ALL SELECT 'instances', id, name, value, version, position, created_at,
+                status, workload_id FROM instances
+            UNION ALL SELECT 'tenants', id, name, value, version, position, created_at,
+                NULL, NULL FROM tenants
+            UNION ALL SELECT 'events', id, name, value, version, position, created_at,
+                NULL, NULL FROM events;
+
+        -- New elements are positioned by the version of their creation, after the
+        -- existing ones
+        INSERT INTO sqlite_sequence (name, seq)
+            SELECT 'changes', 0
+            WHERE NOT EXISTS (SELECT 1 FROM sqlite_sequence WHERE name = 'changes');
+        UPDATE sqlite_sequence
+            SET seq = MAX(seq, (SELECT COALESCE(MAX(position), 0) FROM elements))
+            WHERE name = 'changes';",
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{latest_version, migrate, schema_version};
+    use rusqlite::Connection;
+
+    /// Database as created before the migrations, without versions nor changes
+    fn legacy() -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "PRAGMA case_sensitive_like = ON; PRAGMA foreign_keys = ON;
+                CREATE TABLE cluster (
+                    id              TEXT PRIMARY KEY,
+                    name            TEXT NOT NULL,
+                    value           BLOB NOT NULL
+                );
+                CREATE INDEX cluster_name_index ON cluster (name);
+                CREATE INDEX cluster_name_id_index ON cluster (name,id);
+                INSERT INTO cluster VALUES
+                    ('web', '/workload/pod/default/web', '{\"kind\": \"pod\", \"status\": \"Running\"}'),
+                    ('web-1', '/instance/default/web-1',
+                        '{\"workload_id\": \"web\", \"worker_id\": \"node-1\", \"status\": \"Running\"}'),
+                    ('api-1', '/instance/default/api-1', '{\"workload_id\": \"api\", \"status\": \"Failed\"}'),
+                    ('acme', '/tenant/acme', '{}'),
+                    ('started', '/event/instance/web-1/1', '{\"reason\": \"Started\", \"timestamp\": 1}'),
+                    ('default', '/namespace/default', 'default');",
+            )
+            .unwrap();
+        connection
+    }
+
+    fn count(connection: &Connection, query: &str) -> i64 {
+        connection.query_row(query, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn test_migrate_legacy_database() {
+        let mut connection = legacy();
+        assert_eq!(migrate(&mut connection).unwrap(), latest_version());
+        assert_eq!(schema_version(&connection).unwrap(), latest_version());
+
+        assert_eq!(count(&connection, "SELECT COUNT(*) FROM cluster"), 1);
+        assert_eq!(count(&connection, "SELECT COUNT(*) FROM elements"), 6);
+        assert_eq!(
+            count(
+                &connection,
+                "SELECT COUNT(*) FROM workloads WHERE namespace = 'default' AND status = 'Running'"
+            ),
+            1
+        );
+        // The instance of an unknown workload has none
+        let instances: Vec<(String, Option<String>, Option<String>)> = connection
+            .prepare("SELECT id, workload_id, worker_id FROM instances ORDER BY position")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            instances,
+            vec![
+                ("web-1".into(), Some("web".into()), Some("node-1".into())),
+                ("api-1".into(), None, None),
+            ]
+        );
+        assert_eq!(
+            count(
+                &connection,
+                "SELECT timestamp FROM events WHERE reason = 'Started'"
+            ),
+            1
+        );
+        // The existing elements are recorded as added, a version of 0 would mean
+        // they don't exist
+        assert_eq!(
+            count(
+                &connection,
+                "SELECT COUNT(DISTINCT version) FROM elements WHERE version > 0"
+            ),
+            6
+        );
+        assert_eq!(
+            count(
+                &connection,
+                "SELECT COUNT(*) FROM changes JOIN elements USING (id, version)
+                WHERE type = 'ADDED'"
+            ),
+            6
+        );
+        assert_eq!(
+            count(&connection, "SELECT version FROM elements WHERE id = 'web'"),
+            1
+        );
+        // The next element is created after the existing ones
+        assert!(
+            count(
+                &connection,
+                "SELECT seq FROM sqlite_sequence WHERE name = 'changes'"
+            ) >= count(&connection, "SELECT MAX(position) FROM elements")
+        );
+
+        connection
+            .execute_batch("DELETE FROM workloads WHERE id = 'web'")
+            .unwrap();
+        assert_eq!(
+            count(
+                &connection,
+                "SELECT COUNT(*) FROM instances WHERE workload_id IS NULL"
+            ),
+            2
+        );
+    }
+
+    #[test]
+    fn test_migrate_once() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate(&mut connection).unwrap();
+        migrate(&mut connection).unwrap();
+        assert_eq!(
+            count(&connection, "SELECT COUNT(*) FROM schema_migrations"),
+            latest_version()
+        );
+    }
+
+    #[test]
+    fn test_refuse_newer_schema() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate(&mut connection).unwrap();
+        connection
+            .execute(
+                "INSERT INTO schema_migrations VALUES (?1, 'From the future', 0)",
+                [latest_version() + 1],
+            )
+            .unwrap();
+        assert!(migrate(&mut connection).is_err());
+    }
+}
@@ -1,165 +1,337 @@
+//! Storage of the cluster resources. Elements are stored by id under a name, e.g.
+//! `/workload/pod/default/web`, listed by name prefix, and every write gives the
+//! storage a new version, so the changes can be watched and the writes made
+//! conditional on the version of an element. SQLite is the default backend.
+
+#[cfg(test)]
+pub mod memory;
+pub mod migrations;
+pub mod sqlite;
+
+pub use sqlite::SqliteStorage;
+
 use crate::api::types::element::Element;
+use crate::api::types::watch::WatchEvent;
 
-use dotenv::dotenv;
-use rusqlite::{params, Connection, Result};
-use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
 use uuid::Uuid;
 
-#[allow(dead_code)]
-pub struct RikDataBase {
-    name: String,
+/// Order of listed elements
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortBy {
+    /// Oldest first
+    #[default]
+    CreationTime,
+    /// By database name, so by namespace then name
+    Name,
 }
 
-#[allow(dead_code)]
-impl RikDataBase {
-    pub fn new(name: String) -> Arc<RikDataBase> {
-        Arc::new(RikDataBase { name })
+/// Where a page of a list ended, the next page starts after it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PageCursor {
+    /// Insertion order of the last element
+    pub rowid: i64,
+    pub name: String,
+}
+
+/// Filters and page of a list
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListOptions {
+    /// Most elements listed, all of them if `None`
+    pub limit: Option<usize>,
+    /// End of the previous page
+    pub after: Option<PageCursor>,
+    pub sort: SortBy,
+    /// Status of the elements, e.g. of instances or jobs
+    pub status: Option<String>,
+    /// Workload of the elements, e.g. of instances
+    pub workload_id: Option<String>,
+}
+
+/// Page of a list, with the end of the page when more elements follow
+#[derive(Debug)]
+pub struct Page {
+    pub elements: Vec<Element>,
+    pub next: Option<PageCursor>,
+    /// Version of the storage when the page was read, its changes can be watched
+    /// after it
+    pub version: i64,
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    /// No element has the id
+    NotFound,
+    /// The element was written since the expected version, 0 when it does not exist
+    Conflict { expected: i64, current: i64 },
+    /// Error of the backend
+    Backend(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            StorageError::NotFound => write!(f, "Element not found"),
+            StorageError::Conflict { expected, current } => write!(
+                f,
+                "Resource version {} is stale, the resource is at version {}",
+                expected, current
+            ),
+            StorageError::Backend(e) => e.fmt(f),
+        }
     }
+}
 
-    pub fn init_tables(&self) -> Result<()> {
-        let connection = self.open()?;
-        // only work with sqlite for now
-        connection.execute_batch(
-            "CREATE TABLE IF NOT EXISTS cluster (
-                id              TEXT PRIMARY KEY,
-                name            TEXT NOT NULL,
-                value           BLOB NOT NULL
-            );
-            CREATE INDEX IF NOT EXISTS cluster_name_index ON cluster (name);
-            CREATE INDEX IF NOT EXISTS cluster_name_id_index ON cluster (name,id);",
-        )?;
-        Ok(())
+impl std::error::Error for StorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StorageError::Backend(e) => Some(e.as_ref()),
+            _ => None,
+        }
     }
+}
 
-    pub fn drop_tables(&self) {}
+pub type Result<T, E = StorageError> = std::result::Result<T, E>;
 
-    pub fn open(&self) -> Result<Connection> {
-        dotenv().ok();
-        let file_path = match std::env::var("DATABASE_LOCATION") {
-            Ok(val) => val,
-            Err(_e) => "/var/lib/rik/data/".to_string(),
-        };
-        std::fs::create_dir_all(&file_path).unwrap();
+/// Backend storing the elements. Names are listed by prefix, in which `%` matches
+/// any characters, e.g. `/workload/%/default/` lists the workloads of a namespace.
+/// The values of requests are escaped with [`escape_prefix`] so they only match
+/// themselves.
+pub trait Storage: Send + Sync {
+    /// Get an element by id
+    fn get(&self, id: &str) -> Result<Element>;
+
+    /// List a page of the elements whose name starts with the prefix
+    fn list(&self, prefix: &str, options: &ListOptions) -> Result<Page>;
+
+    /// Create or replace an element, returns its new version. With an expected
+    /// version, the element is only written if it is still at this version, `0`
+    /// meaning that it must not exist yet.
+    fn put(&self, id: &str, name: &str, value: &str, expected_version: Option<i64>) -> Result<i64>;
+
+    /// Delete an element, if it exists. With an expected version, it must exist
+    /// and still be at this version.
+    fn delete(&self, id: &str, expected_version: Option<i64>) -> Result<()>;
+
+    /// Changes made after a version to the elements whose name starts with the
+    /// prefix, oldest first
+    fn watch(&self, prefix: &str, version: i64, limit: usize) -> Result<Vec<WatchEvent>>;
+
+    /// Version of the oldest change kept, changes before it were compacted
+    fn oldest_version(&self) -> Result<Option<i64>>;
+
+    /// Forget the changes but the last ones, returns how many were forgotten
+    fn compact(&self, kept: i64) -> Result<usize>;
+}
 
-        let database_path = format!("{}{}.db", file_path, self.name);
-        Connection::open(&database_path)
+/// Escape the wildcards of a value, e.g. the namespace of a request, so it only
+/// matches itself when it is part of a prefix
+pub fn escape_prefix(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%")
+}
+
+/// Literal parts of a prefix, between its `%` wildcards. `\` escapes the next
+/// character.
+fn prefix_parts(prefix: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = prefix.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => parts.push(String::new()),
+            '\\' => parts.last_mut().unwrap().push(chars.next().unwrap_or('\\')),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    parts
+}
+
+/// Whether a name starts with a prefix, in which `%` matches any characters
+pub fn matches_prefix(name: &str, prefix: &str) -> bool {
+    let parts = prefix_parts(prefix);
+    let mut rest = match name.strip_prefix(parts[0].as_str()) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    // The prefix may be followed by anything, so each part is matched as soon
+    // as possible
+    for part in &parts[1..] {
+        match rest.find(part.as_str()) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
     }
+    true
 }
 
+/// Attempts of a write changing an element, which is read again when it was written
+/// meanwhile
+const MAX_WRITE_ATTEMPTS: usize = 5;
+
+/// Queries on the elements of the cluster, above the storage
 pub struct RikRepository {}
 impl RikRepository {
-    pub fn insert(connection: &Connection, name: &str, value: &str) -> Result<String> {
+    pub fn insert(storage: &dyn Storage, name: &str, value: &str) -> Result<String> {
         let id = Uuid::new_v4().to_string();
-        connection
-            .execute(
-                "INSERT INTO cluster (id, name, value) VALUES (?1, ?2, ?3)",
-                params![id, name, value],
-            )
-            .unwrap();
+        storage.put(&id, name, value, Some(0))?;
         Ok(id)
     }
 
-    pub fn delete(connection: &Connection, id: &String) -> Result<()> {
-        connection.execute("DELETE FROM cluster WHERE id = (?1)", params![id])?;
-        Ok(())
+    pub fn delete(storage: &dyn Storage, id: &str) -> Result<()> {
+        storage.delete(id, None)
     }
 
-    pub fn find_one(connection: &Connection, id: &String, element_type: &str) -> Result<Element> {
-        let mut stmt = connection.prepare(&format!(
-            "SELECT id, name, value FROM cluster WHERE id = '{}' AND name LIKE '{}%'",
-            id, element_type
-        ))?;
-        match stmt.query_row([], |row| {
-            Ok(Element::new(row.get(0)?, row.get(1)?, row.get(2)?))
-        }) {
-            Ok(element) => Ok(element),
-            Err(err) => Err(err),
+    pub fn find_one(storage: &dyn Storage, id: &str, element_type: &str) -> Result<Element> {
+        let element = storage.get(id)?;
+        match matches_prefix(&element.name, element_type) {
+            true => Ok(element),
+            false => Err(StorageError::NotFound),
         }
     }
 
-    pub fn check_duplicate_name(connection: &Connection, name: &str) -> Result<Element> {
-        let mut stmt = connection.prepare(&format!(
-            "SELECT id, name, value FROM cluster WHERE name LIKE '{}%'",
-            name
-        ))?;
-        match stmt.query_row([], |row| {
-            Ok(Element::new(row.get(0)?, row.get(1)?, row.get(2)?))
-        }) {
-            Ok(element) => Ok(element),
-            Err(err) => Err(err),
-        }
+    /// Find an element whose name starts with the given prefix
+    pub fn check_duplicate_name(storage: &dyn Storage, name: &str) -> Result<Element> {
+        let options = ListOptions {
+            limit: Some(1),
+            ..Default::default()
+        };
+        storage
+            .list(name, &options)?
+            .elements
+            .pop()
+            .ok_or(StorageError::NotFound)
     }
 
-    // TODO: add pagination
-    pub fn find_all(connection: &Connection, element_type: &str) -> Result<Vec<Element>> {
-        let mut stmt = connection
-            .prepare(&format!(
-                "SELECT id, name, value FROM cluster WHERE name LIKE '{}%'",
-                element_type
-            ))
-            .unwrap();
-        let elements_iter = stmt
-            .query_map([], |row| {
-                Ok(Element::new(row.get(0)?, row.get(1)?, row.get(2)?))
-            })
-            .unwrap();
+    pub fn find_by_name(storage: &dyn Storage, name: &str) -> Result<Element> {
+        storage
+            .list(name, &ListOptions::default())?
+            .elements
+            .into_iter()
+            .find(|element| element.name == name)
+            .ok_or(StorageError::NotFound)
+    }
 
-        let mut elements: Vec<Element> = Vec::new();
-        for element in elements_iter {
-            elements.push(element?);
-        }
-        Ok(elements)
+    /// Find the elements whose name starts with the given prefix, oldest first
+    pub fn find_all(storage: &dyn Storage, element_type: &str) -> Result<Vec<Element>> {
+        Ok(storage
+            .list(element_type, &ListOptions::default())?
+            .elements)
+    }
+
+    /// List a page of the elements whose name starts with the given prefix
+    pub fn list(storage: &dyn Storage, element_type: &str, options: &ListOptions) -> Result<Page> {
+        storage.list(element_type, options)
+    }
+
+    /// Replace the value of an element, if it is still at the expected version.
+    /// Returns its new version.
+    pub fn update(
+        storage: &dyn Storage,
+        id: &str,
+        value: &str,
+        expected_version: Option<i64>,
+    ) -> Result<i64> {
+        let element = storage.get(id)?;
+        storage.put(id, &element.name, value, expected_version)
     }
 
-    pub fn update(connection: &Connection, id: &String, value: &String) -> Result<()> {
-        connection.execute(
-            "UPDATE cluster SET value=(?1) WHERE id = (?2)",
-            params![value, id],
-        )?;
-        Ok(())
+    /// Change the value of an element, nothing is written when the change returns
+    /// `false`. The element is read and changed again when it was written meanwhile.
+    pub fn modify(
+        storage: &dyn Storage,
+        id: &str,
+        element_type: &str,
+        mut change: impl FnMut(&mut serde_json::Value) -> bool,
+    ) -> Result<()> {
+        let mut attempts = 0;
+        loop {
+            let mut element = RikRepository::find_one(storage, id, element_type)?;
+            if !change(&mut element.value) {
+                return Ok(());
+            }
+            let value = element.value.to_string();
+            attempts += 1;
+            match storage.put(id, &element.name, &value, Some(element.resource_version)) {
+                Err(StorageError::Conflict { .. }) if attempts < MAX_WRITE_ATTEMPTS => continue,
+                result => return result.map(|_| ()),
+            }
+        }
     }
 
+    /// Create an element, or replace the value of the existing one without changing
+    /// its name
     pub fn upsert(
-        connection: &Connection,
-        id: &String,
-        name: &String,
-        value: &String,
+        storage: &dyn Storage,
+        id: &str,
+        name: &str,
+        value: &str,
         element_type: &str,
     ) -> Result<String> {
-        if RikRepository::find_one(connection, id, element_type).is_ok() {
-            RikRepository::update(connection, id, value)?;
-            Ok(id.to_string())
-        } else {
-            connection
-                .execute(
-                    "INSERT INTO cluster (id, name, value) VALUES (?1, ?2, ?3)",
-                    params![id, name, value],
-                )
-                .unwrap();
-            Ok(id.to_string())
+        let mut attempts = 0;
+        loop {
+            let written = match RikRepository::find_one(storage, id, element_type) {
+                Ok(element) => {
+                    storage.put(id, &element.name, value, Some(element.resource_version))
+                }
+                Err(StorageError::NotFound) => storage.put(id, name, value, Some(0)),
+                Err(e) => return Err(e),
+            };
+            attempts += 1;
+            match written {
+                Err(StorageError::Conflict { .. }) if attempts < MAX_WRITE_ATTEMPTS => continue,
+                written => return written.map(|_| id.to_string()),
+            }
         }
     }
 }
 
+/// Check the version of an element before writing it, `current` being `None`
+/// when it does not exist
+fn check_version(expected: Option<i64>, current: Option<i64>) -> Result<()> {
+    match (expected, current) {
+        (None, _) | (Some(0), None) => Ok(()),
+        (Some(_), None) => Err(StorageError::NotFound),
+        (Some(expected), Some(current)) if expected == current => Ok(()),
+        (Some(expected), current) => Err(StorageError::Conflict {
+            expected,
+            current: current.unwrap_or_default(),
+        }),
+    }
+}
+
 #[cfg(test)]
+#[allow(clippy::unnecessary_to_owned)]
 mod test {
-    use crate::database::{RikDataBase, RikRepository};
-    use crate::tests::fixtures::db_connection;
+    use crate::api::types::watch::WatchEventType;
+    use crate::database::memory::MemoryStorage;
+    use crate::database::{
+        escape_prefix, matches_prefix, ListOptions, RikRepository, SortBy, SqliteStorage, Storage,
+        StorageError,
+    };
     use rstest::rstest;
     use uuid::Uuid;
 
+    fn sqlite() -> Box<dyn Storage> {
+        Box::new(SqliteStorage::open_in_memory().unwrap())
+    }
+
+    fn memory() -> Box<dyn Storage> {
+        Box::new(MemoryStorage::default())
+    }
+
     #[rstest]
-    fn test_insert_and_find_ok(db_connection: std::sync::Arc<RikDataBase>) {
-        let connection = db_connection.open().unwrap();
-        connection.execute("DELETE FROM cluster", []).unwrap();
+    #[case::sqlite(sqlite())]
+    #[case::memory(memory())]
+    fn test_insert_and_find_ok(#[case] storage: Box<dyn Storage>) {
+        let storage = storage.as_ref();
         let name = "/workload/pods/default/test-workload";
         let value = "{\"data\": \"test\"}";
-        let inserted_id = match RikRepository::insert(&connection, name, value) {
+        let inserted_id = match RikRepository::insert(storage, name, value) {
             Ok(v) => v,
             Err(_) => panic!("Test failed on database insert"),
         };
 
-        let element = match RikRepository::find_one(&connection, &inserted_id, "/workload") {
+        let element = match RikRepository::find_one(storage, &inserted_id, "/workload") {
             Ok(v) => v,
             Err(_) => panic!("Test failed can't find inserted value"),
         };
@@ -169,11 +341,12 @@ mod test {
     }
 
     #[rstest]
-    fn test_insert_and_find_all_ok(db_connection: std::sync::Arc<RikDataBase>) {
-        let connection = db_connection.open().unwrap();
-        connection.execute("DELETE FROM cluster", []).unwrap();
+    #[case::sqlite(sqlite())]
+    #[case::memory(memory())]
+    fn test_insert_and_find_all_ok(#[case] storage: Box<dyn Storage>) {
+        let storage = storage.as_ref();
         match RikRepository::insert(
-            &connection,
+            storage,
             "/workload/pods/default/test-workload",
             "{\"data\": \"test\"}",
         ) {
@@ -181,7 +354,7 @@ mod test {
             Err(_) => panic!("Test failed on database insert"),
         };
         match RikRepository::insert(
-            &connection,
+            storage,
             "/workload/pods/default/test-workload2",
             "{\"data\": \"test\"}",
         ) {
@@ -189,7 +362,7 @@ mod test {
             Err(_) => panic!("Test failed on database insert"),
         };
 
-        let elements = match RikRepository::find_all(&connection, "/workload") {
+        let elements = match RikRepository::find_all(storage, "/workload") {
             Ok(v) => v,
             Err(_) => panic!("Test failed can't find inserted value"),
         };
@@ -197,17 +370,18 @@ mod test {
     }
 
     #[rstest]
-    fn test_check_duplicate_name(db_connection: std::sync::Arc<RikDataBase>) {
-        let connection = db_connection.open().unwrap();
-        connection.execute("DELETE FROM cluster", []).unwrap();
+    #[case::sqlite(sqlite())]
+    #[case::memory(memory())]
+    fn test_check_duplicate_name(#[case] storage: Box<dyn Storage>) {
+        let storage = storage.as_ref();
         let name = "/workload/pods/default/test-workload";
         let value = "{\"data\": \"test\"}";
-        let inserted_id = match RikRepository::insert(&connection, name, value) {
+        let inserted_id = match RikRepository::insert(storage, name, value) {
             Ok(v) => v,
             Err(_) => panic!("Test failed on database insert"),
         };
         let duplicate = match RikRepository::check_duplicate_name(
-            &connection,
+            storage,
             &format!("/workload/%/default/{}", "test-workload"),
         ) {
             Ok(v) => v,
@@ -219,23 +393,19 @@ mod test {
     }
 
     #[rstest]
-    fn test_upsert_ok(db_connection: std::sync::Arc<RikDataBase>) {
-        let connection = db_connection.open().unwrap();
-        connection.execute("DELETE FROM cluster", []).unwrap();
+    #[case::sqlite(sqlite())]
+    #[case::memory(memory())]
+    fn test_upsert_ok(#[case] storage: Box<dyn Storage>) {
+        let storage = storage.as_ref();
         let id = Uuid::new_v4().to_string();
         let name = "/workload/pods/default/test-workload".to_string();
         let mut value = "{\"data\": \"test\"}".to_string();
-        let inserted_id = match RikRepository::upsert(
-            &connection,
-            &id,
-            &name,
-            &value,
-            &"/workload".to_string(),
-        ) {
-            Ok(v) => v,
-            Err(e) => panic!("Test failed on first insert : {:?}", e),
-        };
-        let element = match RikRepository::find_one(&connection, &inserted_id, "/workload") {
+        let inserted_id =
+            match RikRepository::upsert(storage, &id, &name, &value, &"/workload".to_string()) {
+                Ok(v) => v,
+                Err(e) => panic!("Test failed on first insert : {:?}", e),
+            };
+        let element = match RikRepository::find_one(storage, &inserted_id, "/workload") {
             Ok(v) => v,
             Err(_) => panic!("Test failed can't find inserted value"),
         };
@@ -244,12 +414,11 @@ mod test {
         assert_eq!(element.value, serde_json::json!({"data": "test"}));
 
         value = "{\"data\": \"test_updated\"}".to_string();
-        match RikRepository::upsert(&connection, &id, &name, &value, &"/workload".to_string()) {
+        match RikRepository::upsert(storage, &id, &name, &value, &"/workload".to_string()) {
             Ok(v) => v,
             Err(e) => panic!("Test failed on first insert : {:?}", e),
         };
-        let updated_element = match RikRepository::find_one(&connection, &inserted_id, "/workload")
-        {
+        let updated_element = match RikRepository::find_one(storage, &inserted_id, "/workload") {
             Ok(v) => v,
             Err(_) => panic!("Test failed can't find inserted value"),
         };
@@ -260,4 +429,193 @@ mod test {
             serde_json::json!({"data": "test_updated"})
         );
     }
+
+    #[rstest]
+    #[case::sqlite(sqlite())]
+    #[case::memory(memory())]
+    fn test_list_pages(#[case] storage: Box<dyn Storage>) {
+        let storage = storage.as_ref();
+        for workload in ["web", "api"] {
+            let name = format!("/workload/pod/default/{}", workload);
+            storage.put(workload, &name, "{}", Some(0)).unwrap();
+        }
+        for (name, status, workload) in [
+            ("web-b", "Running", "web"),
+            ("web-a", "Pending", "web"),
+            ("api-a", "Running", "api"),
+        ] {
+            let value = format!(
+                "{{\"status\": \"{}\", \"workload_id\": \"{}\"}}",
+                status, workload
+            );
+            RikRepository::insert(storage, &format!("/instance/default/{}", name), &value).unwrap();
+        }
+        let names = |options: &ListOptions| {
+            let page = RikRepository::list(storage, "/instance/", options).unwrap();
+            let names: Vec<String> = page
+                .elements
+                .into_iter()
+                .map(|element| element.name.replace("/instance/default/", ""))
+                .collect();
+            (names, page.next)
+        };
+
+        let mut options = ListOptions {
+            limit: Some(2),
+            ..Default::default()
+        };
+        let (first, next) = names(&options);
+        assert_eq!(first, vec!["web-b", "web-a"]);
+        options.after = next;
+        assert_eq!(names(&options), (vec![String::from("api-a")], None));
+
+        options = ListOptions {
+            limit: Some(1),
+            sort: SortBy::Name,
+            ..Default::default()
+        };
+        let (first, next) = names(&options);
+        assert_eq!(first, vec!["api-a"]);
+        options.after = next;
+        assert_eq!(names(&options).0, vec!["web-a"]);
+
+        let options = ListOptions {
+            status: Some(String::from("Running")),
+            workload_id: Some(String::from("web")),
+            ..Default::default()
+        };
+        assert_eq!(names(&options), (vec![String::from("web-b")], None));
+
+        // Parameters are bound, not interpreted
+        let options = ListOptions {
+            status: Some(String::from("Running' OR '1'='1")),
+            ..Default::default()
+        };
+        assert!(names(&options).0.is_empty());
+        assert!(RikRepository::find_all(storage, "/instance/' OR '1'='1")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[rstest]
+    #[case::sqlite(sqlite())]
+    #[case::memory(memory())]
+    fn test_put_compare_and_swap(#[case] storage: Box<dyn Storage>) {
+        let storage = storage.as_ref();
+        let id = Uuid::new_v4().to_string();
+        let name = "/tenant/acme";
+        let created = storage.put(&id, name, r#"{"v": 1}"#, Some(0)).unwrap();
+        assert!(matches!(
+            storage.put(&id, name, r#"{"v": 1}"#, Some(0)),
+            Err(StorageError::Conflict { expected: 0, current }) if current == created
+        ));
+
+        let updated = storage
+            .put(&id, name, r#"{"v": 2}"#, Some(created))
+            .unwrap();
+        assert!(updated > created);
+        assert_eq!(storage.get(&id).unwrap().resource_version, updated);
+        // A write based on a stale version is refused
+        assert!(matches!(
+            storage.put(&id, name, r#"{"v": 3}"#, Some(created)),
+            Err(StorageError::Conflict { .. })
+        ));
+        assert!(matches!(
+            storage.delete(&id, Some(created)),
+            Err(StorageError::Conflict { .. })
+        ));
+        assert_eq!(storage.get(&id).unwrap().value["v"], 2);
+
+        storage.delete(&id, Some(updated)).unwrap();
+        assert!(matches!(storage.get(&id), Err(StorageError::NotFound)));
+        assert!(matches!(
+            storage.put(&id, name, r#"{"v": 4}"#, Some(updated)),
+            Err(StorageError::NotFound)
+        ));
+        // Deleting a missing element without a version does nothing
+        storage.delete(&id, None).unwrap();
+    }
+
+    #[rstest]
+    #[case::sqlite(sqlite())]
+    #[case::memory(memory())]
+    fn test_watch(#[case] storage: Box<dyn Storage>) {
+        let storage = storage.as_ref();
+        let web = RikRepository::insert(storage, "/workload/pod/default/web", "{}").unwrap();
+        let page = storage
+            .list("/workload/%/default/", &ListOptions::default())
+            .unwrap();
+        assert_eq!(page.elements.len(), 1);
+
+        RikRepository::update(storage, &web, r#"{"replicas": 2}"#, None).unwrap();
+        RikRepository::insert(storage, "/tenant/acme", "{}").unwrap();
+        RikRepository::delete(storage, &web).unwrap();
+        let changes = storage.watch("/workload/", page.version, 10).unwrap();
+        let types: Vec<WatchEventType> = changes.iter().map(|change| change.event_type).collect();
+        assert_eq!(
+            types,
+            vec![WatchEventType::Modified, WatchEventType::Deleted]
+        );
+        assert_eq!(changes[1].object.value["replicas"], 2);
+        assert!(changes[0].resource_version < changes[1].resource_version);
+
+        assert_eq!(storage.compact(2).unwrap(), 2);
+        assert_eq!(
+            storage.oldest_version().unwrap(),
+            Some(changes[1].resource_version - 1)
+        );
+    }
+
+    #[rstest]
+    #[case::sqlite(sqlite())]
+    #[case::memory(memory())]
+    fn test_list_prefix(#[case] storage: Box<dyn Storage>) {
+        let storage = storage.as_ref();
+        for name in [
+            "/instance/default/web_1",
+            "/instance/default/webx1",
+            "/Instance/a",
+        ] {
+            RikRepository::insert(storage, name, "{}").unwrap();
+        }
+        // `%` is the only wildcard, and names are case sensitive
+        let names = |prefix: &str| -> Vec<String> {
+            RikRepository::find_all(storage, prefix)
+                .unwrap()
+                .into_iter()
+                .map(|element| element.name)
+                .collect()
+        };
+        assert_eq!(
+            names("/instance/default/web_"),
+            vec!["/instance/default/web_1"]
+        );
+        assert_eq!(names("/instance/%/webx"), vec!["/instance/default/webx1"]);
+        assert_eq!(names("/instance/").len(), 2);
+        // Escaped values only match themselves
+        let prefix = format!("/instance/{}/", escape_prefix("%"));
+        assert!(names(&prefix).is_empty());
+        RikRepository::insert(storage, "/instance/100%/a\\b", "{}").unwrap();
+        let prefix = format!("/instance/{}", escape_prefix("100%/a\\"));
+        assert_eq!(names(&prefix), vec!["/instance/100%/a\\b"]);
+    }
+
+    #[test]
+    fn test_matches_prefix() {
+        assert!(matches_prefix("/workload/pod/default/web", "/workload/"));
+        assert!(matches_prefix(
+            "/workload/pod/default/web",
+            "/workload/%/default/"
+        ));
+        assert!(matches_prefix("/event/instance/web/10", "/event/%/web/"));
+        assert!(!matches_prefix(
+            "/workload/pod/prod/web",
+            "/workload/%/default/"
+        ));
+        assert!(!matches_prefix("/tenant/acme", "/workload/"));
+        assert!(!matches_prefix(
+            "/workload/pod/default/web",
+            &format!("/workload/pod/{}/", escape_prefix("%"))
+        ));
+    }
 }
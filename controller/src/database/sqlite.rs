@@ -0,0 +1,421 @@
+//! SQLite storage, the default one. Workloads, instances, tenants and events are rows
+//! of their own table, the other elements rows of the `cluster` table, and their
+//! changes rows of the `changes` table, whose ids are the versions. The schema is
+//! created and upgraded by the [migrations](super::migrations).
+
+use super::migrations::migrate;
+use super::{check_version, ListOptions, Page, PageCursor, Result, SortBy, Storage, StorageError};
+use crate::api::types::element::Element;
+use crate::api::types::watch::{WatchEvent, WatchEventType};
+
+use dotenv::dotenv;
+use rusqlite::{params, Connection, OptionalExtension, ToSql};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> StorageError {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => StorageError::NotFound,
+            e => StorageError::Backend(Box::new(e)),
+        }
+    }
+}
+
+pub struct SqliteStorage {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    /// Open the database of the given name, in the `DATABASE_LOCATION` directory
+    pub fn open(name: &str) -> Result<SqliteStorage> {
+        dotenv().ok();
+        let file_path = match std::env::var("DATABASE_LOCATION") {
+            Ok(val) => val,
+            Err(_e) => "/var/lib/rik/data/".to_string(),
+        };
+        std::fs::create_dir_all(&file_path).map_err(|e| StorageError::Backend(Box::new(e)))?;
+
+        let database_path = format!("{}{}.db", file_path, name);
+        SqliteStorage::new(Connection::open(database_path)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<SqliteStorage> {
+        SqliteStorage::new(Connection::open_in_memory()?)
+    }
+
+    fn new(mut connection: Connection) -> Result<SqliteStorage> {
+        // Names are matched case sensitively, as by the other storages
+        connection.execute_batch("PRAGMA case_sensitive_like = ON; PRAGMA foreign_keys = ON;")?;
+        migrate(&mut connection)?;
+        Ok(SqliteStorage {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+/// Table of a kind of elements, with the columns filled from their value
+struct Table {
+    name: &'static str,
+    /// Prefix of the names of its elements
+    prefix: &'static str,
+    /// Columns, with their SQL expression over the value as JSON, bound as `?6`
+    columns: &'static [(&'static str, &'static str)],
+}
+
+/// Tables of the elements, the other elements are stored in `CLUSTER`
+const TABLES: &[Table] = &[
+    Table {
+        name: "workloads",
+        prefix: "/workload/",
+        columns: &[
+            ("kind", "json_extract(?6, '$.kind')"),
+            (
+                "namespace",
+                "COALESCE(json_extract(?6, '$.namespace'), 'default')",
+            ),
+            ("tenant", "json_extract(?6, '$.tenant')"),
+            ("status", "json_extract(?6, '$.status')"),
+        ],
+    },
+    Table {
+        name: "instances",
+        prefix: "/instance/",
+        columns: &[
+            // An instance whose workload is unknown, e.g. deleted, has none
+            (
+                "workload_id",
+                "(SELECT id FROM workloads WHERE id = json_extract(?6, '$.workload_id'))",
+            ),
+            ("worker_id", "json_extract(?6, '$.worker_id')"),
+            ("status", "json_extract(?6, '$.status')"),
+        ],
+    },
+    Table {
+        name: "tenants",
+        prefix: "/tenant/",
+        columns: &[],
+    },
+    Table {
+        name: "events",
+        prefix: "/event/",
+        columns: &[
+            ("object_kind", "json_extract(?6, '$.object_kind')"),
+            ("object_id", "json_extract(?6, '$.object_id')"),
+            ("reason", "json_extract(?6, '$.reason')"),
+            ("timestamp", "json_extract(?6, '$.timestamp')"),
+        ],
+    },
+];
+
+const CLUSTER: Table = Table {
+    name: "cluster",
+    prefix: "/",
+    columns: &[],
+};
+
+impl Table {
+    /// Table storing the element of the given name
+    fn of(name: &str) -> &'static Table {
+        TABLES
+            .iter()
+            .find(|table| name.starts_with(table.prefix))
+            .unwrap_or(&CLUSTER)
+    }
+
+    /// Update an element, bound as `?1` to `?6`: id, name, value, version, update
+    /// time and value if it is JSON
+    fn update_query(&self) -> String {
+        let mut columns = String::new();
+        for (column, value) in self.columns {
+            columns.push_str(&format!(", {} = {}", column, value));
+        }
+        format!(
+            "UPDATE {} SET name = ?2, value = ?3, version = ?4, updated_at = ?5{} WHERE id = ?1",
+            self.name, columns
+        )
+    }
+
+    /// Insert an element, bound as for an update, then with its position and creation
+    /// time as `?7` and `?8`
+    fn insert_query(&self) -> String {
+        let mut columns = String::new();
+        let mut values = String::new();
+        for (column, value) in self.columns {
+            columns.push_str(&format!(", {}", column));
+            values.push_str(&format!(", {}", value));
+        }
+        format!(
+            "INSERT INTO {} (id, name, value, version, updated_at, position, created_at{})
+            VALUES (?1, ?2, ?3, ?4, ?5, ?7, ?8{})",
+            self.name, columns, values
+        )
+    }
+}
+
+/// `LIKE` pattern of a prefix, in which `%` is the only wildcard and `\` escapes
+/// the next character
+fn like_prefix(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    let mut chars = prefix.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                pattern.push('\\');
+                pattern.push(chars.next().unwrap_or('\\'));
+            }
+            '_' => pattern.push_str("\\_"),
+            c => pattern.push(c),
+        }
+    }
+    pattern.push('%');
+    pattern
+}
+
+/// Version of the last change, 0 if nothing changed yet
+fn last_version(connection: &Connection) -> rusqlite::Result<i64> {
+    connection
+        .query_row(
+            "SELECT seq FROM sqlite_sequence WHERE name = 'changes'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()
+        .map(Option::unwrap_or_default)
+}
+
+/// Seconds since the UNIX epoch
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// Execute a query of a table, the value as JSON is not bound when the table has no
+/// column filled from it
+fn execute(connection: &Connection, query: &str, values: &[&dyn ToSql]) -> rusqlite::Result<usize> {
+    let mut statement = connection.prepare(query)?;
+    let count = statement.parameter_count();
+    statement.execute(&values[..count])
+}
+
+/// Record a change, returns its version
+fn record_change(
+    connection: &Connection,
+    event_type: WatchEventType,
+    id: &str,
+    name: &str,
+    value: &str,
+) -> rusqlite::Result<i64> {
+    connection.execute(
+        "INSERT INTO changes (type, id, name, value) VALUES (?1, ?2, ?3, ?4)",
+        params![event_type.as_str(), id, name, value],
+    )?;
+    Ok(connection.last_insert_rowid())
+}
+
+impl Storage for SqliteStorage {
+    fn get(&self, id: &str) -> Result<Element> {
+        let connection = self.connection.lock().unwrap();
+        Ok(connection.query_row(
+            "SELECT id, name, value, version FROM elements WHERE id = ?1",
+            params![id],
+            |row| Ok(Element::new(row.get(0)?, row.get(1)?, row.get(2)?).with_version(row.get(3)?)),
+        )?)
+    }
+
+    fn list(&self, prefix: &str, options: &ListOptions) -> Result<Page> {
+        let pattern = like_prefix(prefix);
+        let mut query = String::from(
+            "SELECT position, id, name, value, version FROM elements WHERE name LIKE ? ESCAPE '\\'",
+        );
+        let mut values: Vec<&dyn ToSql> = vec![&pattern];
+        if let Some(status) = &options.status {
+            query.push_str(" AND status = ?");
+            values.push(status);
+        }
+        if let Some(workload_id) = &options.workload_id {
+            query.push_str(" AND workload_id = ?");
+            values.push(workload_id);
+        }
+        if let Some(after) = &options.after {
+            match options.sort {
+                SortBy::CreationTime => query.push_str(" AND position > ?"),
+                SortBy::Name => {
+                    query.push_str(" AND (name > ? OR (name = ? AND position > ?))");
+                    values.push(&after.name);
+                    values.push(&after.name);
+                }
+            }
+            values.push(&after.rowid);
+        }
+        query.push_str(match options.sort {
+            SortBy::CreationTime => " ORDER BY position",
+            SortBy::Name => " ORDER BY name, position",
+        });
+        // One more element is read to know whether another page follows
+        let limit = options.limit.map(|limit| limit as i64 + 1);
+        if let Some(limit) = &limit {
+            query.push_str(" LIMIT ?");
+            values.push(limit);
+        }
+
+        let mut connection = self.connection.lock().unwrap();
+        // The elements and the version are read together, so no change is missed
+        // by a watch starting after the page
+        let transaction = connection.transaction()?;
+        let version = last_version(&transaction)?;
+        let mut rows = {
+            let mut stmt = transaction.prepare(&query)?;
+            let rows = stmt.query_map(values.as_slice(), |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    Element::new(row.get(1)?, row.get(2)?, row.get(3)?).with_version(row.get(4)?),
+                ))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<(i64, Element)>>>()?
+        };
+        transaction.commit()?;
+
+        let mut next = None;
+        if matches!(options.limit, Some(limit) if rows.len() > limit) {
+            rows.pop();
+            next = rows.last().map(|(rowid, element)| PageCursor {
+                rowid: *rowid,
+                name: element.name.clone(),
+            });
+        }
+        Ok(Page {
+            elements: rows.into_iter().map(|(_, element)| element).collect(),
+            next,
+            version,
+        })
+    }
+
+    fn put(&self, id: &str, name: &str, value: &str, expected_version: Option<i64>) -> Result<i64> {
+        let table = Table::of(name);
+        let document = serde_json::from_str::<serde_json::Value>(value)
+            .ok()
+            .map(|_| value);
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let current: Option<(String, i64, i64, i64)> = transaction
+            .query_row(
+                "SELECT source, version, position, created_at FROM elements WHERE id = ?1",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()?;
+        check_version(
+            expected_version,
+            current.as_ref().map(|(_, version, ..)| *version),
+        )?;
+
+        let now = now();
+        let version = match current {
+            Some((source, ..)) if source == table.name => {
+                let version =
+                    record_change(&transaction, WatchEventType::Modified, id, name, value)?;
+                execute(
+                    &transaction,
+                    &table.update_query(),
+                    params![id, name, value, version, now, document],
+                )?;
+                version
+            }
+            Some((source, _, position, created_at)) => {
+                // The kind of the element changed with its name
+                let version =
+                    record_change(&transaction, WatchEventType::Modified, id, name, value)?;
+                transaction.execute(
+                    &format!("DELETE FROM {} WHERE id = ?1", source),
+                    params![id],
+                )?;
+                execute(
+                    &transaction,
+                    &table.insert_query(),
+                    params![id, name, value, version, now, document, position, created_at],
+                )?;
+                version
+            }
+            None => {
+                let version = record_change(&transaction, WatchEventType::Added, id, name, value)?;
+                execute(
+                    &transaction,
+                    &table.insert_query(),
+                    params![id, name, value, version, now, document, version, now],
+                )?;
+                version
+            }
+        };
+        transaction.commit()?;
+        Ok(version)
+    }
+
+    fn delete(&self, id: &str, expected_version: Option<i64>) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let current: Option<(String, String, String, i64)> = transaction
+            .query_row(
+                "SELECT source, name, value, version FROM elements WHERE id = ?1",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()?;
+        if expected_version.is_some() {
+            check_version(
+                expected_version,
+                current.as_ref().map(|(.., version)| *version),
+            )?;
+        }
+
+        if let Some((source, name, value, _)) = current {
+            record_change(&transaction, WatchEventType::Deleted, id, &name, &value)?;
+            transaction.execute(
+                &format!("DELETE FROM {} WHERE id = ?1", source),
+                params![id],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn watch(&self, prefix: &str, version: i64, limit: usize) -> Result<Vec<WatchEvent>> {
+        let connection = self.connection.lock().unwrap();
+        let mut stmt = connection.prepare(
+            "SELECT version, type, id, name, value FROM changes
+            WHERE version > ?1 AND name LIKE ?2 ESCAPE '\\' ORDER BY version LIMIT ?3",
+        )?;
+        let changes =
+            stmt.query_map(params![version, like_prefix(prefix), limit as i64], |row| {
+                let event_type = match row.get::<_, String>(1)?.as_str() {
+                    "ADDED" => WatchEventType::Added,
+                    "DELETED" => WatchEventType::Deleted,
+                    _ => WatchEventType::Modified,
+                };
+                let resource_version = row.get(0)?;
+                Ok(WatchEvent {
+                    event_type,
+                    resource_version,
+                    object: Element::new(row.get(2)?, row.get(3)?, row.get(4)?)
+                        .with_version(resource_version),
+                })
+            })?;
+        Ok(changes.collect::<rusqlite::Result<Vec<WatchEvent>>>()?)
+    }
+
+    fn oldest_version(&self) -> Result<Option<i64>> {
+        let connection = self.connection.lock().unwrap();
+        Ok(connection.query_row("SELECT MIN(version) FROM changes", [], |row| row.get(0))?)
+    }
+
+    fn compact(&self, kept: i64) -> Result<usize> {
+        let connection = self.connection.lock().unwrap();
+        Ok(connection.execute(
+            "DELETE FROM changes WHERE version <= (SELECT MAX(version) FROM changes) - ?1",
+            params![kept],
+        )?)
+    }
+}
@@ -1,63 +1,122 @@
-use chrono::Utc;
-use std::sync::mpsc::Receiver;
+//! Structured logging of the controller, built on `tracing`. Every REST request
+//! is handled in a span carrying its request id, which is also the correlation id
+//! sent along with the scheduling requests it triggers. The spans are exported
+//! to an OTLP collector when `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
 
-use colored::{ColoredString, Colorize};
+use std::cell::RefCell;
+use std::fmt;
+use telemetry::{TelemetryConfig, TraceContext};
+use tracing::span::EnteredSpan;
+use uuid::Uuid;
 
-#[allow(dead_code)]
-pub enum LogType {
-    Error,
-    Log,
-    Warn,
-    Debug,
+pub use telemetry::LogFormat;
+
+/// Header carrying the request id, given by the client or generated
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+thread_local! {
+    /// Id of the request handled by the current thread
+    static REQUEST_ID: RefCell<Option<String>> = const { RefCell::new(None) };
 }
 
-pub struct Logger {
-    receiver: Receiver<LoggingChannel>,
-    name: String,
+/// Logging settings, read from `LOG_LEVEL`, `LOG_FORMAT` and `OTEL_EXPORTER_OTLP_ENDPOINT`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoggingConfig {
+    /// Level of the records, or a filter such as `info,controller=debug`
+    pub level: String,
+    pub format: LogFormat,
+    /// OTLP endpoint the spans are exported to, if any
+    pub otlp_endpoint: Option<String>,
 }
 
-impl Logger {
-    pub fn new(logger_receiver: Receiver<LoggingChannel>, name: String) -> Logger {
-        Logger {
-            receiver: logger_receiver,
-            name,
-        }
+impl LoggingConfig {
+    pub fn from_env() -> Result<LoggingConfig, String> {
+        let level = std::env::var("LOG_LEVEL").unwrap_or_else(|_| String::from("info"));
+        let format = match std::env::var("LOG_FORMAT") {
+            Ok(format) => format
+                .parse()
+                .map_err(|e| format!("Invalid LOG_FORMAT: {}", e))?,
+            Err(_) => LogFormat::Human,
+        };
+        let otlp_endpoint = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .ok()
+            .filter(|endpoint| !endpoint.is_empty());
+        Ok(LoggingConfig {
+            level,
+            format,
+            otlp_endpoint,
+        })
     }
+}
 
-    pub fn run(&self) {
-        for notification in &self.receiver {
-            match notification.log_type {
-                LogType::Error => self.error(&notification.message),
-                LogType::Log => self.log(&notification.message),
-                LogType::Warn => self.warn(&notification.message),
-                LogType::Debug => self.debug(&notification.message),
-            }
-        }
-    }
+/// Install the global subscriber, within the Tokio runtime exporting the spans
+pub fn init(config: &LoggingConfig) -> Result<(), Box<dyn std::error::Error>> {
+    telemetry::init(&TelemetryConfig {
+        service_name: String::from("rik-controller"),
+        filter: config.level.clone(),
+        format: config.format,
+        otlp_endpoint: config.otlp_endpoint.clone(),
+    })
+}
 
-    fn log(&self, msg: &str) {
-        self.print(msg, "LOG  ".green());
-    }
+/// Id of the REST request handled by the current thread, if any
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.with(|request_id| request_id.borrow().clone())
+}
 
-    fn error(&self, msg: &str) {
-        self.print(msg, "ERROR".red());
-    }
+/// Generate an id correlating the records of a request across components
+pub fn new_request_id() -> String {
+    Uuid::new_v4().to_string()
+}
 
-    fn warn(&self, msg: &str) {
-        self.print(msg, "WARN ".yellow());
-    }
+/// Span of a REST request, the request id is cleared when it is dropped
+pub struct RequestScope {
+    _span: EnteredSpan,
+}
 
-    fn debug(&self, msg: &str) {
-        self.print(msg, "DEBUG".white());
+impl RequestScope {
+    /// Enter the span of a request, continuing the trace of the client if its
+    /// trace context is given
+    pub fn enter(
+        request_id: &str,
+        method: &impl fmt::Display,
+        path: &str,
+        trace_context: &TraceContext,
+    ) -> RequestScope {
+        REQUEST_ID.with(|current| *current.borrow_mut() = Some(request_id.to_string()));
+        let span = tracing::info_span!("request", request_id, method = %method, path);
+        if !trace_context.is_empty() {
+            telemetry::set_parent(&span, trace_context);
+        }
+        RequestScope {
+            _span: span.entered(),
+        }
     }
+}
 
-    fn print(&self, msg: &str, log_type: ColoredString) {
-        let now = Utc::now().format("%Y-%m-%d %H:%M:%S");
-        println!("{} [Rik] [{}] - {} - {}", log_type, self.name, now, msg);
+impl Drop for RequestScope {
+    fn drop(&mut self) {
+        REQUEST_ID.with(|current| *current.borrow_mut() = None);
     }
 }
 
-pub struct LoggingChannel {
-    pub message: String,
-    pub log_type: LogType,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_scope() {
+        assert_eq!(current_request_id(), None);
+        {
+            let _scope = RequestScope::enter(
+                "abc",
+                &"GET",
+                "/api/v0/workloads.list",
+                &TraceContext::new(),
+            );
+            assert_eq!(current_request_id(), Some(String::from("abc")));
+        }
+        assert_eq!(current_request_id(), None);
+        assert!("yaml".parse::<LogFormat>().is_err());
+    }
 }
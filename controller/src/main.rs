@@ -1,14 +1,19 @@
 mod api;
 mod database;
 mod logger;
+#[cfg(test)]
 mod tests;
 
 use std::sync::mpsc::channel;
 use std::thread;
 
-use crate::database::RikDataBase;
+use crate::database::{SqliteStorage, Storage};
+use api::external::auth::Authenticator;
+use api::external::rbac::AuditLog;
 use api::{external, internal, ApiChannel};
-use logger::{Logger, LoggingChannel};
+use dotenv::dotenv;
+use logger::LoggingConfig;
+use std::sync::Arc;
 
 use tokio::runtime::Builder;
 
@@ -17,37 +22,55 @@ fn main() {
         println!("Rik controller must run with root privileges.");
         std::process::exit(1);
     }
-    let db = RikDataBase::new(String::from("rik"));
-    db.init_tables().unwrap();
+    dotenv().ok();
+    let logging = LoggingConfig::from_env().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
+    // Spans are exported in background on the runtime of the internal API
+    if let Err(e) = runtime.block_on(async { logger::init(&logging) }) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
+    let storage: Arc<dyn Storage> = match SqliteStorage::open("rik") {
+        Ok(storage) => Arc::new(storage),
+        Err(e) => {
+            eprintln!("Cannot open the database: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let authenticator = Authenticator::from_env().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    // Decisions are only audited when requests are authenticated
+    let audit = match authenticator.is_enabled() {
+        true => AuditLog::from_env().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }),
+        false => AuditLog::default(),
+    };
 
-    let (logging_sender, logging_receiver) = channel::<LoggingChannel>();
     let (internal_sender, internal_receiver) = channel::<ApiChannel>();
     let (external_sender, external_receiver) = channel::<ApiChannel>();
 
-    let logger = Logger::new(logging_receiver, String::from("Main"));
-
-    let logging_sender_clone = logging_sender.clone();
-    let internal_api = internal::Server::new(logging_sender, external_sender, internal_receiver);
+    let internal_api = internal::Server::new(external_sender, internal_receiver);
     let external_api =
-        external::Server::new(logging_sender_clone, internal_sender, external_receiver);
+        external::Server::new(internal_sender, external_receiver, authenticator, audit);
     let mut threads = Vec::new();
 
-    let db_clone_internal = db.clone();
-    threads.push(thread::spawn(move || {
-        let future = async move { internal_api.run(db_clone_internal).await };
-        Builder::new_multi_thread()
-            .enable_all()
-            .build()
-            .unwrap()
-            .block_on(future)
-    }));
-
+    let internal_storage = storage.clone();
     threads.push(thread::spawn(move || {
-        external_api.run(db);
+        let future = async move { internal_api.run(internal_storage).await };
+        runtime.block_on(future)
     }));
 
     threads.push(thread::spawn(move || {
-        logger.run();
+        external_api.run(storage);
     }));
 
     for thread in threads {
@@ -1,22 +1,12 @@
 use crate::api::ApiChannel;
-use crate::database::RikDataBase;
-use crate::logger::LoggingChannel;
+use crate::database::memory::MemoryStorage;
 use rstest::fixture;
 use std::sync::mpsc::channel;
 use std::sync::mpsc::{Receiver, Sender};
 
 #[fixture]
-pub fn db_connection() -> std::sync::Arc<RikDataBase> {
-    std::env::set_var("DATABASE_LOCATION", "/tmp/riktest");
-    let db = RikDataBase::new(String::from("test"));
-    db.init_tables().unwrap();
-    db
-}
-
-#[fixture]
-pub fn mock_logger() -> Sender<LoggingChannel> {
-    let (logging_sender, _) = channel::<LoggingChannel>();
-    logging_sender
+pub fn storage() -> MemoryStorage {
+    MemoryStorage::default()
 }
 
 #[fixture]
@@ -33,10 +23,6 @@ pub fn mock_external_receiver() -> Receiver<ApiChannel> {
 
 // #[fixture]
 // pub fn mock_server(db_connection: Connection) {
-//     let external_api = external::Server::new(
-//         logging_sender.clone(),
-//         internal_sender.clone(),
-//         external_receiver,
-//     );
+//     let external_api = external::Server::new(internal_sender.clone(), external_receiver);
 //     external_api.run(db_connection);
 // }
@@ -1,5 +1,14 @@
 pub mod workload {
     use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    /// Namespace of the workloads that don't specify one
+    pub const DEFAULT_NAMESPACE: &str = "default";
+    /// Label of a worker giving the zone it runs in
+    pub const ZONE_LABEL: &str = "topology.rik/zone";
+    /// Label of a worker giving the rack it runs in
+    pub const RACK_LABEL: &str = "topology.rik/rack";
 
     #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
     pub struct EnvConfig {
@@ -15,12 +24,23 @@ pub mod workload {
         pub r#type: String,
     }
 
+    /// Resources reserved for a container
+    #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+    pub struct ResourceRequests {
+        /// CPU, in millicores
+        pub cpu: Option<u32>,
+        /// Memory, in MiB
+        pub memory: Option<u64>,
+    }
+
     #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
     pub struct Container {
         pub name: String,
         pub image: String,
         pub env: Option<Vec<EnvConfig>>,
         pub ports: Option<PortConfig>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub resources: Option<ResourceRequests>,
     }
 
     #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -28,6 +48,112 @@ pub mod workload {
         pub containers: Vec<Container>,
     }
 
+    /// The different kinds of workload, deduced from the `kind` field of a definition
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum WorkloadKind {
+        /// Long-running instances, restarted to match the replicas count
+        Pod,
+        /// Instances that run to completion
+        Job,
+        /// Jobs created on a cron schedule
+        CronJob,
+        /// Exactly one instance on every ready worker matching the node selector
+        DaemonSet,
+    }
+
+    /// Settings of a workload of kind `job`
+    #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+    pub struct JobSpec {
+        /// Number of instances that must succeed for the job to be completed
+        pub completions: Option<u16>,
+        /// Maximum number of instances running at the same time
+        pub parallelism: Option<u16>,
+        /// Number of failed instances tolerated before the job is marked as failed
+        pub backoff_limit: Option<u16>,
+    }
+
+    impl JobSpec {
+        pub fn get_completions(&self) -> u16 {
+            self.completions.unwrap_or(1)
+        }
+
+        pub fn get_parallelism(&self) -> u16 {
+            self.parallelism.unwrap_or(1)
+        }
+
+        pub fn get_backoff_limit(&self) -> u16 {
+            self.backoff_limit.unwrap_or(6)
+        }
+    }
+
+    /// What to do when a cron job is due while a previous job is still running
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub enum ConcurrencyPolicy {
+        /// Jobs can run concurrently
+        #[default]
+        Allow,
+        /// The new job is skipped while the previous one is running
+        Forbid,
+        /// The running job is destroyed and replaced by the new one
+        Replace,
+    }
+
+    /// Keeps the instances of a workload balanced across failure domains, the domain
+    /// of a worker is the value of its `topology_key` label
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    pub struct TopologySpreadConstraint {
+        /// Label of the workers defining the failure domains, e.g. `topology.rik/zone`
+        pub topology_key: String,
+        /// Maximum difference of instances between two domains
+        pub max_skew: Option<u16>,
+    }
+
+    impl TopologySpreadConstraint {
+        pub fn get_max_skew(&self) -> u16 {
+            self.max_skew.unwrap_or(1).max(1)
+        }
+    }
+
+    /// All the instances of a workload are placed together, or none of them is
+    #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+    pub struct GangScheduling {
+        /// Delay to find room for all the instances, in seconds, after which the
+        /// workload is reported unschedulable
+        pub timeout: Option<u64>,
+    }
+
+    impl GangScheduling {
+        pub fn get_timeout(&self) -> Duration {
+            Duration::from_secs(self.timeout.unwrap_or(60))
+        }
+    }
+
+    /// Settings of a workload of kind `cronjob`
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    pub struct CronJobSpec {
+        /// Cron expression, e.g. `*/5 * * * *`
+        pub schedule: String,
+        pub concurrency_policy: Option<ConcurrencyPolicy>,
+        /// Number of succeeded jobs to keep
+        pub successful_jobs_history_limit: Option<u16>,
+        /// Number of failed jobs to keep
+        pub failed_jobs_history_limit: Option<u16>,
+    }
+
+    impl CronJobSpec {
+        pub fn get_concurrency_policy(&self) -> ConcurrencyPolicy {
+            self.concurrency_policy.unwrap_or_default()
+        }
+
+        pub fn get_successful_jobs_history_limit(&self) -> u16 {
+            self.successful_jobs_history_limit.unwrap_or(3)
+        }
+
+        pub fn get_failed_jobs_history_limit(&self) -> u16 {
+            self.failed_jobs_history_limit.unwrap_or(1)
+        }
+    }
+
     #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
     pub struct WorkloadDefinition {
         pub api_version: String,
@@ -35,5 +161,140 @@ pub mod workload {
         pub name: String,
         pub spec: Spec,
         pub replicas: Option<u16>,
+        /// Only used by `job` workloads, and as the job template of `cronjob` workloads
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub job: Option<JobSpec>,
+        /// Only used by `cronjob` workloads
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub cron_job: Option<CronJobSpec>,
+        /// Labels a worker must have to run instances of this workload
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub node_selector: Option<HashMap<String, String>>,
+        /// Constraints spreading the instances across failure domains, only used by
+        /// `pod` and `job` workloads
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub topology_spread: Option<Vec<TopologySpreadConstraint>>,
+        /// Place the instances all-or-nothing, only used by `pod` and `job` workloads
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub gang: Option<GangScheduling>,
+        /// Tenant owning the workload, its resource quota applies to the workload
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub tenant: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub namespace: Option<String>,
+    }
+
+    impl WorkloadDefinition {
+        pub fn get_kind(&self) -> WorkloadKind {
+            match self.kind.to_lowercase().as_str() {
+                "job" | "jobs" => WorkloadKind::Job,
+                "cronjob" | "cronjobs" => WorkloadKind::CronJob,
+                "daemonset" | "daemonsets" => WorkloadKind::DaemonSet,
+                _ => WorkloadKind::Pod,
+            }
+        }
+
+        pub fn get_namespace(&self) -> &str {
+            self.namespace.as_deref().unwrap_or(DEFAULT_NAMESPACE)
+        }
+
+        /// Get the job settings, falling back to the defaults
+        pub fn get_job_spec(&self) -> JobSpec {
+            self.job.clone().unwrap_or_default()
+        }
+
+        /// Get the resources requested by a single instance, the sum of its containers requests
+        pub fn get_requests(&self) -> ResourceRequests {
+            let requests = self
+                .spec
+                .containers
+                .iter()
+                .filter_map(|container| container.resources.as_ref());
+            ResourceRequests {
+                cpu: Some(requests.clone().filter_map(|r| r.cpu).sum()),
+                memory: Some(requests.filter_map(|r| r.memory).sum()),
+            }
+        }
+    }
+}
+
+pub mod quota {
+    use crate::workload::WorkloadDefinition;
+    use serde::{Deserialize, Serialize};
+    use std::fmt;
+
+    /// Limits on the resources a tenant can use, unset limits are unlimited
+    #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+    pub struct ResourceQuota {
+        pub max_workloads: Option<u64>,
+        pub max_instances: Option<u64>,
+        /// Total CPU requests, in millicores
+        pub cpu: Option<u64>,
+        /// Total memory requests, in MiB
+        pub memory: Option<u64>,
+    }
+
+    /// Resources used by a tenant
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct ResourceUsage {
+        pub workloads: u64,
+        pub instances: u64,
+        pub cpu: u64,
+        pub memory: u64,
+    }
+
+    impl ResourceUsage {
+        /// Account for instances of a workload
+        pub fn add_instances(&mut self, definition: &WorkloadDefinition, count: u64) {
+            let requests = definition.get_requests();
+            self.instances += count;
+            self.cpu += count * requests.cpu.unwrap_or(0) as u64;
+            self.memory += count * requests.memory.unwrap_or(0);
+        }
+    }
+
+    /// A limit of a quota was exceeded
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct QuotaExceeded {
+        /// Name of the exceeded limit
+        pub limit: &'static str,
+        pub requested: u64,
+        pub max: u64,
+    }
+
+    impl fmt::Display for QuotaExceeded {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "Quota exceeded for {}: requested {}, limited to {}",
+                self.limit, self.requested, self.max
+            )
+        }
+    }
+
+    impl std::error::Error for QuotaExceeded {}
+
+    impl ResourceQuota {
+        /// Check the usage fits in the quota
+        pub fn check(&self, usage: &ResourceUsage) -> Result<(), QuotaExceeded> {
+            let limits = [
+                ("max_workloads", self.max_workloads, usage.workloads),
+                ("max_instances", self.max_instances, usage.instances),
+                ("cpu", self.cpu, usage.cpu),
+                ("memory", self.memory, usage.memory),
+            ];
+            for (limit, max, requested) in limits {
+                if let Some(max) = max {
+                    if requested > max {
+                        return Err(QuotaExceeded {
+                            limit,
+                            requested,
+                            max,
+                        });
+                    }
+                }
+            }
+            Ok(())
+        }
     }
 }
@@ -0,0 +1,202 @@
+//! Logs and traces shared by the components of the cluster.
+//!
+//! Records are written to stdout, as readable lines or JSON objects. When an OTLP
+//! endpoint is configured, spans are also exported to it, so a request can be followed
+//! from the controller to the scheduler and the riklet running its instances.
+//! The context of a trace is propagated with W3C `traceparent` headers, in gRPC
+//! metadata or in the messages sent over gRPC streams.
+
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use opentelemetry::sdk::{trace, Resource};
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use std::collections::HashMap;
+use std::error::Error;
+use std::str::FromStr;
+use tonic::metadata::{KeyRef, MetadataKey, MetadataMap, MetadataValue};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+/// Trace context of a span, as W3C trace context headers
+pub type TraceContext = HashMap<String, String>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// One colored line per record
+    Human,
+    /// One JSON object per record, with the fields of its spans
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "human" => Ok(LogFormat::Human),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!(
+                "unknown log format {}, expected human or json",
+                format
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TelemetryConfig {
+    /// Name of the component, reported as the `service.name` of its spans
+    pub service_name: String,
+    /// Level of the records, or a filter such as `info,controller=debug`
+    pub filter: String,
+    pub format: LogFormat,
+    /// OTLP gRPC endpoint spans are exported to, e.g. `http://127.0.0.1:4317`
+    pub otlp_endpoint: Option<String>,
+}
+
+/// Install the global subscriber. Records of the `log` crate are handled as well.
+/// The OTLP exporter runs in background on the current Tokio runtime.
+pub fn init(config: &TelemetryConfig) -> Result<(), Box<dyn Error>> {
+    let filter = EnvFilter::try_new(&config.filter)
+        .map_err(|e| format!("invalid log level {}: {}", config.filter, e))?;
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let otlp = match &config.otlp_endpoint {
+        Some(endpoint) => {
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(trace::config().with_resource(Resource::new(vec![
+                    KeyValue::new("service.name", config.service_name.clone()),
+                ])))
+                .install_batch(opentelemetry::runtime::Tokio)?;
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        None => None,
+    };
+
+    let registry = tracing_subscriber::registry().with(filter).with(otlp);
+    match config.format {
+        LogFormat::Human => registry.with(tracing_subscriber::fmt::layer()).try_init()?,
+        LogFormat::Json => registry
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_current_span(false),
+            )
+            .try_init()?,
+    }
+    Ok(())
+}
+
+/// Export the spans which are not exported yet
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+/// Trace context of the current span
+pub fn current_context() -> TraceContext {
+    let mut context = TraceContext::new();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&Span::current().context(), &mut context)
+    });
+    context
+}
+
+/// Continue the trace of a context received from another component
+pub fn set_parent(span: &Span, context: &TraceContext) {
+    let parent = global::get_text_map_propagator(|propagator| propagator.extract(context));
+    span.set_parent(parent);
+}
+
+/// Add the trace context of the current span to the metadata of a gRPC request
+pub fn inject_metadata(metadata: &mut MetadataMap) {
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&Span::current().context(), &mut MetadataInjector(metadata))
+    });
+}
+
+/// Continue the trace of the context found in the metadata of a gRPC request
+pub fn set_parent_from_metadata(span: &Span, metadata: &MetadataMap) {
+    let parent = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&MetadataExtractor(metadata))
+    });
+    span.set_parent(parent);
+}
+
+struct MetadataInjector<'a>(&'a mut MetadataMap);
+
+impl Injector for MetadataInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(key), Ok(value)) = (
+            MetadataKey::from_bytes(key.as_bytes()),
+            MetadataValue::try_from(&value),
+        ) {
+            self.0.insert(key, value);
+        }
+    }
+}
+
+struct MetadataExtractor<'a>(&'a MetadataMap);
+
+impl Extractor for MetadataExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0
+            .keys()
+            .map(|key| match key {
+                KeyRef::Ascii(key) => key.as_str(),
+                KeyRef::Binary(key) => key.as_str(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{TraceContextExt, TracerProvider};
+
+    #[test]
+    fn test_context_propagation() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = trace::TracerProvider::builder().build();
+        let tracer = provider.tracer("test");
+        let subscriber =
+            tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let request = tracing::info_span!("request");
+            let trace_id = request.context().span().span_context().trace_id();
+
+            let mut metadata = MetadataMap::new();
+            request.in_scope(|| inject_metadata(&mut metadata));
+            assert!(metadata.get("traceparent").is_some());
+
+            let grpc = tracing::info_span!(parent: None, "grpc");
+            set_parent_from_metadata(&grpc, &metadata);
+            assert_eq!(grpc.context().span().span_context().trace_id(), trace_id);
+
+            let context = grpc.in_scope(current_context);
+            let instance = tracing::info_span!(parent: None, "instance");
+            set_parent(&instance, &context);
+            assert_eq!(
+                instance.context().span().span_context().trace_id(),
+                trace_id
+            );
+        });
+
+        assert!("yaml".parse::<LogFormat>().is_err());
+    }
+}
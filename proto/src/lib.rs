@@ -1,4 +1,5 @@
-use common::{ResourceStatus, WorkloadRequestKind};
+use common::{Event, EventType, ResourceStatus, WorkloadRequestKind};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub mod common {
     tonic::include_proto!("common");
@@ -16,6 +17,7 @@ impl From<i32> for WorkloadRequestKind {
     fn from(w: i32) -> Self {
         match w {
             1 => WorkloadRequestKind::Destroy,
+            2 => WorkloadRequestKind::Reconcile,
             _ => WorkloadRequestKind::Create,
         }
     }
@@ -24,6 +26,7 @@ impl From<i32> for WorkloadRequestKind {
 impl From<i32> for ResourceStatus {
     fn from(w: i32) -> Self {
         match w {
+            7 => ResourceStatus::Succeeded,
             6 => ResourceStatus::Destroying,
             5 => ResourceStatus::Creating,
             4 => ResourceStatus::Terminated,
@@ -35,4 +38,37 @@ impl From<i32> for ResourceStatus {
     }
 }
 
+impl Event {
+    pub const WORKLOAD: &'static str = "workload";
+    pub const INSTANCE: &'static str = "instance";
+    pub const WORKER: &'static str = "worker";
+
+    /// Create a normal event about an object, emitted now
+    pub fn new(object_kind: &str, object_id: &str, reason: &str, message: String) -> Event {
+        Event {
+            object_kind: object_kind.to_string(),
+            object_id: object_id.to_string(),
+            reason: reason.to_string(),
+            message,
+            event_type: EventType::Normal.into(),
+            source: String::new(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs() as i64)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Mark the event as a warning, something went wrong
+    pub fn warning(mut self) -> Event {
+        self.event_type = EventType::Warning.into();
+        self
+    }
+
+    pub fn with_source(mut self, source: &str) -> Event {
+        self.source = source.to_string();
+        self
+    }
+}
+
 pub extern crate protobuf;
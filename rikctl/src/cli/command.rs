@@ -14,6 +14,7 @@ impl CreateCommand {
         match self.resource {
             CreateResource::Workload(handler) => Box::new(handler),
             CreateResource::Instance(handler) => Box::new(handler),
+            CreateResource::Namespace(handler) => Box::new(handler),
         }
     }
 }
@@ -30,6 +31,8 @@ impl GetMultipleCommand {
         match self.resource {
             GetMultipleResource::Instances(handler) => Box::new(handler),
             GetMultipleResource::Workload(handler) => Box::new(handler),
+            GetMultipleResource::Namespaces(handler) => Box::new(handler),
+            GetMultipleResource::Events(handler) => Box::new(handler),
         }
     }
 }
@@ -0,0 +1,49 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use clap::Args;
+use prettytable::row;
+
+use crate::cli::Handler;
+use crate::core::client::{Client, EventClient};
+use crate::core::config::Configuration;
+use crate::core::get_display_table;
+
+#[derive(Debug, Args)]
+pub struct GetMultipleEvent {
+    /// Kind of the objects the events are about: workload, instance or worker.
+    #[clap(short, long)]
+    pub kind: Option<String>,
+
+    /// Identifier of the object the events are about.
+    #[clap(short, long)]
+    pub object: Option<String>,
+}
+
+#[async_trait]
+impl Handler for GetMultipleEvent {
+    async fn handler(&self) -> Result<()> {
+        let config = Configuration::load()?;
+        let events = Client::init(config.cluster)
+            .get_events(self.kind.as_deref(), self.object.as_deref())
+            .await?;
+
+        let mut table = get_display_table();
+        table.set_titles(row!["AGE", "TYPE", "REASON", "OBJECT", "SOURCE", "MESSAGE"]);
+        if events.is_empty() {
+            table.add_row(row!["", "", "", "", "", ""]);
+        }
+        for event in events {
+            table.add_row(row![
+                event.value.age(),
+                event.value.event_type,
+                event.value.reason,
+                format!("{}/{}", event.value.object_kind, event.value.object_id),
+                event.value.source,
+                event.value.message
+            ]);
+        }
+
+        table.printstd();
+        Ok(())
+    }
+}
@@ -7,6 +7,7 @@ use anyhow::Result;
 use async_trait::async_trait;
 use clap::Args;
 use prettytable::row;
+use std::time::Duration;
 #[derive(Debug, Args)]
 pub struct CreateInstance {
     #[clap(short, long)]
@@ -35,13 +36,32 @@ impl Handler for CreateInstance {
 }
 
 #[derive(Debug, Args)]
-pub struct GetMultipleInstance {}
+pub struct GetMultipleInstance {
+    /// Namespace of the instances.
+    #[clap(short, long)]
+    pub namespace: Option<String>,
+
+    /// List the instances of every namespace.
+    #[clap(short = 'A', long)]
+    pub all_namespaces: bool,
+
+    /// Watch the changes of the instances after listing them.
+    #[clap(short, long)]
+    pub watch: bool,
+}
 
 #[async_trait]
 impl Handler for GetMultipleInstance {
     async fn handler(&self) -> Result<()> {
         let config = Configuration::load()?;
-        let instances = Client::init(config.cluster).get_instances().await?;
+        let namespace = config.cluster.namespace(&self.namespace);
+        let namespace = (!self.all_namespaces).then_some(namespace.as_str());
+        if self.watch {
+            return watch_instances(&Client::init(config.cluster.clone()), namespace).await;
+        }
+        let instances = Client::init(config.cluster.clone())
+            .get_instances(namespace)
+            .await?;
 
         let mut table = get_display_table();
         table.set_titles(row!["ID", "NAME", "STATUS"]);
@@ -56,3 +76,42 @@ impl Handler for GetMultipleInstance {
         Ok(())
     }
 }
+
+/// Print the changes of the instances as they happen, the watch resumes
+/// after the last change received when the connection is lost.
+async fn watch_instances(client: &Client, namespace: Option<&str>) -> Result<()> {
+    println!("{:<10} {:<38} {:<32} STATUS", "EVENT", "ID", "NAME");
+    let mut resource_version = None;
+    loop {
+        let mut stream = match client.watch_instances(namespace, resource_version).await {
+            Ok(stream) => stream,
+            // The changes after the last one received may be compacted already
+            Err(e) if resource_version.is_some() => {
+                eprintln!("Cannot resume the watch: {}", e);
+                resource_version = None;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        loop {
+            match stream.next().await {
+                Ok(Some(event)) => {
+                    resource_version = Some(event.resource_version);
+                    println!(
+                        "{:<10} {:<38} {:<32} {}",
+                        event.event_type,
+                        event.object.id,
+                        event.object.name,
+                        event.object.value.status
+                    );
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    eprintln!("Watch interrupted: {}", e);
+                    break;
+                }
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
@@ -1,7 +1,11 @@
+mod event;
 mod instance;
+mod namespace;
 mod workload;
 
+use crate::cli::resource::event::GetMultipleEvent;
 use crate::cli::resource::instance::{CreateInstance, GetMultipleInstance};
+use crate::cli::resource::namespace::{CreateNamespace, GetMultipleNamespace};
 use crate::cli::resource::workload::{CreateWorkload, GetMultipleWorkload};
 use clap::Subcommand;
 
@@ -11,6 +15,8 @@ pub enum CreateResource {
     Workload(CreateWorkload),
     /// Create an instance
     Instance(CreateInstance),
+    /// Create a namespace
+    Namespace(CreateNamespace),
 }
 
 #[derive(Debug, Subcommand)]
@@ -19,4 +25,8 @@ pub enum GetMultipleResource {
     Instances(GetMultipleInstance),
     /// List workloads,
     Workload(GetMultipleWorkload),
+    /// List namespaces
+    Namespaces(GetMultipleNamespace),
+    /// List events, oldest first
+    Events(GetMultipleEvent),
 }
@@ -0,0 +1,67 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use clap::Args;
+use prettytable::row;
+
+use crate::cli::Handler;
+use crate::core::client::{Client, NamespaceClient};
+use crate::core::config::Configuration;
+use crate::core::get_display_table;
+use crate::core::namespace::Namespace;
+
+#[derive(Debug, Args)]
+pub struct CreateNamespace {
+    /// Name of the namespace
+    pub name: String,
+    /// Tenant owning the namespace
+    #[clap(short, long)]
+    pub tenant: Option<String>,
+}
+
+#[async_trait]
+impl Handler for CreateNamespace {
+    async fn handler(&self) -> Result<()> {
+        let config = Configuration::load()?;
+
+        let namespace = Namespace {
+            name: self.name.clone(),
+            tenant: self.tenant.clone(),
+        };
+        let namespace_id = Client::init(config.cluster)
+            .create_namespace(&namespace)
+            .await?;
+
+        println!(
+            "Namespace {} has been successfully created with ID : {}",
+            &namespace.name, namespace_id
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct GetMultipleNamespace {}
+
+#[async_trait]
+impl Handler for GetMultipleNamespace {
+    async fn handler(&self) -> Result<()> {
+        let config = Configuration::load()?;
+        let namespaces = Client::init(config.cluster).get_namespaces().await?;
+
+        let mut table = get_display_table();
+        table.set_titles(row!["ID", "NAME", "TENANT"]);
+        if namespaces.is_empty() {
+            table.add_row(row!["", "", ""]);
+        }
+        for namespace in namespaces {
+            table.add_row(row![
+                namespace.id,
+                namespace.name,
+                namespace.value.tenant.unwrap_or_default()
+            ]);
+        }
+
+        table.printstd();
+        Ok(())
+    }
+}
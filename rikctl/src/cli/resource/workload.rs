@@ -19,6 +19,10 @@ pub struct CreateWorkload {
     /// If present, the output of the command will only be the ID of the workload.
     #[clap(short, long)]
     pub quiet: bool,
+
+    /// Namespace of the workload, if not defined in the workload file.
+    #[clap(short, long)]
+    pub namespace: Option<String>,
 }
 
 #[async_trait]
@@ -27,7 +31,10 @@ impl Handler for CreateWorkload {
         let config = Configuration::load()?;
 
         // Parse the workload file
-        let workload = Workload::try_from(self.file.clone())?;
+        let mut workload = Workload::try_from(self.file.clone())?;
+        if workload.namespace.is_none() {
+            workload.namespace = Some(config.cluster.namespace(&self.namespace));
+        }
         let workload_id = Client::init(config.cluster)
             .create_workload(&workload)
             .await?;
@@ -41,25 +48,51 @@ impl Handler for CreateWorkload {
 }
 
 #[derive(Debug, Args)]
-pub struct GetMultipleWorkload {}
+pub struct GetMultipleWorkload {
+    /// Namespace of the workloads.
+    #[clap(short, long)]
+    pub namespace: Option<String>,
+
+    /// List the workloads of every namespace.
+    #[clap(short = 'A', long)]
+    pub all_namespaces: bool,
+}
 
 #[async_trait]
 impl Handler for GetMultipleWorkload {
     async fn handler(&self) -> Result<()> {
         let config = Configuration::load()?;
-        let workloads = Client::init(config.cluster).get_workloads().await?;
+        let namespace = config.cluster.namespace(&self.namespace);
+        let namespace = (!self.all_namespaces).then_some(namespace.as_str());
+        let workloads = Client::init(config.cluster.clone())
+            .get_workloads(namespace)
+            .await?;
 
         let mut table = get_display_table();
-        table.set_titles(row!["ID", "API VERSION", "NAME", "CONTAINERS"]);
+        table.set_titles(row![
+            "ID",
+            "NAMESPACE",
+            "API VERSION",
+            "KIND",
+            "NAME",
+            "CONTAINERS",
+            "STATUS",
+            "REASON"
+        ]);
         if workloads.is_empty() {
-            table.add_row(row!["", "", "", ""]);
+            table.add_row(row!["", "", "", "", "", "", "", ""]);
         }
         for workload in workloads {
+            let reason = workload.value.scheduling_failure_reason();
             table.add_row(row![
                 workload.id,
+                workload.value.namespace.unwrap_or_default(),
                 workload.value.api_version,
+                workload.value.kind,
                 workload.name,
-                workload.value.spec.containers.len()
+                workload.value.spec.containers.len(),
+                workload.value.status.unwrap_or_default(),
+                reason
             ]);
         }
 
@@ -1,14 +1,24 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
 use reqwest::Client as HttpClient;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
 use crate::core::config;
+use crate::core::event::Event;
+use crate::core::namespace::Namespace;
+use crate::core::watch::WatchStream;
 use crate::core::workload::Workload;
 
 use super::instance::Instance;
 
+/// Resources requested at once by the lists, which are read page by page
+const PAGE_SIZE: &str = "100";
+/// Header giving the token of the next page of a list
+const CONTINUE_HEADER: &str = "X-Continue";
+
 /// `ResponseEntity` holds data about an entity
 /// returned by the API.
 #[derive(Debug, Deserialize, Serialize)]
@@ -20,18 +30,48 @@ pub struct ResponseEntity<T> {
 
 #[async_trait]
 pub trait WorkloadClient {
-    async fn get_workloads(&self) -> Result<Vec<ResponseEntity<Workload>>>;
+    /// List the workloads of a namespace, or of every namespace if `None`
+    async fn get_workloads(&self, namespace: Option<&str>)
+        -> Result<Vec<ResponseEntity<Workload>>>;
     async fn create_workload(&self, workload: &Workload) -> Result<String>;
+    #[allow(dead_code)]
     async fn delete_workload(&self, workload: &str) -> Result<String>;
 }
 
 #[async_trait]
 pub trait InstanceClient {
-    async fn get_instances(&self) -> Result<Vec<ResponseEntity<Instance>>>;
+    /// List the instances of a namespace, or of every namespace if `None`
+    async fn get_instances(&self, namespace: Option<&str>)
+        -> Result<Vec<ResponseEntity<Instance>>>;
+    /// Watch the changes of the instances of a namespace, or of every namespace if `None`.
+    /// The instances are first streamed as added, unless the watch resumes after
+    /// a resource version.
+    async fn watch_instances(
+        &self,
+        namespace: Option<&str>,
+        resource_version: Option<i64>,
+    ) -> Result<WatchStream<Instance>>;
     async fn create_instance(&self, workload_id: &str, replicas: &Option<usize>) -> Result<()>;
+    #[allow(dead_code)]
     async fn delete_instance(&self, workload_id: &str) -> Result<String>;
 }
 
+#[async_trait]
+pub trait NamespaceClient {
+    async fn get_namespaces(&self) -> Result<Vec<ResponseEntity<Namespace>>>;
+    async fn create_namespace(&self, namespace: &Namespace) -> Result<String>;
+}
+
+#[async_trait]
+pub trait EventClient {
+    /// List the events, optionally about a kind of object or an object
+    async fn get_events(
+        &self,
+        kind: Option<&str>,
+        object: Option<&str>,
+    ) -> Result<Vec<ResponseEntity<Event>>>;
+}
+
 /// `Client` provides the ability to interact
 /// with the cluster controller by using HTTP Protocol.
 #[derive(Debug)]
@@ -47,9 +87,22 @@ pub struct Client {
 
 impl Client {
     pub fn init(config: config::Cluster) -> Self {
+        let mut headers = HeaderMap::new();
+        if let Some(token) = &config.token {
+            match HeaderValue::from_str(&format!("Bearer {}", token)) {
+                Ok(mut value) => {
+                    value.set_sensitive(true);
+                    headers.insert(AUTHORIZATION, value);
+                }
+                Err(_) => eprintln!("Invalid cluster token, requests are sent without it"),
+            }
+        }
         Self {
             endpoint: config.server,
-            http_client: HttpClient::new(),
+            http_client: HttpClient::builder()
+                .default_headers(headers)
+                .build()
+                .unwrap_or_default(),
         }
     }
 
@@ -57,15 +110,56 @@ impl Client {
     pub fn endpoint(&self, path: &str) -> String {
         format!("{}/{}", self.endpoint, path)
     }
+
+    /// List every resource of an endpoint, optionally of a namespace,
+    /// following the pages of the list
+    async fn list<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        namespace: Option<&str>,
+    ) -> Result<Vec<ResponseEntity<T>>> {
+        let mut resources = Vec::new();
+        let mut continue_token: Option<String> = None;
+        loop {
+            let params: Vec<(&str, &str)> = [
+                ("limit", Some(PAGE_SIZE)),
+                ("namespace", namespace),
+                ("continue", continue_token.as_deref()),
+            ]
+            .into_iter()
+            .filter_map(|(key, value)| value.map(|value| (key, value)))
+            .collect();
+            let response = self
+                .http_client
+                .get(self.endpoint(path))
+                .query(&params)
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                return Err(anyhow::Error::msg(response.text().await?));
+            }
+            let next = response
+                .headers()
+                .get(CONTINUE_HEADER)
+                .and_then(|token| token.to_str().ok())
+                .map(String::from);
+            let page: Vec<ResponseEntity<T>> = serde_json::from_str(&response.text().await?)?;
+            resources.extend(page);
+            match next {
+                Some(next) => continue_token = Some(next),
+                None => return Ok(resources),
+            }
+        }
+    }
 }
 
 #[async_trait]
 impl WorkloadClient for Client {
-    async fn get_workloads(&self) -> Result<Vec<ResponseEntity<Workload>>> {
-        let endpoint = self.endpoint("api/v0/workloads.list");
-        let response = self.http_client.get(endpoint).send().await?;
-        let data: Vec<ResponseEntity<Workload>> = serde_json::from_str(&response.text().await?)?;
-        Ok(data)
+    async fn get_workloads(
+        &self,
+        namespace: Option<&str>,
+    ) -> Result<Vec<ResponseEntity<Workload>>> {
+        self.list("api/v0/workloads.list", namespace).await
     }
 
     async fn create_workload(&self, workload: &Workload) -> Result<String> {
@@ -88,11 +182,33 @@ impl WorkloadClient for Client {
 }
 #[async_trait]
 impl InstanceClient for Client {
-    async fn get_instances(&self) -> Result<Vec<ResponseEntity<Instance>>> {
-        let endpoint = self.endpoint("api/v0/instances.list");
-        let response = self.http_client.get(endpoint).send().await?;
-        let data: Vec<ResponseEntity<Instance>> = serde_json::from_str(&response.text().await?)?;
-        Ok(data)
+    async fn get_instances(
+        &self,
+        namespace: Option<&str>,
+    ) -> Result<Vec<ResponseEntity<Instance>>> {
+        self.list("api/v0/instances.list", namespace).await
+    }
+
+    async fn watch_instances(
+        &self,
+        namespace: Option<&str>,
+        resource_version: Option<i64>,
+    ) -> Result<WatchStream<Instance>> {
+        let endpoint = self.endpoint("api/v1/instances");
+        let resource_version = resource_version.map(|version| version.to_string());
+        let params: Vec<(&str, &str)> = [
+            ("watch", Some("true")),
+            ("namespace", namespace),
+            ("resourceVersion", resource_version.as_deref()),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|value| (key, value)))
+        .collect();
+        let response = self.http_client.get(endpoint).query(&params).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow::Error::msg(response.text().await?));
+        }
+        Ok(WatchStream::new(response))
     }
 
     async fn create_instance(&self, workload_id: &str, replicas: &Option<usize>) -> Result<()> {
@@ -135,3 +251,56 @@ impl InstanceClient for Client {
         Ok(json.to_string())
     }
 }
+
+#[async_trait]
+impl NamespaceClient for Client {
+    async fn get_namespaces(&self) -> Result<Vec<ResponseEntity<Namespace>>> {
+        let endpoint = self.endpoint("api/v0/namespaces.list");
+        let response = self.http_client.get(endpoint).send().await?;
+        let data: Vec<ResponseEntity<Namespace>> = serde_json::from_str(&response.text().await?)?;
+        Ok(data)
+    }
+
+    async fn create_namespace(&self, namespace: &Namespace) -> Result<String> {
+        let endpoint = self.endpoint("api/v0/namespaces.create");
+
+        let response = self
+            .http_client
+            .post(endpoint)
+            .body(serde_json::to_string(namespace)?)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow::Error::msg(response.text().await?));
+        }
+        let json: Value = serde_json::from_str(&response.text().await?)?;
+        Ok(json["id"].to_string())
+    }
+}
+
+#[async_trait]
+impl EventClient for Client {
+    async fn get_events(
+        &self,
+        kind: Option<&str>,
+        object: Option<&str>,
+    ) -> Result<Vec<ResponseEntity<Event>>> {
+        let endpoint = self.endpoint("api/v0/events.list");
+        let filters: Vec<(&str, &str)> = [("kind", kind), ("object", object)]
+            .into_iter()
+            .filter_map(|(key, value)| value.map(|value| (key, value)))
+            .collect();
+        let response = self
+            .http_client
+            .get(endpoint)
+            .query(&filters)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(anyhow::Error::msg(response.text().await?));
+        }
+        let data: Vec<ResponseEntity<Event>> = serde_json::from_str(&response.text().await?)?;
+        Ok(data)
+    }
+}
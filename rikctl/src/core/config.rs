@@ -2,6 +2,9 @@ use anyhow::{Context, Error, Result};
 use config::{Config, Environment, File};
 use serde::{Deserialize, Serialize};
 
+/// Namespace of the cluster resources when none is configured
+pub const DEFAULT_NAMESPACE: &str = "default";
+
 /// `Configuration` hold the configuration of the tool
 /// in order to be able to interact with the remote cluster.
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
@@ -17,6 +20,12 @@ pub struct Cluster {
     pub name: String,
     /// The endpoint of the cluster
     pub server: String,
+    /// The namespace used when none is given on the command line
+    #[serde(default)]
+    pub namespace: Option<String>,
+    /// The bearer token sent to authenticate the requests
+    #[serde(default)]
+    pub token: Option<String>,
 }
 
 impl Configuration {
@@ -49,11 +58,24 @@ impl Configuration {
     }
 }
 
+impl Cluster {
+    /// Get the namespace to use, the one given on the command line
+    /// takes precedence over the one of the configuration
+    pub fn namespace(&self, namespace: &Option<String>) -> String {
+        namespace
+            .clone()
+            .or_else(|| self.namespace.clone())
+            .unwrap_or_else(|| DEFAULT_NAMESPACE.to_string())
+    }
+}
+
 impl Default for Cluster {
     fn default() -> Self {
         Self {
             name: "RIK-local".to_string(),
             server: "http://127.0.0.1:5000".to_string(),
+            namespace: None,
+            token: None,
         }
     }
 }
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// `Event` describes a lifecycle transition of an object of the cluster,
+/// e.g. an instance being scheduled on a worker.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Event {
+    pub object_kind: String,
+    pub object_id: String,
+    pub reason: String,
+    pub message: String,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub source: String,
+    /// Seconds since the UNIX epoch
+    pub timestamp: i64,
+}
+
+impl Event {
+    /// Time elapsed since the event, in a short human readable form
+    pub fn age(&self) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() as i64)
+            .unwrap_or_default();
+        format_age(now - self.timestamp)
+    }
+}
+
+fn format_age(seconds: i64) -> String {
+    match seconds.max(0) {
+        seconds if seconds < 60 => format!("{}s", seconds),
+        seconds if seconds < 3600 => format!("{}m", seconds / 60),
+        seconds if seconds < 86400 => format!("{}h", seconds / 3600),
+        seconds => format!("{}d", seconds / 86400),
+    }
+}
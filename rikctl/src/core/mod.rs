@@ -2,7 +2,10 @@ use prettytable::{format, Table};
 
 pub mod client;
 pub mod config;
+pub mod event;
 pub mod instance;
+pub mod namespace;
+pub mod watch;
 pub mod workload;
 
 pub fn get_display_table() -> Table {
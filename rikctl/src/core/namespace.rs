@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+/// `Namespace` groups resources of the cluster, names are unique within a namespace.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Namespace {
+    pub name: String,
+    /// Tenant owning the namespace, its roles apply to the resources of the namespace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
@@ -0,0 +1,66 @@
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::marker::PhantomData;
+
+use crate::core::client::ResponseEntity;
+
+/// `WatchEvent` describes a change of a watched resource.
+#[derive(Debug, Deserialize)]
+pub struct WatchEvent<T> {
+    /// `ADDED`, `MODIFIED` or `DELETED`
+    #[serde(rename = "type")]
+    pub event_type: String,
+    /// A watch resumes after the resource version of the last event it received
+    pub resource_version: i64,
+    pub object: ResponseEntity<T>,
+}
+
+/// `WatchStream` reads the changes streamed by the controller
+/// as server-sent events.
+pub struct WatchStream<T> {
+    response: reqwest::Response,
+    buffer: Vec<u8>,
+    resource: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned> WatchStream<T> {
+    pub fn new(response: reqwest::Response) -> Self {
+        Self {
+            response,
+            buffer: Vec::new(),
+            resource: PhantomData,
+        }
+    }
+
+    /// Wait for the next change, `None` once the controller closed the stream
+    pub async fn next(&mut self) -> Result<Option<WatchEvent<T>>> {
+        loop {
+            // Server-sent events end with an empty line
+            if let Some(end) = self.buffer.windows(2).position(|bytes| bytes == b"\n\n") {
+                let message: Vec<u8> = self.buffer.drain(..end + 2).collect();
+                match parse_message(&String::from_utf8_lossy(&message))? {
+                    Some(event) => return Ok(Some(event)),
+                    None => continue,
+                }
+            }
+            match self.response.chunk().await? {
+                Some(chunk) => self.buffer.extend_from_slice(&chunk),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Parse a server-sent event, heartbeats carry no data
+fn parse_message<T: DeserializeOwned>(message: &str) -> Result<Option<WatchEvent<T>>> {
+    let data: Vec<&str> = message
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(str::trim_start)
+        .collect();
+    if data.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&data.join("\n"))?))
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::fs::File;
 use std::path::PathBuf;
 
@@ -9,6 +10,46 @@ pub struct Workload {
     pub kind: String,
     pub name: String,
     pub spec: Spec,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replicas: Option<u16>,
+    /// Settings of a `job` workload (completions, parallelism, backoff_limit).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job: Option<Value>,
+    /// Settings of a `cronjob` workload (schedule, concurrency_policy, history limits).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cron_job: Option<Value>,
+    /// Labels a worker must have to run the workload instances.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_selector: Option<Value>,
+    /// Constraints spreading the workload instances across failure domains
+    /// (topology_key, max_skew).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub topology_spread: Option<Value>,
+    /// Place all the workload instances together or none of them (timeout).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gang: Option<Value>,
+    /// Tenant owning the workload, its quota applies to the workload.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Status reported by the cluster, e.g. the outcome of a job.
+    #[serde(skip_serializing)]
+    pub status: Option<String>,
+    /// Why the cluster could not schedule the workload (reason, message).
+    #[serde(skip_serializing)]
+    pub scheduling_failure: Option<Value>,
+}
+
+impl Workload {
+    /// Machine-readable reason of the last scheduling failure, if any
+    pub fn scheduling_failure_reason(&self) -> String {
+        self.scheduling_failure
+            .as_ref()
+            .and_then(|failure| failure["reason"].as_str())
+            .unwrap_or_default()
+            .to_string()
+    }
 }
 
 /// `Spec` hold the workload specification.
@@ -24,6 +65,9 @@ pub struct Spec {
 pub struct Container {
     pub name: String,
     pub image: String,
+    /// Resources requested by the container (cpu in millicores, memory in MiB).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<Value>,
 }
 
 /// Workload related errors
@@ -95,6 +95,47 @@ impl Runc {
         self.exec(&args).await.map(|_| ())
     }
 
+    /// Run a container in the foreground until its process exits, then return its exit code.
+    ///
+    /// Unlike the other commands, there is no timeout as the container can run for
+    /// an unbounded amount of time.
+    pub async fn run_to_completion(
+        &self,
+        id: &str,
+        bundle: &Path,
+        opts: Option<&CreateArgs>,
+    ) -> Result<i32> {
+        let mut args = vec![String::from("run")];
+        Self::append_opts(&mut args, opts.map(|opts| opts as &dyn Args))?;
+
+        let bundle: String = bundle
+            .canonicalize()
+            .context(InvalidPathError {})?
+            .to_string_lossy()
+            .parse()
+            .unwrap();
+
+        args.push(String::from("--bundle"));
+        args.push(bundle);
+        args.push(String::from(id));
+
+        let args = self.concat_args(&args)?;
+        debug!("{} {}", self.command.to_str().unwrap(), &args.join(" "));
+
+        let status = Command::new(&self.command)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await
+            .context(ProcessSpawnError {})?;
+
+        // runc forwards the exit code of the container process, a missing code
+        // means the process was killed by a signal
+        Ok(status.code().unwrap_or(-1))
+    }
+
     /// Get the state of a container
     pub async fn state(&self, id: &str) -> Result<Container> {
         let args = vec![String::from("state"), String::from(id)];
@@ -144,7 +185,7 @@ impl Executable for Runc {
     async fn exec(&self, args: &[String]) -> Result<String> {
         let args = self.concat_args(args)?;
         let process = Command::new(&self.command)
-            .args(&args.clone())
+            .args(args.clone())
             .stdin(Stdio::null())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
@@ -283,6 +324,7 @@ impl Drop for Runc {
 /// these tests should be ignored because we can't run Runc container in CI pipeline.
 /// To run, use the following `cargo test --workspace --ignored`
 #[cfg(test)]
+#[allow(clippy::ptr_arg, clippy::field_reassign_with_default)]
 mod tests {
     use uuid::Uuid;
 
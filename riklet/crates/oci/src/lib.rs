@@ -49,7 +49,7 @@ trait Executable: Args {
 
     fn concat_args(&self, args: &[String]) -> Result<Vec<String>> {
         let mut combined = self.args()?;
-        combined.append(&mut args.iter().cloned().map(String::from).collect());
+        combined.append(&mut args.to_vec());
         Ok(combined)
     }
 
@@ -150,7 +150,7 @@ impl Executable for Skopeo {
     async fn exec(&self, args: &[String]) -> Result<String> {
         let args = self.concat_args(args)?;
         let process = Command::new(&self.command)
-            .args(&args.clone())
+            .args(args.clone())
             .stdin(Stdio::null())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
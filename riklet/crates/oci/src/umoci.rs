@@ -96,7 +96,7 @@ impl Executable for Umoci {
     async fn exec(&self, args: &[String]) -> Result<String> {
         let args = self.concat_args(args)?;
         let process = Command::new(&self.command)
-            .args(&args.clone())
+            .args(args.clone())
             .stdin(Stdio::null())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
@@ -33,7 +33,7 @@ pub fn generate_hash<T: Hash>(t: &T) -> u64 {
 /// Unpack a .tar.gz archive into the provided location
 pub fn unpack(archive: &str, dest: &Path) -> std::io::Result<()> {
     let tar_gz =
-        File::open(archive).expect(&format!("Unable to unzip the archive {}", archive)[..]);
+        File::open(archive).unwrap_or_else(|_| panic!("Unable to unzip the archive {}", archive));
     let tar = GzDecoder::new(tar_gz);
     let mut archive = Archive::new(tar);
     archive.unpack(dest).unwrap();
@@ -0,0 +1,56 @@
+use crate::config::{Configuration, TlsConfiguration};
+use proto::worker::worker_client::WorkerClient;
+use std::error::Error;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
+use tonic::{Request, Status};
+
+/// gRPC client of the scheduler workers endpoint
+pub type SchedulerClient = WorkerClient<InterceptedService<Channel, JoinToken>>;
+
+/// Add the bootstrap token of the riklet to every request sent to the scheduler
+#[derive(Clone)]
+pub struct JoinToken(Option<MetadataValue<Ascii>>);
+
+impl Interceptor for JoinToken {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(token) = &self.0 {
+            request
+                .metadata_mut()
+                .insert("authorization", token.clone());
+        }
+        Ok(request)
+    }
+}
+
+fn load_tls_config(tls: &TlsConfiguration) -> Result<ClientTlsConfig, Box<dyn Error>> {
+    let mut config =
+        ClientTlsConfig::new().ca_certificate(Certificate::from_pem(std::fs::read(&tls.ca_cert)?));
+    if let (Some(cert), Some(key)) = (&tls.cert, &tls.key) {
+        config = config.identity(Identity::from_pem(
+            std::fs::read(cert)?,
+            std::fs::read(key)?,
+        ));
+    }
+    if let Some(domain) = &tls.domain {
+        config = config.domain_name(domain);
+    }
+    Ok(config)
+}
+
+/// Connect to the scheduler, over TLS if configured
+pub async fn connect(config: &Configuration) -> Result<SchedulerClient, Box<dyn Error>> {
+    let mut endpoint = Endpoint::from_shared(config.master_ip.clone())?;
+    if let Some(tls) = &config.tls {
+        endpoint = endpoint.tls_config(load_tls_config(tls)?)?;
+    }
+    let channel = endpoint.connect().await?;
+
+    let token = match &config.join_token {
+        Some(token) => Some(format!("Bearer {}", token).parse()?),
+        None => None,
+    };
+    Ok(WorkerClient::with_interceptor(channel, JoinToken(token)))
+}
@@ -1,4 +1,5 @@
 use cri::container::RuncConfiguration;
+use definition::workload::{RACK_LABEL, ZONE_LABEL};
 use log::{debug, info};
 use oci::image_manager::ImageManagerConfiguration;
 use oci::skopeo::SkopeoConfiguration;
@@ -6,14 +7,16 @@ use oci::umoci::UmociConfiguration;
 use serde::{Deserialize, Serialize};
 use shared::utils::{create_directory_if_not_exists, create_file_with_parent_folders};
 use snafu::Snafu;
+use std::collections::HashMap;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use crate::constants::DEFAULT_COMMAND_TIMEOUT;
 use clap::Parser;
-use env_logger::Env;
+use telemetry::{LogFormat, TelemetryConfig};
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Snafu)]
 pub enum Error {
     #[snafu(display("Unable to load the configuration file. Error {}", source))]
@@ -34,6 +37,7 @@ pub enum Error {
         source: std::io::Error,
         path: PathBuf,
     },
+    #[allow(dead_code)]
     #[snafu(display("Unable to parse the IP. Error {}", source))]
     InvalidIpError { source: std::net::AddrParseError },
 }
@@ -58,6 +62,35 @@ pub struct CliConfiguration {
         help = "If set and there is a config file, values defined by the CLI will override values of the configuration file."
     )]
     pub override_config: bool,
+    #[arg(
+        short,
+        long = "label",
+        value_parser = parse_label,
+        help = "A label of the worker, in the key=value format. Can be used multiple times."
+    )]
+    pub labels: Vec<(String, String)>,
+    #[arg(long, help = "The zone the worker runs in, used to spread workloads.")]
+    pub zone: Option<String>,
+    #[arg(long, help = "The rack the worker runs in, used to spread workloads.")]
+    pub rack: Option<String>,
+    #[arg(
+        long,
+        help = "The bootstrap token presented to the scheduler to join the cluster."
+    )]
+    pub join_token: Option<String>,
+    #[arg(
+        long,
+        help = "The OTLP gRPC endpoint the traces are exported to, e.g. http://127.0.0.1:4317."
+    )]
+    pub otlp_endpoint: Option<String>,
+}
+
+/// Parse a label given in the key=value format
+fn parse_label(label: &str) -> Result<(String, String), String> {
+    match label.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("invalid label {}, expected key=value", label)),
+    }
 }
 
 impl CliConfiguration {
@@ -75,8 +108,38 @@ impl CliConfiguration {
 pub struct Configuration {
     pub master_ip: String,
     pub log_level: String,
+    /// Bootstrap token presented to the scheduler to join the cluster
+    #[serde(default)]
+    pub join_token: Option<String>,
     pub runner: RuncConfiguration,
     pub manager: ImageManagerConfiguration,
+    /// Labels of the worker, used by the scheduler to match workloads node selectors
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    /// Zone of the worker, advertised with the `topology.rik/zone` label
+    #[serde(default)]
+    pub zone: Option<String>,
+    /// Rack of the worker, advertised with the `topology.rik/rack` label
+    #[serde(default)]
+    pub rack: Option<String>,
+    /// Connect to the scheduler over TLS when set
+    #[serde(default)]
+    pub tls: Option<TlsConfiguration>,
+    /// OTLP endpoint the traces are exported to, traces are not exported when unset
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Serialize, PartialEq, Eq, Clone)]
+pub struct TlsConfiguration {
+    /// PEM encoded CA certificate used to verify the scheduler
+    pub ca_cert: PathBuf,
+    /// PEM encoded client certificate, required if the scheduler verifies clients
+    pub cert: Option<PathBuf>,
+    /// PEM encoded private key of the client certificate
+    pub key: Option<PathBuf>,
+    /// Name expected in the scheduler certificate, the master host by default
+    pub domain: Option<String>,
 }
 
 impl Configuration {
@@ -128,9 +191,13 @@ impl Configuration {
             }
         };
 
-        // Init the logger with the log level defined by the -v option.
-        env_logger::Builder::from_env(Env::default().default_filter_or(opts.get_log_level()))
-            .init();
+        // Init the logger with the log level defined by the -v option, unless RUST_LOG is set.
+        telemetry::init(&TelemetryConfig {
+            service_name: String::from("riklet"),
+            filter: std::env::var("RUST_LOG").unwrap_or_else(|_| opts.get_log_level().to_string()),
+            format: LogFormat::Human,
+            otlp_endpoint: configuration.otlp_endpoint.clone(),
+        })?;
 
         debug!("Loaded configuration from file {}", path.display());
 
@@ -142,8 +209,37 @@ impl Configuration {
     /// Override the configuration instance
     pub fn override_config(&mut self, opts: &CliConfiguration) {
         if let Some(master_ip) = opts.master_ip.clone() {
-            self.master_ip = format!("http://{}", master_ip);
+            let scheme = match self.tls {
+                Some(_) => "https",
+                None => "http",
+            };
+            self.master_ip = format!("{}://{}", scheme, master_ip);
+        }
+        self.labels.extend(opts.labels.iter().cloned());
+        if let Some(zone) = opts.zone.clone() {
+            self.zone = Some(zone);
+        }
+        if let Some(rack) = opts.rack.clone() {
+            self.rack = Some(rack);
+        }
+        if let Some(join_token) = opts.join_token.clone() {
+            self.join_token = Some(join_token);
+        }
+        if let Some(otlp_endpoint) = opts.otlp_endpoint.clone() {
+            self.otlp_endpoint = Some(otlp_endpoint);
+        }
+    }
+
+    /// Get the labels advertised to the scheduler, including the topology of the worker
+    pub fn worker_labels(&self) -> HashMap<String, String> {
+        let mut labels = self.labels.clone();
+        if let Some(zone) = &self.zone {
+            labels.insert(ZONE_LABEL.to_string(), zone.clone());
+        }
+        if let Some(rack) = &self.rack {
+            labels.insert(RACK_LABEL.to_string(), rack.clone());
         }
+        labels
     }
 
     /// Create all directories and files used by Riklet to work properly
@@ -174,6 +270,7 @@ impl Default for Configuration {
         Self {
             master_ip: String::from("http://127.0.0.1:4995"),
             log_level: String::from("info"),
+            join_token: None,
             runner: RuncConfiguration {
                 debug: false,
                 rootless: false,
@@ -196,6 +293,11 @@ impl Default for Configuration {
                     ..Default::default()
                 },
             },
+            labels: HashMap::new(),
+            zone: None,
+            rack: None,
+            tls: None,
+            otlp_endpoint: None,
         }
     }
 }
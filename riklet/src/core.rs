@@ -1,28 +1,34 @@
+use crate::client::{self, SchedulerClient};
 use crate::config::Configuration;
 use crate::emitters::metrics_emitter::MetricsEmitter;
 use crate::structs::{Container, WorkloadDefinition};
 use crate::traits::EventEmitter;
 use cri::console::ConsoleSocket;
 use cri::container::{CreateArgs, DeleteArgs, Runc};
+use futures_util::future::join_all;
 use node_metrics::metrics_manager::MetricsManager;
 use oci::image_manager::ImageManager;
-use proto::common::{InstanceMetric, WorkerMetric, WorkerRegistration, WorkerStatus};
-use proto::worker::worker_client::WorkerClient;
+use proto::common::{
+    Event as ClusterEvent, InstanceMetric, ResourceStatus, WorkerMetric, WorkerRegistration,
+    WorkerStatus,
+};
 use proto::worker::InstanceScheduling;
 use std::collections::HashMap;
 use std::error::Error;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tonic::{transport::Channel, Request, Streaming};
+use tonic::{Request, Streaming};
+use tracing::{info_span, Instrument};
 
 #[derive(Debug)]
 pub struct Riklet {
     hostname: String,
-    client: WorkerClient<Channel>,
+    client: SchedulerClient,
     stream: Streaming<InstanceScheduling>,
     image_manager: ImageManager,
-    container_runtime: Runc,
-    workloads: HashMap<String, Vec<Container>>,
+    container_runtime: Arc<Runc>,
+    workloads: Arc<Mutex<HashMap<String, Vec<Container>>>>,
 }
 
 impl Riklet {
@@ -52,19 +58,20 @@ impl Riklet {
         let config = Configuration::load()?;
 
         // Connect to the master node scheduler
-        let mut client = WorkerClient::connect(config.master_ip.clone()).await?;
+        let mut client = client::connect(&config).await?;
         log::debug!("gRPC WorkerClient connected.");
 
         // Register this node to the master
         let request = Request::new(WorkerRegistration {
             hostname: hostname.clone(),
+            labels: config.worker_labels(),
         });
         let stream = client.register(request).await?.into_inner();
 
         log::trace!("Registration success");
 
         // Initialize the container runtime
-        let container_runtime = Runc::new(config.runner.clone())?;
+        let container_runtime = Arc::new(Runc::new(config.runner.clone())?);
         // Initialize the image manager
         let image_manager = ImageManager::new(config.manager.clone())?;
 
@@ -74,7 +81,7 @@ impl Riklet {
             image_manager,
             client,
             stream,
-            workloads: HashMap::<String, Vec<Container>>::new(),
+            workloads: Arc::new(Mutex::new(HashMap::<String, Vec<Container>>::new())),
         })
     }
 
@@ -86,7 +93,10 @@ impl Riklet {
         match &workload.action {
             // Create
             0 => {
-                self.create_workload(workload).await?;
+                // Continue the trace of the scheduling request, up to the instance running
+                let span = info_span!("create_workload", instance_id = %workload.instance_id);
+                telemetry::set_parent(&span, &workload.trace_context);
+                self.create_workload(workload).instrument(span).await?;
             }
             // Delete
             1 => {
@@ -110,36 +120,24 @@ impl Riklet {
         let containers = workload_definition.get_containers(instance_id);
 
         // Inform the scheduler that the workload is creating
-        self.send_status(5, instance_id).await;
+        self.send_status(ResourceStatus::Creating, instance_id)
+            .await;
 
         self.workloads
+            .lock()
+            .unwrap()
             .insert(instance_id.clone(), containers.clone());
 
+        if workload_definition.is_job() {
+            return self.run_job(instance_id, containers).await;
+        }
+
         for container in containers {
             let id = container.id.unwrap();
 
-            let image = &self.image_manager.pull(&container.image[..]).await?;
-
-            // New console socket for the container
-            let socket_path = PathBuf::from(format!("/tmp/{}", &id));
-            let console_socket = ConsoleSocket::new(&socket_path)?;
+            let image = &self.pull_image(instance_id, &container.image).await?;
 
-            tokio::spawn(async move {
-                match console_socket
-                    .get_listener()
-                    .as_ref()
-                    .unwrap()
-                    .accept()
-                    .await
-                {
-                    Ok((stream, _socket_addr)) => {
-                        Box::leak(Box::new(stream));
-                    }
-                    Err(err) => {
-                        log::error!("Receive PTY master error : {:?}", err)
-                    }
-                }
-            });
+            let socket_path = Riklet::open_console_socket(&id)?;
             self.container_runtime
                 .run(
                     &id[..],
@@ -161,21 +159,153 @@ impl Riklet {
             "Workload '{}' successfully processed.",
             &workload.instance_id
         );
+        self.send_event(ClusterEvent::new(
+            ClusterEvent::INSTANCE,
+            instance_id,
+            "Started",
+            format!("Started the containers of instance {}", instance_id),
+        ))
+        .await;
 
         // Inform the scheduler that the containers are running
-        self.send_status(2, instance_id).await;
+        self.send_status(ResourceStatus::Running, instance_id).await;
+
+        Ok(())
+    }
+
+    /// Run the containers of a job instance in background. Once every container exited,
+    /// the containers are removed and the outcome of the instance is reported to the scheduler.
+    async fn run_job(
+        &mut self,
+        instance_id: &str,
+        containers: Vec<Container>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut bundles = Vec::new();
+        for container in containers {
+            let id = container.id.unwrap();
+            let image = self.pull_image(instance_id, &container.image).await?;
+            let socket_path = Riklet::open_console_socket(&id)?;
+            bundles.push((id, image.bundle.unwrap(), socket_path));
+        }
+
+        let container_runtime = self.container_runtime.clone();
+        let workloads = self.workloads.clone();
+        let client = self.client.clone();
+        let hostname = self.hostname.clone();
+        let job_id = instance_id.to_string();
+
+        // Inform the scheduler that the containers are running, before they can
+        // complete
+        self.send_status(ResourceStatus::Running, instance_id).await;
+
+        tokio::spawn(async move {
+            let runs = bundles.iter().map(|(id, bundle, socket_path)| {
+                let container_runtime = container_runtime.clone();
+                async move {
+                    container_runtime
+                        .run_to_completion(
+                            &id[..],
+                            bundle,
+                            Some(&CreateArgs {
+                                pid_file: None,
+                                console_socket: Some(socket_path.clone()),
+                                no_pivot: false,
+                                no_new_keyring: false,
+                                detach: false,
+                            }),
+                        )
+                        .await
+                }
+            });
+
+            // The instance succeeds only if every container exited successfully
+            let exit_code = join_all(runs)
+                .await
+                .into_iter()
+                .map(|result| {
+                    result.unwrap_or_else(|e| {
+                        log::error!("Failed to run a container of job '{}': {}", job_id, e);
+                        -1
+                    })
+                })
+                .find(|exit_code| *exit_code != 0)
+                .unwrap_or(0);
+
+            for (id, _, _) in &bundles {
+                if let Err(e) = container_runtime
+                    .delete(&id[..], Some(&DeleteArgs { force: true }))
+                    .await
+                {
+                    log::warn!("Failed to clean up container {}: {}", id, e);
+                }
+            }
+            workloads.lock().unwrap().remove(&job_id);
+
+            let status = if exit_code == 0 {
+                ResourceStatus::Succeeded
+            } else {
+                ResourceStatus::Failed
+            };
+            log::info!("Job '{}' completed with exit code {}.", &job_id, exit_code);
+            let event = match status {
+                ResourceStatus::Succeeded => ClusterEvent::new(
+                    ClusterEvent::INSTANCE,
+                    &job_id,
+                    "Completed",
+                    format!("Instance {} exited successfully", job_id),
+                ),
+                _ => ClusterEvent::new(
+                    ClusterEvent::INSTANCE,
+                    &job_id,
+                    "Failed",
+                    format!("Instance {} exited with code {}", job_id, exit_code),
+                )
+                .warning(),
+            };
+            Riklet::emit_cluster_event(client.clone(), hostname.clone(), event).await;
+            Riklet::emit_instance_status(client, hostname, &job_id, status, exit_code).await;
+        });
+
+        log::info!("Job '{}' successfully started.", instance_id);
 
         Ok(())
     }
 
+    /// Create a new console socket for a container, the PTY master sent by runc
+    /// is accepted in background.
+    fn open_console_socket(id: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let socket_path = PathBuf::from(format!("/tmp/{}", id));
+        let console_socket = ConsoleSocket::new(&socket_path)?;
+
+        tokio::spawn(async move {
+            match console_socket
+                .get_listener()
+                .as_ref()
+                .unwrap()
+                .accept()
+                .await
+            {
+                Ok((stream, _socket_addr)) => {
+                    Box::leak(Box::new(stream));
+                }
+                Err(err) => {
+                    log::error!("Receive PTY master error : {:?}", err)
+                }
+            }
+        });
+
+        Ok(socket_path)
+    }
+
     async fn delete_workload(
         &mut self,
         workload: &InstanceScheduling,
     ) -> Result<(), Box<dyn Error>> {
         let instance_id = &workload.instance_id;
-        let containers = self.workloads.get(&instance_id[..]).unwrap();
+        let containers = self.workloads.lock().unwrap().remove(&instance_id[..]);
 
-        for container in containers {
+        // The containers of a job are already removed once the job completed
+        for container in containers.unwrap_or_default() {
             self.container_runtime
                 .delete(
                     &container.id.as_ref().unwrap()[..],
@@ -190,22 +320,92 @@ impl Riklet {
             &workload.instance_id
         );
 
+        self.send_event(ClusterEvent::new(
+            ClusterEvent::INSTANCE,
+            instance_id,
+            "Stopped",
+            format!("Stopped the containers of instance {}", instance_id),
+        ))
+        .await;
         // Inform the scheduler that the containers are running
-        self.send_status(4, instance_id).await;
+        self.send_status(ResourceStatus::Terminated, instance_id)
+            .await;
 
         Ok(())
     }
 
-    async fn send_status(&self, status: i32, instance_id: &str) {
-        MetricsEmitter::emit_event(
+    /// Pull the image of a container of an instance
+    async fn pull_image(
+        &mut self,
+        instance_id: &str,
+        image: &str,
+    ) -> Result<oci::image::Image, Box<dyn Error>> {
+        self.send_event(ClusterEvent::new(
+            ClusterEvent::INSTANCE,
+            instance_id,
+            "Pulling",
+            format!("Pulling image {}", image),
+        ))
+        .await;
+        let image_pulled = self.image_manager.pull(image).await?;
+        self.send_event(ClusterEvent::new(
+            ClusterEvent::INSTANCE,
+            instance_id,
+            "Pulled",
+            format!("Successfully pulled image {}", image),
+        ))
+        .await;
+        Ok(image_pulled)
+    }
+
+    async fn send_event(&self, event: ClusterEvent) {
+        Riklet::emit_cluster_event(self.client.clone(), self.hostname.clone(), event).await;
+    }
+
+    /// Report an event about an object of this worker, it is forwarded to the controller
+    async fn emit_cluster_event(client: SchedulerClient, hostname: String, event: ClusterEvent) {
+        let event = event.with_source(&hostname);
+        if let Err(e) = MetricsEmitter::emit_event(
+            client,
+            vec![WorkerStatus {
+                identifier: hostname,
+                status: Some(proto::common::worker_status::Status::Event(event)),
+            }],
+        )
+        .await
+        {
+            log::error!("Failed to emit event: {}", e);
+        }
+    }
+
+    async fn send_status(&self, status: ResourceStatus, instance_id: &str) {
+        Riklet::emit_instance_status(
             self.client.clone(),
+            self.hostname.clone(),
+            instance_id,
+            status,
+            0,
+        )
+        .await;
+    }
+
+    async fn emit_instance_status(
+        client: SchedulerClient,
+        hostname: String,
+        instance_id: &str,
+        status: ResourceStatus,
+        exit_code: i32,
+    ) {
+        MetricsEmitter::emit_event(
+            client,
             vec![WorkerStatus {
-                identifier: self.hostname.clone(),
+                identifier: hostname,
                 status: Some(proto::common::worker_status::Status::Instance(
                     InstanceMetric {
                         instance_id: instance_id.to_string().clone(),
-                        status,
+                        status: status.into(),
                         metrics: "".to_string(),
+                        exit_code,
                     },
                 )),
             }],
@@ -247,7 +447,23 @@ impl Riklet {
         self.start_metrics_updater();
 
         while let Some(workload) = &self.stream.message().await? {
-            let _ = self.handle_workload(workload).await;
+            if let Err(e) = self.handle_workload(workload).await {
+                log::error!(
+                    "Failed to handle instance '{}': {}",
+                    &workload.instance_id,
+                    e
+                );
+                self.send_event(
+                    ClusterEvent::new(
+                        ClusterEvent::INSTANCE,
+                        &workload.instance_id,
+                        "Failed",
+                        format!("Failed to handle instance {}: {}", workload.instance_id, e),
+                    )
+                    .warning(),
+                )
+                .await;
+            }
         }
         Ok(())
     }
@@ -1,17 +1,16 @@
+use crate::client::SchedulerClient;
 use crate::traits::EventEmitter;
 use futures_util::stream;
 use proto::common::WorkerStatus;
-use proto::worker::worker_client::WorkerClient;
 use std::error::Error;
-use tonic::transport::Channel;
 use tonic::Request;
 
 pub struct MetricsEmitter;
 
 #[async_trait::async_trait]
-impl EventEmitter<Vec<WorkerStatus>, WorkerClient<Channel>> for MetricsEmitter {
+impl EventEmitter<Vec<WorkerStatus>, SchedulerClient> for MetricsEmitter {
     async fn emit_event(
-        mut client: WorkerClient<Channel>,
+        mut client: SchedulerClient,
         event: Vec<WorkerStatus>,
     ) -> std::result::Result<(), Box<dyn Error>> {
         // creating a new Request
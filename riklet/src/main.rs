@@ -1,3 +1,4 @@
+mod client;
 mod config;
 mod constants;
 mod core;
@@ -27,7 +28,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
-    riklet.accept().await?;
+    let result = riklet.accept().await;
+    // Export the remaining spans before exiting
+    telemetry::shutdown();
+    result?;
 
     Ok(())
 }
@@ -44,6 +44,11 @@ pub struct WorkloadDefinition {
 }
 
 impl WorkloadDefinition {
+    /// Instances of a job run to completion instead of being long-running
+    pub fn is_job(&self) -> bool {
+        matches!(self.kind.to_lowercase().as_str(), "job" | "jobs")
+    }
+
     pub fn get_containers(&self, instance_id: &str) -> Vec<Container> {
         let mut containers = Vec::<Container>::new();
         for mut container in self.spec.containers.clone() {
@@ -1,43 +1,349 @@
-use clap::{App, Arg};
+use crate::state_manager::SchedulingStrategy;
+use clap::{App, Arg, ArgMatches};
 use std::error::Error;
 use std::fmt;
-use std::net::SocketAddrV4;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Configuration file loaded when `--config` is not given, if it exists
+const DEFAULT_CONFIG_FILE: &str = "/etc/rik/scheduler.toml";
+
+/// Prefix of the environment variables overriding the configuration file
+const ENV_PREFIX: &str = "RIK_SCHEDULER_";
+
+/// Settings of the configuration file, with the command line argument
+/// overriding each of them, if any
+const SETTINGS: &[(&str, Option<&str>)] = &[
+    ("log_level", None),
+    ("shutdown_timeout", Some("shutdown_timeout")),
+    ("join_token_file", Some("join_token_file")),
+    ("listeners.workers", Some("workers_ip")),
+    ("listeners.controllers", Some("controllers_ip")),
+    ("listeners.metrics", Some("metrics_ip")),
+    ("channels.events", None),
+    ("channels.state_manager", None),
+    ("channels.streams", None),
+    ("scheduling.strategy", Some("strategy")),
+    ("scheduling.worker_timeout", Some("worker_timeout")),
+    ("scheduling.restore_grace_period", None),
+    ("tls.cert", Some("tls_cert")),
+    ("tls.key", Some("tls_key")),
+    ("tls.client_ca", Some("tls_client_ca")),
+    ("persistence.state_file", Some("state_file")),
+    ("tracing.otlp_endpoint", Some("otlp_endpoint")),
+];
 
 #[derive(Debug)]
 pub struct ConfigParser {
-    pub workers_endpoint: SocketAddrV4,
-    pub controller_endpoint: SocketAddrV4,
+    pub workers_endpoint: SocketAddr,
+    pub controller_endpoint: SocketAddr,
+    /// Endpoint serving the Prometheus metrics
+    pub metrics_endpoint: SocketAddr,
     pub verbosity_level: String,
+    /// Maximum duration of a graceful shutdown
+    pub shutdown_timeout: Duration,
+    /// File where the state is persisted on shutdown, and restored from on startup
+    pub state_file: PathBuf,
+    /// TLS is enabled on both endpoints when set
+    pub tls: Option<TlsSettings>,
+    /// File containing the bootstrap tokens workers must present to register,
+    /// one per line
+    pub join_token_file: Option<PathBuf>,
+    pub channels: ChannelSettings,
+    pub scheduling: SchedulingSettings,
+    /// OTLP endpoint the spans are exported to, spans are not exported when unset
+    pub otlp_endpoint: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TlsSettings {
+    /// PEM encoded certificate of the scheduler
+    pub cert: PathBuf,
+    /// PEM encoded private key of the scheduler
+    pub key: PathBuf,
+    /// PEM encoded CA certificate, clients must present a certificate
+    /// signed by it when set
+    pub client_ca: Option<PathBuf>,
+}
+
+/// Capacities of the internal channels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelSettings {
+    /// Events waiting to be processed by the manager
+    pub events: usize,
+    /// Events waiting to be processed by the state manager
+    pub state_manager: usize,
+    /// Messages waiting to be sent on the stream of each worker and controller
+    pub streams: usize,
+}
+
+impl Default for ChannelSettings {
+    fn default() -> Self {
+        ChannelSettings {
+            events: 1024,
+            state_manager: 1024,
+            streams: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedulingSettings {
+    /// How a worker is chosen among the eligible ones
+    pub strategy: SchedulingStrategy,
+    /// A ready worker which didn't report its metrics for this long is lost,
+    /// and its instances are rescheduled
+    pub worker_timeout: Duration,
+    /// Delay given to the workers to register again after the state is restored,
+    /// the instances of the workers that didn't come back are then forgotten
+    pub restore_grace_period: Duration,
+}
+
+impl Default for SchedulingSettings {
+    fn default() -> Self {
+        SchedulingSettings {
+            strategy: SchedulingStrategy::Random,
+            worker_timeout: Duration::from_secs(60),
+            restore_grace_period: Duration::from_secs(60),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum ConfigParserError {
-    InvalidWorkersEndpoint,
-    InvalidControllersEndpoint,
+    /// The configuration file could not be read
+    UnreadableFile { path: PathBuf, reason: String },
+    /// The configuration file is not valid TOML
+    InvalidFile { path: PathBuf, reason: String },
+    /// The configuration file has a setting the scheduler doesn't know
+    UnknownSetting { path: PathBuf, key: String },
+    InvalidSetting {
+        key: String,
+        /// Where the value comes from
+        origin: String,
+        value: String,
+        reason: String,
+    },
+    /// Two endpoints are configured on the same address
+    ConflictingEndpoints { first: String, second: String },
+    /// A file given in the configuration does not exist
+    MissingFile { key: String, path: PathBuf },
+    /// Certificate and private key must be given together, and client
+    /// verification requires TLS
+    InvalidTlsConfiguration,
+}
+
+/// Where the settings are looked up, by order of precedence: the command line,
+/// the environment then the configuration file
+struct Sources<'a, E> {
+    matches: &'a ArgMatches<'a>,
+    env: E,
+    file: Option<(PathBuf, toml::Value)>,
+}
+
+impl<E> Sources<'_, E>
+where
+    E: Fn(&str) -> Option<String>,
+{
+    /// Value of a setting and where it comes from, if set
+    fn get(&self, key: &str) -> Option<(String, String)> {
+        let arg = SETTINGS
+            .iter()
+            .find(|(setting, _)| *setting == key)
+            .and_then(|(_, arg)| *arg);
+        if let Some(value) = arg.and_then(|arg| self.matches.value_of(arg)) {
+            return Some((value.to_string(), "the command line".to_string()));
+        }
+
+        let variable = env_variable(key);
+        if let Some(value) = (self.env)(&variable) {
+            return Some((value, variable));
+        }
+
+        let (path, file) = self.file.as_ref()?;
+        let value = key
+            .split('.')
+            .try_fold(file, |value, part| value.get(part))?;
+        let value = match value {
+            toml::Value::String(value) => value.clone(),
+            value => value.to_string(),
+        };
+        Some((value, path.display().to_string()))
+    }
+
+    /// Parse a setting, or fall back on its default value
+    fn parse<T>(&self, key: &str, default: T) -> Result<T, ConfigParserError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        Ok(self.parse_optional(key)?.unwrap_or(default))
+    }
+
+    fn parse_optional<T>(&self, key: &str) -> Result<Option<T>, ConfigParserError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.get(key)
+            .map(|(value, origin)| {
+                value
+                    .parse()
+                    .map_err(|e: T::Err| ConfigParserError::InvalidSetting {
+                        key: key.to_string(),
+                        origin,
+                        value,
+                        reason: e.to_string(),
+                    })
+            })
+            .transpose()
+    }
+
+    /// Parse a number of seconds, which must be positive if required
+    fn parse_duration(
+        &self,
+        key: &str,
+        default: Duration,
+        positive: bool,
+    ) -> Result<Duration, ConfigParserError> {
+        let seconds: u64 = self.parse(key, default.as_secs())?;
+        if positive && seconds == 0 {
+            return Err(self.invalid(key, "must be greater than 0"));
+        }
+        Ok(Duration::from_secs(seconds))
+    }
+
+    fn parse_capacity(&self, key: &str, default: usize) -> Result<usize, ConfigParserError> {
+        let capacity = self.parse(key, default)?;
+        if capacity == 0 {
+            return Err(self.invalid(key, "must be greater than 0"));
+        }
+        Ok(capacity)
+    }
+
+    fn invalid(&self, key: &str, reason: &str) -> ConfigParserError {
+        let (value, origin) = self.get(key).unwrap_or_default();
+        ConfigParserError::InvalidSetting {
+            key: key.to_string(),
+            origin,
+            value,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+/// Name of the environment variable overriding a setting,
+/// e.g. `RIK_SCHEDULER_LISTENERS_WORKERS` for `listeners.workers`
+fn env_variable(key: &str) -> String {
+    format!("{}{}", ENV_PREFIX, key.replace('.', "_").to_uppercase())
 }
 
 impl ConfigParser {
     pub fn new() -> Result<ConfigParser, ConfigParserError> {
-        let matches = App::new("RIK scheduler")
+        let matches = ConfigParser::app().get_matches();
+        ConfigParser::parse(&matches, |variable| std::env::var(variable).ok())
+    }
+
+    fn app() -> App<'static, 'static> {
+        App::new("RIK scheduler")
             .version("1.0")
             .author("Polytech Montpellier - DO3 - 2023")
+            .arg(
+                Arg::with_name("config")
+                    .long("config")
+                    .value_name("PATH")
+                    .help("Configuration file [default: /etc/rik/scheduler.toml, if it exists]")
+                    .takes_value(true),
+            )
             .arg(
                 Arg::with_name("workers_ip")
                     .short("wip")
                     .long("workersip")
                     .value_name("WORKERS_IP")
-                    .help("Workers endpoint IPv4")
-                    .takes_value(true)
-                    .default_value("0.0.0.0:4995"),
+                    .help("Workers endpoint, IPv4 or IPv6 [default: 0.0.0.0:4995]")
+                    .takes_value(true),
             )
             .arg(
                 Arg::with_name("controllers_ip")
                     .short("cip")
                     .long("ctrlip")
                     .value_name("CONTROLLERS_IP")
-                    .help("Controllers endpoint IPv4")
-                    .takes_value(true)
-                    .default_value("0.0.0.0:4996"),
+                    .help("Controllers endpoint, IPv4 or IPv6 [default: 0.0.0.0:4996]")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("metrics_ip")
+                    .long("metrics-ip")
+                    .value_name("METRICS_IP")
+                    .help("Prometheus metrics endpoint, IPv4 or IPv6 [default: 0.0.0.0:4997]")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("shutdown_timeout")
+                    .long("shutdown-timeout")
+                    .value_name("SECONDS")
+                    .help("Maximum duration of a graceful shutdown, in seconds [default: 30]")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("strategy")
+                    .long("strategy")
+                    .value_name("STRATEGY")
+                    .help("How a worker is chosen among the eligible ones: random or least_allocated [default: random]")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("worker_timeout")
+                    .long("worker-timeout")
+                    .value_name("SECONDS")
+                    .help("Delay without metrics after which a worker is lost, in seconds [default: 60]")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("state_file")
+                    .long("state-file")
+                    .value_name("PATH")
+                    .help("File where the state is persisted on shutdown [default: /var/lib/rik/scheduler/state.json]")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("tls_cert")
+                    .long("tls-cert")
+                    .value_name("PATH")
+                    .help("PEM certificate used to serve both endpoints over TLS")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("tls_key")
+                    .long("tls-key")
+                    .value_name("PATH")
+                    .help("PEM private key of the TLS certificate")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("tls_client_ca")
+                    .long("tls-client-ca")
+                    .value_name("PATH")
+                    .help("PEM CA certificate, clients must present a certificate signed by it")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("otlp_endpoint")
+                    .long("otlp-endpoint")
+                    .value_name("URL")
+                    .help("OTLP gRPC endpoint the traces are exported to, e.g. http://127.0.0.1:4317")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("join_token_file")
+                    .long("join-token-file")
+                    .value_name("PATH")
+                    .help(
+                        "File containing the tokens workers must present to register, one per line",
+                    )
+                    .takes_value(true),
             )
             .arg(
                 Arg::with_name("v")
@@ -45,25 +351,189 @@ impl ConfigParser {
                     .multiple(true)
                     .help("Sets the level of verbosity"),
             )
-            .get_matches();
+    }
 
-        let workers_ip: SocketAddrV4 = matches
-            .value_of("workers_ip")
-            .unwrap()
-            .parse()
-            .map_err(|_| ConfigParserError::InvalidWorkersEndpoint)?;
+    /// Build the configuration from the command line, the environment and the
+    /// configuration file, and validate it
+    fn parse<E>(matches: &ArgMatches, env: E) -> Result<ConfigParser, ConfigParserError>
+    where
+        E: Fn(&str) -> Option<String>,
+    {
+        let file = match matches.value_of("config") {
+            Some(path) => Some(ConfigParser::read_file(Path::new(path))?),
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Some(ConfigParser::read_file(Path::new(DEFAULT_CONFIG_FILE))?)
+            }
+            None => None,
+        };
+        let sources = Sources { matches, env, file };
 
-        let controllers_ip: SocketAddrV4 = matches
-            .value_of("controllers_ip")
-            .unwrap()
-            .parse()
-            .map_err(|_| ConfigParserError::InvalidControllersEndpoint)?;
+        let verbosity_level = match matches.occurrences_of("v") {
+            0 => sources
+                .get("log_level")
+                .map(|(level, _)| level)
+                .unwrap_or_else(|| ConfigParser::get_verbosity_level(0)),
+            occurrences => ConfigParser::get_verbosity_level(occurrences),
+        };
 
-        Ok(ConfigParser {
-            workers_endpoint: workers_ip,
-            controller_endpoint: controllers_ip,
-            verbosity_level: ConfigParser::get_verbosity_level(matches.occurrences_of("v")),
-        })
+        let defaults = SchedulingSettings::default();
+        let scheduling = SchedulingSettings {
+            strategy: sources.parse("scheduling.strategy", defaults.strategy)?,
+            worker_timeout: sources.parse_duration(
+                "scheduling.worker_timeout",
+                defaults.worker_timeout,
+                true,
+            )?,
+            restore_grace_period: sources.parse_duration(
+                "scheduling.restore_grace_period",
+                defaults.restore_grace_period,
+                false,
+            )?,
+        };
+
+        let defaults = ChannelSettings::default();
+        let channels = ChannelSettings {
+            events: sources.parse_capacity("channels.events", defaults.events)?,
+            state_manager: sources
+                .parse_capacity("channels.state_manager", defaults.state_manager)?,
+            streams: sources.parse_capacity("channels.streams", defaults.streams)?,
+        };
+
+        let tls = ConfigParser::get_tls_settings(
+            sources.get("tls.cert").map(|(path, _)| path).as_deref(),
+            sources.get("tls.key").map(|(path, _)| path).as_deref(),
+            sources
+                .get("tls.client_ca")
+                .map(|(path, _)| path)
+                .as_deref(),
+        )?;
+
+        let config = ConfigParser {
+            workers_endpoint: sources
+                .parse("listeners.workers", SocketAddr::from(([0, 0, 0, 0], 4995)))?,
+            controller_endpoint: sources.parse(
+                "listeners.controllers",
+                SocketAddr::from(([0, 0, 0, 0], 4996)),
+            )?,
+            metrics_endpoint: sources
+                .parse("listeners.metrics", SocketAddr::from(([0, 0, 0, 0], 4997)))?,
+            verbosity_level,
+            shutdown_timeout: sources.parse_duration(
+                "shutdown_timeout",
+                Duration::from_secs(30),
+                false,
+            )?,
+            state_file: sources.parse(
+                "persistence.state_file",
+                PathBuf::from("/var/lib/rik/scheduler/state.json"),
+            )?,
+            tls,
+            join_token_file: sources.parse_optional("join_token_file")?,
+            channels,
+            scheduling,
+            otlp_endpoint: sources.parse_optional("tracing.otlp_endpoint")?,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Read the configuration file, and check it only has known settings
+    fn read_file(path: &Path) -> Result<(PathBuf, toml::Value), ConfigParserError> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| ConfigParserError::UnreadableFile {
+                path: path.to_path_buf(),
+                reason: e.to_string(),
+            })?;
+        let file: toml::Value =
+            toml::from_str(&contents).map_err(|e| ConfigParserError::InvalidFile {
+                path: path.to_path_buf(),
+                reason: e.to_string(),
+            })?;
+
+        let mut keys = Vec::new();
+        ConfigParser::collect_keys(&file, None, &mut keys);
+        if let Some(key) = keys
+            .into_iter()
+            .find(|key| !SETTINGS.iter().any(|(setting, _)| setting == key))
+        {
+            return Err(ConfigParserError::UnknownSetting {
+                path: path.to_path_buf(),
+                key,
+            });
+        }
+        Ok((path.to_path_buf(), file))
+    }
+
+    /// Dotted keys of every value of a TOML document
+    fn collect_keys(value: &toml::Value, prefix: Option<&str>, keys: &mut Vec<String>) {
+        match value {
+            toml::Value::Table(table) => {
+                for (name, value) in table {
+                    let key = match prefix {
+                        Some(prefix) => format!("{}.{}", prefix, name),
+                        None => name.clone(),
+                    };
+                    ConfigParser::collect_keys(value, Some(&key), keys);
+                }
+            }
+            _ => keys.extend(prefix.map(String::from)),
+        }
+    }
+
+    /// Check the settings are consistent, so the scheduler fails at startup
+    /// instead of when they are used
+    fn validate(&self) -> Result<(), ConfigParserError> {
+        let endpoints = [
+            ("listeners.workers", self.workers_endpoint),
+            ("listeners.controllers", self.controller_endpoint),
+            ("listeners.metrics", self.metrics_endpoint),
+        ];
+        for (i, (first, addr)) in endpoints.iter().enumerate() {
+            for (second, other) in &endpoints[i + 1..] {
+                // Port 0 asks the system for any available port
+                if addr.port() != 0 && addr == other {
+                    return Err(ConfigParserError::ConflictingEndpoints {
+                        first: first.to_string(),
+                        second: second.to_string(),
+                    });
+                }
+            }
+        }
+
+        let mut files = vec![];
+        if let Some(tls) = &self.tls {
+            files.push(("tls.cert", &tls.cert));
+            files.push(("tls.key", &tls.key));
+            files.extend(tls.client_ca.iter().map(|path| ("tls.client_ca", path)));
+        }
+        files.extend(
+            self.join_token_file
+                .iter()
+                .map(|path| ("join_token_file", path)),
+        );
+        match files.into_iter().find(|(_, path)| !path.exists()) {
+            Some((key, path)) => Err(ConfigParserError::MissingFile {
+                key: key.to_string(),
+                path: path.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn get_tls_settings(
+        cert: Option<&str>,
+        key: Option<&str>,
+        client_ca: Option<&str>,
+    ) -> Result<Option<TlsSettings>, ConfigParserError> {
+        match (cert, key) {
+            (Some(cert), Some(key)) => Ok(Some(TlsSettings {
+                cert: PathBuf::from(cert),
+                key: PathBuf::from(key),
+                client_ca: client_ca.map(PathBuf::from),
+            })),
+            (None, None) if client_ca.is_none() => Ok(None),
+            _ => Err(ConfigParserError::InvalidTlsConfiguration),
+        }
     }
 
     fn get_verbosity_level(occurrences: u64) -> String {
@@ -77,7 +547,53 @@ impl ConfigParser {
 
 impl fmt::Display for ConfigParserError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            ConfigParserError::UnreadableFile { path, reason } => write!(
+                f,
+                "could not read the configuration file {}: {}",
+                path.display(),
+                reason
+            ),
+            ConfigParserError::InvalidFile { path, reason } => write!(
+                f,
+                "the configuration file {} is not valid TOML: {}",
+                path.display(),
+                reason
+            ),
+            ConfigParserError::UnknownSetting { path, key } => write!(
+                f,
+                "unknown setting {} in the configuration file {}",
+                key,
+                path.display()
+            ),
+            ConfigParserError::InvalidSetting {
+                key,
+                origin,
+                value,
+                reason,
+            } => write!(
+                f,
+                "invalid value \"{}\" for {} (from {}): {}",
+                value, key, origin, reason
+            ),
+            ConfigParserError::ConflictingEndpoints { first, second } => write!(
+                f,
+                "{} and {} are configured on the same address",
+                first, second
+            ),
+            ConfigParserError::MissingFile { key, path } => {
+                write!(
+                    f,
+                    "the file {} given by {} does not exist",
+                    path.display(),
+                    key
+                )
+            }
+            ConfigParserError::InvalidTlsConfiguration => write!(
+                f,
+                "tls.cert and tls.key must be given together, and tls.client_ca requires them"
+            ),
+        }
     }
 }
 
@@ -86,10 +602,168 @@ impl Error for ConfigParserError {}
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
+    use std::io::Write;
+
+    fn parse(args: &[&str], env: &[(&str, &str)]) -> Result<ConfigParser, ConfigParserError> {
+        let matches = ConfigParser::app()
+            .get_matches_from(std::iter::once("scheduler").chain(args.iter().copied()));
+        let env: HashMap<String, String> = env
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        ConfigParser::parse(&matches, |variable| env.get(variable).cloned())
+    }
+
+    fn config_file(contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("rik-scheduler-{}.toml", rand::random::<u64>()));
+        std::fs::File::create(&path)
+            .unwrap()
+            .write_all(contents.as_bytes())
+            .unwrap();
+        path
+    }
 
     #[test]
     fn test_verbosity_infinite() {
         let verbosity = ConfigParser::get_verbosity_level(999999);
         assert_eq!(verbosity, "trace");
     }
+
+    #[test]
+    fn test_tls_settings() {
+        assert!(ConfigParser::get_tls_settings(None, None, None)
+            .unwrap()
+            .is_none());
+        let tls = ConfigParser::get_tls_settings(Some("cert.pem"), Some("key.pem"), Some("ca.pem"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(tls.client_ca, Some(PathBuf::from("ca.pem")));
+        assert!(ConfigParser::get_tls_settings(Some("cert.pem"), None, None).is_err());
+        assert!(ConfigParser::get_tls_settings(None, None, Some("ca.pem")).is_err());
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = parse(&[], &[]).unwrap();
+        assert_eq!(config.workers_endpoint, "0.0.0.0:4995".parse().unwrap());
+        assert_eq!(config.verbosity_level, "info");
+        assert_eq!(config.channels, ChannelSettings::default());
+        assert_eq!(config.scheduling, SchedulingSettings::default());
+        assert!(config.tls.is_none());
+        assert!(config.otlp_endpoint.is_none());
+    }
+
+    #[test]
+    fn test_config_file_with_overrides() {
+        let path = config_file(
+            r#"
+            log_level = "warn"
+            shutdown_timeout = 10
+
+            [listeners]
+            workers = "[::]:5995"
+            controllers = "[::1]:5996"
+
+            [channels]
+            streams = 64
+
+            [scheduling]
+            strategy = "least_allocated"
+            worker_timeout = 15
+
+            [persistence]
+            state_file = "/tmp/state.json"
+
+            [tracing]
+            otlp_endpoint = "http://127.0.0.1:4317"
+            "#,
+        );
+        let config_path = path.to_str().unwrap();
+
+        let config = parse(&["--config", config_path], &[]).unwrap();
+        assert_eq!(config.workers_endpoint, "[::]:5995".parse().unwrap());
+        assert_eq!(config.controller_endpoint, "[::1]:5996".parse().unwrap());
+        assert_eq!(config.verbosity_level, "warn");
+        assert_eq!(config.shutdown_timeout, Duration::from_secs(10));
+        assert_eq!(config.channels.streams, 64);
+        assert_eq!(config.channels.events, 1024);
+        assert_eq!(
+            config.scheduling.strategy,
+            SchedulingStrategy::LeastAllocated
+        );
+        assert_eq!(config.scheduling.worker_timeout, Duration::from_secs(15));
+        assert_eq!(config.state_file, PathBuf::from("/tmp/state.json"));
+        assert_eq!(
+            config.otlp_endpoint.as_deref(),
+            Some("http://127.0.0.1:4317")
+        );
+
+        // The environment overrides the file, and the command line overrides both
+        let config = parse(
+            &["--config", config_path, "--workersip", "127.0.0.1:6995"],
+            &[
+                ("RIK_SCHEDULER_LISTENERS_WORKERS", "127.0.0.1:7995"),
+                ("RIK_SCHEDULER_CHANNELS_STREAMS", "32"),
+                ("RIK_SCHEDULER_LOG_LEVEL", "error"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.workers_endpoint, "127.0.0.1:6995".parse().unwrap());
+        assert_eq!(config.channels.streams, 32);
+        assert_eq!(config.verbosity_level, "error");
+        let config = parse(&["--config", config_path, "-vv"], &[]).unwrap();
+        assert_eq!(config.verbosity_level, "trace");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_configuration() {
+        let error = parse(&["--config", "/nonexistent/scheduler.toml"], &[]).unwrap_err();
+        assert!(matches!(error, ConfigParserError::UnreadableFile { .. }));
+
+        let path = config_file("[listeners]\nworker = \"0.0.0.0:4995\"\n");
+        let error = parse(&["--config", path.to_str().unwrap()], &[]).unwrap_err();
+        assert!(
+            matches!(&error, ConfigParserError::UnknownSetting { key, .. } if key == "listeners.worker")
+        );
+        std::fs::remove_file(path).unwrap();
+
+        let error = parse(&[], &[("RIK_SCHEDULER_SCHEDULING_STRATEGY", "fastest")]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid value \"fastest\" for scheduling.strategy (from RIK_SCHEDULER_SCHEDULING_STRATEGY): \
+            unknown strategy, expected one of random, least_allocated"
+        );
+
+        let error = parse(&["--worker-timeout", "0"], &[]).unwrap_err();
+        assert!(
+            matches!(&error, ConfigParserError::InvalidSetting { key, .. } if key == "scheduling.worker_timeout")
+        );
+
+        let error = parse(&[], &[("RIK_SCHEDULER_CHANNELS_EVENTS", "0")]).unwrap_err();
+        assert!(
+            matches!(&error, ConfigParserError::InvalidSetting { key, .. } if key == "channels.events")
+        );
+
+        let error = parse(&["--ctrlip", "0.0.0.0:4995"], &[]).unwrap_err();
+        assert!(matches!(
+            error,
+            ConfigParserError::ConflictingEndpoints { .. }
+        ));
+
+        let error = parse(
+            &[
+                "--tls-cert",
+                "/nonexistent/cert.pem",
+                "--tls-key",
+                "/nonexistent/key.pem",
+            ],
+            &[],
+        )
+        .unwrap_err();
+        assert!(matches!(&error, ConfigParserError::MissingFile { key, .. } if key == "tls.cert"));
+    }
 }
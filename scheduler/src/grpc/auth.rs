@@ -0,0 +1,281 @@
+use crate::config_parser::TlsSettings;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tonic::service::Interceptor;
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+use tonic::{Request, Status};
+
+/// Load the certificates used to serve the gRPC endpoints over TLS. Client
+/// certificates are required when a client CA is configured.
+pub fn load_tls_config(settings: &TlsSettings) -> io::Result<ServerTlsConfig> {
+    let cert = std::fs::read(&settings.cert)?;
+    let key = std::fs::read(&settings.key)?;
+    let mut config = ServerTlsConfig::new().identity(Identity::from_pem(cert, key));
+    if let Some(client_ca) = &settings.client_ca {
+        config = config.client_ca_root(Certificate::from_pem(std::fs::read(client_ca)?));
+    }
+    Ok(config)
+}
+
+/// Tokens workers must present to join the cluster, as a `authorization: Bearer <token>`
+/// metadata. A token followed by a hostname, `<token> <hostname>`, only lets this
+/// worker join. Every worker is accepted when no token is configured.
+#[derive(Clone, Default)]
+pub struct JoinTokens {
+    tokens: Arc<Vec<JoinToken>>,
+}
+
+struct JoinToken {
+    token: String,
+    /// Worker the token is reserved to
+    hostname: Option<String>,
+}
+
+/// Join token a request was authenticated with, added to the extensions of the
+/// request
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WorkerCredential {
+    /// Position of the token among the join tokens
+    token: usize,
+    hostname: Option<String>,
+}
+
+impl JoinTokens {
+    /// Tokens given as the lines of the file, `<token>` or `<token> <hostname>`
+    pub fn new(tokens: Vec<String>) -> JoinTokens {
+        JoinTokens {
+            tokens: Arc::new(
+                tokens
+                    .iter()
+                    .map(|line| {
+                        let mut fields = line.split_whitespace();
+                        JoinToken {
+                            token: fields.next().unwrap_or_default().to_string(),
+                            hostname: fields.next().map(String::from),
+                        }
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Read the tokens from a file, one per line. Empty lines and lines
+    /// starting with `#` are ignored.
+    pub fn from_file(path: &Path) -> io::Result<JoinTokens> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(JoinTokens::new(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(String::from)
+                .collect(),
+        ))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    fn find(&self, token: &str) -> Option<WorkerCredential> {
+        self.tokens
+            .iter()
+            .position(|expected| constant_time_eq(expected.token.as_bytes(), token.as_bytes()))
+            .map(|position| WorkerCredential {
+                token: position,
+                hostname: self.tokens[position].hostname.clone(),
+            })
+    }
+}
+
+impl Interceptor for JoinTokens {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        if self.is_empty() {
+            return Ok(request);
+        }
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let credential = match token {
+            Some(token) => self
+                .find(token)
+                .ok_or_else(|| Status::unauthenticated("Invalid join token"))?,
+            None => return Err(Status::unauthenticated("Missing join token")),
+        };
+        let mut request = request;
+        request.extensions_mut().insert(credential);
+        Ok(request)
+    }
+}
+
+/// Workers bound to the join token they registered with, so a token only acts on
+/// behalf of the workers it registered. A token shared by several workers is bound
+/// to a hostname by its first registration, until the scheduler restarts.
+#[derive(Clone, Debug, Default)]
+pub struct WorkerBindings {
+    workers: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl WorkerBindings {
+    /// Bind a worker to the credential of its registration. It is refused if the
+    /// token is reserved to another worker, or the worker registered with another
+    /// token. Without credential, when no token is configured, every worker is
+    /// accepted.
+    #[allow(clippy::result_large_err)]
+    pub fn bind(
+        &self,
+        credential: Option<&WorkerCredential>,
+        hostname: &str,
+    ) -> Result<(), Status> {
+        let credential = match credential {
+            Some(credential) => credential,
+            None => return Ok(()),
+        };
+        check_reserved(credential, hostname)?;
+        let mut workers = self.workers.lock().unwrap_or_else(|e| e.into_inner());
+        match workers.get(hostname) {
+            Some(token) if *token != credential.token => Err(Status::permission_denied(format!(
+                "Worker {} registered with another join token",
+                hostname
+            ))),
+            _ => {
+                workers.insert(hostname.to_string(), credential.token);
+                Ok(())
+            }
+        }
+    }
+
+    /// Check a credential may report the status of a worker, the one it registered
+    #[allow(clippy::result_large_err)]
+    pub fn check(
+        &self,
+        credential: Option<&WorkerCredential>,
+        hostname: &str,
+    ) -> Result<(), Status> {
+        let credential = match credential {
+            Some(credential) => credential,
+            None => return Ok(()),
+        };
+        check_reserved(credential, hostname)?;
+        let workers = self.workers.lock().unwrap_or_else(|e| e.into_inner());
+        match workers.get(hostname) {
+            Some(token) if *token == credential.token => Ok(()),
+            _ => Err(Status::permission_denied(format!(
+                "Join token not registered for worker {}",
+                hostname
+            ))),
+        }
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn check_reserved(credential: &WorkerCredential, hostname: &str) -> Result<(), Status> {
+    match &credential.hostname {
+        Some(reserved) if reserved != hostname => Err(Status::permission_denied(format!(
+            "Join token reserved to worker {}",
+            reserved
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Compare secrets without leaking their common prefix length through timing
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+#[allow(clippy::result_large_err)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    fn request_with_token(token: Option<&str>) -> Request<()> {
+        let mut request = Request::new(());
+        if let Some(token) = token {
+            request.metadata_mut().insert(
+                "authorization",
+                format!("Bearer {}", token).parse().unwrap(),
+            );
+        }
+        request
+    }
+
+    #[test]
+    fn test_no_join_tokens() {
+        let mut tokens = JoinTokens::default();
+        assert!(tokens.call(request_with_token(None)).is_ok());
+    }
+
+    #[test]
+    fn test_join_tokens() {
+        let mut tokens = JoinTokens::new(vec!["abcdef.0123456789".to_string()]);
+        assert!(tokens
+            .call(request_with_token(Some("abcdef.0123456789")))
+            .is_ok());
+        assert_eq!(
+            tokens
+                .call(request_with_token(Some("abcdef.012345678")))
+                .unwrap_err()
+                .code(),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            tokens.call(request_with_token(None)).unwrap_err().code(),
+            Code::Unauthenticated
+        );
+    }
+
+    #[test]
+    fn test_worker_bindings() {
+        let mut tokens = JoinTokens::new(vec![
+            "shared".to_string(),
+            "other".to_string(),
+            "reserved node-3".to_string(),
+        ]);
+        let mut credential = |token: &str| {
+            tokens
+                .call(request_with_token(Some(token)))
+                .unwrap()
+                .extensions()
+                .get::<WorkerCredential>()
+                .cloned()
+        };
+        let (shared, other, reserved) = (
+            credential("shared"),
+            credential("other"),
+            credential("reserved"),
+        );
+        let bindings = WorkerBindings::default();
+
+        assert!(bindings.bind(shared.as_ref(), "node-1").is_ok());
+        assert!(bindings.bind(shared.as_ref(), "node-1").is_ok());
+        assert!(bindings.check(shared.as_ref(), "node-1").is_ok());
+        // Another token cannot take over the worker, nor report its status
+        let code = |result: Result<(), Status>| result.unwrap_err().code();
+        assert_eq!(
+            code(bindings.bind(other.as_ref(), "node-1")),
+            Code::PermissionDenied
+        );
+        assert_eq!(
+            code(bindings.check(other.as_ref(), "node-1")),
+            Code::PermissionDenied
+        );
+        // A reserved token only acts as its worker
+        assert_eq!(
+            code(bindings.bind(reserved.as_ref(), "node-2")),
+            Code::PermissionDenied
+        );
+        assert!(bindings.bind(reserved.as_ref(), "node-3").is_ok());
+        assert_eq!(
+            code(bindings.bind(shared.as_ref(), "node-3")),
+            Code::PermissionDenied
+        );
+        // Every worker is accepted without join tokens
+        assert!(bindings.bind(None, "node-1").is_ok());
+    }
+}
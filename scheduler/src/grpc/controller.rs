@@ -1,31 +1,58 @@
 use crate::grpc::GRPCService;
-use log::error;
+use log::{debug, error};
 use proto::common::WorkerStatus;
 use proto::controller::controller_server::Controller as ControllerClient;
-use proto::controller::WorkloadScheduling;
+use proto::controller::{SchedulingResult, WorkloadScheduling};
 use scheduler::Send;
 use scheduler::{Event, WorkloadRequest};
 use tokio::sync::mpsc::channel;
+use tokio::sync::oneshot;
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Request, Response, Status};
+use tracing::{info_span, Instrument};
 
 #[tonic::async_trait]
 impl ControllerClient for GRPCService {
     async fn schedule_instance(
         &self,
         _request: Request<WorkloadScheduling>,
-    ) -> Result<Response<()>, Status> {
-        let parsed_body = _request.get_ref().clone().unpack().map_err(|e| {
+    ) -> Result<Response<SchedulingResult>, Status> {
+        self.check_not_shutting_down()?;
+        let correlation_id = _request.get_ref().correlation_id.clone();
+        let mut parsed_body = _request.get_ref().clone().unpack().map_err(|e| {
             error!(
-                "Failed to parse ScheduleInstance from controller, reason: {}",
-                e
+                "[{}] Failed to parse ScheduleInstance from controller, reason: {}",
+                correlation_id, e
             );
             Status::invalid_argument(e.to_string())
         })?;
 
-        self.send(Event::ScheduleRequest(parsed_body)).await?;
-
-        Ok(Response::new(()))
+        let workload_id = parsed_body.workload_id.clone();
+        debug!(
+            "[{}] Received ScheduleInstance for workload {}",
+            correlation_id, workload_id
+        );
+
+        // The span of the request continues the trace of the controller, and is
+        // the parent of the spans of the instances scheduled for it
+        let span = info_span!("ScheduleInstance", %workload_id, %correlation_id);
+        telemetry::set_parent_from_metadata(&span, _request.metadata());
+        parsed_body.trace_context = span.in_scope(telemetry::current_context);
+
+        async {
+            let (reply, result) = oneshot::channel();
+            self.send(Event::ScheduleRequest(Box::new(parsed_body), reply))
+                .await?;
+
+            let failure = result
+                .await
+                .map_err(|_| Status::unavailable("The scheduling request was not processed"))?
+                .err()
+                .map(|e| e.to_failure(&workload_id));
+            Ok(Response::new(SchedulingResult { failure }))
+        }
+        .instrument(span)
+        .await
     }
 
     type GetStatusUpdatesStream = ReceiverStream<Result<WorkerStatus, Status>>;
@@ -34,7 +61,7 @@ impl ControllerClient for GRPCService {
         &self,
         _request: Request<()>,
     ) -> Result<Response<Self::GetStatusUpdatesStream>, Status> {
-        let (stream_tx, stream_rx) = channel::<Result<WorkerStatus, Status>>(1024);
+        let (stream_tx, stream_rx) = channel::<Result<WorkerStatus, Status>>(self.stream_capacity);
         let addr = _request
             .remote_addr()
             .unwrap_or_else(|| "0.0.0.0:000".parse().unwrap());
@@ -45,10 +72,16 @@ impl ControllerClient for GRPCService {
 }
 
 #[cfg(test)]
+#[allow(
+    clippy::result_large_err,
+    clippy::assertions_on_constants,
+    clippy::items_after_test_module
+)]
 mod tests {
     use super::*;
     use definition::workload::{Container, Spec, WorkloadDefinition};
-    use proto::common::{WorkerStatus, WorkloadRequestKind};
+    use proto::common::{SchedulingFailureReason, WorkerStatus, WorkloadRequestKind};
+    use scheduler::SchedulerError;
     use std::net::SocketAddr;
     use tokio::sync::mpsc::error::SendError;
     use tonic::{Code, Request};
@@ -66,33 +99,57 @@ mod tests {
                 kind: "pods".to_string(),
                 name: "workload-debian".to_string(),
                 replicas: Some(2),
+                job: None,
+                cron_job: None,
+                node_selector: None,
+                topology_spread: None,
+                gang: None,
+                tenant: None,
+                namespace: None,
                 spec: Spec {
                     containers: vec![Container {
                         name: " debian".to_string(),
                         image: "debian:latest".to_string(),
                         env: None,
                         ports: None,
+                        resources: None,
                     }],
                 },
             })
             .map_err(|e| Status::invalid_argument(e.to_string()))?,
             action: WorkloadRequestKind::Create.into(),
+            quota: "".to_string(),
+            correlation_id: "c0ffee".to_string(),
+            instances: 0,
         };
 
         let mock_request = Request::new(workload.clone());
 
-        service.schedule_instance(mock_request).await?;
+        let response = tokio::spawn(async move { service.schedule_instance(mock_request).await });
 
         let message = receiver.recv().await.unwrap();
         match message {
-            Event::ScheduleRequest(content) => assert_eq!(
-                workload
-                    .unpack()
-                    .map_err(|e| { Status::invalid_argument(e.to_string()) })?,
-                content
-            ),
+            Event::ScheduleRequest(content, reply) => {
+                assert_eq!(
+                    workload
+                        .unpack()
+                        .map_err(|e| { Status::invalid_argument(e.to_string()) })?,
+                    *content
+                );
+                reply
+                    .send(Err(SchedulerError::QuotaExceeded("cpu".to_string())))
+                    .unwrap();
+            }
             _ => assert!(false),
         };
+
+        // The failure of the request is reported to the controller
+        let failure = response.await.unwrap()?.into_inner().failure.unwrap();
+        assert_eq!(failure.workload_id, "test");
+        assert_eq!(
+            failure.reason,
+            i32::from(SchedulingFailureReason::QuotaExceeded)
+        );
         Ok(())
     }
 
@@ -1,10 +1,13 @@
+pub mod auth;
 mod controller;
 mod worker;
 
+use auth::WorkerBindings;
 use log::error;
 use scheduler::Event;
 use scheduler::Send;
 use tokio::sync::mpsc::Sender;
+use tokio::sync::watch;
 use tonic::{Code, Status};
 
 #[derive(Debug, Clone)]
@@ -13,11 +16,42 @@ pub struct GRPCService {
     /// In the case the worker doesn't know its ID yet, put 0 in the first
     /// item of the tuple
     sender: Sender<Event>,
+    /// Becomes true once the scheduler is shutting down
+    shutdown: watch::Receiver<bool>,
+    /// Capacity of the streams opened to workers and controllers
+    stream_capacity: usize,
+    /// Join tokens the workers registered with
+    worker_bindings: WorkerBindings,
 }
 
 impl GRPCService {
     pub fn new(sender: Sender<Event>) -> GRPCService {
-        GRPCService { sender }
+        let (_, shutdown) = watch::channel(false);
+        GRPCService {
+            sender,
+            shutdown,
+            stream_capacity: 1024,
+            worker_bindings: WorkerBindings::default(),
+        }
+    }
+
+    pub fn with_shutdown(mut self, shutdown: watch::Receiver<bool>) -> GRPCService {
+        self.shutdown = shutdown;
+        self
+    }
+
+    pub fn with_stream_capacity(mut self, stream_capacity: usize) -> GRPCService {
+        self.stream_capacity = stream_capacity;
+        self
+    }
+
+    /// New registrations and scheduling requests are refused while shutting down
+    #[allow(clippy::result_large_err)]
+    fn check_not_shutting_down(&self) -> Result<(), Status> {
+        match *self.shutdown.borrow() {
+            true => Err(Status::unavailable("Scheduler is shutting down")),
+            false => Ok(()),
+        }
     }
 }
 
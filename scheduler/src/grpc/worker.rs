@@ -1,3 +1,4 @@
+use crate::grpc::auth::WorkerCredential;
 use crate::grpc::GRPCService;
 use proto::common::worker_status::Status;
 use proto::common::{WorkerRegistration, WorkerStatus};
@@ -17,8 +18,9 @@ impl WorkerClient for GRPCService {
         &self,
         _request: Request<WorkerRegistration>,
     ) -> Result<Response<Self::RegisterStream>, tonic::Status> {
+        self.check_not_shutting_down()?;
         // Streaming channel that sends workloads
-        let (stream_tx, stream_rx) = channel::<WorkerRegisterChannelType>(1024);
+        let (stream_tx, stream_rx) = channel::<WorkerRegisterChannelType>(self.stream_capacity);
         let addr = _request
             .remote_addr()
             .unwrap_or_else(|| "0.0.0.0:000".parse().unwrap());
@@ -28,7 +30,12 @@ impl WorkerClient for GRPCService {
             }
             hostname => Ok(hostname.clone()),
         }?;
-        self.send(Event::Register(stream_tx, addr, body)).await?;
+        // The worker is bound to the join token it registers with
+        self.worker_bindings
+            .bind(_request.extensions().get::<WorkerCredential>(), &body)?;
+        let labels = _request.get_ref().labels.clone();
+        self.send(Event::Register(stream_tx, addr, body, labels))
+            .await?;
 
         Ok(Response::new(ReceiverStream::new(stream_rx)))
     }
@@ -44,10 +51,14 @@ impl WorkerClient for GRPCService {
         &self,
         _request: Request<tonic::Streaming<WorkerStatus>>,
     ) -> Result<Response<()>, tonic::Status> {
+        let credential = _request.extensions().get::<WorkerCredential>().cloned();
         let mut stream = _request.into_inner();
 
         while let Some(data) = stream.try_next().await? {
             let identifier = data.identifier;
+            // Only the statuses of the workers registered with the join token are accepted
+            self.worker_bindings
+                .check(credential.as_ref(), &identifier)?;
             let data = data.status.unwrap();
             match data {
                 Status::Worker(metrics) => {
@@ -58,6 +69,17 @@ impl WorkerClient for GRPCService {
                     self.send(Event::InstanceMetricsUpdate(identifier, metrics))
                         .await?
                 }
+                Status::Event(mut event) => {
+                    if event.source.is_empty() {
+                        event.source = identifier;
+                    }
+                    self.send(Event::ClusterEvent(event)).await?
+                }
+                Status::Workload(_) | Status::SchedulingFailure(_) => {
+                    return Err(tonic::Status::invalid_argument(
+                        "Workers cannot report the status of a workload",
+                    ))
+                }
             };
         }
 
@@ -66,11 +88,15 @@ impl WorkerClient for GRPCService {
 }
 
 #[cfg(test)]
+#[allow(clippy::result_large_err, clippy::assertions_on_constants)]
 mod tests {
     use super::*;
+    use crate::grpc::auth::JoinTokens;
     use proto::worker::InstanceScheduling;
+    use std::collections::HashMap;
     use std::net::SocketAddr;
     use tokio::sync::mpsc::error::SendError;
+    use tonic::service::Interceptor;
     use tonic::{Code, Request};
 
     #[tokio::test]
@@ -82,13 +108,14 @@ mod tests {
 
         let mock_request = Request::new(WorkerRegistration {
             hostname: hostname.clone(),
+            labels: HashMap::new(),
         });
 
         let _ = service.register(mock_request).await;
 
         let message = receiver.recv().await.unwrap();
         match message {
-            Event::Register(_, socket, host) => {
+            Event::Register(_, socket, host, _) => {
                 assert_eq!(hostname, host);
                 let default_socket: SocketAddr = "0.0.0.0:0".parse().unwrap();
                 assert_eq!(default_socket, socket);
@@ -105,6 +132,7 @@ mod tests {
 
         let mock_request = Request::new(WorkerRegistration {
             hostname: "".to_string(),
+            labels: HashMap::new(),
         });
         let fallback = service.register(mock_request).await;
         assert!(fallback.is_err());
@@ -114,6 +142,56 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_register_while_shutting_down() {
+        let (sender, mut receiver) = channel::<Event>(1024);
+        let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+
+        let service = GRPCService::new(sender).with_shutdown(shutdown_rx);
+        shutdown_tx.send(true).unwrap();
+
+        let mock_request = Request::new(WorkerRegistration {
+            hostname: "debian".to_string(),
+            labels: HashMap::new(),
+        });
+        let result = service.register(mock_request).await;
+        assert_eq!(result.err().unwrap().code(), Code::Unavailable);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_register_with_another_join_token() {
+        let (sender, mut receiver) = channel::<Event>(1024);
+        let service = GRPCService::new(sender);
+        let mut tokens = JoinTokens::new(vec!["first".to_string(), "second".to_string()]);
+        let mut registration = |token: &str| {
+            let mut request = Request::new(());
+            request.metadata_mut().insert(
+                "authorization",
+                format!("Bearer {}", token).parse().unwrap(),
+            );
+            let credential = tokens
+                .call(request)
+                .unwrap()
+                .extensions()
+                .get::<WorkerCredential>()
+                .cloned()
+                .unwrap();
+            let mut request = Request::new(WorkerRegistration {
+                hostname: "debian".to_string(),
+                labels: HashMap::new(),
+            });
+            request.extensions_mut().insert(credential);
+            request
+        };
+
+        assert!(service.register(registration("first")).await.is_ok());
+        assert!(receiver.recv().await.is_some());
+        let result = service.register(registration("second")).await;
+        assert_eq!(result.err().unwrap().code(), Code::PermissionDenied);
+        assert!(receiver.try_recv().is_err());
+    }
+
     #[tokio::test]
     async fn test_register_event() -> Result<(), tonic::Status> {
         let (sender, mut receiver) = channel::<Event>(1024);
@@ -123,13 +201,14 @@ mod tests {
 
         let mock_request = Request::new(WorkerRegistration {
             hostname: hostname.clone(),
+            labels: HashMap::new(),
         });
 
         service.register(mock_request).await?;
 
         let message = receiver.recv().await.unwrap();
         match message {
-            Event::Register(_, _, _) => assert!(true),
+            Event::Register(_, _, _, _) => assert!(true),
             _ => assert!(false),
         };
         Ok(())
@@ -145,6 +224,7 @@ mod tests {
 
         let mock_request = Request::new(WorkerRegistration {
             hostname: hostname.clone(),
+            labels: HashMap::new(),
         });
 
         let mut stream = service
@@ -156,7 +236,7 @@ mod tests {
 
         let message = receiver.recv().await.unwrap();
         match message {
-            Event::Register(sender, _, _) => {
+            Event::Register(sender, _, _, _) => {
                 sender.send(Err(tonic::Status::cancelled("Sample"))).await?;
                 let rcv = stream.recv().await.unwrap();
                 assert!(rcv.is_err());
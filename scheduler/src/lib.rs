@@ -1,30 +1,49 @@
+use definition::quota::ResourceQuota;
 use definition::workload::WorkloadDefinition;
 use log::{error, info};
 use node_metrics::metrics::Metrics;
-use proto::common::{InstanceMetric, WorkerMetric, WorkerStatus, WorkloadRequestKind};
+use proto::common::{
+    InstanceMetric, SchedulingFailure, SchedulingFailureReason, WorkerMetric, WorkerStatus,
+    WorkloadMetric, WorkloadRequestKind,
+};
 use proto::controller::WorkloadScheduling;
 use proto::worker::InstanceScheduling;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
 use std::net::SocketAddr;
+use std::time::Instant;
+use telemetry::TraceContext;
 use tokio::sync::mpsc::error::SendError;
 use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
 use tonic::Status;
 
+/// Source of the events emitted by the scheduler itself
+pub const SCHEDULER_EVENT_SOURCE: &str = "scheduler";
+
 /// Define the structure of message send through the channel between
 /// the manager and a worker
 pub type WorkloadChannelType = Result<WorkloadScheduling, Status>;
 
 pub type WorkerRegisterChannelType = Result<InstanceScheduling, tonic::Status>;
 
+/// Channel used to answer a scheduling request once the StateManager processed it
+pub type ScheduleReplyChannel = oneshot::Sender<Result<(), SchedulerError>>;
+
 #[derive(Debug)]
 pub enum Event {
     /// Workers register to the Scheduler so they can serve
-    /// the cluster
-    Register(Sender<WorkerRegisterChannelType>, SocketAddr, String),
+    /// the cluster, with their hostname and labels
+    Register(
+        Sender<WorkerRegisterChannelType>,
+        SocketAddr,
+        String,
+        HashMap<String, String>,
+    ),
     /// Controller can send workload, we use the verb Schedule to describe
-    /// this event
-    ScheduleRequest(WorkloadRequest),
+    /// this event. The outcome of the request is sent back on the reply channel
+    ScheduleRequest(Box<WorkloadRequest>, ScheduleReplyChannel),
     /// The StateManager uses this event to send a workload to a worker
     /// String is for the worker id
     Schedule(String, InstanceScheduling),
@@ -52,16 +71,49 @@ pub enum Event {
     /// let metrics = InstanceMetric {
     ///     status: 1,
     ///     metrics: "{metricA: 10, metricB: 100}".to_string(),
-    ///     instance_id: "test".to_string()
+    ///     instance_id: "test".to_string(),
+    ///     exit_code: 0
     /// };
     /// ```
     InstanceMetric(String, InstanceMetric),
     /// Metrics received from workers to tell about themselves
     /// These metrics will be used inside the state manager
     InstanceMetricsUpdate(String, InstanceMetric),
+    /// Status relative to a whole workload, e.g. the outcome of a job, this
+    /// event will send it to the controller
+    WorkloadMetric(String, WorkloadMetric),
+    /// Lifecycle event of an object of the cluster, emitted by the scheduler
+    /// itself or by a worker, this event will send it to the controller
+    ClusterEvent(proto::common::Event),
+    /// Instances of a workload could not be placed after its scheduling request
+    /// was accepted, this event will send the failure to the controller
+    SchedulingFailure(SchedulingFailure),
+    /// The scheduler received a termination signal, pending events are
+    /// processed, then workers and controller are disconnected
+    Shutdown,
 }
 
-#[derive(Debug)]
+impl Event {
+    /// Name of the event, used to label the span processing it
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Register(..) => "Register",
+            Event::ScheduleRequest(..) => "ScheduleRequest",
+            Event::Schedule(..) => "Schedule",
+            Event::Subscribe(..) => "Subscribe",
+            Event::WorkerMetric(..) => "WorkerMetric",
+            Event::WorkerMetricsUpdate(..) => "WorkerMetricsUpdate",
+            Event::InstanceMetric(..) => "InstanceMetric",
+            Event::InstanceMetricsUpdate(..) => "InstanceMetricsUpdate",
+            Event::WorkloadMetric(..) => "WorkloadMetric",
+            Event::ClusterEvent(..) => "ClusterEvent",
+            Event::SchedulingFailure(..) => "SchedulingFailure",
+            Event::Shutdown => "Shutdown",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum SchedulerError {
     /// Current max is 256 workers, given more workers, it returns
     /// a cluster full error
@@ -77,11 +129,67 @@ pub enum SchedulerError {
     /// In case we are ordering something but the workload doesn't exist in the
     /// memory
     WorkloadDontExists(String),
+    /// A job runs to completion, it cannot be scheduled again while it is running
+    JobAlreadyScheduled(String),
+    /// Scheduling the workload would exceed the resource quota of its tenant,
+    /// the message names the exceeded limit
+    QuotaExceeded(String),
+}
+
+impl SchedulerError {
+    /// Short name of the error, used as a metric label
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SchedulerError::ClusterFull => "cluster_full",
+            SchedulerError::RegistrationFailed(_) => "registration_failed",
+            SchedulerError::ClientDisconnected => "client_disconnected",
+            SchedulerError::StateManagerFailed => "state_manager_failed",
+            SchedulerError::CannotDoubleReplicas => "cannot_double_replicas",
+            SchedulerError::WorkloadDontExists(_) => "workload_not_found",
+            SchedulerError::JobAlreadyScheduled(_) => "job_already_scheduled",
+            SchedulerError::QuotaExceeded(_) => "quota_exceeded",
+        }
+    }
+
+    /// Machine-readable reason reported to the controller when a scheduling
+    /// request fails
+    pub fn reason(&self) -> SchedulingFailureReason {
+        match self {
+            SchedulerError::CannotDoubleReplicas => SchedulingFailureReason::CannotDoubleReplicas,
+            SchedulerError::WorkloadDontExists(_) => SchedulingFailureReason::WorkloadNotFound,
+            SchedulerError::JobAlreadyScheduled(_) => SchedulingFailureReason::JobAlreadyScheduled,
+            SchedulerError::QuotaExceeded(_) => SchedulingFailureReason::QuotaExceeded,
+            _ => SchedulingFailureReason::UnknownFailure,
+        }
+    }
+
+    /// Describe the failure of a scheduling request on a workload
+    pub fn to_failure(&self, workload_id: &str) -> SchedulingFailure {
+        SchedulingFailure {
+            workload_id: workload_id.to_string(),
+            reason: self.reason().into(),
+            message: self.to_string(),
+        }
+    }
 }
 
 impl fmt::Display for SchedulerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            SchedulerError::CannotDoubleReplicas => {
+                write!(f, "The workload is being destroyed, it cannot be scaled")
+            }
+            SchedulerError::WorkloadDontExists(id) => write!(f, "Workload {} does not exist", id),
+            SchedulerError::JobAlreadyScheduled(id) => {
+                write!(
+                    f,
+                    "Job {} is already running, it cannot be scheduled again",
+                    id
+                )
+            }
+            SchedulerError::QuotaExceeded(message) => write!(f, "{}", message),
+            _ => write!(f, "{:?}", self),
+        }
     }
 }
 
@@ -154,10 +262,14 @@ pub struct Worker {
     pub channel: Sender<WorkerRegisterChannelType>,
     /// Remote addr of the worker
     pub addr: SocketAddr,
+    /// Labels of the worker, matched against the node selector of workloads
+    pub labels: HashMap<String, String>,
     /// State of worker
     state: WorkerState,
     /// Most recent metric the worker has on its state
     metric: Option<Metrics>,
+    /// When the worker last registered or reported its metrics
+    last_seen: Instant,
 }
 
 impl Worker {
@@ -166,13 +278,16 @@ impl Worker {
             id,
             channel,
             addr,
+            labels: HashMap::new(),
             state: WorkerState::NotReady,
             metric: None,
+            last_seen: Instant::now(),
         }
     }
 
     pub fn set_channel(&mut self, sender: Sender<WorkerRegisterChannelType>) {
         self.channel = sender;
+        self.last_seen = Instant::now();
     }
 
     pub fn set_state(&mut self, state: WorkerState) {
@@ -188,6 +303,7 @@ impl Worker {
 
     pub fn set_metrics(&mut self, metric: Metrics) {
         self.metric = Some(metric);
+        self.last_seen = Instant::now();
         self.update_state();
     }
 
@@ -195,6 +311,10 @@ impl Worker {
         &self.metric
     }
 
+    pub fn last_seen(&self) -> Instant {
+        self.last_seen
+    }
+
     fn update_state(&mut self) {
         match self.state {
             WorkerState::Ready => {
@@ -220,6 +340,15 @@ impl Worker {
     pub fn is_ready(&self) -> bool {
         matches!(self.state, WorkerState::Ready)
     }
+
+    /// Check the worker has every label required by a node selector
+    pub fn matches_selector(&self, selector: &Option<HashMap<String, String>>) -> bool {
+        selector.as_ref().is_none_or(|selector| {
+            selector
+                .iter()
+                .all(|(key, value)| self.labels.get(key) == Some(value))
+        })
+    }
 }
 
 #[tonic::async_trait]
@@ -232,6 +361,14 @@ pub struct WorkloadRequest {
     pub workload_id: String,
     pub definition: WorkloadDefinition,
     pub action: WorkloadRequestKind,
+    /// Number of instances wanted by a reconcile request
+    pub instances: u16,
+    /// Resource quota of the tenant owning the workload
+    pub quota: Option<Box<ResourceQuota>>,
+    /// Id correlating the logs of the request with the controller ones
+    pub correlation_id: String,
+    /// Trace context of the span handling the request
+    pub trace_context: TraceContext,
 }
 
 impl WorkloadRequest {
@@ -239,10 +376,14 @@ impl WorkloadRequest {
         Ok(WorkloadRequest {
             workload_id: workload.workload_id,
             definition: serde_json::from_str(&workload.definition)?,
-            action: match workload.action {
-                1 => WorkloadRequestKind::Destroy,
-                _ => WorkloadRequestKind::Create,
+            action: WorkloadRequestKind::from(workload.action),
+            instances: workload.instances.min(u16::MAX as u32) as u16,
+            quota: match workload.quota.is_empty() {
+                true => None,
+                false => Some(serde_json::from_str(&workload.quota)?),
             },
+            correlation_id: workload.correlation_id,
+            trace_context: TraceContext::new(),
         })
     }
 }
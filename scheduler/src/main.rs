@@ -1,24 +1,43 @@
 mod config_parser;
 mod grpc;
+mod metrics;
+#[cfg(test)]
+mod simulation;
 mod state_manager;
 
 use crate::config_parser::ConfigParser;
+use crate::grpc::auth::{load_tls_config, JoinTokens};
 use crate::grpc::GRPCService;
+use crate::metrics::{MetricsExporter, SchedulerMetrics};
 use crate::state_manager::{StateManager, StateManagerEvent};
-use env_logger::Env;
 use log::{debug, error, info, warn};
 use proto::common::worker_status::Status;
-use proto::common::WorkerStatus;
+use proto::common::{Event as ClusterEvent, WorkerStatus};
 use proto::controller::controller_server::ControllerServer;
 use proto::worker::worker_server::WorkerServer;
-use scheduler::Event;
 use scheduler::{Controller, SchedulerError, Worker, WorkerRegisterChannelType};
+use scheduler::{Event, SCHEDULER_EVENT_SOURCE};
+use std::collections::HashMap;
 use std::default::Default;
-use std::net::{SocketAddr, SocketAddrV4};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
+use telemetry::{LogFormat, TelemetryConfig};
+use tokio::net::TcpListener;
+use tokio::signal::unix::{signal, SignalKind};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
-use tokio::sync::Mutex;
-use tonic::transport::Server;
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Server, ServerTlsConfig};
+use tracing::{debug_span, info_span, Instrument};
+
+/// Listeners the scheduler endpoints are served on
+pub struct Listeners {
+    pub workers: TcpListener,
+    pub controllers: TcpListener,
+    pub metrics: TcpListener,
+}
 
 #[derive(Debug)]
 pub struct Manager {
@@ -26,43 +45,135 @@ pub struct Manager {
     channel: Receiver<Event>,
     controller: Option<Controller>,
     state_manager: Sender<StateManagerEvent>,
+    state_manager_handle: Option<JoinHandle<()>>,
 }
 
 impl Manager {
-    async fn run(
-        workers_listener: SocketAddrV4,
-        controllers_listener: SocketAddrV4,
+    async fn run(config: ConfigParser) -> Result<Manager, Box<dyn std::error::Error>> {
+        let (sender, receiver) = channel::<Event>(config.channels.events);
+        let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+        Manager::handle_signals(sender.clone(), shutdown_sender, config.shutdown_timeout);
+
+        let listeners = Listeners {
+            workers: TcpListener::bind(config.workers_endpoint).await?,
+            controllers: TcpListener::bind(config.controller_endpoint).await?,
+            metrics: TcpListener::bind(config.metrics_endpoint).await?,
+        };
+        Manager::serve(&config, listeners, (sender, receiver), shutdown_receiver).await
+    }
+
+    /// Serve the workers and controllers endpoints on the given listeners, and
+    /// process events until the scheduler is shut down
+    async fn serve(
+        config: &ConfigParser,
+        listeners: Listeners,
+        (sender, receiver): (Sender<Event>, Receiver<Event>),
+        shutdown_receiver: watch::Receiver<bool>,
     ) -> Result<Manager, Box<dyn std::error::Error>> {
-        let (sender, receiver) = channel::<Event>(1024);
-        let (state_sender, receiver_sender) = channel::<StateManagerEvent>(1024);
+        let (state_sender, receiver_sender) =
+            channel::<StateManagerEvent>(config.channels.state_manager);
+        let metrics = Arc::new(SchedulerMetrics::new());
 
         let mut instance = Manager {
             workers: Arc::new(Mutex::new(Vec::new())),
             channel: receiver,
             controller: None,
             state_manager: state_sender,
+            state_manager_handle: None,
+        };
+        let tls = match &config.tls {
+            Some(settings) => Some(load_tls_config(settings)?),
+            None => {
+                warn!("TLS is disabled, gRPC endpoints are served in plaintext");
+                None
+            }
+        };
+        let join_tokens = match &config.join_token_file {
+            Some(path) => JoinTokens::from_file(path)?,
+            None => JoinTokens::default(),
+        };
+        if join_tokens.is_empty() {
+            warn!("No join token configured, any worker can register");
+        }
+
+        instance.run_workers_listener(
+            Manager::server_builder(&tls)?,
+            listeners.workers,
+            sender.clone(),
+            shutdown_receiver.clone(),
+            join_tokens,
+            config.channels.streams,
+        );
+        instance.run_controllers_listener(
+            Manager::server_builder(&tls)?,
+            listeners.controllers,
+            sender.clone(),
+            shutdown_receiver.clone(),
+            config.channels.streams,
+        );
+
+        let exporter = MetricsExporter {
+            metrics: metrics.clone(),
+            workers: instance.workers.clone(),
+            events: sender.clone(),
+            state_events: instance.state_manager.clone(),
+            channels: config.channels,
         };
-        instance.run_workers_listener(workers_listener, sender.clone());
-        instance.run_controllers_listener(controllers_listener, sender.clone());
+        let mut shutdown = shutdown_receiver;
+        tokio::spawn(exporter.serve(listeners.metrics, async move {
+            let _ = shutdown.changed().await;
+        }));
+
         let workers = instance.workers.clone();
-        tokio::spawn(async move {
-            let mut sm = StateManager::new(sender.clone(), workers);
+        let state_file = config.state_file.clone();
+        let scheduling = config.scheduling;
+        instance.state_manager_handle = Some(tokio::spawn(async move {
+            let mut sm = StateManager::new(sender.clone(), workers)
+                .with_state_file(state_file)
+                .with_scheduling(scheduling)
+                .with_metrics(metrics);
             if let Err(e) = sm.run(receiver_sender).await {
                 error!("StateManager failed, reason: {}", e);
             }
-        });
+        }));
 
         let channel_listener = instance.listen();
         channel_listener.await?;
         Ok(instance)
     }
 
-    fn run_workers_listener(&self, listener: SocketAddrV4, sender: Sender<Event>) {
-        let server = WorkerServer::new(GRPCService::new(sender));
-        tokio::spawn(async move {
-            let server = Server::builder().add_service(server).serve(listener.into());
+    fn server_builder(tls: &Option<ServerTlsConfig>) -> Result<Server, tonic::transport::Error> {
+        match tls {
+            Some(tls) => Server::builder().tls_config(tls.clone()),
+            None => Ok(Server::builder()),
+        }
+    }
 
-            info!("Worker gRPC listening on {}", listener);
+    fn run_workers_listener(
+        &self,
+        mut builder: Server,
+        listener: TcpListener,
+        sender: Sender<Event>,
+        mut shutdown: watch::Receiver<bool>,
+        join_tokens: JoinTokens,
+        stream_capacity: usize,
+    ) {
+        let server = WorkerServer::with_interceptor(
+            GRPCService::new(sender)
+                .with_shutdown(shutdown.clone())
+                .with_stream_capacity(stream_capacity),
+            join_tokens,
+        );
+        tokio::spawn(async move {
+            if let Ok(addr) = listener.local_addr() {
+                info!("Worker gRPC listening on {}", addr);
+            }
+            let server = builder.add_service(server).serve_with_incoming_shutdown(
+                TcpListenerStream::new(listener),
+                async move {
+                    let _ = shutdown.changed().await;
+                },
+            );
 
             if let Err(e) = server.await {
                 error!("{}", e);
@@ -70,12 +181,29 @@ impl Manager {
         });
     }
 
-    fn run_controllers_listener(&self, listener: SocketAddrV4, sender: Sender<Event>) {
-        let server = ControllerServer::new(GRPCService::new(sender));
+    fn run_controllers_listener(
+        &self,
+        mut builder: Server,
+        listener: TcpListener,
+        sender: Sender<Event>,
+        mut shutdown: watch::Receiver<bool>,
+        stream_capacity: usize,
+    ) {
+        let server = ControllerServer::new(
+            GRPCService::new(sender)
+                .with_shutdown(shutdown.clone())
+                .with_stream_capacity(stream_capacity),
+        );
         tokio::spawn(async move {
-            let server = Server::builder().add_service(server).serve(listener.into());
-
-            info!("Controller gRPC listening on {}", listener);
+            if let Ok(addr) = listener.local_addr() {
+                info!("Controller gRPC listening on {}", addr);
+            }
+            let server = builder.add_service(server).serve_with_incoming_shutdown(
+                TcpListenerStream::new(listener),
+                async move {
+                    let _ = shutdown.changed().await;
+                },
+            );
 
             if let Err(e) = server.await {
                 error!("{}", e);
@@ -83,21 +211,121 @@ impl Manager {
         });
     }
 
+    /// Start a graceful shutdown on SIGTERM or SIGINT. The process is
+    /// killed if the shutdown doesn't complete before the deadline.
+    fn handle_signals(sender: Sender<Event>, shutdown: watch::Sender<bool>, deadline: Duration) {
+        tokio::spawn(async move {
+            let mut terminate = match signal(SignalKind::terminate()) {
+                Ok(terminate) => terminate,
+                Err(e) => {
+                    error!("Cannot listen to SIGTERM, reason: {}", e);
+                    return;
+                }
+            };
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => info!("Received SIGINT"),
+                _ = terminate.recv() => info!("Received SIGTERM"),
+            }
+
+            info!("Shutting down gracefully, deadline: {:?}", deadline);
+            // Stop accepting new connections, registrations and scheduling requests
+            let _ = shutdown.send(true);
+            if sender.send(Event::Shutdown).await.is_err() {
+                error!("Manager is not listening anymore, cannot shutdown gracefully");
+            }
+
+            tokio::time::sleep(deadline).await;
+            error!("Graceful shutdown did not complete before the deadline, exiting");
+            std::process::exit(1);
+        });
+    }
+
+    /// Process the pending events, stop the StateManager so it persists
+    /// the state, then disconnect the workers and the controller
+    async fn shutdown(&mut self) {
+        self.flush_events().await;
+
+        if self
+            .state_manager
+            .send(StateManagerEvent::Shutdown)
+            .await
+            .is_ok()
+        {
+            if let Some(handle) = self.state_manager_handle.take() {
+                let _ = handle.await;
+            }
+        }
+        // Events sent by the StateManager before it stopped
+        self.flush_events().await;
+
+        let status = tonic::Status::unavailable("Scheduler is shutting down");
+        for worker in self.workers.lock().await.drain(..) {
+            if worker.channel.send(Err(status.clone())).await.is_err() {
+                debug!("Worker {} was already disconnected", worker.id);
+            }
+        }
+        if let Some(controller) = self.controller.take() {
+            let _ = controller.send(Err(status)).await;
+        }
+        info!("Scheduler stopped");
+    }
+
+    async fn flush_events(&mut self) {
+        while let Ok(event) = self.channel.try_recv() {
+            match event {
+                Event::Register(channel, _, hostname, _) => {
+                    debug!("Refusing registration of {}, shutting down", hostname);
+                    let _ = channel
+                        .send(Err(tonic::Status::unavailable(
+                            "Scheduler is shutting down",
+                        )))
+                        .await;
+                }
+                Event::Shutdown => {}
+                event => self.handle_event(event).await,
+            }
+        }
+    }
+
     async fn listen(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         while let Some(e) = self.channel.recv().await {
+            if let Event::Shutdown = e {
+                self.shutdown().await;
+                break;
+            }
+            self.handle_event(e).await;
+        }
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, e: Event) {
+        // Scheduling requests are traced, the other events only at the debug level
+        let span = match &e {
+            Event::ScheduleRequest(workload, _) => {
+                let span =
+                    info_span!("event", name = e.name(), workload_id = %workload.workload_id);
+                telemetry::set_parent(&span, &workload.trace_context);
+                span
+            }
+            _ => debug_span!("event", name = e.name()),
+        };
+        async {
             match e {
-                Event::Register(channel, addr, hostname) => {
-                    if let Err(e) = self.register(channel.clone(), addr, hostname.clone()).await {
+                Event::Register(channel, addr, hostname, labels) => {
+                    if let Err(e) = self
+                        .register(channel.clone(), addr, hostname.clone(), labels)
+                        .await
+                    {
                         error!(
                             "Failed to register worker {} ({}), reason: {}",
                             hostname, addr, e
                         )
                     }
                 }
-                Event::ScheduleRequest(workload) => {
+                Event::ScheduleRequest(workload, reply) => {
                     if let Err(e) = self
                         .state_manager
-                        .send(StateManagerEvent::Schedule(workload))
+                        .send(StateManagerEvent::Schedule(workload, reply))
                         .await
                     {
                         error!("Failed to communicate with StateManager, reason: {}", e);
@@ -163,6 +391,36 @@ impl Manager {
                         }
                     }
                 }
+                Event::WorkloadMetric(identifier, metrics) => {
+                    if let Some(controller) = &self.controller {
+                        if let Err(e) = controller
+                            .send(Ok(WorkerStatus {
+                                identifier,
+                                status: Some(Status::Workload(metrics)),
+                            }))
+                            .await
+                        {
+                            error!("Failed to send WorkloadMetric to controller, reason: {}", e);
+                        }
+                    }
+                }
+                Event::ClusterEvent(event) => self.send_cluster_event(event).await,
+                Event::SchedulingFailure(failure) => {
+                    if let Some(controller) = &self.controller {
+                        if let Err(e) = controller
+                            .send(Ok(WorkerStatus {
+                                identifier: SCHEDULER_EVENT_SOURCE.to_string(),
+                                status: Some(Status::SchedulingFailure(failure)),
+                            }))
+                            .await
+                        {
+                            error!(
+                                "Failed to send SchedulingFailure to controller, reason: {}",
+                                e
+                            );
+                        }
+                    }
+                }
                 Event::InstanceMetricsUpdate(_, metrics) => {
                     if self
                         .state_manager
@@ -187,9 +445,11 @@ impl Manager {
                         );
                     }
                 }
+                Event::Shutdown => {}
             }
         }
-        Ok(())
+        .instrument(span)
+        .await
     }
 
     async fn get_worker_sender(&self, hostname: &str) -> Option<Sender<WorkerRegisterChannelType>> {
@@ -211,6 +471,7 @@ impl Manager {
         channel: Sender<WorkerRegisterChannelType>,
         addr: SocketAddr,
         hostname: String,
+        labels: HashMap<String, String>,
     ) -> Result<(), SchedulerError> {
         let mut workers = self.workers.lock().await;
         if let Some(worker) = workers.iter_mut().find(|worker| worker.id.eq(&*hostname)) {
@@ -228,25 +489,75 @@ impl Manager {
             } else {
                 info!("Worker {} is back ready", hostname);
                 worker.set_channel(channel);
+                worker.labels = labels;
             }
         } else {
-            let worker = Worker::new(hostname, channel, addr);
+            let mut worker = Worker::new(hostname.clone(), channel, addr);
+            worker.labels = labels;
             info!(
                 "Worker {} is now registered, ip: {}",
                 worker.id, worker.addr
             );
             workers.push(worker);
         }
+        drop(workers);
+        self.send_cluster_event(
+            ClusterEvent::new(
+                ClusterEvent::WORKER,
+                &hostname,
+                "Registered",
+                format!("Worker {} registered from {}", hostname, addr),
+            )
+            .with_source(SCHEDULER_EVENT_SOURCE),
+        )
+        .await;
         Ok(())
     }
+
+    /// Forward an event of the cluster to the controller, which persists it
+    async fn send_cluster_event(&self, event: ClusterEvent) {
+        debug!(
+            "{} {}/{}: {}",
+            event.reason, event.object_kind, event.object_id, event.message
+        );
+        if let Some(controller) = &self.controller {
+            if let Err(e) = controller
+                .send(Ok(WorkerStatus {
+                    identifier: event.source.clone(),
+                    status: Some(Status::Event(event)),
+                }))
+                .await
+            {
+                error!("Failed to send ClusterEvent to controller, reason: {}", e);
+            }
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let config = ConfigParser::new()?;
-    env_logger::Builder::from_env(Env::default().default_filter_or(&config.verbosity_level)).init();
+    let config = match ConfigParser::new() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    // RUST_LOG still takes precedence over the configured level
+    let filter = std::env::var("RUST_LOG").unwrap_or_else(|_| config.verbosity_level.clone());
+    let telemetry = TelemetryConfig {
+        service_name: String::from("rik-scheduler"),
+        filter,
+        format: LogFormat::Human,
+        otlp_endpoint: config.otlp_endpoint.clone(),
+    };
+    if let Err(e) = telemetry::init(&telemetry) {
+        eprintln!("Invalid configuration: {}", e);
+        std::process::exit(1);
+    }
     info!("Starting up...");
-    let manager = Manager::run(config.workers_endpoint, config.controller_endpoint);
-    manager.await?;
+    let manager = Manager::run(config).await;
+    telemetry::shutdown();
+    manager?;
     Ok(())
 }
@@ -0,0 +1,293 @@
+use crate::config_parser::ChannelSettings;
+use crate::state_manager::StateManagerEvent;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use log::{error, info};
+use prometheus::{
+    Encoder, GaugeVec, Histogram, HistogramOpts, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use scheduler::{Event, Worker};
+use std::convert::Infallible;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::Mutex;
+
+/// Metrics of the scheduler, exposed in the Prometheus text format
+pub struct SchedulerMetrics {
+    registry: Registry,
+    workers_registered: IntGauge,
+    workers_ready: IntGauge,
+    desired_instances: IntGaugeVec,
+    running_instances: IntGaugeVec,
+    scheduling_latency: Histogram,
+    scheduling_failures: IntCounterVec,
+    channel_depth: IntGaugeVec,
+    worker_cpu_count: IntGaugeVec,
+    worker_cpu_usage: GaugeVec,
+    worker_memory_total: IntGaugeVec,
+    worker_memory_free: IntGaugeVec,
+    worker_disk_total: IntGaugeVec,
+    worker_disk_free: IntGaugeVec,
+}
+
+impl SchedulerMetrics {
+    pub fn new() -> SchedulerMetrics {
+        let registry = Registry::new_custom(Some("rik_scheduler".to_string()), None).unwrap();
+        let metrics = SchedulerMetrics {
+            workers_registered: IntGauge::new("workers_registered", "Registered workers").unwrap(),
+            workers_ready: IntGauge::new("workers_ready", "Workers ready to run instances")
+                .unwrap(),
+            desired_instances: IntGaugeVec::new(
+                Opts::new(
+                    "workload_desired_instances",
+                    "Desired instances of a workload",
+                ),
+                &["workload"],
+            )
+            .unwrap(),
+            running_instances: IntGaugeVec::new(
+                Opts::new(
+                    "workload_running_instances",
+                    "Running instances of a workload",
+                ),
+                &["workload"],
+            )
+            .unwrap(),
+            scheduling_latency: Histogram::with_opts(HistogramOpts::new(
+                "scheduling_latency_seconds",
+                "Delay between an instance being requested and its placement on a worker",
+            ))
+            .unwrap(),
+            scheduling_failures: IntCounterVec::new(
+                Opts::new(
+                    "scheduling_failures_total",
+                    "Scheduling requests that failed",
+                ),
+                &["reason"],
+            )
+            .unwrap(),
+            channel_depth: IntGaugeVec::new(
+                Opts::new("event_channel_depth", "Events waiting to be processed"),
+                &["channel"],
+            )
+            .unwrap(),
+            worker_cpu_count: IntGaugeVec::new(
+                Opts::new("worker_cpu_count", "CPUs of a worker"),
+                &["worker"],
+            )
+            .unwrap(),
+            worker_cpu_usage: GaugeVec::new(
+                Opts::new("worker_cpu_usage_percent", "CPU usage of a worker"),
+                &["worker"],
+            )
+            .unwrap(),
+            worker_memory_total: IntGaugeVec::new(
+                Opts::new("worker_memory_total_bytes", "Memory of a worker"),
+                &["worker"],
+            )
+            .unwrap(),
+            worker_memory_free: IntGaugeVec::new(
+                Opts::new("worker_memory_free_bytes", "Free memory of a worker"),
+                &["worker"],
+            )
+            .unwrap(),
+            worker_disk_total: IntGaugeVec::new(
+                Opts::new("worker_disk_total_bytes", "Size of a disk of a worker"),
+                &["worker", "disk"],
+            )
+            .unwrap(),
+            worker_disk_free: IntGaugeVec::new(
+                Opts::new("worker_disk_free_bytes", "Free space of a disk of a worker"),
+                &["worker", "disk"],
+            )
+            .unwrap(),
+            registry,
+        };
+        metrics.register();
+        metrics
+    }
+
+    fn register(&self) {
+        let collectors: Vec<Box<dyn prometheus::core::Collector>> = vec![
+            Box::new(self.workers_registered.clone()),
+            Box::new(self.workers_ready.clone()),
+            Box::new(self.desired_instances.clone()),
+            Box::new(self.running_instances.clone()),
+            Box::new(self.scheduling_latency.clone()),
+            Box::new(self.scheduling_failures.clone()),
+            Box::new(self.channel_depth.clone()),
+            Box::new(self.worker_cpu_count.clone()),
+            Box::new(self.worker_cpu_usage.clone()),
+            Box::new(self.worker_memory_total.clone()),
+            Box::new(self.worker_memory_free.clone()),
+            Box::new(self.worker_disk_total.clone()),
+            Box::new(self.worker_disk_free.clone()),
+        ];
+        for collector in collectors {
+            self.registry.register(collector).unwrap();
+        }
+    }
+
+    /// Record the workers and the resources they reported
+    pub fn observe_workers(&self, workers: &[Worker]) {
+        self.workers_registered.set(workers.len() as i64);
+        self.workers_ready
+            .set(workers.iter().filter(|worker| worker.is_ready()).count() as i64);
+
+        // Workers which are gone must disappear from the metrics
+        self.worker_cpu_count.reset();
+        self.worker_cpu_usage.reset();
+        self.worker_memory_total.reset();
+        self.worker_memory_free.reset();
+        self.worker_disk_total.reset();
+        self.worker_disk_free.reset();
+        for worker in workers {
+            if let Some(metrics) = worker.get_metrics() {
+                let id = worker.id.as_str();
+                self.worker_cpu_count
+                    .with_label_values(&[id])
+                    .set(metrics.cpu.total.into());
+                self.worker_cpu_usage
+                    .with_label_values(&[id])
+                    .set((100.0 - metrics.cpu.free).into());
+                self.worker_memory_total
+                    .with_label_values(&[id])
+                    .set(metrics.memory.total as i64);
+                self.worker_memory_free
+                    .with_label_values(&[id])
+                    .set(metrics.memory.free as i64);
+                for disk in &metrics.disks {
+                    let labels = [id, disk.disk_name.as_str()];
+                    self.worker_disk_total
+                        .with_label_values(&labels)
+                        .set(disk.total as i64);
+                    self.worker_disk_free
+                        .with_label_values(&labels)
+                        .set(disk.free as i64);
+                }
+            }
+        }
+    }
+
+    /// Forget the instances of every workload, before they are observed again
+    pub fn reset_workloads(&self) {
+        self.desired_instances.reset();
+        self.running_instances.reset();
+    }
+
+    pub fn observe_workload(&self, workload_id: &str, desired: usize, running: usize) {
+        self.desired_instances
+            .with_label_values(&[workload_id])
+            .set(desired as i64);
+        self.running_instances
+            .with_label_values(&[workload_id])
+            .set(running as i64);
+    }
+
+    pub fn observe_scheduling_latency(&self, latency: Duration) {
+        self.scheduling_latency.observe(latency.as_secs_f64());
+    }
+
+    pub fn record_scheduling_failure(&self, reason: &str) {
+        self.scheduling_failures.with_label_values(&[reason]).inc();
+    }
+
+    pub fn observe_channel_depth(&self, channel: &str, depth: usize) {
+        self.channel_depth
+            .with_label_values(&[channel])
+            .set(depth as i64);
+    }
+
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+impl Default for SchedulerMetrics {
+    fn default() -> Self {
+        SchedulerMetrics::new()
+    }
+}
+
+/// Collects the metrics which are only known by the manager when they are scraped
+#[derive(Clone)]
+pub struct MetricsExporter {
+    pub metrics: Arc<SchedulerMetrics>,
+    pub workers: Arc<Mutex<Vec<Worker>>>,
+    pub events: Sender<Event>,
+    pub state_events: Sender<StateManagerEvent>,
+    pub channels: ChannelSettings,
+}
+
+impl MetricsExporter {
+    async fn render(&self) -> String {
+        self.metrics
+            .observe_workers(self.workers.lock().await.as_slice());
+        self.metrics
+            .observe_channel_depth("manager", self.channels.events - self.events.capacity());
+        self.metrics.observe_channel_depth(
+            "state_manager",
+            self.channels.state_manager - self.state_events.capacity(),
+        );
+        self.metrics.encode()
+    }
+
+    async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        match (request.method(), request.uri().path()) {
+            (&Method::GET, "/metrics") => Response::builder()
+                .header("Content-Type", TextEncoder::new().format_type())
+                .body(Body::from(self.render().await))
+                .unwrap(),
+            _ => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap(),
+        }
+    }
+
+    /// Serve the `/metrics` endpoint until the shutdown future completes
+    pub async fn serve(self, listener: TcpListener, shutdown: impl Future<Output = ()>) {
+        if let Ok(addr) = listener.local_addr() {
+            info!("Metrics listening on {}", addr);
+        }
+        let listener = match listener.into_std() {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Cannot serve metrics, reason: {}", e);
+                return;
+            }
+        };
+        let server = match hyper::Server::from_tcp(listener) {
+            Ok(server) => server,
+            Err(e) => {
+                error!("Cannot serve metrics, reason: {}", e);
+                return;
+            }
+        };
+
+        let make_service = make_service_fn(move |_| {
+            let exporter = self.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let exporter = exporter.clone();
+                    async move { Ok::<_, Infallible>(exporter.handle(request).await) }
+                }))
+            }
+        });
+        if let Err(e) = server
+            .serve(make_service)
+            .with_graceful_shutdown(shutdown)
+            .await
+        {
+            error!("{}", e);
+        }
+    }
+}
@@ -0,0 +1,258 @@
+//! In-process simulation of a cluster, to test the scheduling behaviour
+//! without root privileges nor containers. The `Manager` is started on
+//! ephemeral ports, fake workers speak the `worker.Worker` protocol and
+//! the controller status stream is recorded.
+
+mod scenarios;
+mod worker;
+
+pub use worker::{FakeWorker, FakeWorkerConfig, InstanceBehaviour};
+
+use crate::config_parser::{ChannelSettings, ConfigParser, SchedulingSettings};
+use crate::{Listeners, Manager};
+use proto::common::worker_status::Status;
+use proto::common::{
+    ResourceStatus, SchedulingFailure, SchedulingFailureReason, WorkerStatus, WorkloadRequestKind,
+};
+use proto::controller::controller_client::ControllerClient;
+use proto::controller::WorkloadScheduling;
+use scheduler::Event;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{channel, Sender};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tonic::transport::Channel;
+
+/// Delay after which an expectation on the cluster is considered unmet
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct SimulatedCluster {
+    workers_addr: SocketAddr,
+    metrics_addr: SocketAddr,
+    controller: ControllerClient<Channel>,
+    /// Status updates received by the controller, in order
+    statuses: Arc<Mutex<Vec<WorkerStatus>>>,
+    events: Sender<Event>,
+    shutdown: watch::Sender<bool>,
+    manager: JoinHandle<()>,
+    state_file: PathBuf,
+}
+
+impl SimulatedCluster {
+    /// Start a scheduler, and connect a controller to it
+    pub async fn start() -> SimulatedCluster {
+        SimulatedCluster::start_with(SchedulingSettings::default()).await
+    }
+
+    /// Start a scheduler with the given scheduling settings
+    pub async fn start_with(scheduling: SchedulingSettings) -> SimulatedCluster {
+        let state_file =
+            std::env::temp_dir().join(format!("rik-simulation-{}.json", rand::random::<u64>()));
+        SimulatedCluster::start_at(scheduling, state_file).await
+    }
+
+    /// Start a scheduler persisting its state to the given file
+    async fn start_at(scheduling: SchedulingSettings, state_file: PathBuf) -> SimulatedCluster {
+        let listeners = Listeners {
+            workers: TcpListener::bind("127.0.0.1:0").await.unwrap(),
+            controllers: TcpListener::bind("127.0.0.1:0").await.unwrap(),
+            metrics: TcpListener::bind("127.0.0.1:0").await.unwrap(),
+        };
+        let workers_addr = listeners.workers.local_addr().unwrap();
+        let controllers_addr = listeners.controllers.local_addr().unwrap();
+        let metrics_addr = listeners.metrics.local_addr().unwrap();
+
+        let config = ConfigParser {
+            workers_endpoint: "127.0.0.1:0".parse().unwrap(),
+            controller_endpoint: "127.0.0.1:0".parse().unwrap(),
+            metrics_endpoint: "127.0.0.1:0".parse().unwrap(),
+            verbosity_level: "info".to_string(),
+            shutdown_timeout: TIMEOUT,
+            state_file: state_file.clone(),
+            tls: None,
+            join_token_file: None,
+            channels: ChannelSettings::default(),
+            scheduling,
+            otlp_endpoint: None,
+        };
+
+        let (sender, receiver) = channel::<Event>(config.channels.events);
+        let (shutdown, shutdown_receiver) = watch::channel(false);
+        let events = sender.clone();
+        let manager = tokio::spawn(async move {
+            if let Err(e) =
+                Manager::serve(&config, listeners, (sender, receiver), shutdown_receiver)
+                    .await
+                    .map_err(|e| e.to_string())
+            {
+                panic!("Manager failed: {}", e);
+            }
+        });
+
+        let mut controller = ControllerClient::connect(format!("http://{}", controllers_addr))
+            .await
+            .unwrap();
+        let mut stream = controller
+            .get_status_updates(tonic::Request::new(()))
+            .await
+            .unwrap()
+            .into_inner();
+        let statuses = Arc::new(Mutex::new(Vec::new()));
+        let received = statuses.clone();
+        tokio::spawn(async move {
+            while let Ok(Some(status)) = stream.message().await {
+                received.lock().unwrap().push(status);
+            }
+        });
+
+        SimulatedCluster {
+            workers_addr,
+            metrics_addr,
+            controller,
+            statuses,
+            events,
+            shutdown,
+            manager,
+            state_file,
+        }
+    }
+
+    /// Attach a fake worker to the scheduler
+    pub async fn add_worker(&self, config: FakeWorkerConfig) -> FakeWorker {
+        FakeWorker::start(self.workers_addr, config).await.unwrap()
+    }
+
+    /// Ask the scheduler to create a workload, as the controller does
+    pub async fn create(
+        &mut self,
+        workload_id: &str,
+        definition: serde_json::Value,
+    ) -> Result<Option<SchedulingFailure>, tonic::Status> {
+        self.schedule(workload_id, definition, WorkloadRequestKind::Create)
+            .await
+    }
+
+    /// Ask the scheduler to destroy a workload, as the controller does
+    pub async fn destroy(
+        &mut self,
+        workload_id: &str,
+        definition: serde_json::Value,
+    ) -> Result<Option<SchedulingFailure>, tonic::Status> {
+        self.schedule(workload_id, definition, WorkloadRequestKind::Destroy)
+            .await
+    }
+
+    async fn schedule(
+        &mut self,
+        workload_id: &str,
+        definition: serde_json::Value,
+        action: WorkloadRequestKind,
+    ) -> Result<Option<SchedulingFailure>, tonic::Status> {
+        self.controller
+            .schedule_instance(tonic::Request::new(WorkloadScheduling {
+                workload_id: workload_id.to_string(),
+                definition: definition.to_string(),
+                action: action.into(),
+                quota: String::new(),
+                correlation_id: String::new(),
+                instances: 0,
+            }))
+            .await
+            .map(|response| response.into_inner().failure)
+    }
+
+    /// Status updates received by the controller so far
+    pub fn statuses(&self) -> Vec<WorkerStatus> {
+        self.statuses.lock().unwrap().clone()
+    }
+
+    /// Whether the controller received the given status for an instance
+    pub fn has_instance_status(&self, instance_id: &str, status: ResourceStatus) -> bool {
+        self.statuses().iter().any(|update| {
+            matches!(&update.status, Some(Status::Instance(metric))
+                if metric.instance_id == instance_id && metric.status == status as i32)
+        })
+    }
+
+    /// Whether the controller received the given status for a workload
+    pub fn has_workload_status(&self, workload_id: &str, status: ResourceStatus) -> bool {
+        self.statuses().iter().any(|update| {
+            matches!(&update.status, Some(Status::Workload(metric))
+                if metric.workload_id == workload_id && metric.status == status as i32)
+        })
+    }
+
+    /// Whether the controller received an event with the given reason about an object
+    pub fn has_event(&self, object_id: &str, reason: &str) -> bool {
+        self.statuses().iter().any(|update| {
+            matches!(&update.status, Some(Status::Event(event))
+                if event.object_id == object_id && event.reason == reason)
+        })
+    }
+
+    /// Whether the controller received a scheduling failure for a workload
+    pub fn has_scheduling_failure(
+        &self,
+        workload_id: &str,
+        reason: SchedulingFailureReason,
+    ) -> bool {
+        self.statuses().iter().any(|update| {
+            matches!(&update.status, Some(Status::SchedulingFailure(failure))
+                if failure.workload_id == workload_id && failure.reason == reason as i32)
+        })
+    }
+
+    /// Scrape the Prometheus metrics of the scheduler
+    pub async fn metrics(&self) -> String {
+        let mut stream = TcpStream::connect(self.metrics_addr).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.0\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    /// Shut the scheduler down gracefully, as on SIGTERM
+    pub async fn shutdown(self) {
+        let state_file = self.stop().await;
+        let _ = std::fs::remove_file(state_file);
+    }
+
+    /// Shut the scheduler down, and start a new one restoring its state
+    pub async fn restart(self, scheduling: SchedulingSettings) -> SimulatedCluster {
+        let state_file = self.stop().await;
+        SimulatedCluster::start_at(scheduling, state_file).await
+    }
+
+    /// Stop the scheduler, and give back the file its state was persisted to
+    async fn stop(self) -> PathBuf {
+        let _ = self.shutdown.send(true);
+        self.events.send(Event::Shutdown).await.unwrap();
+        tokio::time::timeout(TIMEOUT, self.manager)
+            .await
+            .expect("Scheduler did not shut down in time")
+            .unwrap();
+        self.state_file
+    }
+}
+
+/// Wait until a condition on the cluster is met, panic after `TIMEOUT`
+pub async fn eventually<F>(description: &str, mut condition: F)
+where
+    F: FnMut() -> bool,
+{
+    let started = tokio::time::Instant::now();
+    while !condition() {
+        if started.elapsed() > TIMEOUT {
+            panic!("Timed out waiting for: {}", description);
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
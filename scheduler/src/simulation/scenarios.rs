@@ -0,0 +1,416 @@
+use super::{eventually, FakeWorker, FakeWorkerConfig, InstanceBehaviour, SimulatedCluster};
+use crate::config_parser::SchedulingSettings;
+use definition::workload::ZONE_LABEL;
+use proto::common::{ResourceStatus, SchedulingFailureReason};
+use serde_json::json;
+use std::time::Duration;
+
+fn pod(name: &str, replicas: u16) -> serde_json::Value {
+    json!({
+        "api_version": "v0",
+        "kind": "pod",
+        "name": name,
+        "spec": { "containers": [{ "name": name, "image": "debian:latest" }] },
+        "replicas": replicas,
+    })
+}
+
+#[tokio::test]
+async fn test_placement_honors_node_selector() {
+    let mut cluster = SimulatedCluster::start().await;
+    let node_a = cluster
+        .add_worker(FakeWorkerConfig::new("node-a").with_label("zone", "a"))
+        .await;
+    let node_b = cluster
+        .add_worker(FakeWorkerConfig::new("node-b").with_label("zone", "b"))
+        .await;
+
+    let mut definition = pod("web", 2);
+    definition["node_selector"] = json!({ "zone": "b" });
+    cluster.create("web", definition).await.unwrap();
+
+    eventually("2 instances running on node-b", || {
+        node_b.instances().len() == 2
+    })
+    .await;
+    assert!(node_a.received().is_empty());
+    for instance_id in node_b.instances() {
+        eventually("instance reported as running to the controller", || {
+            cluster.has_instance_status(&instance_id, ResourceStatus::Running)
+        })
+        .await;
+    }
+    cluster.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_reschedule_on_worker_disconnect() {
+    let mut cluster = SimulatedCluster::start().await;
+    let mut workers = vec![
+        cluster.add_worker(FakeWorkerConfig::new("node-1")).await,
+        cluster.add_worker(FakeWorkerConfig::new("node-2")).await,
+    ];
+    cluster.create("web", pod("web", 1)).await.unwrap();
+
+    eventually("instance running on a worker", || {
+        workers.iter().any(|worker| worker.instances().len() == 1)
+    })
+    .await;
+    let index = workers
+        .iter()
+        .position(|worker| worker.instances().len() == 1)
+        .unwrap();
+    workers.remove(index).disconnect();
+
+    let remaining = &workers[0];
+    eventually("instance rescheduled on the remaining worker", || {
+        remaining.instances().len() == 1
+    })
+    .await;
+    cluster.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_destroy_workload() {
+    let mut cluster = SimulatedCluster::start().await;
+    let worker = cluster.add_worker(FakeWorkerConfig::new("node-1")).await;
+    cluster.create("web", pod("web", 2)).await.unwrap();
+    eventually("2 instances running", || worker.instances().len() == 2).await;
+
+    let instances = worker.instances();
+    cluster.destroy("web", pod("web", 2)).await.unwrap();
+    eventually("instances destroyed", || worker.instances().is_empty()).await;
+    for instance_id in instances {
+        eventually("instance reported as destroying to the controller", || {
+            cluster.has_instance_status(&instance_id, ResourceStatus::Destroying)
+        })
+        .await;
+    }
+    cluster.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_job_outcome_reported_to_controller() {
+    let mut cluster = SimulatedCluster::start().await;
+    let succeeding = cluster
+        .add_worker(
+            FakeWorkerConfig::new("node-1")
+                .with_label("outcome", "success")
+                .with_behaviour(InstanceBehaviour::Exit(0)),
+        )
+        .await;
+    let failing = cluster
+        .add_worker(
+            FakeWorkerConfig::new("node-2")
+                .with_label("outcome", "failure")
+                .with_behaviour(InstanceBehaviour::Exit(1)),
+        )
+        .await;
+
+    let mut batch = pod("batch", 1);
+    batch["kind"] = json!("job");
+    batch["job"] = json!({ "completions": 2 });
+    batch["node_selector"] = json!({ "outcome": "success" });
+    cluster.create("batch", batch).await.unwrap();
+
+    let mut flaky = pod("flaky", 1);
+    flaky["kind"] = json!("job");
+    flaky["job"] = json!({ "backoff_limit": 1 });
+    flaky["node_selector"] = json!({ "outcome": "failure" });
+    cluster.create("flaky", flaky).await.unwrap();
+
+    eventually("job reported as succeeded", || {
+        cluster.has_workload_status("batch", ResourceStatus::Succeeded)
+    })
+    .await;
+    eventually("job reported as failed", || {
+        cluster.has_workload_status("flaky", ResourceStatus::Failed)
+    })
+    .await;
+    assert_eq!(succeeding.received().len(), 2);
+    assert_eq!(failing.received().len(), 2);
+    cluster.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_not_ready_worker_is_not_eligible() {
+    let mut cluster = SimulatedCluster::start().await;
+    let ready = cluster.add_worker(FakeWorkerConfig::new("node-1")).await;
+    let not_ready = cluster.add_worker(FakeWorkerConfig::new("node-2")).await;
+    not_ready.set_ready(false);
+    // Let the scheduler receive the metrics of both workers
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    cluster.create("web", pod("web", 3)).await.unwrap();
+    eventually("3 instances running on the ready worker", || {
+        ready.instances().len() == 3
+    })
+    .await;
+    assert!(not_ready.received().is_empty());
+    cluster.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_shutdown_disconnects_workers() {
+    let cluster = SimulatedCluster::start().await;
+    let worker = cluster.add_worker(FakeWorkerConfig::new("node-1")).await;
+    cluster.shutdown().await;
+    eventually("worker disconnected", || worker.is_disconnected()).await;
+}
+
+#[tokio::test]
+async fn test_instance_failure_reported_to_controller() {
+    let mut cluster = SimulatedCluster::start().await;
+    let worker = cluster
+        .add_worker(FakeWorkerConfig::new("node-1").with_behaviour(InstanceBehaviour::Hang))
+        .await;
+    cluster.create("web", pod("web", 1)).await.unwrap();
+    eventually("instance scheduled", || worker.instances().len() == 1).await;
+
+    let instance_id = worker.instances().remove(0);
+    assert!(!cluster.has_instance_status(&instance_id, ResourceStatus::Running));
+    worker.fail_instance(&instance_id, 137).await;
+    eventually("instance reported as failed to the controller", || {
+        cluster.has_instance_status(&instance_id, ResourceStatus::Failed)
+    })
+    .await;
+    cluster.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_metrics_endpoint() {
+    let mut cluster = SimulatedCluster::start().await;
+    let worker = cluster
+        .add_worker(FakeWorkerConfig::new("node-1").with_metrics(json!({
+            "cpu": { "total": 4, "free": 75.0 },
+            "memory": { "total": 8192, "free": 2048 },
+            "disks": [{ "disk_name": "sda", "total": 1000, "free": 400 }],
+        })))
+        .await;
+    cluster.create("web", pod("web", 2)).await.unwrap();
+    eventually("2 instances running", || worker.instances().len() == 2).await;
+
+    let mut batch = pod("batch", 1);
+    batch["kind"] = json!("job");
+    cluster.create("batch", batch.clone()).await.unwrap();
+    // A job cannot be scheduled twice
+    cluster.create("batch", batch).await.unwrap();
+
+    let expected = [
+        "rik_scheduler_workers_registered 1",
+        "rik_scheduler_workers_ready 1",
+        "rik_scheduler_workload_desired_instances{workload=\"web\"} 2",
+        "rik_scheduler_workload_running_instances{workload=\"web\"} 2",
+        "rik_scheduler_scheduling_failures_total{reason=\"job_already_scheduled\"} 1",
+        "rik_scheduler_event_channel_depth{channel=\"manager\"}",
+        "rik_scheduler_worker_cpu_count{worker=\"node-1\"} 4",
+        "rik_scheduler_worker_cpu_usage_percent{worker=\"node-1\"} 25",
+        "rik_scheduler_worker_memory_free_bytes{worker=\"node-1\"} 2048",
+        "rik_scheduler_worker_disk_total_bytes{disk=\"sda\",worker=\"node-1\"} 1000",
+        "rik_scheduler_scheduling_latency_seconds_count",
+    ];
+    let mut metrics = String::new();
+    for _ in 0..50 {
+        metrics = cluster.metrics().await;
+        if expected.iter().all(|line| metrics.contains(line)) {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    for line in expected {
+        assert!(
+            metrics.contains(line),
+            "{} not found in:\n{}",
+            line,
+            metrics
+        );
+    }
+    cluster.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_events_reported_to_controller() {
+    let mut cluster = SimulatedCluster::start().await;
+    let worker = cluster.add_worker(FakeWorkerConfig::new("node-1")).await;
+    eventually("worker registration reported", || {
+        cluster.has_event("node-1", "Registered")
+    })
+    .await;
+
+    cluster.create("web", pod("web", 1)).await.unwrap();
+    eventually("instance running", || worker.instances().len() == 1).await;
+    let instance_id = worker.instances().remove(0);
+    eventually("instance placement reported", || {
+        cluster.has_event(&instance_id, "Scheduled")
+    })
+    .await;
+
+    let mut definition = pod("db", 1);
+    definition["node_selector"] = json!({ "zone": "unknown" });
+    cluster.create("db", definition).await.unwrap();
+    eventually("scheduling failure reported", || {
+        cluster.has_event("db", "FailedScheduling")
+    })
+    .await;
+    cluster.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_topology_spread_across_zones() {
+    let mut cluster = SimulatedCluster::start().await;
+    let mut zone_a = Vec::new();
+    let mut zone_b = Vec::new();
+    for (hostname, zone) in [("a-1", "a"), ("a-2", "a"), ("a-3", "a"), ("b-1", "b")] {
+        let config = FakeWorkerConfig::new(hostname).with_label(ZONE_LABEL, zone);
+        let worker = cluster.add_worker(config).await;
+        match zone {
+            "a" => zone_a.push(worker),
+            _ => zone_b.push(worker),
+        }
+    }
+    let running = |workers: &Vec<FakeWorker>| -> usize {
+        workers.iter().map(|worker| worker.instances().len()).sum()
+    };
+
+    for hostname in ["a-1", "a-2", "a-3", "b-1"] {
+        eventually("worker ready", || cluster.has_event(hostname, "NodeReady")).await;
+    }
+
+    let mut definition = pod("web", 4);
+    definition["topology_spread"] = json!([{ "topology_key": ZONE_LABEL, "max_skew": 1 }]);
+    cluster.create("web", definition).await.unwrap();
+    eventually("instances spread across zones", || {
+        running(&zone_a) == 2 && running(&zone_b) == 2
+    })
+    .await;
+
+    // Losing zone b, its instances are replaced in the remaining zone
+    zone_b.remove(0).disconnect();
+    eventually("instances rescheduled in zone a", || running(&zone_a) == 4).await;
+    assert!(!cluster.has_event("web", "TopologySpreadUnsatisfied"));
+    cluster.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_scheduling_failures_reported_to_controller() {
+    let mut cluster = SimulatedCluster::start().await;
+    let worker = cluster.add_worker(FakeWorkerConfig::new("node-1")).await;
+
+    // Rejected requests are answered right away
+    let failure = cluster
+        .destroy("web", pod("web", 1))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(failure.workload_id, "web");
+    assert_eq!(
+        failure.reason,
+        SchedulingFailureReason::WorkloadNotFound as i32
+    );
+
+    // Instances that cannot be placed are reported once the request was accepted
+    let mut definition = pod("db", 1);
+    definition["node_selector"] = json!({ "zone": "unknown" });
+    assert!(cluster.create("db", definition).await.unwrap().is_none());
+    eventually("no eligible worker reported", || {
+        cluster.has_scheduling_failure("db", SchedulingFailureReason::NoEligibleWorker)
+    })
+    .await;
+    assert!(worker.received().is_empty());
+    cluster.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_silent_worker_is_lost() {
+    let mut cluster = SimulatedCluster::start_with(SchedulingSettings {
+        worker_timeout: Duration::from_millis(500),
+        ..SchedulingSettings::default()
+    })
+    .await;
+    let mut config = FakeWorkerConfig::new("node-1");
+    // Only the first metrics are reported
+    config.metrics_interval = Duration::from_secs(3600);
+    let silent = cluster.add_worker(config).await;
+    eventually("worker ready", || cluster.has_event("node-1", "NodeReady")).await;
+
+    cluster.create("web", pod("web", 1)).await.unwrap();
+    eventually("instance running on the silent worker", || {
+        silent.instances().len() == 1
+    })
+    .await;
+
+    let healthy = cluster.add_worker(FakeWorkerConfig::new("node-2")).await;
+    eventually("worker lost", || cluster.has_event("node-1", "WorkerLost")).await;
+    eventually("instance rescheduled on the healthy worker", || {
+        healthy.instances().len() == 1
+    })
+    .await;
+    cluster.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_gang_scheduling_all_or_nothing() {
+    let mut cluster = SimulatedCluster::start().await;
+    let one_cpu = |hostname: &str| {
+        FakeWorkerConfig::new(hostname).with_metrics(json!({
+            "cpu": { "total": 1, "free": 100.0 },
+            "memory": { "total": 1073741824u64, "free": 1073741824u64 },
+            "disks": [],
+        }))
+    };
+    let first = cluster.add_worker(one_cpu("node-1")).await;
+    eventually("worker ready", || cluster.has_event("node-1", "NodeReady")).await;
+
+    // Only two of the three instances fit on the worker
+    let mut definition = pod("trainer", 3);
+    definition["spec"]["containers"][0]["resources"] = json!({ "cpu": 500 });
+    definition["gang"] = json!({ "timeout": 1 });
+    assert!(cluster
+        .create("trainer", definition)
+        .await
+        .unwrap()
+        .is_none());
+    eventually("gang reported unschedulable", || {
+        cluster.has_scheduling_failure("trainer", SchedulingFailureReason::Unschedulable)
+    })
+    .await;
+    assert!(cluster.has_event("trainer", "Unschedulable"));
+    assert!(first.received().is_empty());
+
+    // With room for all of them, the instances are placed together
+    let second = cluster.add_worker(one_cpu("node-2")).await;
+    eventually("all instances running", || {
+        first.instances().len() + second.instances().len() == 3
+    })
+    .await;
+    cluster.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_worker_not_back_after_restore() {
+    let scheduling = SchedulingSettings {
+        restore_grace_period: Duration::from_millis(500),
+        ..SchedulingSettings::default()
+    };
+    let mut cluster = SimulatedCluster::start_with(scheduling).await;
+    let gone = cluster.add_worker(FakeWorkerConfig::new("node-1")).await;
+    cluster.create("web", pod("web", 1)).await.unwrap();
+    eventually("instance running on node-1", || gone.instances().len() == 1).await;
+    let instance_id = gone.instances().remove(0);
+
+    // node-1 doesn't register again with the restarted scheduler
+    let cluster = cluster.restart(scheduling).await;
+    gone.disconnect();
+    let remaining = cluster.add_worker(FakeWorkerConfig::new("node-2")).await;
+    eventually("instance forgotten after the grace period", || {
+        cluster.has_event(&instance_id, "Rescheduling")
+    })
+    .await;
+    assert!(cluster.has_event("node-1", "WorkerLost"));
+    eventually("instance rescheduled on node-2", || {
+        remaining.instances().len() == 1
+    })
+    .await;
+    assert_ne!(remaining.instances()[0], instance_id);
+    cluster.shutdown().await;
+}
@@ -0,0 +1,233 @@
+use proto::common::worker_status::Status;
+use proto::common::{
+    InstanceMetric, ResourceStatus, WorkerMetric, WorkerRegistration, WorkerStatus,
+    WorkloadRequestKind,
+};
+use proto::worker::worker_client::WorkerClient;
+use proto::worker::InstanceScheduling;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tonic::transport::Channel;
+use tonic::Request;
+
+/// How a fake worker runs the instances it receives
+#[derive(Clone, Copy, Debug)]
+pub enum InstanceBehaviour {
+    /// Instances keep running until they are destroyed
+    Run,
+    /// Instances exit with the given code right after they started
+    Exit(i32),
+    /// Instances are accepted, but their status is never reported
+    Hang,
+}
+
+#[derive(Clone, Debug)]
+pub struct FakeWorkerConfig {
+    pub hostname: String,
+    pub labels: HashMap<String, String>,
+    /// Metrics reported by the worker, JSON encoded
+    pub metrics: String,
+    pub metrics_interval: Duration,
+    pub behaviour: InstanceBehaviour,
+}
+
+impl FakeWorkerConfig {
+    pub fn new(hostname: &str) -> FakeWorkerConfig {
+        FakeWorkerConfig {
+            hostname: hostname.to_string(),
+            labels: HashMap::new(),
+            metrics: "{}".to_string(),
+            metrics_interval: Duration::from_millis(50),
+            behaviour: InstanceBehaviour::Run,
+        }
+    }
+
+    pub fn with_label(mut self, key: &str, value: &str) -> FakeWorkerConfig {
+        self.labels.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn with_metrics(mut self, metrics: serde_json::Value) -> FakeWorkerConfig {
+        self.metrics = metrics.to_string();
+        self
+    }
+
+    pub fn with_behaviour(mut self, behaviour: InstanceBehaviour) -> FakeWorkerConfig {
+        self.behaviour = behaviour;
+        self
+    }
+}
+
+#[derive(Default)]
+struct FakeWorkerState {
+    /// Instances currently running on the worker
+    running: HashMap<String, InstanceScheduling>,
+    /// Every scheduling order received, in order
+    received: Vec<InstanceScheduling>,
+    disconnected: bool,
+}
+
+/// A worker registered to the scheduler, which runs nothing but reports
+/// statuses as a riklet would
+pub struct FakeWorker {
+    pub hostname: String,
+    client: WorkerClient<Channel>,
+    state: Arc<Mutex<FakeWorkerState>>,
+    ready: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl FakeWorker {
+    pub async fn start(
+        addr: SocketAddr,
+        config: FakeWorkerConfig,
+    ) -> Result<FakeWorker, Box<dyn std::error::Error>> {
+        let mut client = WorkerClient::connect(format!("http://{}", addr)).await?;
+        let mut stream = client
+            .register(Request::new(WorkerRegistration {
+                hostname: config.hostname.clone(),
+                labels: config.labels.clone(),
+            }))
+            .await?
+            .into_inner();
+
+        let hostname = config.hostname.clone();
+        let state = Arc::new(Mutex::new(FakeWorkerState::default()));
+        let ready = Arc::new(AtomicBool::new(true));
+
+        let worker_client = client.clone();
+        let worker_state = state.clone();
+        let worker_ready = ready.clone();
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(config.metrics_interval);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        let status = match worker_ready.load(Ordering::SeqCst) {
+                            true => ResourceStatus::Running,
+                            false => ResourceStatus::Failed,
+                        };
+                        let metric = Status::Worker(WorkerMetric {
+                            status: status.into(),
+                            metrics: config.metrics.clone(),
+                        });
+                        send_status(&worker_client, &config.hostname, metric).await;
+                    }
+                    message = stream.message() => match message {
+                        Ok(Some(instance)) => {
+                            handle_instance(&worker_client, &config, &worker_state, instance).await
+                        }
+                        Ok(None) | Err(_) => {
+                            worker_state.lock().unwrap().disconnected = true;
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(FakeWorker {
+            hostname,
+            client,
+            state,
+            ready,
+            handle,
+        })
+    }
+
+    /// Identifiers of the instances running on the worker, sorted
+    pub fn instances(&self) -> Vec<String> {
+        let mut instances: Vec<String> =
+            self.state.lock().unwrap().running.keys().cloned().collect();
+        instances.sort();
+        instances
+    }
+
+    /// Every scheduling order received by the worker, in order
+    pub fn received(&self) -> Vec<InstanceScheduling> {
+        self.state.lock().unwrap().received.clone()
+    }
+
+    /// Whether the scheduler closed the registration stream
+    pub fn is_disconnected(&self) -> bool {
+        self.state.lock().unwrap().disconnected
+    }
+
+    /// Report the worker as ready or not in the next metrics
+    pub fn set_ready(&self, ready: bool) {
+        self.ready.store(ready, Ordering::SeqCst);
+    }
+
+    /// Make a running instance fail with the given exit code
+    pub async fn fail_instance(&self, instance_id: &str, exit_code: i32) {
+        self.state.lock().unwrap().running.remove(instance_id);
+        let metric = instance_metric(instance_id, ResourceStatus::Failed, exit_code);
+        send_status(&self.client, &self.hostname, metric).await;
+    }
+
+    /// Drop the connection to the scheduler, as a crashed riklet would
+    pub fn disconnect(self) {
+        self.handle.abort();
+    }
+}
+
+fn instance_metric(instance_id: &str, status: ResourceStatus, exit_code: i32) -> Status {
+    Status::Instance(InstanceMetric {
+        instance_id: instance_id.to_string(),
+        status: status.into(),
+        metrics: String::new(),
+        exit_code,
+    })
+}
+
+async fn send_status(client: &WorkerClient<Channel>, hostname: &str, status: Status) {
+    let update = WorkerStatus {
+        identifier: hostname.to_string(),
+        status: Some(status),
+    };
+    // The scheduler may be shutting down, the next update is sent anyway
+    let _ = client
+        .clone()
+        .send_status_updates(Request::new(tokio_stream::iter(vec![update])))
+        .await;
+}
+
+async fn handle_instance(
+    client: &WorkerClient<Channel>,
+    config: &FakeWorkerConfig,
+    state: &Mutex<FakeWorkerState>,
+    instance: InstanceScheduling,
+) {
+    let instance_id = instance.instance_id.clone();
+    let is_create = instance.action == WorkloadRequestKind::Create as i32;
+    {
+        let mut state = state.lock().unwrap();
+        state.received.push(instance.clone());
+        match is_create {
+            true => state.running.insert(instance_id.clone(), instance),
+            false => state.running.remove(&instance_id),
+        };
+    }
+
+    let statuses = match (is_create, config.behaviour) {
+        (false, _) => vec![(ResourceStatus::Terminated, 0)],
+        (true, InstanceBehaviour::Hang) => vec![],
+        (true, InstanceBehaviour::Run) => vec![(ResourceStatus::Running, 0)],
+        (true, InstanceBehaviour::Exit(exit_code)) => {
+            state.lock().unwrap().running.remove(&instance_id);
+            let outcome = match exit_code {
+                0 => ResourceStatus::Succeeded,
+                _ => ResourceStatus::Failed,
+            };
+            vec![(ResourceStatus::Running, 0), (outcome, exit_code)]
+        }
+    };
+    for (status, exit_code) in statuses {
+        let metric = instance_metric(&instance_id, status, exit_code);
+        send_status(client, &config.hostname, metric).await;
+    }
+}
@@ -0,0 +1,81 @@
+use definition::workload::ResourceRequests;
+use scheduler::Worker;
+
+/// Resources allocated on a worker, to the instances placed on it and the
+/// reservations of gang scheduled workloads
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Allocation {
+    pub instances: usize,
+    /// CPU, in millicores
+    pub cpu: u64,
+    /// Memory, in MiB
+    pub memory: u64,
+}
+
+impl Allocation {
+    pub fn add(&mut self, requests: &ResourceRequests) {
+        self.instances += 1;
+        self.cpu += requests.cpu.unwrap_or(0) as u64;
+        self.memory += requests.memory.unwrap_or(0);
+    }
+}
+
+/// Whether a worker has room for one more instance with the given requests.
+/// The capacity of a worker is given by its metrics, a worker which didn't
+/// report them yet accepts any instance.
+pub fn fits(worker: &Worker, allocation: &Allocation, requests: &ResourceRequests) -> bool {
+    let metrics = match worker.get_metrics() {
+        Some(metrics) => metrics,
+        None => return true,
+    };
+    let cpu = requests.cpu.unwrap_or(0) as u64;
+    let memory = requests.memory.unwrap_or(0);
+    let cpu_capacity = metrics.cpu.total as u64 * 1000;
+    let memory_capacity = metrics.memory.total / (1024 * 1024);
+    (cpu == 0 || allocation.cpu + cpu <= cpu_capacity)
+        && (memory == 0 || allocation.memory + memory <= memory_capacity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use node_metrics::metrics::{CpuMetrics, MemoryMetrics, Metrics};
+
+    fn worker(cpus: u8, memory_mib: u64) -> Worker {
+        let (sender, _) = tokio::sync::mpsc::channel(1);
+        let mut worker = Worker::new(
+            "node-1".to_string(),
+            sender,
+            "127.0.0.1:8080".parse().unwrap(),
+        );
+        worker.set_metrics(Metrics {
+            cpu: CpuMetrics {
+                total: cpus,
+                free: 100.0,
+            },
+            memory: MemoryMetrics {
+                total: memory_mib * 1024 * 1024,
+                free: memory_mib * 1024 * 1024,
+            },
+            disks: vec![],
+        });
+        worker
+    }
+
+    #[test]
+    fn test_fits() {
+        let worker = worker(2, 1024);
+        let requests = ResourceRequests {
+            cpu: Some(500),
+            memory: Some(256),
+        };
+        let mut allocation = Allocation::default();
+        for _ in 0..4 {
+            assert!(fits(&worker, &allocation, &requests));
+            allocation.add(&requests);
+        }
+        assert!(!fits(&worker, &allocation, &requests));
+        // Instances without requests always fit
+        assert!(fits(&worker, &allocation, &ResourceRequests::default()));
+    }
+}
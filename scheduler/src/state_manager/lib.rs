@@ -12,6 +12,7 @@ pub fn get_random_hash(size: usize) -> String {
 
 pub fn int_to_resource_status(status: &i32) -> ResourceStatus {
     match status {
+        7 => ResourceStatus::Succeeded,
         6 => ResourceStatus::Destroying,
         5 => ResourceStatus::Creating,
         4 => ResourceStatus::Terminated,
@@ -1,22 +1,44 @@
+mod capacity;
 mod lib;
+mod snapshot;
+mod topology;
 
+pub use topology::SchedulingStrategy;
+
+use crate::config_parser::SchedulingSettings;
+use crate::metrics::SchedulerMetrics;
+use crate::state_manager::capacity::Allocation;
 use crate::state_manager::lib::{get_random_hash, int_to_resource_status};
-use definition::workload::WorkloadDefinition;
-use log::{debug, error, info};
-use proto::common::{InstanceMetric, ResourceStatus, WorkerMetric, WorkloadRequestKind};
+use crate::state_manager::snapshot::{
+    InstanceSnapshot, JobSnapshot, StateSnapshot, WorkloadSnapshot,
+};
+use crate::state_manager::topology::Placement;
+use definition::quota::{ResourceQuota, ResourceUsage};
+use definition::workload::{JobSpec, WorkloadDefinition, WorkloadKind};
+use log::{debug, error, info, warn};
+use proto::common::{
+    Event as ClusterEvent, InstanceMetric, ResourceStatus, SchedulingFailure,
+    SchedulingFailureReason, WorkerMetric, WorkloadMetric, WorkloadRequestKind,
+};
 use proto::worker::InstanceScheduling;
-use rand::seq::IteratorRandom;
-use scheduler::{Event, SchedulerError, Worker, WorkerState, WorkloadRequest};
+use scheduler::{
+    Event, ScheduleReplyChannel, SchedulerError, Worker, WorkerState, WorkloadRequest,
+    SCHEDULER_EVENT_SOURCE,
+};
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Instant;
+use telemetry::TraceContext;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::Mutex;
+use tracing::info_span;
 
 #[derive(Debug)]
 pub enum StateManagerEvent {
-    Schedule(WorkloadRequest),
+    Schedule(Box<WorkloadRequest>, ScheduleReplyChannel),
     Shutdown,
     InstanceUpdate(InstanceMetric),
     WorkerUpdate(String, WorkerMetric),
@@ -32,6 +54,12 @@ pub struct StateManager {
     state: HashMap<String, Workload>,
     workers: Arc<Mutex<Vec<Worker>>>,
     manager_channel: Sender<Event>,
+    /// File where the state is persisted on shutdown
+    state_file: Option<PathBuf>,
+    /// When the state was restored from a previous run
+    restored_at: Option<Instant>,
+    metrics: Arc<SchedulerMetrics>,
+    scheduling: SchedulingSettings,
 }
 
 impl StateManager {
@@ -42,20 +70,81 @@ impl StateManager {
             state: HashMap::with_capacity(20),
             manager_channel,
             workers,
+            state_file: None,
+            restored_at: None,
+            metrics: Arc::new(SchedulerMetrics::new()),
+            scheduling: SchedulingSettings::default(),
         }
     }
 
+    pub fn with_metrics(mut self, metrics: Arc<SchedulerMetrics>) -> StateManager {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn with_state_file(mut self, state_file: PathBuf) -> StateManager {
+        self.state_file = Some(state_file);
+        self
+    }
+
+    pub fn with_scheduling(mut self, scheduling: SchedulingSettings) -> StateManager {
+        self.scheduling = scheduling;
+        self
+    }
+
     pub async fn run(
         &mut self,
         mut receiver: Receiver<StateManagerEvent>,
     ) -> Result<(), SchedulerError> {
-        while let Some(message) = receiver.recv().await {
+        self.restore().await;
+        // Workers are also checked periodically, so a silent worker is detected
+        // even when no event is received
+        let mut heartbeat = tokio::time::interval(self.scheduling.worker_timeout / 2);
+        loop {
+            let message = tokio::select! {
+                message = receiver.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+                _ = heartbeat.tick() => {
+                    self.scan_workers().await;
+                    self.update_state().await;
+                    continue;
+                }
+            };
             let _ = match message {
                 StateManagerEvent::Shutdown => {
                     info!("Shutting down StateManager");
+                    self.persist().await;
                     return Ok(());
                 }
-                StateManagerEvent::Schedule(workload) => self.process_schedule_request(workload),
+                StateManagerEvent::Schedule(workload, reply) => {
+                    let workload_id = workload.workload_id.clone();
+                    let span = info_span!("process_schedule_request", %workload_id);
+                    telemetry::set_parent(&span, &workload.trace_context);
+                    let result = span.in_scope(|| self.process_schedule_request(*workload));
+                    if reply.send(result.clone()).is_err() {
+                        debug!(
+                            "Controller stopped waiting for the scheduling of {}",
+                            workload_id
+                        );
+                    }
+                    if let Err(e) = &result {
+                        self.metrics.record_scheduling_failure(e.kind());
+                        StateManager::emit(
+                            &self.manager_channel,
+                            ClusterEvent::new(
+                                ClusterEvent::WORKLOAD,
+                                &workload_id,
+                                "RequestRejected",
+                                format!("Request on workload {} was rejected: {}", workload_id, e),
+                            )
+                            .warning(),
+                        )
+                        .await;
+                    }
+                    result
+                }
                 StateManagerEvent::InstanceUpdate(metrics) => {
                     let _ = self
                         .manager_channel
@@ -64,7 +153,7 @@ impl StateManager {
                             metrics.clone(),
                         ))
                         .await;
-                    self.process_instance_update(metrics)
+                    self.process_instance_update(metrics).await
                 }
                 StateManagerEvent::WorkerUpdate(identifier, metrics) => {
                     self.process_metric_update(identifier, metrics).await
@@ -72,22 +161,118 @@ impl StateManager {
             };
             self.scan_workers().await;
             self.update_state().await;
+            self.observe_workloads().await;
         }
         Err(SchedulerError::StateManagerFailed)
     }
 
+    /// Restore the state persisted by a previous run, if any
+    async fn restore(&mut self) {
+        let state_file = match &self.state_file {
+            Some(state_file) => state_file,
+            None => return,
+        };
+        match StateSnapshot::take(state_file).await {
+            Ok(Some(snapshot)) => {
+                info!(
+                    "Restoring {} workloads from {}",
+                    snapshot.workloads.len(),
+                    state_file.display()
+                );
+                for workload in snapshot.workloads {
+                    let workload = Workload::from(workload);
+                    self.state.insert(workload.id.clone(), workload);
+                }
+                self.restored_at = Some(Instant::now());
+            }
+            Ok(None) => {}
+            Err(e) => error!(
+                "Could not restore state from {}, reason: {}",
+                state_file.display(),
+                e
+            ),
+        }
+    }
+
+    /// Persist the state, so the next run can restore it
+    async fn persist(&self) {
+        if let Some(state_file) = &self.state_file {
+            let snapshot = StateSnapshot {
+                workloads: self.state.values().map(WorkloadSnapshot::from).collect(),
+            };
+            match snapshot.save(state_file).await {
+                Ok(_) => info!(
+                    "Persisted {} workloads to {}",
+                    snapshot.workloads.len(),
+                    state_file.display()
+                ),
+                Err(e) => error!(
+                    "Could not persist state to {}, reason: {}",
+                    state_file.display(),
+                    e
+                ),
+            }
+        }
+    }
+
     async fn scan_workers(&mut self) {
         let mut deactivated_workers = Vec::new();
+        // Workers still hosting instances, a worker which flipped to NotReady before
+        // it was lost holds instances waiting for it, e.g. the Destroying ones of
+        // daemon sets, which must be forgotten too
+        let hosting: HashSet<&String> = self
+            .state
+            .values()
+            .flat_map(|workload| workload.instances.values())
+            .filter_map(|instance| instance.worker_id.as_ref())
+            .collect();
         let mut state = self.workers.lock().await;
         {
             for worker in state.iter_mut() {
-                if worker.channel.is_closed() && worker.is_ready() {
+                let is_lost = worker.channel.is_closed()
+                    || worker.last_seen().elapsed() > self.scheduling.worker_timeout;
+                if is_lost && (worker.is_ready() || hosting.contains(&worker.id)) {
                     worker.set_state(WorkerState::NotReady);
                     deactivated_workers.push(worker.id.clone());
                 }
             }
         }
 
+        // Workers that didn't register again after a restore are considered gone
+        if self
+            .restored_at
+            .is_some_and(|restored_at| restored_at.elapsed() > self.scheduling.restore_grace_period)
+        {
+            self.restored_at = None;
+            for workload in self.state.values() {
+                for instance in workload.instances.values() {
+                    if let Some(worker_id) = &instance.worker_id {
+                        if !state.iter().any(|worker| &worker.id == worker_id)
+                            && !deactivated_workers.contains(worker_id)
+                        {
+                            info!("Worker {} did not come back after restore", worker_id);
+                            deactivated_workers.push(worker_id.clone());
+                        }
+                    }
+                }
+            }
+        }
+        drop(state);
+
+        for worker_id in &deactivated_workers {
+            StateManager::emit(
+                &self.manager_channel,
+                ClusterEvent::new(
+                    ClusterEvent::WORKER,
+                    worker_id,
+                    "WorkerLost",
+                    format!("Worker {} is not reachable anymore", worker_id),
+                )
+                .warning(),
+            )
+            .await;
+        }
+
         // In the case we deactivated any worker, we want to reschedule the instances linked to that
         let mut instances_to_delete = Vec::new();
         let instances = self.state.iter_mut();
@@ -104,13 +289,35 @@ impl StateManager {
         }
 
         for (workload_id, instance_id) in &instances_to_delete {
-            if let Some(workload) = self.state.get_mut(workload_id) {
-                workload.instances.remove(instance_id);
+            let instance = match self.state.get_mut(workload_id) {
+                Some(workload) => workload.instances.remove(instance_id),
+                None => None,
+            };
+            // Instances being destroyed are gone with their worker, nothing replaces them
+            if instance.is_none_or(|instance| instance.status == ResourceStatus::Destroying) {
+                continue;
             }
+            StateManager::emit(
+                &self.manager_channel,
+                ClusterEvent::new(
+                    ClusterEvent::INSTANCE,
+                    instance_id,
+                    "Rescheduling",
+                    format!(
+                        "Instance {} of workload {} was lost with its worker and will be replaced",
+                        instance_id, workload_id
+                    ),
+                )
+                .warning(),
+            )
+            .await;
         }
     }
 
-    fn process_instance_update(&mut self, metrics: InstanceMetric) -> Result<(), SchedulerError> {
+    async fn process_instance_update(
+        &mut self,
+        metrics: InstanceMetric,
+    ) -> Result<(), SchedulerError> {
         debug!(
             "[process_instance_update] Instance {} and received {} status",
             metrics.instance_id, &metrics.status
@@ -128,6 +335,50 @@ impl StateManager {
                     &metrics.instance_id, &workload.id
                 );
                 workload.instances.remove(&metrics.instance_id);
+            } else if workload.job.is_some()
+                && matches!(status, ResourceStatus::Succeeded | ResourceStatus::Failed)
+            {
+                // Instances of a job run to completion, they are never recreated once done,
+                // we only keep track of their outcome
+                let instance = workload.instances.remove(&metrics.instance_id).unwrap();
+                info!(
+                    "Instance {} of job {} completed with {:#?} status, exit code: {}",
+                    instance.id, &workload.id, status, metrics.exit_code
+                );
+                if instance.status == ResourceStatus::Destroying {
+                    return Ok(());
+                }
+
+                if let Some(job_status) = workload.record_job_outcome(status) {
+                    info!("Job {} is now {:#?}", &workload.id, job_status);
+                    let event = match job_status {
+                        ResourceStatus::Succeeded => ClusterEvent::new(
+                            ClusterEvent::WORKLOAD,
+                            &workload.id,
+                            "Completed",
+                            format!("Job {} completed successfully", workload.id),
+                        ),
+                        _ => ClusterEvent::new(
+                            ClusterEvent::WORKLOAD,
+                            &workload.id,
+                            "BackoffLimitExceeded",
+                            format!("Job {} has reached its backoff limit", workload.id),
+                        )
+                        .warning(),
+                    };
+                    StateManager::emit(&self.manager_channel, event).await;
+                    let _ = self
+                        .manager_channel
+                        .send(Event::WorkloadMetric(
+                            "scheduler".to_string(),
+                            WorkloadMetric {
+                                status: job_status.into(),
+                                metrics: "".to_string(),
+                                workload_id: workload.id.clone(),
+                            },
+                        ))
+                        .await;
+                }
             } else {
                 let instance = workload.instances.get_mut(&metrics.instance_id).unwrap();
                 instance.status = int_to_resource_status(&metrics.status);
@@ -153,11 +404,36 @@ impl StateManager {
     ) -> Result<(), SchedulerError> {
         let mut lock = self.workers.lock().await;
         if let Some(worker) = lock.iter_mut().find(|worker| worker.id.eq(&identifier)) {
+            let was_ready = worker.is_ready();
+            if let Ok(node_metrics) = serde_json::from_str(&metrics.metrics) {
+                worker.set_metrics(node_metrics);
+            }
             if int_to_resource_status(&metrics.status) == ResourceStatus::Running {
                 worker.set_state(WorkerState::Ready);
             } else {
                 worker.set_state(WorkerState::NotReady);
             }
+            let is_ready = worker.is_ready();
+            drop(lock);
+
+            if was_ready != is_ready {
+                let event = match is_ready {
+                    true => ClusterEvent::new(
+                        ClusterEvent::WORKER,
+                        &identifier,
+                        "NodeReady",
+                        format!("Worker {} is ready", identifier),
+                    ),
+                    false => ClusterEvent::new(
+                        ClusterEvent::WORKER,
+                        &identifier,
+                        "NodeNotReady",
+                        format!("Worker {} is not ready", identifier),
+                    )
+                    .warning(),
+                };
+                StateManager::emit(&self.manager_channel, event).await;
+            }
         } else {
             error!(
                 "Received metrics for worker {} but could not find registration associated",
@@ -169,36 +445,101 @@ impl StateManager {
     }
 
     async fn update_state(&mut self) {
-        if self.workers.lock().await.len() == 0 {
+        if self.workers.lock().await.is_empty() {
             info!("State isn't updated as there is no worker available");
             return;
         }
 
+        // Workers on which each daemon set must run, computed ahead so the workers
+        // aren't locked while we send events to the manager
+        let daemon_targets: HashMap<String, Vec<String>> = {
+            let workers = self.workers.lock().await;
+            self.state
+                .values()
+                .filter(|workload| workload.definition.get_kind() == WorkloadKind::DaemonSet)
+                .map(|workload| {
+                    let targets = match workload.status {
+                        ResourceStatus::Destroying => Vec::new(),
+                        _ => workers
+                            .iter()
+                            .filter(|worker| {
+                                worker.is_ready()
+                                    && worker.matches_selector(&workload.definition.node_selector)
+                            })
+                            .map(|worker| worker.id.clone())
+                            .collect(),
+                    };
+                    (workload.id.clone(), targets)
+                })
+                .collect()
+        };
+
         let mut scheduled: Vec<(String, WorkloadInstance)> = Vec::new();
+        // Gang scheduled workloads, with their number of missing instances
+        let mut gangs: Vec<(String, u16)> = Vec::new();
 
         // Well I'm sorry for this piece of code which isn't a art piece! Had some trouble with
         // ownership
         for (id, workload) in self.state.iter_mut() {
-            let length_diff: i32 = workload.replicas as i32 - (workload.instances.len() as i32);
+            // Reservations are made again below by the gangs still missing instances
+            workload.reservations.clear();
+            if let Some(targets) = daemon_targets.get(id) {
+                // A daemon set has exactly one instance on every matching worker
+                let mut is_pending = false;
+                for worker_id in targets {
+                    let is_running = workload.instances.values().any(|instance| {
+                        instance.worker_id.as_ref() == Some(worker_id)
+                            && instance.status != ResourceStatus::Destroying
+                    });
+                    if !is_running {
+                        is_pending = true;
+                        debug!(
+                            "Daemon set {} has no instance on worker {}",
+                            workload.id, worker_id
+                        );
+                        scheduled.push((
+                            id.clone(),
+                            WorkloadInstance::new(
+                                workload.generate_instance_id(),
+                                ResourceStatus::Pending,
+                                Some(worker_id.clone()),
+                                workload.definition.clone(),
+                            ),
+                        ));
+                    }
+                }
+
+                for instance in workload.instances.values_mut().filter(|instance| {
+                    instance.status != ResourceStatus::Destroying
+                        && !instance
+                            .worker_id
+                            .as_ref()
+                            .is_some_and(|worker_id| targets.contains(worker_id))
+                }) {
+                    StateManager::destroy_instance(&self.manager_channel, instance).await;
+                }
+                workload.set_pending(is_pending);
+                continue;
+            }
+
+            let length_diff: i32 =
+                workload.desired_instances() as i32 - (workload.instances.len() as i32);
+            workload.set_pending(length_diff > 0);
             match length_diff.cmp(&0) {
                 Ordering::Greater => {
                     debug!(
                         "Divergence detected on {}, divergence length: {}",
                         workload.id, length_diff
                     );
+                    if workload.definition.gang.is_some() {
+                        gangs.push((id.clone(), length_diff as u16));
+                        continue;
+                    }
                     for _ in 0..length_diff {
-                        // Generate an instance ID, and ensure it is unique
-                        let mut workload_id = get_random_hash(4).to_ascii_lowercase();
-                        while workload.instances.contains_key(id) {
-                            workload_id = get_random_hash(4).to_ascii_lowercase();
-                        }
-                        workload_id =
-                            format!("{}-{}", workload.definition.name.clone(), workload_id);
-
                         scheduled.push((
                             id.clone(),
                             WorkloadInstance::new(
-                                workload_id.clone(),
+                                workload.generate_instance_id(),
                                 ResourceStatus::Pending,
                                 None,
                                 workload.definition.clone(),
@@ -214,42 +555,13 @@ impl StateManager {
                     let mut removed: Vec<String> = Vec::new();
                     // As length_diff is negative, we need the opposite
                     for _ in 0..(-length_diff) {
-                        if let Some((id, instance)) = workload
-                            .instances
-                            .iter_mut()
-                            .find(|(id, _)| !removed.contains(id))
+                        if let Some((id, instance)) =
+                            workload.instances.iter_mut().find(|(id, instance)| {
+                                !removed.contains(id)
+                                    && instance.status != ResourceStatus::Destroying
+                            })
                         {
-                            instance.status = ResourceStatus::Destroying;
-                            debug!(
-                                "WorkloadInstance {} went to {:#?}",
-                                &instance.id, &instance.status
-                            );
-
-                            let _ = self
-                                .manager_channel
-                                .send(Event::Schedule(
-                                    instance.worker_id.clone().unwrap(),
-                                    InstanceScheduling {
-                                        instance_id: instance.id.clone(),
-                                        action: WorkloadRequestKind::Destroy.into(),
-                                        definition: serde_json::to_string(
-                                            &instance.definition.clone(),
-                                        )
-                                        .unwrap(),
-                                    },
-                                ))
-                                .await;
-                            let _ = self
-                                .manager_channel
-                                .send(Event::InstanceMetric(
-                                    "scheduler".to_string(),
-                                    InstanceMetric {
-                                        status: ResourceStatus::Destroying.into(),
-                                        metrics: "".to_string(),
-                                        instance_id: instance.id.clone(),
-                                    },
-                                ))
-                                .await;
+                            StateManager::destroy_instance(&self.manager_channel, instance).await;
                             removed.push(id.clone());
                         }
                     }
@@ -258,45 +570,67 @@ impl StateManager {
             }
         }
 
-        for (workload_id, mut instance) in scheduled.into_iter() {
-            if let Some(worker_id) = self.get_eligible_worker().await {
-                let _ = self
-                    .manager_channel
-                    .send(Event::Schedule(
-                        worker_id.clone(),
-                        InstanceScheduling {
-                            instance_id: instance.id.clone(),
-                            action: WorkloadRequestKind::Create as i32,
-                            definition: serde_json::to_string(&instance.definition.clone())
-                                .unwrap(),
-                        },
-                    ))
-                    .await;
-                let _ = self
-                    .manager_channel
-                    .send(Event::InstanceMetric(
-                        "scheduler".to_string(),
-                        InstanceMetric {
-                            status: ResourceStatus::Pending.into(),
-                            metrics: format!("\"workload_id\": \"{}\"", workload_id.clone()),
-                            instance_id: instance.id.clone(),
+        for (workload_id, missing) in gangs {
+            self.schedule_gang(&workload_id, missing).await;
+        }
+
+        for (workload_id, instance) in scheduled.into_iter() {
+            // Instances of daemon sets are already bound to their worker, they are
+            // charged to the quota of their tenant before being placed
+            let placement = match &instance.worker_id {
+                Some(worker_id) => {
+                    if let Err(e) = self.check_daemon_quota(&workload_id) {
+                        let event = ClusterEvent::new(
+                            ClusterEvent::WORKLOAD,
+                            &workload_id,
+                            "FailedScheduling",
+                            format!(
+                                "Cannot run daemon set {} on worker {}: {}",
+                                workload_id, worker_id, e
+                            ),
+                        );
+                        self.report_failed_scheduling(e.to_failure(&workload_id), event)
+                            .await;
+                        continue;
+                    }
+                    Some(Placement {
+                        worker_id: worker_id.clone(),
+                        unsatisfied: None,
+                    })
+                }
+                None => self.get_eligible_worker(&workload_id, &[]).await,
+            };
+            match placement {
+                Some(placement) => {
+                    self.schedule_instance(&workload_id, instance, placement)
+                        .await
+                }
+                None => {
+                    error!("Trying to schedule but cannot find any eligible worker");
+                    self.report_failed_scheduling(
+                        SchedulingFailure {
+                            workload_id: workload_id.clone(),
+                            reason: SchedulingFailureReason::NoEligibleWorker.into(),
+                            message: String::from("No ready worker matches the workload"),
                         },
-                    ))
+                        ClusterEvent::new(
+                            ClusterEvent::WORKLOAD,
+                            &workload_id,
+                            "FailedScheduling",
+                            format!("No eligible worker for workload {}", workload_id),
+                        ),
+                    )
                     .await;
-                let state = self.state.get_mut(&workload_id).unwrap();
-                {
-                    instance.set_worker(Some(worker_id));
-                    state.instances.insert(instance.id.clone(), instance);
                 }
-            } else {
-                error!("Trying to schedule but cannot find any eligible worker");
             }
         }
 
         let mut to_be_deleted = Vec::new();
         for key in self.state.keys().clone() {
             if let Some(workload) = self.state.get(key) {
-                if workload.replicas == 0 && workload.instances.is_empty() {
+                if (workload.replicas == 0 || workload.is_completed())
+                    && workload.instances.is_empty()
+                {
                     to_be_deleted.push(key.clone());
                 }
             }
@@ -308,16 +642,308 @@ impl StateManager {
         }
     }
 
+    /// Send an instance to the worker it was placed on
+    async fn schedule_instance(
+        &mut self,
+        workload_id: &str,
+        mut instance: WorkloadInstance,
+        placement: Placement,
+    ) {
+        let Placement {
+            worker_id,
+            unsatisfied,
+        } = placement;
+        if let Some(reason) = unsatisfied {
+            warn!(
+                "Cannot honor the topology spread of workload {}: {}",
+                workload_id, reason
+            );
+            StateManager::emit(
+                &self.manager_channel,
+                ClusterEvent::new(
+                    ClusterEvent::WORKLOAD,
+                    workload_id,
+                    "TopologySpreadUnsatisfied",
+                    format!(
+                        "Instance {} placed on worker {} anyway, {}",
+                        instance.id, worker_id, reason
+                    ),
+                )
+                .warning(),
+            )
+            .await;
+        }
+        // The worker creating the instance continues the trace of the workload request
+        let span = info_span!(
+            "schedule_instance",
+            %workload_id,
+            instance_id = %instance.id,
+            %worker_id
+        );
+        if let Some(workload) = self.state.get(workload_id) {
+            telemetry::set_parent(&span, &workload.trace_context);
+        }
+        let _ = self
+            .manager_channel
+            .send(Event::Schedule(
+                worker_id.clone(),
+                InstanceScheduling {
+                    instance_id: instance.id.clone(),
+                    action: WorkloadRequestKind::Create as i32,
+                    definition: serde_json::to_string(&instance.definition.clone()).unwrap(),
+                    trace_context: span.in_scope(telemetry::current_context),
+                },
+            ))
+            .await;
+        let _ = self
+            .manager_channel
+            .send(Event::InstanceMetric(
+                "scheduler".to_string(),
+                InstanceMetric {
+                    status: ResourceStatus::Pending.into(),
+                    metrics: format!(
+                        "\"workload_id\": \"{}\", \"worker_id\": \"{}\"",
+                        workload_id, worker_id
+                    ),
+                    instance_id: instance.id.clone(),
+                    exit_code: 0,
+                },
+            ))
+            .await;
+        StateManager::emit(
+            &self.manager_channel,
+            ClusterEvent::new(
+                ClusterEvent::INSTANCE,
+                &instance.id,
+                "Scheduled",
+                format!(
+                    "Assigned instance {} of workload {} to worker {}",
+                    instance.id, workload_id, worker_id
+                ),
+            ),
+        )
+        .await;
+        let state = self.state.get_mut(workload_id).unwrap();
+        if let Some(pending_since) = state.pending_since {
+            self.metrics
+                .observe_scheduling_latency(pending_since.elapsed());
+        }
+        state.failed_scheduling = false;
+        {
+            instance.set_worker(Some(worker_id));
+            state.instances.insert(instance.id.clone(), instance);
+        }
+    }
+
+    /// Place the missing instances of a gang scheduled workload all together. Until
+    /// they can all be placed, the workers found for some of them are reserved, and
+    /// released after the gang timeout when the workload is reported unschedulable.
+    async fn schedule_gang(&mut self, workload_id: &str, missing: u16) {
+        let mut placements: Vec<Placement> = Vec::new();
+        let mut planned: Vec<String> = Vec::new();
+        for _ in 0..missing {
+            match self.get_eligible_worker(workload_id, &planned).await {
+                Some(placement) => {
+                    planned.push(placement.worker_id.clone());
+                    placements.push(placement);
+                }
+                None => break,
+            }
+        }
+
+        let workload = match self.state.get_mut(workload_id) {
+            Some(workload) => workload,
+            None => return,
+        };
+        if placements.len() == missing as usize {
+            debug!(
+                "Placing the {} instances of gang scheduled workload {}",
+                missing, workload_id
+            );
+            for placement in placements {
+                let workload = self.state.get(workload_id).unwrap();
+                let instance = WorkloadInstance::new(
+                    workload.generate_instance_id(),
+                    ResourceStatus::Pending,
+                    None,
+                    workload.definition.clone(),
+                );
+                self.schedule_instance(workload_id, instance, placement)
+                    .await;
+            }
+            return;
+        }
+
+        let timeout = workload
+            .definition
+            .gang
+            .clone()
+            .unwrap_or_default()
+            .get_timeout();
+        let timed_out = workload
+            .pending_since
+            .is_some_and(|pending_since| pending_since.elapsed() > timeout);
+        if !timed_out {
+            debug!(
+                "Reserved {} of the {} instances of gang scheduled workload {}",
+                planned.len(),
+                missing,
+                workload_id
+            );
+            workload.reservations = planned;
+            return;
+        }
+
+        self.report_failed_scheduling(
+            SchedulingFailure {
+                workload_id: workload_id.to_string(),
+                reason: SchedulingFailureReason::Unschedulable.into(),
+                message: format!(
+                    "Only {} of the {} instances could be placed together",
+                    planned.len(),
+                    missing
+                ),
+            },
+            ClusterEvent::new(
+                ClusterEvent::WORKLOAD,
+                workload_id,
+                "Unschedulable",
+                format!(
+                    "Could not place the {} instances of workload {} within {}s, none was started",
+                    missing,
+                    workload_id,
+                    timeout.as_secs()
+                ),
+            ),
+        )
+        .await;
+    }
+
+    /// Report a workload whose pending instances cannot be placed, only once
+    /// until an instance of the workload gets scheduled
+    async fn report_failed_scheduling(&mut self, failure: SchedulingFailure, event: ClusterEvent) {
+        let reason = failure.reason().as_str_name().to_lowercase();
+        self.metrics.record_scheduling_failure(&reason);
+        let workload = match self.state.get_mut(&failure.workload_id) {
+            Some(workload) => workload,
+            None => return,
+        };
+        if workload.failed_scheduling {
+            return;
+        }
+        workload.failed_scheduling = true;
+        let _ = self
+            .manager_channel
+            .send(Event::SchedulingFailure(failure))
+            .await;
+        StateManager::emit(&self.manager_channel, event.warning()).await;
+    }
+
+    /// Record the desired and running instances of every workload
+    async fn observe_workloads(&self) {
+        let workers = self.workers.lock().await;
+        self.metrics.reset_workloads();
+        for workload in self.state.values() {
+            let desired = match workload.definition.get_kind() {
+                WorkloadKind::DaemonSet if workload.status != ResourceStatus::Destroying => workers
+                    .iter()
+                    .filter(|worker| {
+                        worker.is_ready()
+                            && worker.matches_selector(&workload.definition.node_selector)
+                    })
+                    .count(),
+                WorkloadKind::DaemonSet => 0,
+                _ => workload.desired_instances() as usize,
+            };
+            let running = workload
+                .instances
+                .values()
+                .filter(|instance| instance.status == ResourceStatus::Running)
+                .count();
+            self.metrics
+                .observe_workload(&workload.id, desired, running);
+        }
+    }
+
+    /// Send an event about an object of the cluster to the controller
+    async fn emit(manager_channel: &Sender<Event>, event: ClusterEvent) {
+        let _ = manager_channel
+            .send(Event::ClusterEvent(
+                event.with_source(SCHEDULER_EVENT_SOURCE),
+            ))
+            .await;
+    }
+
+    /// Ask the worker of an instance to destroy it
+    async fn destroy_instance(manager_channel: &Sender<Event>, instance: &mut WorkloadInstance) {
+        instance.status = ResourceStatus::Destroying;
+        debug!(
+            "WorkloadInstance {} went to {:#?}",
+            &instance.id, &instance.status
+        );
+        let worker_id = instance.worker_id.clone().unwrap_or_default();
+        StateManager::emit(
+            manager_channel,
+            ClusterEvent::new(
+                ClusterEvent::INSTANCE,
+                &instance.id,
+                "Killing",
+                format!("Stopping instance {} on worker {}", instance.id, worker_id),
+            ),
+        )
+        .await;
+
+        let _ = manager_channel
+            .send(Event::Schedule(
+                instance.worker_id.clone().unwrap(),
+                InstanceScheduling {
+                    instance_id: instance.id.clone(),
+                    action: WorkloadRequestKind::Destroy.into(),
+                    definition: serde_json::to_string(&instance.definition.clone()).unwrap(),
+                    trace_context: telemetry::current_context(),
+                },
+            ))
+            .await;
+        let _ = manager_channel
+            .send(Event::InstanceMetric(
+                "scheduler".to_string(),
+                InstanceMetric {
+                    status: ResourceStatus::Destroying.into(),
+                    metrics: "".to_string(),
+                    instance_id: instance.id.clone(),
+                    exit_code: 0,
+                },
+            ))
+            .await;
+    }
+
     fn process_schedule_request(&mut self, request: WorkloadRequest) -> Result<(), SchedulerError> {
         debug!(
-            "[process_schedule_request] Received workload id {}, action: {:#?}",
-            request.workload_id, request.action
+            "[process_schedule_request] [{}] Received workload id {}, action: {:#?}",
+            request.correlation_id, request.workload_id, request.action
         );
 
-        match request.action {
+        let correlation_id = request.correlation_id.clone();
+        let workload_id = request.workload_id.clone();
+        let trace_context = request.trace_context.clone();
+        let quota = request.quota.clone();
+        let result = match request.action {
             WorkloadRequestKind::Create => self.action_create_workload(request),
             WorkloadRequestKind::Destroy => self.action_destroy_workload(request),
+            WorkloadRequestKind::Reconcile => self.action_reconcile_workload(request),
+        };
+        // The instances scheduled from now on are traced with the last request
+        if let Some(workload) = self.state.get_mut(&workload_id) {
+            workload.trace_context = trace_context;
+            workload.quota = quota;
         }
+        if let Err(e) = &result {
+            warn!(
+                "[process_schedule_request] [{}] Rejected request on workload {}: {}",
+                correlation_id, workload_id, e
+            );
+        }
+        result
     }
 
     fn action_create_workload(&mut self, request: WorkloadRequest) -> Result<(), SchedulerError> {
@@ -327,16 +953,45 @@ impl StateManager {
                 return Err(SchedulerError::CannotDoubleReplicas);
             }
 
-            let def_replicas = &workload.definition.replicas.unwrap_or(1);
-            self.action_add_replicas(&request.workload_id, def_replicas)?;
+            if workload.job.is_some() {
+                error!("Job {} is already scheduled", workload.id);
+                return Err(SchedulerError::JobAlreadyScheduled(workload.id.clone()));
+            }
+
+            if workload.definition.get_kind() == WorkloadKind::DaemonSet {
+                // A daemon set already runs on every matching worker
+                info!("Daemon set {} is already scheduled", workload.id);
+                return Ok(());
+            }
+
+            let def_replicas = workload.definition.replicas.unwrap_or(1);
+            self.check_quota(&request, def_replicas as u64)?;
+            self.action_add_replicas(&request.workload_id, &def_replicas)?;
         } else {
+            let job = match request.definition.get_kind() {
+                WorkloadKind::Job => Some(JobState::new(request.definition.get_job_spec())),
+                _ => None,
+            };
             let workload = Workload {
-                id: request.workload_id,
+                id: request.workload_id.clone(),
                 replicas: request.definition.replicas.unwrap_or(1),
-                definition: request.definition,
+                job,
+                definition: request.definition.clone(),
                 instances: HashMap::new(),
                 status: ResourceStatus::Pending,
+                pending_since: Some(Instant::now()),
+                failed_scheduling: false,
+                reservations: Vec::new(),
+                trace_context: TraceContext::new(),
+                quota: request.quota.clone(),
+            };
+            // Instances of daemon sets depend on the workers, they are not known yet and
+            // are charged to the quota as they are created
+            let instances = match workload.definition.get_kind() {
+                WorkloadKind::DaemonSet => 0,
+                _ => workload.desired_instances(),
             };
+            self.check_quota(&request, instances as u64)?;
 
             info!("[process_schedule_request] Received scheduling request for {}, with {:#?} replicas", workload.id, workload.definition.replicas);
 
@@ -345,6 +1000,130 @@ impl StateManager {
         Ok(())
     }
 
+    /// Align the state with the desired state of the controller, e.g. after a restart
+    /// of the scheduler or a lost request. A workload which isn't known is created again,
+    /// and the replicas of a pod workload are set to the instances wanted. The request
+    /// is idempotent, the other workloads are left as they are.
+    fn action_reconcile_workload(
+        &mut self,
+        request: WorkloadRequest,
+    ) -> Result<(), SchedulerError> {
+        let current = self.state.get(&request.workload_id).map(|workload| {
+            (
+                workload.replicas,
+                workload.status,
+                workload.definition.get_kind(),
+            )
+        });
+        match current {
+            None => {
+                info!(
+                    "[process_schedule_request] [{}] Workload {} is missing, creating it again",
+                    request.correlation_id, request.workload_id
+                );
+                let is_pod = request.definition.get_kind() == WorkloadKind::Pod;
+                if is_pod {
+                    self.check_quota(&request, request.instances as u64)?;
+                }
+                let workload_id = request.workload_id.clone();
+                let instances = request.instances;
+                self.action_create_workload(request)?;
+                if let (true, Some(workload)) = (is_pod, self.state.get_mut(&workload_id)) {
+                    workload.replicas = instances;
+                }
+                Ok(())
+            }
+            Some((replicas, status, WorkloadKind::Pod))
+                if status != ResourceStatus::Destroying && replicas != request.instances =>
+            {
+                info!(
+                    "[process_schedule_request] [{}] Reconcile workload {} from {} to {} replicas",
+                    request.correlation_id, request.workload_id, replicas, request.instances
+                );
+                if request.instances > replicas {
+                    let missing = request.instances - replicas;
+                    self.check_quota(&request, missing as u64)?;
+                    self.action_add_replicas(&request.workload_id, &missing)
+                } else {
+                    self.action_minus_replicas(
+                        &request.workload_id,
+                        &(replicas - request.instances),
+                    )
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Resources used by the workloads of a tenant
+    fn tenant_usage(&self, tenant: &str) -> ResourceUsage {
+        let mut usage = ResourceUsage::default();
+        for workload in self.state.values().filter(|workload| {
+            workload.definition.tenant.as_deref() == Some(tenant)
+                && workload.status != ResourceStatus::Destroying
+        }) {
+            let instances = match workload.definition.get_kind() {
+                WorkloadKind::DaemonSet => workload.instances.len(),
+                _ => workload.desired_instances() as usize,
+            };
+            usage.workloads += 1;
+            usage.add_instances(&workload.definition, instances as u64);
+        }
+        usage
+    }
+
+    /// Check the quota of the workload tenant allows to add instances to the workload.
+    /// The controller already checks quotas, this protects against concurrent requests.
+    /// Workloads without tenant are deliberately not limited, they belong to the
+    /// administrators of the cluster.
+    fn check_quota(&self, request: &WorkloadRequest, instances: u64) -> Result<(), SchedulerError> {
+        self.check_tenant_quota(
+            &request.workload_id,
+            &request.definition,
+            request.quota.as_deref(),
+            instances,
+        )
+    }
+
+    /// Check the quota of the workload tenant allows one more instance of a daemon
+    /// set, which gets instances as matching workers join the cluster
+    fn check_daemon_quota(&self, workload_id: &str) -> Result<(), SchedulerError> {
+        match self.state.get(workload_id) {
+            Some(workload) => self.check_tenant_quota(
+                workload_id,
+                &workload.definition,
+                workload.quota.as_deref(),
+                1,
+            ),
+            None => Ok(()),
+        }
+    }
+
+    fn check_tenant_quota(
+        &self,
+        workload_id: &str,
+        definition: &WorkloadDefinition,
+        quota: Option<&ResourceQuota>,
+        instances: u64,
+    ) -> Result<(), SchedulerError> {
+        if let (Some(quota), Some(tenant)) = (quota, &definition.tenant) {
+            let mut usage = self.tenant_usage(tenant);
+            if !self.state.contains_key(workload_id) {
+                usage.workloads += 1;
+            }
+            usage.add_instances(definition, instances);
+
+            if let Err(e) = quota.check(&usage) {
+                error!(
+                    "Cannot schedule workload {} of tenant {}: {}",
+                    workload_id, tenant, e
+                );
+                return Err(SchedulerError::QuotaExceeded(e.to_string()));
+            }
+        }
+        Ok(())
+    }
+
     fn action_add_replicas(
         &mut self,
         workload_id: &str,
@@ -361,6 +1140,7 @@ impl StateManager {
         );
 
         workload.replicas += replicas;
+        workload.set_pending(true);
         Ok(())
     }
 
@@ -394,7 +1174,7 @@ impl StateManager {
             return Err(SchedulerError::WorkloadDontExists(request.workload_id));
         }
 
-        let mut workload = workload.unwrap();
+        let workload = workload.unwrap();
 
         if workload.status == ResourceStatus::Destroying {
             return Ok(());
@@ -417,15 +1197,78 @@ impl StateManager {
         Ok(())
     }
 
-    async fn get_eligible_worker(&self) -> Option<String> {
+    /// Choose the worker of a new instance of a workload, among the ready workers
+    /// matching its node selector and having room for it. The workers already
+    /// planned for other instances of the workload are taken into account.
+    async fn get_eligible_worker(
+        &self,
+        workload_id: &str,
+        planned: &[String],
+    ) -> Option<Placement> {
+        let workload = self.state.get(workload_id)?;
+        let definition = &workload.definition;
+        let requests = definition.get_requests();
+        let mut allocations = self.allocations();
+        for worker_id in planned {
+            allocations
+                .entry(worker_id.clone())
+                .or_default()
+                .add(&requests);
+        }
+
         let workers = self.workers.lock().await;
-        {
-            let workers = workers.iter().filter(|worker| worker.is_ready());
-            if let Some(worker) = workers.choose(&mut rand::thread_rng()) {
-                return Some(worker.id.clone());
+        let candidates: Vec<&Worker> = workers
+            .iter()
+            .filter(|worker| {
+                worker.is_ready()
+                    && worker.matches_selector(&definition.node_selector)
+                    && capacity::fits(
+                        worker,
+                        &allocations.get(&worker.id).copied().unwrap_or_default(),
+                        &requests,
+                    )
+            })
+            .collect();
+        let placed_on: Vec<String> = workload
+            .instances
+            .values()
+            .filter(|instance| instance.status != ResourceStatus::Destroying)
+            .filter_map(|instance| instance.worker_id.clone())
+            .chain(planned.iter().cloned())
+            .collect();
+        let load = allocations
+            .into_iter()
+            .map(|(worker_id, allocation)| (worker_id, allocation.instances))
+            .collect();
+        topology::place(
+            definition.topology_spread.as_deref().unwrap_or_default(),
+            &candidates,
+            &workers,
+            &placed_on,
+            self.scheduling.strategy,
+            &load,
+        )
+    }
+
+    /// Resources allocated on each worker, by the instances placed on it and
+    /// the reservations of gang scheduled workloads
+    fn allocations(&self) -> HashMap<String, Allocation> {
+        let mut allocations: HashMap<String, Allocation> = HashMap::new();
+        for workload in self.state.values() {
+            let requests = workload.definition.get_requests();
+            let workers = workload
+                .instances
+                .values()
+                .filter_map(|instance| instance.worker_id.as_ref())
+                .chain(workload.reservations.iter());
+            for worker_id in workers {
+                allocations
+                    .entry(worker_id.clone())
+                    .or_default()
+                    .add(&requests);
             }
         }
-        None
+        allocations
     }
 }
 
@@ -437,6 +1280,170 @@ pub struct Workload {
     instances: HashMap<String, WorkloadInstance>,
     status: ResourceStatus,
     id: String,
+    /// Progress of the workload, only for workloads of kind `job`
+    job: Option<JobState>,
+    /// Since when instances of the workload are waiting to be scheduled
+    pending_since: Option<Instant>,
+    /// No eligible worker was found for the pending instances, it was reported
+    failed_scheduling: bool,
+    /// Workers reserved for the pending instances of a gang scheduled workload,
+    /// until all of them can be placed
+    reservations: Vec<String>,
+    /// Trace context of the last request on the workload
+    trace_context: TraceContext,
+    /// Resource quota of the tenant, as of the last request on the workload. The
+    /// instances of daemon sets are charged to it as they are created.
+    quota: Option<Box<ResourceQuota>>,
+}
+
+impl Workload {
+    fn set_pending(&mut self, is_pending: bool) {
+        match is_pending {
+            true => {
+                self.pending_since.get_or_insert_with(Instant::now);
+            }
+            false => self.pending_since = None,
+        }
+    }
+
+    /// Generate an instance ID, and ensure it is unique within the workload
+    fn generate_instance_id(&self) -> String {
+        loop {
+            let instance_id = format!(
+                "{}-{}",
+                self.definition.name,
+                get_random_hash(4).to_ascii_lowercase()
+            );
+            if !self.instances.contains_key(&instance_id) {
+                return instance_id;
+            }
+        }
+    }
+
+    /// The number of instances this workload should currently have
+    fn desired_instances(&self) -> u16 {
+        match &self.job {
+            None => self.replicas,
+            Some(_) if self.status != ResourceStatus::Pending => 0,
+            Some(job) => {
+                let remaining = job.spec.get_completions().saturating_sub(job.succeeded);
+                job.spec.get_parallelism().min(remaining)
+            }
+        }
+    }
+
+    /// A completed workload will never need instances again
+    fn is_completed(&self) -> bool {
+        matches!(
+            self.status,
+            ResourceStatus::Succeeded | ResourceStatus::Failed
+        )
+    }
+
+    /// Record the outcome of a job instance, returns the final status of the job
+    /// once it is completed
+    fn record_job_outcome(&mut self, status: ResourceStatus) -> Option<ResourceStatus> {
+        if self.status != ResourceStatus::Pending {
+            return None;
+        }
+        let job = self.job.as_mut()?;
+
+        if status == ResourceStatus::Succeeded {
+            job.succeeded += 1;
+        } else {
+            job.failed += 1;
+        }
+
+        if job.succeeded >= job.spec.get_completions() {
+            self.status = ResourceStatus::Succeeded;
+        } else if job.failed > job.spec.get_backoff_limit() {
+            self.status = ResourceStatus::Failed;
+        } else {
+            return None;
+        }
+        Some(self.status)
+    }
+}
+
+impl From<&Workload> for WorkloadSnapshot {
+    fn from(workload: &Workload) -> WorkloadSnapshot {
+        WorkloadSnapshot {
+            id: workload.id.clone(),
+            definition: workload.definition.clone(),
+            replicas: workload.replicas,
+            status: workload.status.into(),
+            job: workload.job.as_ref().map(|job| JobSnapshot {
+                succeeded: job.succeeded,
+                failed: job.failed,
+            }),
+            instances: workload
+                .instances
+                .values()
+                .map(|instance| InstanceSnapshot {
+                    id: instance.id.clone(),
+                    worker_id: instance.worker_id.clone(),
+                    status: instance.status.into(),
+                })
+                .collect(),
+            quota: workload.quota.as_deref().cloned(),
+        }
+    }
+}
+
+impl From<WorkloadSnapshot> for Workload {
+    fn from(snapshot: WorkloadSnapshot) -> Workload {
+        let definition = snapshot.definition;
+        let job = snapshot.job.map(|job| JobState {
+            spec: definition.get_job_spec(),
+            succeeded: job.succeeded,
+            failed: job.failed,
+        });
+        let instances = snapshot
+            .instances
+            .into_iter()
+            .map(|instance| {
+                let instance = WorkloadInstance::new(
+                    instance.id,
+                    int_to_resource_status(&instance.status),
+                    instance.worker_id,
+                    definition.clone(),
+                );
+                (instance.id.clone(), instance)
+            })
+            .collect();
+        Workload {
+            replicas: snapshot.replicas,
+            definition,
+            instances,
+            status: int_to_resource_status(&snapshot.status),
+            id: snapshot.id,
+            job,
+            pending_since: None,
+            failed_scheduling: false,
+            reservations: Vec::new(),
+            trace_context: TraceContext::new(),
+            quota: snapshot.quota.map(Box::new),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct JobState {
+    spec: JobSpec,
+    /// Number of instances that exited successfully
+    succeeded: u16,
+    /// Number of instances that failed
+    failed: u16,
+}
+
+impl JobState {
+    pub fn new(spec: JobSpec) -> JobState {
+        JobState {
+            spec,
+            succeeded: 0,
+            failed: 0,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -475,3 +1482,407 @@ impl WorkloadInstance {
         self.worker_id = worker;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use definition::workload::{Container, ResourceRequests, Spec};
+
+    fn job_workload(spec: JobSpec) -> Workload {
+        Workload {
+            replicas: 1,
+            definition: WorkloadDefinition {
+                api_version: "v0".to_string(),
+                kind: "job".to_string(),
+                name: "job-debian".to_string(),
+                spec: Spec { containers: vec![] },
+                replicas: None,
+                job: Some(spec.clone()),
+                cron_job: None,
+                node_selector: None,
+                topology_spread: None,
+                gang: None,
+                tenant: None,
+                namespace: None,
+            },
+            instances: HashMap::new(),
+            status: ResourceStatus::Pending,
+            id: "test".to_string(),
+            job: Some(JobState::new(spec)),
+            pending_since: None,
+            failed_scheduling: false,
+            reservations: Vec::new(),
+            trace_context: TraceContext::new(),
+            quota: None,
+        }
+    }
+
+    #[test]
+    fn test_job_desired_instances() {
+        let mut workload = job_workload(JobSpec {
+            completions: Some(3),
+            parallelism: Some(2),
+            backoff_limit: None,
+        });
+        assert_eq!(workload.desired_instances(), 2);
+
+        assert_eq!(workload.record_job_outcome(ResourceStatus::Succeeded), None);
+        assert_eq!(workload.record_job_outcome(ResourceStatus::Succeeded), None);
+        assert_eq!(workload.desired_instances(), 1);
+
+        assert_eq!(
+            workload.record_job_outcome(ResourceStatus::Succeeded),
+            Some(ResourceStatus::Succeeded)
+        );
+        assert_eq!(workload.desired_instances(), 0);
+        assert!(workload.is_completed());
+    }
+
+    #[test]
+    fn test_job_backoff_limit() {
+        let mut workload = job_workload(JobSpec {
+            completions: None,
+            parallelism: None,
+            backoff_limit: Some(1),
+        });
+
+        assert_eq!(workload.record_job_outcome(ResourceStatus::Failed), None);
+        assert_eq!(workload.desired_instances(), 1);
+        assert_eq!(
+            workload.record_job_outcome(ResourceStatus::Failed),
+            Some(ResourceStatus::Failed)
+        );
+        assert_eq!(workload.desired_instances(), 0);
+        // Outcomes received after the job completed are ignored
+        assert_eq!(workload.record_job_outcome(ResourceStatus::Succeeded), None);
+        assert_eq!(workload.status, ResourceStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn test_daemon_set_runs_on_matching_workers() {
+        let (manager_sender, _manager_receiver) = tokio::sync::mpsc::channel::<Event>(1024);
+        let (worker_sender, _worker_receiver) = tokio::sync::mpsc::channel(1024);
+
+        let mut workers = Vec::new();
+        for (hostname, zone) in [("node-1", "a"), ("node-2", "a"), ("node-3", "b")] {
+            let mut worker = Worker::new(
+                hostname.to_string(),
+                worker_sender.clone(),
+                "127.0.0.1:8080".parse().unwrap(),
+            );
+            worker.labels.insert("zone".to_string(), zone.to_string());
+            worker.set_state(WorkerState::Ready);
+            workers.push(worker);
+        }
+        let workers = Arc::new(Mutex::new(workers));
+        let mut state_manager = StateManager::new(manager_sender, workers.clone());
+
+        let definition = WorkloadDefinition {
+            api_version: "v0".to_string(),
+            kind: "daemonset".to_string(),
+            name: "agent".to_string(),
+            spec: Spec { containers: vec![] },
+            replicas: None,
+            job: None,
+            cron_job: None,
+            node_selector: Some(HashMap::from([("zone".to_string(), "a".to_string())])),
+            topology_spread: None,
+            gang: None,
+            tenant: None,
+            namespace: None,
+        };
+        let request = WorkloadRequest {
+            workload_id: "agent".to_string(),
+            definition,
+            action: WorkloadRequestKind::Create,
+            instances: 0,
+            quota: None,
+            correlation_id: String::new(),
+            trace_context: TraceContext::new(),
+        };
+        state_manager.process_schedule_request(request).unwrap();
+        state_manager.update_state().await;
+
+        let running_on = |state_manager: &StateManager| {
+            let mut workers: Vec<String> = state_manager.state["agent"]
+                .instances
+                .values()
+                .filter(|instance| instance.status != ResourceStatus::Destroying)
+                .filter_map(|instance| instance.worker_id.clone())
+                .collect();
+            workers.sort();
+            workers
+        };
+        assert_eq!(running_on(&state_manager), vec!["node-1", "node-2"]);
+
+        // Instances are removed from workers that no longer match
+        workers.lock().await[1]
+            .labels
+            .insert("zone".to_string(), "b".to_string());
+        state_manager.update_state().await;
+        assert_eq!(running_on(&state_manager), vec!["node-1"]);
+
+        // And scheduled on workers that start to match
+        workers.lock().await[2]
+            .labels
+            .insert("zone".to_string(), "a".to_string());
+        state_manager.update_state().await;
+        assert_eq!(running_on(&state_manager), vec!["node-1", "node-3"]);
+    }
+
+    #[tokio::test]
+    async fn test_daemon_set_on_lost_worker() {
+        let (manager_sender, _manager_receiver) = tokio::sync::mpsc::channel::<Event>(1024);
+        let (worker_sender, _worker_receiver) = tokio::sync::mpsc::channel(1024);
+        let (lost_sender, lost_receiver) = tokio::sync::mpsc::channel(1024);
+
+        let mut workers = Vec::new();
+        for (hostname, sender) in [("node-1", worker_sender), ("node-2", lost_sender)] {
+            let mut worker = Worker::new(
+                hostname.to_string(),
+                sender,
+                "127.0.0.1:8080".parse().unwrap(),
+            );
+            worker.set_state(WorkerState::Ready);
+            workers.push(worker);
+        }
+        let workers = Arc::new(Mutex::new(workers));
+        let mut state_manager = StateManager::new(manager_sender, workers.clone());
+
+        let definition = WorkloadDefinition {
+            api_version: "v0".to_string(),
+            kind: "daemonset".to_string(),
+            name: "agent".to_string(),
+            spec: Spec { containers: vec![] },
+            replicas: None,
+            job: None,
+            cron_job: None,
+            node_selector: None,
+            topology_spread: None,
+            gang: None,
+            tenant: None,
+            namespace: None,
+        };
+        let request = WorkloadRequest {
+            workload_id: "agent".to_string(),
+            definition,
+            action: WorkloadRequestKind::Create,
+            instances: 0,
+            quota: None,
+            correlation_id: String::new(),
+            trace_context: TraceContext::new(),
+        };
+        state_manager.process_schedule_request(request).unwrap();
+        state_manager.update_state().await;
+        assert_eq!(state_manager.state["agent"].instances.len(), 2);
+
+        // The worker flips to NotReady, its instance is being destroyed
+        workers.lock().await[1].set_state(WorkerState::NotReady);
+        state_manager.update_state().await;
+        let on_node_2 = |state_manager: &StateManager| {
+            state_manager.state["agent"]
+                .instances
+                .values()
+                .filter(|instance| instance.worker_id.as_deref() == Some("node-2"))
+                .map(|instance| instance.status)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(on_node_2(&state_manager), vec![ResourceStatus::Destroying]);
+
+        // Then it is lost before confirming, the instance is forgotten with it
+        drop(lost_receiver);
+        state_manager.scan_workers().await;
+        assert!(on_node_2(&state_manager).is_empty());
+        assert_eq!(state_manager.state["agent"].instances.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_daemon_set_quota() {
+        let (manager_sender, mut manager_receiver) = tokio::sync::mpsc::channel::<Event>(1024);
+        let (worker_sender, _worker_receiver) = tokio::sync::mpsc::channel(1024);
+
+        let mut workers = Vec::new();
+        for hostname in ["node-1", "node-2", "node-3"] {
+            let mut worker = Worker::new(
+                hostname.to_string(),
+                worker_sender.clone(),
+                "127.0.0.1:8080".parse().unwrap(),
+            );
+            worker.set_state(WorkerState::Ready);
+            workers.push(worker);
+        }
+        let mut state_manager = StateManager::new(manager_sender, Arc::new(Mutex::new(workers)));
+
+        let request = WorkloadRequest {
+            workload_id: "agent".to_string(),
+            definition: WorkloadDefinition {
+                api_version: "v0".to_string(),
+                kind: "daemonset".to_string(),
+                name: "agent".to_string(),
+                spec: Spec { containers: vec![] },
+                replicas: None,
+                job: None,
+                cron_job: None,
+                node_selector: None,
+                topology_spread: None,
+                gang: None,
+                tenant: Some("acme".to_string()),
+                namespace: None,
+            },
+            action: WorkloadRequestKind::Create,
+            instances: 0,
+            quota: Some(Box::new(ResourceQuota {
+                max_workloads: None,
+                max_instances: Some(2),
+                cpu: None,
+                memory: None,
+            })),
+            correlation_id: String::new(),
+            trace_context: TraceContext::new(),
+        };
+        state_manager.process_schedule_request(request).unwrap();
+        state_manager.update_state().await;
+
+        // The third worker would exceed the quota of the tenant
+        assert_eq!(state_manager.state["agent"].instances.len(), 2);
+        let mut failures = Vec::new();
+        while let Ok(event) = manager_receiver.try_recv() {
+            if let Event::SchedulingFailure(failure) = event {
+                failures.push(failure);
+            }
+        }
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].reason(), SchedulingFailureReason::QuotaExceeded);
+
+        // Until the quota allows it
+        state_manager.state.get_mut("agent").unwrap().quota = None;
+        state_manager.update_state().await;
+        assert_eq!(state_manager.state["agent"].instances.len(), 3);
+    }
+
+    #[test]
+    fn test_tenant_quota() {
+        let (manager_sender, _manager_receiver) = tokio::sync::mpsc::channel::<Event>(1024);
+        let mut state_manager = StateManager::new(manager_sender, Arc::new(Mutex::new(vec![])));
+
+        let request = |workload_id: &str| WorkloadRequest {
+            workload_id: workload_id.to_string(),
+            definition: WorkloadDefinition {
+                api_version: "v0".to_string(),
+                kind: "pods".to_string(),
+                name: workload_id.to_string(),
+                spec: Spec {
+                    containers: vec![Container {
+                        name: "debian".to_string(),
+                        image: "debian:latest".to_string(),
+                        env: None,
+                        ports: None,
+                        resources: Some(ResourceRequests {
+                            cpu: Some(500),
+                            memory: Some(256),
+                        }),
+                    }],
+                },
+                replicas: Some(2),
+                job: None,
+                cron_job: None,
+                node_selector: None,
+                topology_spread: None,
+                gang: None,
+                tenant: Some("acme".to_string()),
+                namespace: None,
+            },
+            action: WorkloadRequestKind::Create,
+            instances: 0,
+            quota: Some(Box::new(ResourceQuota {
+                max_workloads: Some(2),
+                max_instances: Some(6),
+                cpu: Some(2500),
+                memory: None,
+            })),
+            correlation_id: String::new(),
+            trace_context: TraceContext::new(),
+        };
+
+        assert!(state_manager
+            .process_schedule_request(request("web"))
+            .is_ok());
+        // 4 instances requesting 500 millicores each
+        assert!(state_manager
+            .process_schedule_request(request("web"))
+            .is_ok());
+        match state_manager.process_schedule_request(request("web")) {
+            Err(SchedulerError::QuotaExceeded(message)) => assert!(message.contains("cpu")),
+            _ => unreachable!(),
+        }
+
+        let mut api = request("api");
+        api.definition.spec.containers[0].resources = None;
+        api.definition.replicas = Some(3);
+        match state_manager.process_schedule_request(api) {
+            Err(SchedulerError::QuotaExceeded(message)) => {
+                assert!(message.contains("max_instances"))
+            }
+            _ => unreachable!(),
+        }
+
+        let mut api = request("api");
+        api.definition.spec.containers[0].resources = None;
+        api.definition.replicas = Some(1);
+        assert!(state_manager.process_schedule_request(api).is_ok());
+        match state_manager.process_schedule_request(request("worker")) {
+            Err(SchedulerError::QuotaExceeded(message)) => {
+                assert!(message.contains("max_workloads"))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_reconcile_workload() {
+        let (manager_sender, _manager_receiver) = tokio::sync::mpsc::channel::<Event>(1024);
+        let mut state_manager = StateManager::new(manager_sender, Arc::new(Mutex::new(vec![])));
+        let request = |action: WorkloadRequestKind, instances: u16| WorkloadRequest {
+            workload_id: "web".to_string(),
+            definition: WorkloadDefinition {
+                api_version: "v0".to_string(),
+                kind: "pod".to_string(),
+                name: "web".to_string(),
+                spec: Spec { containers: vec![] },
+                replicas: Some(2),
+                job: None,
+                cron_job: None,
+                node_selector: None,
+                topology_spread: None,
+                gang: None,
+                tenant: None,
+                namespace: None,
+            },
+            action,
+            instances,
+            quota: None,
+            correlation_id: String::new(),
+            trace_context: TraceContext::new(),
+        };
+
+        // A workload lost by the scheduler is created again with the instances wanted
+        state_manager
+            .process_schedule_request(request(WorkloadRequestKind::Reconcile, 4))
+            .unwrap();
+        assert_eq!(state_manager.state["web"].replicas, 4);
+        // Reconcile requests are idempotent
+        state_manager
+            .process_schedule_request(request(WorkloadRequestKind::Reconcile, 4))
+            .unwrap();
+        assert_eq!(state_manager.state["web"].replicas, 4);
+        state_manager
+            .process_schedule_request(request(WorkloadRequestKind::Reconcile, 1))
+            .unwrap();
+        assert_eq!(state_manager.state["web"].replicas, 1);
+        // The replicas added by a create request are still the ones of the definition
+        state_manager
+            .process_schedule_request(request(WorkloadRequestKind::Create, 0))
+            .unwrap();
+        assert_eq!(state_manager.state["web"].replicas, 3);
+    }
+}
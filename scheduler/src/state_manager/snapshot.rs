@@ -0,0 +1,126 @@
+use definition::quota::ResourceQuota;
+use definition::workload::WorkloadDefinition;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+
+/// State of the scheduler persisted on shutdown, so it can be restored
+/// on the next startup
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct StateSnapshot {
+    pub workloads: Vec<WorkloadSnapshot>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WorkloadSnapshot {
+    pub id: String,
+    pub definition: WorkloadDefinition,
+    pub replicas: u16,
+    /// A `common.ResourceStatus`
+    pub status: i32,
+    /// Progress of the job, only for workloads of kind `job`
+    pub job: Option<JobSnapshot>,
+    pub instances: Vec<InstanceSnapshot>,
+    /// Resource quota of the tenant, as of the last request on the workload
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<ResourceQuota>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct JobSnapshot {
+    pub succeeded: u16,
+    pub failed: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InstanceSnapshot {
+    pub id: String,
+    pub worker_id: Option<String>,
+    /// A `common.ResourceStatus`
+    pub status: i32,
+}
+
+impl StateSnapshot {
+    pub async fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let content = serde_json::to_vec(self)?;
+        tokio::fs::write(path, content).await
+    }
+
+    /// Load a snapshot, and remove it so a stale state is never restored twice
+    pub async fn take(path: &Path) -> io::Result<Option<StateSnapshot>> {
+        let content = match tokio::fs::read(path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        tokio::fs::remove_file(path).await?;
+        Ok(Some(serde_json::from_slice(&content)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_snapshot_round_trip() {
+        let path = std::env::temp_dir()
+            .join(format!("rik-snapshot-{}", std::process::id()))
+            .join("state.json");
+        let definition: WorkloadDefinition = serde_json::from_value(serde_json::json!({
+            "api_version": "v0",
+            "kind": "job",
+            "name": "backup",
+            "spec": { "containers": [{ "name": "backup", "image": "debian:latest" }] },
+            "tenant": "acme",
+        }))
+        .unwrap();
+        let quota = ResourceQuota {
+            max_workloads: Some(2),
+            max_instances: None,
+            cpu: Some(1500),
+            memory: None,
+        };
+        let snapshot = StateSnapshot {
+            workloads: vec![WorkloadSnapshot {
+                id: "backup".to_string(),
+                definition: definition.clone(),
+                replicas: 1,
+                status: 2,
+                job: Some(JobSnapshot {
+                    succeeded: 1,
+                    failed: 3,
+                }),
+                instances: vec![InstanceSnapshot {
+                    id: "backup-0".to_string(),
+                    worker_id: Some("node-1".to_string()),
+                    status: 1,
+                }],
+                quota: Some(quota.clone()),
+            }],
+        };
+        snapshot.save(&path).await.unwrap();
+
+        let restored = StateSnapshot::take(&path).await.unwrap().unwrap();
+        assert_eq!(restored.workloads.len(), 1);
+        let workload = &restored.workloads[0];
+        assert_eq!(workload.id, "backup");
+        assert_eq!(workload.definition, definition);
+        assert_eq!(workload.replicas, 1);
+        assert_eq!(workload.status, 2);
+        let job = workload.job.as_ref().unwrap();
+        assert_eq!((job.succeeded, job.failed), (1, 3));
+        assert_eq!(workload.instances.len(), 1);
+        assert_eq!(workload.instances[0].id, "backup-0");
+        assert_eq!(workload.instances[0].worker_id.as_deref(), Some("node-1"));
+        assert_eq!(workload.instances[0].status, 1);
+        assert_eq!(workload.quota, Some(quota));
+
+        // A snapshot is only restored once
+        assert!(StateSnapshot::take(&path).await.unwrap().is_none());
+        let _ = std::fs::remove_dir(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,311 @@
+use definition::workload::TopologySpreadConstraint;
+use rand::seq::IteratorRandom;
+use scheduler::Worker;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// How a worker is chosen among the eligible ones, once the topology spread
+/// constraints are honored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedulingStrategy {
+    /// Any eligible worker
+    Random,
+    /// The eligible worker running the fewest instances
+    LeastAllocated,
+}
+
+impl SchedulingStrategy {
+    fn choose<'a>(
+        &self,
+        workers: impl Iterator<Item = &'a Worker>,
+        load: &HashMap<String, usize>,
+    ) -> Option<&'a Worker> {
+        match self {
+            SchedulingStrategy::Random => workers.choose(&mut rand::thread_rng()),
+            SchedulingStrategy::LeastAllocated => {
+                let load_of = |worker: &Worker| load.get(&worker.id).copied().unwrap_or(0);
+                let workers: Vec<&Worker> = workers.collect();
+                let lowest = workers.iter().map(|worker| load_of(worker)).min()?;
+                workers
+                    .into_iter()
+                    .filter(|worker| load_of(worker) == lowest)
+                    .choose(&mut rand::thread_rng())
+            }
+        }
+    }
+}
+
+impl FromStr for SchedulingStrategy {
+    type Err = String;
+
+    fn from_str(strategy: &str) -> Result<Self, Self::Err> {
+        match strategy {
+            "random" => Ok(SchedulingStrategy::Random),
+            "least_allocated" => Ok(SchedulingStrategy::LeastAllocated),
+            _ => Err("unknown strategy, expected one of random, least_allocated".to_string()),
+        }
+    }
+}
+
+/// Worker chosen for a new instance of a workload
+#[derive(Debug, PartialEq, Eq)]
+pub struct Placement {
+    pub worker_id: String,
+    /// Why the topology spread constraints of the workload could not be honored,
+    /// the instance is placed anyway
+    pub unsatisfied: Option<String>,
+}
+
+/// Choose the worker of a new instance among the eligible workers, so the instances
+/// of the workload stay balanced across the failure domains of its constraints.
+/// Only the domains of eligible workers are considered, the instances left on a
+/// domain that went down don't prevent spreading the others. The strategy picks
+/// the worker among the equally balanced ones, given the instances each runs.
+pub fn place(
+    constraints: &[TopologySpreadConstraint],
+    candidates: &[&Worker],
+    workers: &[Worker],
+    placed_on: &[String],
+    strategy: SchedulingStrategy,
+    load: &HashMap<String, usize>,
+) -> Option<Placement> {
+    if constraints.is_empty() {
+        return strategy
+            .choose(candidates.iter().copied(), load)
+            .map(|worker| Placement {
+                worker_id: worker.id.clone(),
+                unsatisfied: None,
+            });
+    }
+
+    let in_domains: Vec<&Worker> = candidates
+        .iter()
+        .filter(|worker| {
+            constraints
+                .iter()
+                .all(|constraint| worker.labels.contains_key(&constraint.topology_key))
+        })
+        .copied()
+        .collect();
+    if in_domains.is_empty() {
+        let keys: Vec<&str> = constraints
+            .iter()
+            .map(|constraint| constraint.topology_key.as_str())
+            .collect();
+        return strategy
+            .choose(candidates.iter().copied(), load)
+            .map(|worker| Placement {
+                worker_id: worker.id.clone(),
+                unsatisfied: Some(format!(
+                    "no eligible worker has the label {}",
+                    keys.join(", ")
+                )),
+            });
+    }
+
+    // Number of instances in every domain, for each constraint
+    let counts: Vec<HashMap<&str, usize>> = constraints
+        .iter()
+        .map(|constraint| {
+            let key = &constraint.topology_key;
+            let mut counts: HashMap<&str, usize> = in_domains
+                .iter()
+                .map(|worker| (worker.labels[key].as_str(), 0))
+                .collect();
+            for worker_id in placed_on {
+                let domain = workers
+                    .iter()
+                    .find(|worker| &worker.id == worker_id)
+                    .and_then(|worker| worker.labels.get(key));
+                if let Some(count) = domain.and_then(|domain| counts.get_mut(domain.as_str())) {
+                    *count += 1;
+                }
+            }
+            counts
+        })
+        .collect();
+
+    let score = |worker: &Worker| -> usize {
+        constraints
+            .iter()
+            .zip(&counts)
+            .map(|(constraint, counts)| counts[worker.labels[&constraint.topology_key].as_str()])
+            .sum()
+    };
+    let lowest = in_domains.iter().map(|worker| score(worker)).min()?;
+    let worker = strategy.choose(
+        in_domains
+            .into_iter()
+            .filter(|worker| score(worker) == lowest),
+        load,
+    )?;
+
+    let unsatisfied = constraints
+        .iter()
+        .zip(counts)
+        .find_map(|(constraint, mut counts)| {
+            *counts
+                .get_mut(worker.labels[&constraint.topology_key].as_str())
+                .unwrap() += 1;
+            let skew = counts.values().max().unwrap() - counts.values().min().unwrap();
+            (skew > constraint.get_max_skew() as usize).then(|| {
+                format!(
+                    "skew of {} across {} exceeds the max skew of {}",
+                    skew,
+                    constraint.topology_key,
+                    constraint.get_max_skew()
+                )
+            })
+        });
+
+    Some(Placement {
+        worker_id: worker.id.clone(),
+        unsatisfied,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use definition::workload::ZONE_LABEL;
+
+    const RANDOM: SchedulingStrategy = SchedulingStrategy::Random;
+
+    fn zoned_workers(zones: &[(&str, Option<&str>)]) -> Vec<Worker> {
+        let (sender, _) = tokio::sync::mpsc::channel(1);
+        zones
+            .iter()
+            .map(|(hostname, zone)| {
+                let mut worker = Worker::new(
+                    hostname.to_string(),
+                    sender.clone(),
+                    "127.0.0.1:8080".parse().unwrap(),
+                );
+                if let Some(zone) = zone {
+                    worker
+                        .labels
+                        .insert(ZONE_LABEL.to_string(), zone.to_string());
+                }
+                worker
+            })
+            .collect()
+    }
+
+    fn zone_spread(max_skew: u16) -> Vec<TopologySpreadConstraint> {
+        vec![TopologySpreadConstraint {
+            topology_key: ZONE_LABEL.to_string(),
+            max_skew: Some(max_skew),
+        }]
+    }
+
+    #[test]
+    fn test_spread_across_zones() {
+        let workers = zoned_workers(&[
+            ("node-1", Some("a")),
+            ("node-2", Some("a")),
+            ("node-3", Some("b")),
+        ]);
+        let candidates: Vec<&Worker> = workers.iter().collect();
+        let mut placed_on = Vec::new();
+        for _ in 0..4 {
+            let placement = place(
+                &zone_spread(1),
+                &candidates,
+                &workers,
+                &placed_on,
+                RANDOM,
+                &HashMap::new(),
+            )
+            .unwrap();
+            assert_eq!(placement.unsatisfied, None);
+            placed_on.push(placement.worker_id);
+        }
+        let in_zone_b = placed_on.iter().filter(|id| *id == "node-3").count();
+        assert_eq!(in_zone_b, 2);
+    }
+
+    #[test]
+    fn test_lost_zone_is_ignored() {
+        let workers = zoned_workers(&[("node-1", Some("a")), ("node-2", Some("b"))]);
+        // Zone b went down, its instances are replaced in the remaining zone
+        let candidates = vec![&workers[0]];
+        let placed_on = vec!["node-1".to_string()];
+        let placement = place(
+            &zone_spread(1),
+            &candidates,
+            &workers,
+            &placed_on,
+            RANDOM,
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(placement.worker_id, "node-1");
+        assert_eq!(placement.unsatisfied, None);
+    }
+
+    #[test]
+    fn test_unsatisfiable_spread_degrades() {
+        let workers = zoned_workers(&[("node-1", Some("a")), ("node-2", Some("b"))]);
+        let candidates: Vec<&Worker> = workers.iter().collect();
+        let placed_on = vec!["node-1".to_string(); 3];
+        let placement = place(
+            &zone_spread(1),
+            &candidates,
+            &workers,
+            &placed_on,
+            RANDOM,
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(placement.worker_id, "node-2");
+        assert!(placement.unsatisfied.is_some());
+
+        // Workers without the label are used when no worker has it
+        let workers = zoned_workers(&[("node-1", None)]);
+        let candidates: Vec<&Worker> = workers.iter().collect();
+        let placement = place(
+            &zone_spread(1),
+            &candidates,
+            &workers,
+            &[],
+            RANDOM,
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(placement.worker_id, "node-1");
+        assert!(placement.unsatisfied.is_some());
+    }
+
+    #[test]
+    fn test_least_allocated_strategy() {
+        let workers = zoned_workers(&[
+            ("node-1", Some("a")),
+            ("node-2", Some("a")),
+            ("node-3", Some("b")),
+        ]);
+        let candidates: Vec<&Worker> = workers.iter().collect();
+        let load = HashMap::from([("node-1".to_string(), 3), ("node-3".to_string(), 1)]);
+        let placement = place(
+            &[],
+            &candidates,
+            &workers,
+            &[],
+            SchedulingStrategy::LeastAllocated,
+            &load,
+        )
+        .unwrap();
+        assert_eq!(placement.worker_id, "node-2");
+
+        // The spread comes first, the strategy only breaks the ties
+        let placement = place(
+            &zone_spread(1),
+            &candidates,
+            &workers,
+            &["node-2".to_string()],
+            SchedulingStrategy::LeastAllocated,
+            &load,
+        )
+        .unwrap();
+        assert_eq!(placement.worker_id, "node-3");
+    }
+}